@@ -0,0 +1,46 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use std::{path::Path, process::Command};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+fn git(args: &[&str]) -> Option<String> {
+  Command::new("git")
+    .args(args)
+    .output()
+    .ok()
+    .filter(|output| output.status.success())
+    .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Embeds build metadata that is printed by --version-json.
+fn main() {
+  let git_commit = git(&["rev-parse", "--short", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+  println!("cargo:rustc-env=BUILD_GIT_COMMIT={}", git_commit);
+
+  let build_date = OffsetDateTime::now_utc()
+    .format(&Rfc3339)
+    .unwrap_or_else(|_| "unknown".to_string());
+  println!("cargo:rustc-env=BUILD_DATE={}", build_date);
+
+  // Used by self-update to find the release artifact for this platform
+  let target = std::env::var("TARGET").unwrap_or_else(|_| "unknown".to_string());
+  println!("cargo:rustc-env=BUILD_TARGET={}", target);
+
+  println!("cargo:rerun-if-env-changed=SELF_UPDATE_PUBLIC_KEY");
+
+  // HEAD changes when another branch is checked out, while a commit changes the branch that HEAD points to.
+  // The branch is a file of its own until git gc moves it into packed-refs.
+  // Paths that don't exist are left out, since cargo would otherwise run the build script on every build.
+  let mut git_paths = vec!["HEAD".to_string(), "packed-refs".to_string()];
+  if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+    git_paths.push(branch);
+  }
+  for name in git_paths {
+    if let Some(path) = git(&["rev-parse", "--git-path", &name]) {
+      if Path::new(&path).exists() {
+        println!("cargo:rerun-if-changed={}", path);
+      }
+    }
+  }
+}
@@ -0,0 +1,218 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+// --alias-target
+// Alias records need the hosted zone ID of the target, which for AWS services is a fixed ID per service and region. The ID is looked up from the DNS name of the target so that it doesn't have to be given with --alias-target-hosted-zone-id.
+// https://docs.aws.amazon.com/general/latest/gr/elb.html
+// https://docs.aws.amazon.com/general/latest/gr/s3.html#s3_website_region_endpoints
+// https://docs.aws.amazon.com/general/latest/gr/apigateway.html
+
+use aws_sdk_route53::types::{AliasTarget, ResourceRecordSet, RrType};
+
+use crate::types;
+
+// The same hosted zone ID is used for all CloudFront distributions
+pub const CLOUDFRONT_HOSTED_ZONE_ID: &str = "Z2FDTNDATAQYW2";
+pub const GLOBAL_ACCELERATOR_HOSTED_ZONE_ID: &str = "Z2BJ6XQ5FK7U4H";
+
+// Region, Application and Classic Load Balancers, Network Load Balancers, S3 website endpoints, API Gateway regional endpoints
+// The IDs are unique per region, except in ap-northeast-3 where S3 website endpoints and API Gateway use the same hosted zone ID.
+#[rustfmt::skip]
+pub const REGIONAL_HOSTED_ZONE_IDS: [(&str, &str, &str, &str, &str); 21] = [
+  ("us-east-1",      "Z35SXDOTRQ7X7K", "Z26RNL4JYFTOTI", "Z3AQBSTGFYJSTF", "Z1UJRXOUMOOFQ8"),
+  ("us-east-2",      "Z3AADJGX6KTTL2", "ZLMOA37VPKANP",  "Z2O1EMRO9K5GLX", "ZOJJZC49E0EPZ"),
+  ("us-west-1",      "Z368ELLRRE2KJ0", "Z24FKFUX50B4VW", "Z2F56UZL2M1ACD", "Z2MUQ32089INYE"),
+  ("us-west-2",      "Z1H1FL5HABSF5",  "Z18D5FSROUN65G", "Z3BJ6K6RIION7M", "Z2OJLYMUO9EFXC"),
+  ("af-south-1",     "Z268VQBMOI5EKX", "Z203XCE67M25HM", "Z83WF9RJE8B12",  "Z2DHW2332DAMTN"),
+  ("ap-east-1",      "Z3DQVH9N71FHZ0", "Z12Y7K3UBGUAD1", "ZNB98KWMFR0R6",  "Z3FD1VL90ND7K5"),
+  ("ap-south-1",     "ZP97RAFLXTNZK",  "ZVDDRBQ08TROA",  "Z11RGJOFQNVJUP", "Z3VO1THU9YC4UR"),
+  ("ap-northeast-1", "Z14GRHDCWA56QT", "Z31USIVHYNEOWT", "Z2M4EHUR26P7ZW", "Z1YSHQZHG15GKL"),
+  ("ap-northeast-2", "ZWKZPGTI48KDX",  "ZIBE1TIR4HY56",  "Z3W03O7B5YMIYP", "Z20JF4UZKIW1U8"),
+  ("ap-northeast-3", "Z5LXEXXYW11ES",  "Z1GWIQ4HH19I5X", "Z2YQB5RD63NC85", "Z2YQB5RD63NC85"),
+  ("ap-southeast-1", "Z1LMS91P8CMLE5", "ZKVM4W9LS7TM",   "Z3O0J2DXBE1FTB", "ZL327KTPIQFUL"),
+  ("ap-southeast-2", "Z1GM3OXH4ZPM65", "ZCT6FZBF4DROD",  "Z1WCIGYICN2BYD", "Z2RPCDW04V8134"),
+  ("ca-central-1",   "ZQSVJUPU6J1EY",  "Z2EPGBW3API2WT", "Z1QDHH18159H29", "Z19DQILCV0OWEC"),
+  ("eu-central-1",   "Z215JYRZR1TBD5", "Z3F0SRJ5LGBH90", "Z21DNDUVLTQW6Q", "Z1U9ULNL0V5AJ3"),
+  ("eu-west-1",      "Z32O12XQLNTSW2", "Z2IFOLAFXWLO4F", "Z1BKCTXD74EZPE", "ZLY8HYME6SFDD"),
+  ("eu-west-2",      "ZHURV8PSTC4K8",  "ZD4D7Y8KGAS4G",  "Z3GKZC51ZF0DB4", "ZJ5UAJN8Y3Z2Q"),
+  ("eu-west-3",      "Z3Q77PNBQS71R4", "Z1CMS0P5QUZ6D5", "Z3R1K369G5AVDG", "Z3KY65QIEKYHQQ"),
+  ("eu-south-1",     "Z3ULH7SSC9OV64", "Z23146JA1KNAFP", "Z30OZKI7KPW7MI", "Z3BT4WSQ9TDYZV"),
+  ("eu-north-1",     "Z23TAZ7KKB2L8M", "Z1UDT6IFJ4EJM",  "Z3BAZG2TWCNX0D", "Z3UWIKFBOOGXPP"),
+  ("me-south-1",     "ZS929ML54UICD",  "Z3QSRYVP46NYYV", "Z1MPMWCPA7YB62", "Z20ZBPC0SS8806"),
+  ("sa-east-1",      "Z2P70J7HTTTPLU", "ZTK26PT1VY4CU",  "Z7KQH4QJS55SO",  "ZCMLWB8V5SYIT"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AliasService {
+  CloudFront,
+  GlobalAccelerator,
+  LoadBalancer,
+  NetworkLoadBalancer,
+  S3Website,
+  ApiGateway,
+}
+
+// The regions that were launched before 2014 use a dash between s3-website and the region
+const S3_WEBSITE_DASH_REGIONS: [&str; 8] = [
+  "us-east-1",
+  "us-west-1",
+  "us-west-2",
+  "ap-northeast-1",
+  "ap-southeast-1",
+  "ap-southeast-2",
+  "eu-west-1",
+  "sa-east-1",
+];
+
+// Route 53 returns the DNS name of an alias target in lowercase and with a trailing dot.
+pub fn normalize_dns_name(dns_name: &str) -> String {
+  format!("{}.", dns_name.trim_end_matches('.').to_lowercase())
+}
+
+// Returns the AWS service and region of the DNS name, or None if it is not the endpoint of a supported AWS service.
+pub fn get_alias_service(dns_name: &str) -> Option<(AliasService, Option<String>)> {
+  let dns_name = normalize_dns_name(dns_name);
+  let dns_name = dns_name.trim_end_matches('.');
+  if dns_name.ends_with(".cloudfront.net") {
+    return Some((AliasService::CloudFront, None));
+  }
+  if dns_name.ends_with(".awsglobalaccelerator.com") {
+    return Some((AliasService::GlobalAccelerator, None));
+  }
+  let labels: Vec<&str> = dns_name
+    .strip_suffix(".amazonaws.com")?
+    .split('.')
+    .collect();
+  match labels[..] {
+    // my-alb-123.us-east-1.elb.amazonaws.com (also with a dualstack. or internal- prefix)
+    [.., region, "elb"] => Some((AliasService::LoadBalancer, Some(region.to_string()))),
+    // my-nlb-123.elb.us-east-1.amazonaws.com
+    [.., "elb", region] => Some((AliasService::NetworkLoadBalancer, Some(region.to_string()))),
+    // bucket.s3-website.eu-central-1.amazonaws.com
+    [.., "s3-website", region] => Some((AliasService::S3Website, Some(region.to_string()))),
+    // d-abc123.execute-api.us-east-1.amazonaws.com, the regional domain name of an API Gateway custom domain name
+    [.., "execute-api", region] => Some((AliasService::ApiGateway, Some(region.to_string()))),
+    // bucket.s3-website-us-east-1.amazonaws.com
+    [.., label] => label
+      .strip_prefix("s3-website-")
+      .map(|region| (AliasService::S3Website, Some(region.to_string()))),
+    [] => None,
+  }
+}
+
+// Returns None if the DNS name is not the endpoint of a supported AWS service, in which case it is assumed to be a record in the same hosted zone.
+pub fn get_alias_hosted_zone_id(dns_name: &str) -> Result<Option<&'static str>, String> {
+  let Some((service, region)) = get_alias_service(dns_name) else {
+    return Ok(None);
+  };
+  let regional = |region: Option<String>, index: usize| {
+    let region = region.unwrap_or_default();
+    REGIONAL_HOSTED_ZONE_IDS
+      .iter()
+      .find(|ids| ids.0 == region)
+      .map(|ids| [ids.1, ids.2, ids.3, ids.4][index])
+      .ok_or_else(|| {
+        format!(
+          "the hosted zone ID of {:?} endpoints in {} is not known, use --alias-target-hosted-zone-id",
+          service, region
+        )
+      })
+  };
+  match service {
+    AliasService::CloudFront => Ok(Some(CLOUDFRONT_HOSTED_ZONE_ID)),
+    AliasService::GlobalAccelerator => Ok(Some(GLOBAL_ACCELERATOR_HOSTED_ZONE_ID)),
+    AliasService::LoadBalancer => regional(region, 0).map(Some),
+    AliasService::NetworkLoadBalancer => regional(region, 1).map(Some),
+    AliasService::S3Website => regional(region, 2).map(Some),
+    AliasService::ApiGateway => regional(region, 3).map(Some),
+  }
+}
+
+// --alias-s3-website
+// The alias target is the website endpoint of the region, and the bucket must have the same name as the record.
+pub fn get_s3_website_endpoint(region: &str) -> String {
+  if S3_WEBSITE_DASH_REGIONS.contains(&region) {
+    format!("s3-website-{}.amazonaws.com", region)
+  } else {
+    format!("s3-website.{}.amazonaws.com", region)
+  }
+}
+
+// --alias-api-gateway
+// An API is reached through a custom domain name with the same name as the record. Returns the DNS name and the hosted zone ID of the custom domain name, which are the alias target.
+pub async fn get_api_gateway_target(
+  apigateway_client: &aws_sdk_apigatewayv2::Client,
+  record_name: &str,
+  api_id: &str,
+) -> Result<(String, String), String> {
+  let domain_name = record_name.trim_end_matches('.');
+  let response = apigateway_client
+    .get_domain_name()
+    .domain_name(domain_name)
+    .send()
+    .await
+    .map_err(|err| {
+      format!(
+        "could not get the API Gateway custom domain name {} (create it with the certificate for the name first): {}",
+        domain_name, err
+      )
+    })?;
+  let configuration = response
+    .domain_name_configurations()
+    .first()
+    .ok_or_else(|| format!("the custom domain name {} has no endpoint", domain_name))?;
+  let (Some(dns_name), Some(hosted_zone_id)) = (
+    configuration.api_gateway_domain_name(),
+    configuration.hosted_zone_id(),
+  ) else {
+    return Err(format!(
+      "the custom domain name {} is not ready yet",
+      domain_name
+    ));
+  };
+
+  let mappings = apigateway_client
+    .get_api_mappings()
+    .domain_name(domain_name)
+    .send()
+    .await
+    .map_err(|err| format!("could not get the API mappings of {}: {}", domain_name, err))?;
+  if !mappings
+    .items()
+    .iter()
+    .any(|mapping| mapping.api_id() == Some(api_id))
+  {
+    return Err(format!(
+      "the API {} is not mapped to the custom domain name {}",
+      api_id, domain_name
+    ));
+  }
+  Ok((dns_name.to_string(), hosted_zone_id.to_string()))
+}
+
+pub fn build_alias_record_set(
+  record_name: &str,
+  record_type: &RrType,
+  hosted_zone_id: &str,
+  dns_name: &str,
+  evaluate_target_health: bool,
+  routing: &types::RecordRouting,
+) -> ResourceRecordSet {
+  ResourceRecordSet::builder()
+    .set_set_identifier(routing.set_identifier.clone())
+    .set_cidr_routing_config(routing.cidr_routing_config.clone())
+    .set_geo_proximity_location(routing.geo_proximity_location.clone())
+    .set_weight(routing.weight)
+    .name(record_name)
+    .r#type(record_type.clone())
+    .alias_target(
+      AliasTarget::builder()
+        .hosted_zone_id(hosted_zone_id)
+        .dns_name(dns_name)
+        .evaluate_target_health(evaluate_target_health)
+        .build()
+        .expect("error building alias target"),
+    )
+    .build()
+    .expect("error building resource record set")
+}
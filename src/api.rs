@@ -0,0 +1,154 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::{types, utils};
+
+use aws_sdk_route53::types::RrType;
+use hyper::{
+  header,
+  service::{make_service_fn, service_fn},
+  Body, Method, Request, Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+pub struct ApiOptions {
+  pub token: String,
+  pub allowed_record_names: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateRecordBody {
+  #[serde(rename = "type")]
+  record_type: Option<String>,
+  value: Vec<String>,
+  ttl: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+  error: String,
+}
+
+// PUT /records/{name} with a JSON body like {"type": "A", "value": ["192.0.2.1"], "ttl": 60}
+pub async fn serve(listen: SocketAddr, options: ApiOptions, handler: types::UpdateHandler) {
+  let options = Arc::new(options);
+  let make_service = make_service_fn(move |_| {
+    let options = options.clone();
+    let handler = handler.clone();
+    async move {
+      Ok::<_, Infallible>(service_fn(move |req| {
+        handle_request(req, options.clone(), handler.clone())
+      }))
+    }
+  });
+
+  eprintln!("Listening for API requests on http://{}/records/", listen);
+  hyper::Server::bind(&listen)
+    .serve(make_service)
+    .await
+    .expect("server error");
+}
+
+async fn handle_request(
+  req: Request<Body>,
+  options: Arc<ApiOptions>,
+  handler: types::UpdateHandler,
+) -> Result<Response<Body>, Infallible> {
+  let Some(record_name) = req
+    .uri()
+    .path()
+    .strip_prefix("/records/")
+    .map(|name| name.to_string())
+  else {
+    return Ok(error_response(StatusCode::NOT_FOUND, "not found"));
+  };
+  if req.method() != Method::PUT {
+    return Ok(error_response(
+      StatusCode::METHOD_NOT_ALLOWED,
+      "method not allowed",
+    ));
+  }
+
+  let authorized = req
+    .headers()
+    .get(header::AUTHORIZATION)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.strip_prefix("Bearer "))
+    .is_some_and(|token| utils::constant_time_eq(token.as_bytes(), options.token.as_bytes()));
+  if !authorized {
+    return Ok(error_response(StatusCode::UNAUTHORIZED, "unauthorized"));
+  }
+
+  if !record_name.contains('.') {
+    return Ok(error_response(
+      StatusCode::BAD_REQUEST,
+      "the record name must be fully qualified",
+    ));
+  }
+  if !utils::matches_record_name_pattern(&record_name, &options.allowed_record_names) {
+    return Ok(error_response(
+      StatusCode::FORBIDDEN,
+      "updating this record name is not allowed",
+    ));
+  }
+
+  let body = match hyper::body::to_bytes(req.into_body()).await {
+    Ok(body) => body,
+    Err(err) => return Ok(error_response(StatusCode::BAD_REQUEST, &err.to_string())),
+  };
+  let body: UpdateRecordBody = match serde_json::from_slice(&body) {
+    Ok(body) => body,
+    Err(err) => return Ok(error_response(StatusCode::BAD_REQUEST, &err.to_string())),
+  };
+  if body.value.is_empty() {
+    return Ok(error_response(
+      StatusCode::BAD_REQUEST,
+      "value must not be empty",
+    ));
+  }
+  let record_type = match body.record_type.map(|t| t.to_uppercase()) {
+    Some(t) if !RrType::values().contains(&t.as_str()) => {
+      return Ok(error_response(
+        StatusCode::BAD_REQUEST,
+        &format!("unsupported record type: {}", t),
+      ))
+    }
+    t => t.map(|t| RrType::from(t.as_str())),
+  };
+
+  let request = types::UpdateRequest {
+    record_name: record_name.clone(),
+    record_type,
+    value_source: Some(types::ValueSource::Values(body.value)),
+    alias_target: None,
+    ttl: body.ttl,
+  };
+  match handler(request).await {
+    Ok(result) => Ok(json_response(
+      StatusCode::OK,
+      &types::UpdateResultJson::from(&result),
+    )),
+    Err(err) => {
+      eprintln!("Error updating {}: {}", record_name, err);
+      Ok(error_response(StatusCode::INTERNAL_SERVER_ERROR, &err))
+    }
+  }
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+  Response::builder()
+    .status(status)
+    .header(header::CONTENT_TYPE, "application/json")
+    .body(Body::from(serde_json::to_string(body).unwrap()))
+    .unwrap()
+}
+
+fn error_response(status: StatusCode, error: &str) -> Response<Body> {
+  json_response(
+    status,
+    &ErrorResponse {
+      error: error.to_string(),
+    },
+  )
+}
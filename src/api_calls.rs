@@ -0,0 +1,54 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::config::{
+  interceptors::BeforeSerializationInterceptorContextRef, ConfigBag, Intercept,
+};
+use aws_sdk_route53::error::BoxError;
+use aws_smithy_runtime_api::client::orchestrator::Metadata;
+use std::sync::Mutex;
+
+// The number of calls per operation, in the order that the operations were first called.
+static API_CALLS: Mutex<Vec<(String, usize)>> = Mutex::new(Vec::new());
+
+pub fn record_api_call(operation: &str) {
+  let mut api_calls = API_CALLS.lock().unwrap();
+  match api_calls.iter_mut().find(|(name, _)| name == operation) {
+    Some((_, count)) => *count += 1,
+    None => api_calls.push((operation.to_string(), 1)),
+  }
+}
+
+// e.g. ListHostedZones x1, ListResourceRecordSets x2, ChangeResourceRecordSets x1, GetChange x12
+pub fn get_summary() -> String {
+  let api_calls = API_CALLS.lock().unwrap();
+  if api_calls.is_empty() {
+    return "none".to_string();
+  }
+  api_calls
+    .iter()
+    .map(|(name, count)| format!("{} x{}", name, count))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+// Counts each API call once, retried attempts are not counted separately.
+#[derive(Debug)]
+pub struct ApiCallCounter;
+
+impl Intercept for ApiCallCounter {
+  fn name(&self) -> &'static str {
+    "ApiCallCounter"
+  }
+
+  fn read_before_execution(
+    &self,
+    _context: &BeforeSerializationInterceptorContextRef<'_>,
+    cfg: &mut ConfigBag,
+  ) -> Result<(), BoxError> {
+    if let Some(metadata) = cfg.load::<Metadata>() {
+      record_api_call(metadata.name());
+    }
+    Ok(())
+  }
+}
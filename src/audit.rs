@@ -0,0 +1,55 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogEntry {
+  pub timestamp: String,
+  pub hostname: String,
+  pub hosted_zone_id: String,
+  pub record_name: String,
+  pub record_type: String,
+  pub old_value: Vec<String>,
+  pub new_value: Vec<String>,
+  pub change_id: String,
+}
+
+// Local files get one JSON line appended per change. S3 does not support appending to objects, so each change is written as a separate object under the prefix instead.
+pub async fn write_audit_log(
+  destination: &str,
+  entry: &AuditLogEntry,
+  shared_config: &aws_config::SdkConfig,
+) {
+  let line = serde_json::to_string(entry).expect("error serializing audit log entry");
+
+  if let Some(location) = destination.strip_prefix("s3://") {
+    let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+    let change_id = entry.change_id.rsplit('/').next().unwrap_or_default();
+    let key = format!(
+      "{}{}-{}.json",
+      prefix,
+      entry.timestamp.replace(':', ""),
+      change_id
+    );
+    let s3_client = aws_sdk_s3::Client::new(shared_config);
+    s3_client
+      .put_object()
+      .bucket(bucket)
+      .key(&key)
+      .content_type("application/json")
+      .body(format!("{}\n", line).into_bytes().into())
+      .send()
+      .await
+      .expect("could not write audit log to S3");
+    eprintln!("Wrote audit log to s3://{}/{}", bucket, key);
+  } else {
+    let mut file = std::fs::OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(destination)
+      .expect("could not open audit log file");
+    writeln!(file, "{}", line).expect("could not write to audit log file");
+  }
+}
@@ -0,0 +1,242 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::utils;
+
+use aws_sdk_route53::error::DisplayErrorContext;
+use aws_sdk_route53::types::{Change, ChangeAction, ChangeBatch, ResourceRecordSet};
+use std::{collections::HashMap, fmt, future::Future, pin::Pin, sync::Mutex, time::Instant};
+
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, String>> + Send + 'a>>;
+
+// The errors of calls that the IAM policy does not allow start with this, so that the caller can fall back to the defaults.
+pub const ACCESS_DENIED: &str = "access denied";
+
+// The DNS operations that the commands need, so that they can run against something other than the Route 53 API, e.g. MemoryBackend in tests.
+// The change methods apply all of the record sets atomically and return a change ID that can be passed to wait.
+pub trait DnsBackend: Send + Sync {
+  // Returns all of the record sets with the name (any type and set identifier).
+  fn get<'a>(
+    &'a self,
+    hosted_zone_id: &'a str,
+    record_name: &'a str,
+  ) -> BackendFuture<'a, Vec<ResourceRecordSet>>;
+  fn upsert<'a>(
+    &'a self,
+    hosted_zone_id: &'a str,
+    record_sets: Vec<ResourceRecordSet>,
+    comment: Option<String>,
+  ) -> BackendFuture<'a, String>;
+  fn delete<'a>(
+    &'a self,
+    hosted_zone_id: &'a str,
+    record_sets: Vec<ResourceRecordSet>,
+    comment: Option<String>,
+  ) -> BackendFuture<'a, String>;
+  fn wait<'a>(&'a self, change_id: &'a str) -> BackendFuture<'a, ()>;
+}
+
+impl fmt::Debug for dyn DnsBackend {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "DnsBackend")
+  }
+}
+
+pub struct Route53Backend {
+  pub client: aws_sdk_route53::Client,
+  // --wait-max-attempts
+  pub max_attempts: Option<u32>,
+  // --startup-grace, the record sets are listed again until then if the network is not up yet
+  pub startup_deadline: Option<Instant>,
+}
+
+impl Route53Backend {
+  async fn change(
+    &self,
+    hosted_zone_id: &str,
+    action: ChangeAction,
+    record_sets: Vec<ResourceRecordSet>,
+    comment: Option<String>,
+  ) -> Result<String, String> {
+    let mut change_batch_builder = ChangeBatch::builder().set_comment(comment);
+    for record_set in record_sets {
+      let change = Change::builder()
+        .action(action.clone())
+        .resource_record_set(record_set)
+        .build()
+        .map_err(|err| format!("error building change set: {}", err))?;
+      change_batch_builder = change_batch_builder.changes(change);
+    }
+    let change_batch = change_batch_builder
+      .build()
+      .map_err(|err| format!("error building change batch: {}", err))?;
+    let response = self
+      .client
+      .change_resource_record_sets()
+      .hosted_zone_id(hosted_zone_id)
+      .change_batch(change_batch)
+      .send()
+      .await
+      .map_err(|err| format!("could not update DNS: {}", DisplayErrorContext(&err)))?;
+    println!("{:?}", response);
+    Ok(response.change_info().unwrap().id().to_string())
+  }
+}
+
+impl DnsBackend for Route53Backend {
+  fn get<'a>(
+    &'a self,
+    hosted_zone_id: &'a str,
+    record_name: &'a str,
+  ) -> BackendFuture<'a, Vec<ResourceRecordSet>> {
+    Box::pin(async move {
+      // The record sets are sorted by name, so starting the listing at the record name returns all of the record sets with that name on the first page
+      let record_sets = utils::retry_until_deadline(self.startup_deadline, || {
+        self
+          .client
+          .list_resource_record_sets()
+          .hosted_zone_id(hosted_zone_id)
+          .start_record_name(record_name)
+          .send()
+      })
+      .await
+      .map_err(|err| {
+        if utils::is_access_denied(&err) {
+          format!("{} when listing record sets", ACCESS_DENIED)
+        } else {
+          format!("could not list record sets: {}", DisplayErrorContext(&err))
+        }
+      })?
+      .resource_record_sets;
+      Ok(
+        record_sets
+          .into_iter()
+          .filter(|r| r.name() == record_name)
+          .collect(),
+      )
+    })
+  }
+
+  fn upsert<'a>(
+    &'a self,
+    hosted_zone_id: &'a str,
+    record_sets: Vec<ResourceRecordSet>,
+    comment: Option<String>,
+  ) -> BackendFuture<'a, String> {
+    Box::pin(self.change(hosted_zone_id, ChangeAction::Upsert, record_sets, comment))
+  }
+
+  fn delete<'a>(
+    &'a self,
+    hosted_zone_id: &'a str,
+    record_sets: Vec<ResourceRecordSet>,
+    comment: Option<String>,
+  ) -> BackendFuture<'a, String> {
+    Box::pin(self.change(hosted_zone_id, ChangeAction::Delete, record_sets, comment))
+  }
+
+  fn wait<'a>(&'a self, change_id: &'a str) -> BackendFuture<'a, ()> {
+    Box::pin(utils::wait_for_change_with_progress(
+      &self.client,
+      change_id,
+      self.max_attempts,
+      utils::print_change_progress,
+    ))
+  }
+}
+
+// Keeps the record sets in memory and applies the changes immediately, e.g. for tests.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+  // Hosted zone ID -> record sets
+  pub zones: Mutex<HashMap<String, Vec<ResourceRecordSet>>>,
+  change_count: Mutex<u64>,
+}
+
+fn is_same_record_set(a: &ResourceRecordSet, b: &ResourceRecordSet) -> bool {
+  a.name() == b.name() && a.r#type() == b.r#type() && a.set_identifier() == b.set_identifier()
+}
+
+impl MemoryBackend {
+  pub fn new(zones: HashMap<String, Vec<ResourceRecordSet>>) -> Self {
+    MemoryBackend {
+      zones: Mutex::new(zones),
+      change_count: Mutex::new(0),
+    }
+  }
+
+  fn next_change_id(&self) -> String {
+    let mut change_count = self.change_count.lock().unwrap();
+    *change_count += 1;
+    format!("/change/C{}", change_count)
+  }
+}
+
+impl DnsBackend for MemoryBackend {
+  fn get<'a>(
+    &'a self,
+    hosted_zone_id: &'a str,
+    record_name: &'a str,
+  ) -> BackendFuture<'a, Vec<ResourceRecordSet>> {
+    Box::pin(async move {
+      let zones = self.zones.lock().unwrap();
+      let record_sets = zones
+        .get(hosted_zone_id)
+        .ok_or_else(|| format!("no such hosted zone: {}", hosted_zone_id))?;
+      Ok(
+        record_sets
+          .iter()
+          .filter(|r| r.name() == record_name)
+          .cloned()
+          .collect(),
+      )
+    })
+  }
+
+  fn upsert<'a>(
+    &'a self,
+    hosted_zone_id: &'a str,
+    record_sets: Vec<ResourceRecordSet>,
+    _comment: Option<String>,
+  ) -> BackendFuture<'a, String> {
+    Box::pin(async move {
+      let mut zones = self.zones.lock().unwrap();
+      let zone = zones
+        .get_mut(hosted_zone_id)
+        .ok_or_else(|| format!("no such hosted zone: {}", hosted_zone_id))?;
+      for record_set in record_sets {
+        zone.retain(|r| !is_same_record_set(r, &record_set));
+        zone.push(record_set);
+      }
+      Ok(self.next_change_id())
+    })
+  }
+
+  fn delete<'a>(
+    &'a self,
+    hosted_zone_id: &'a str,
+    record_sets: Vec<ResourceRecordSet>,
+    _comment: Option<String>,
+  ) -> BackendFuture<'a, String> {
+    Box::pin(async move {
+      let mut zones = self.zones.lock().unwrap();
+      let zone = zones
+        .get_mut(hosted_zone_id)
+        .ok_or_else(|| format!("no such hosted zone: {}", hosted_zone_id))?;
+      // Like Route 53, the record set has to match exactly and nothing is changed if one of them does not
+      if let Some(record_set) = record_sets.iter().find(|r| !zone.contains(r)) {
+        return Err(format!(
+          "the record set {} {} was not found",
+          record_set.r#type().as_str(),
+          record_set.name()
+        ));
+      }
+      zone.retain(|r| !record_sets.contains(r));
+      Ok(self.next_change_id())
+    })
+  }
+
+  fn wait<'a>(&'a self, _change_id: &'a str) -> BackendFuture<'a, ()> {
+    Box::pin(async { Ok(()) })
+  }
+}
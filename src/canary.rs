@@ -0,0 +1,103 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::backend::DnsBackend;
+
+use aws_sdk_route53::types::{ResourceRecordSet, RrType};
+use std::{
+  net::{IpAddr, SocketAddr},
+  time::Duration,
+};
+
+// The probe connects to the new address directly when the URL uses the record name, so that it does not depend on the resolvers having picked up the change.
+pub fn get_probe_address(
+  url: &reqwest::Url,
+  record_name: &str,
+  record_type: &RrType,
+  values: &[String],
+) -> Option<SocketAddr> {
+  if !matches!(record_type, RrType::A | RrType::Aaaa) {
+    return None;
+  }
+  let host = url.host_str()?;
+  if !host.eq_ignore_ascii_case(record_name.trim_end_matches('.')) {
+    return None;
+  }
+  let address: IpAddr = values.first()?.parse().ok()?;
+  // reqwest uses the port from the URL
+  Some(SocketAddr::new(address, 0))
+}
+
+// Returns an error if all of the attempts fail.
+pub async fn probe(
+  url: &str,
+  record_name: &str,
+  record_type: &RrType,
+  values: &[String],
+  attempts: u32,
+) -> Result<(), String> {
+  let parsed_url =
+    reqwest::Url::parse(url).map_err(|err| format!("invalid URL {}: {}", url, err))?;
+  let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(10));
+  if let Some(address) = get_probe_address(&parsed_url, record_name, record_type, values) {
+    eprintln!("Probing {} using the new address {}", url, address.ip());
+    builder = builder.resolve(parsed_url.host_str().unwrap(), address);
+  }
+  let client = builder.build().expect("error building HTTP client");
+  let mut last_err = String::new();
+  for attempt in 1..=attempts {
+    last_err = match client.get(url).send().await {
+      Ok(response) if response.status().is_success() => {
+        eprintln!("Probe of {} succeeded: {}", url, response.status());
+        return Ok(());
+      }
+      Ok(response) => format!(
+        "response from {} returned status code: {}",
+        url,
+        response.status()
+      ),
+      Err(err) => format!("request to {} failed: {}", url, err),
+    };
+    eprintln!(
+      "Probe attempt {}/{} failed: {}",
+      attempt, attempts, last_err
+    );
+    if attempt < attempts {
+      tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+  }
+  Err(last_err)
+}
+
+// Restores the previous record set, or deletes the new record set if the record did not exist before.
+pub async fn rollback(
+  backend: &dyn DnsBackend,
+  hosted_zone_id: &str,
+  previous: Option<&ResourceRecordSet>,
+  new: &ResourceRecordSet,
+) -> String {
+  let comment = Some("Rollback after failed verification".to_string());
+  let result = match previous {
+    Some(previous) => {
+      eprintln!(
+        "Rolling back {} {} to the previous value",
+        previous.r#type().as_str(),
+        previous.name()
+      );
+      backend
+        .upsert(hosted_zone_id, vec![previous.clone()], comment)
+        .await
+    }
+    None => {
+      eprintln!(
+        "Rolling back by deleting {} {}, it did not exist before",
+        new.r#type().as_str(),
+        new.name()
+      );
+      backend
+        .delete(hosted_zone_id, vec![new.clone()], comment)
+        .await
+    }
+  };
+  result.unwrap_or_else(|err| panic!("could not roll back the record: {}", err))
+}
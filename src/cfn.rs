@@ -0,0 +1,128 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// https://docs.aws.amazon.com/AWSCloudFormation/latest/UserGuide/crpg-ref-requests.html
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CustomResourceRequest {
+  pub request_type: RequestType,
+  #[serde(rename = "ResponseURL")]
+  pub response_url: String,
+  pub stack_id: String,
+  pub request_id: String,
+  pub logical_resource_id: String,
+  pub physical_resource_id: Option<String>,
+  pub resource_properties: ResourceProperties,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+pub enum RequestType {
+  Create,
+  Update,
+  Delete,
+}
+
+// CloudFormation passes all of the properties as strings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ResourceProperties {
+  pub record_name: String,
+  pub record_type: Option<String>,
+  pub value: OneOrMany,
+  #[serde(rename = "TTL")]
+  pub ttl: Option<String>,
+  pub hosted_zone_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+  One(String),
+  Many(Vec<String>),
+}
+impl OneOrMany {
+  pub fn into_vec(self) -> Vec<String> {
+    match self {
+      OneOrMany::One(value) => vec![value],
+      OneOrMany::Many(values) => values,
+    }
+  }
+}
+
+// https://docs.aws.amazon.com/AWSCloudFormation/latest/UserGuide/crpg-ref-responses.html
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CustomResourceResponse {
+  pub status: String,
+  pub reason: Option<String>,
+  pub physical_resource_id: String,
+  pub stack_id: String,
+  pub request_id: String,
+  pub logical_resource_id: String,
+  pub data: HashMap<String, String>,
+}
+
+// The physical resource ID identifies the record set, so that it can be deleted later and so that CloudFormation replaces the resource when the record name changes.
+pub fn format_physical_resource_id(
+  hosted_zone_id: &str,
+  record_name: &str,
+  record_type: &str,
+) -> String {
+  format!("{}|{}|{}", hosted_zone_id, record_name, record_type)
+}
+
+// Returns None if the ID was not created by this program, e.g. the request ID that CloudFormation uses when the create failed.
+pub fn parse_physical_resource_id(id: &str) -> Option<(&str, &str, &str)> {
+  let mut parts = id.splitn(3, '|');
+  let hosted_zone_id = parts.next().filter(|s| !s.is_empty())?;
+  let record_name = parts.next().filter(|s| !s.is_empty())?;
+  let record_type = parts.next().filter(|s| !s.is_empty())?;
+  Some((hosted_zone_id, record_name, record_type))
+}
+
+pub fn build_response(
+  request: &CustomResourceRequest,
+  result: Result<(String, HashMap<String, String>), String>,
+) -> CustomResourceResponse {
+  // A failed create does not have a physical resource ID yet, and it has to be set in the response anyway
+  let fallback_id = request
+    .physical_resource_id
+    .clone()
+    .unwrap_or_else(|| request.request_id.clone());
+  let (status, reason, physical_resource_id, data) = match result {
+    Ok((physical_resource_id, data)) => ("SUCCESS", None, physical_resource_id, data),
+    Err(err) => ("FAILED", Some(err), fallback_id, HashMap::new()),
+  };
+  CustomResourceResponse {
+    status: status.to_string(),
+    reason,
+    physical_resource_id,
+    stack_id: request.stack_id.clone(),
+    request_id: request.request_id.clone(),
+    logical_resource_id: request.logical_resource_id.clone(),
+    data,
+  }
+}
+
+// The response is uploaded to a presigned S3 URL, which does not allow a Content-Type header.
+pub async fn send_response(url: &str, response: &CustomResourceResponse) -> Result<(), String> {
+  let body = serde_json::to_string(response).expect("error serializing the response");
+  println!("{}", body);
+  let response = reqwest::Client::new()
+    .put(url)
+    .body(body)
+    .send()
+    .await
+    .map_err(|err| format!("request to {} failed: {}", url, err))?;
+  if !response.status().is_success() {
+    return Err(format!(
+      "response from {} returned status code: {}",
+      url,
+      response.status()
+    ));
+  }
+  Ok(())
+}
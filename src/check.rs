@@ -0,0 +1,467 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::{types, utils};
+
+use aws_sdk_route53::types::{ResourceRecordSet, RrType};
+use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::proto::rr::RecordType;
+use hickory_resolver::TokioAsyncResolver;
+use std::{
+  net::{IpAddr, Ipv4Addr, SocketAddr},
+  str::FromStr,
+  time::{Duration, Instant},
+};
+
+pub const PUBLIC_RESOLVERS: [(&str, IpAddr); 3] = [
+  ("Google", IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8))),
+  ("Cloudflare", IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))),
+  ("Quad9", IpAddr::V4(Ipv4Addr::new(9, 9, 9, 9))),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Answer {
+  // The TTL is the time left until the resolver has to ask the authoritative name servers again.
+  Values { values: Vec<String>, ttl: u32 },
+  NotFound { negative_ttl: Option<u32> },
+  Error(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckStatus {
+  Match,
+  Different,
+  Missing,
+  Unexpected,
+  Error,
+}
+
+// Queries the resolver directly, bypassing the local cache and the system resolver configuration.
+pub async fn query(resolver: IpAddr, name: &str, record_type: &RrType) -> Answer {
+  let config = ResolverConfig::from_parts(
+    None,
+    vec![],
+    NameServerConfigGroup::from_ips_clear(&[resolver], 53, true),
+  );
+  let mut opts = ResolverOpts::default();
+  opts.cache_size = 0;
+  lookup(TokioAsyncResolver::tokio(config, opts), name, record_type).await
+}
+
+// Queries the resolvers from the system configuration (/etc/resolv.conf).
+pub async fn query_system(name: &str, record_type: &RrType) -> Answer {
+  match TokioAsyncResolver::tokio_from_system_conf() {
+    Ok(resolver) => lookup(resolver, name, record_type).await,
+    Err(err) => Answer::Error(format!(
+      "could not read the system resolver configuration: {}",
+      err
+    )),
+  }
+}
+
+async fn lookup(resolver: TokioAsyncResolver, name: &str, record_type: &RrType) -> Answer {
+  let record_type = match RecordType::from_str(record_type.as_str()) {
+    Ok(record_type) => record_type,
+    Err(err) => return Answer::Error(err.to_string()),
+  };
+  match resolver.lookup(name, record_type).await {
+    Ok(lookup) => {
+      // CNAME records that were followed to get to the answer are also included
+      let records: Vec<_> = lookup
+        .records()
+        .iter()
+        .filter(|r| r.record_type() == record_type)
+        .collect();
+      Answer::Values {
+        values: records
+          .iter()
+          .filter_map(|r| r.data())
+          .map(|data| data.to_string())
+          .collect(),
+        ttl: records.iter().map(|r| r.ttl()).min().unwrap_or_default(),
+      }
+    }
+    Err(err) => match err.kind() {
+      ResolveErrorKind::NoRecordsFound { negative_ttl, .. } => Answer::NotFound {
+        negative_ttl: *negative_ttl,
+      },
+      _ => Answer::Error(err.to_string()),
+    },
+  }
+}
+
+// Makes the values from Route 53 comparable with the values returned by the resolvers.
+// Route 53 has the TXT strings in quotes, and names may be returned with a different case or without the trailing dot.
+pub fn normalize_value(record_type: &RrType, value: &str) -> String {
+  if *record_type == RrType::Txt {
+    if value.starts_with('"') {
+      // "foo" "bar" is one value made up of multiple strings
+      return value
+        .split('"')
+        .skip(1)
+        .step_by(2)
+        .collect::<Vec<_>>()
+        .concat();
+    }
+    return value.to_string();
+  }
+  value.trim_end_matches('.').to_lowercase()
+}
+
+// The record has multiple record sets when it uses a routing policy, and the resolver is expected to return the values of one of them.
+pub fn compare(record_type: &RrType, expected: &[Vec<String>], answer: &Answer) -> CheckStatus {
+  let normalize = |values: &[String]| {
+    let mut values: Vec<String> = values
+      .iter()
+      .map(|v| normalize_value(record_type, v))
+      .collect();
+    values.sort();
+    values.dedup();
+    values
+  };
+  match answer {
+    Answer::Values { values, .. } if expected.is_empty() => {
+      if values.is_empty() {
+        CheckStatus::Match
+      } else {
+        CheckStatus::Unexpected
+      }
+    }
+    Answer::Values { values, .. } => {
+      let values = normalize(values);
+      if expected.iter().any(|e| normalize(e) == values) {
+        CheckStatus::Match
+      } else {
+        CheckStatus::Different
+      }
+    }
+    Answer::NotFound { .. } if expected.is_empty() => CheckStatus::Match,
+    Answer::NotFound { .. } => CheckStatus::Missing,
+    Answer::Error(_) => CheckStatus::Error,
+  }
+}
+
+pub fn describe_answer(answer: &Answer) -> String {
+  match answer {
+    Answer::Values { values, ttl } if values.is_empty() => format!("no records (TTL {})", ttl),
+    Answer::Values { values, ttl } => format!("{} (TTL {})", values.join(","), ttl),
+    Answer::NotFound {
+      negative_ttl: Some(negative_ttl),
+    } => format!("not found (negative TTL {})", negative_ttl),
+    Answer::NotFound { negative_ttl: None } => "not found".to_string(),
+    Answer::Error(err) => format!("error: {}", err),
+  }
+}
+
+// The resolvers cache the previous answer until the TTL runs out, so a different answer is expected for a while after a change.
+fn describe_status(status: CheckStatus, answer: &Answer) -> String {
+  let expires = match answer {
+    Answer::Values { ttl, .. } => Some(*ttl),
+    Answer::NotFound { negative_ttl } => *negative_ttl,
+    Answer::Error(_) => None,
+  };
+  let lag = expires
+    .map(|ttl| format!(", the cached answer expires in {} seconds", ttl))
+    .unwrap_or_default();
+  match status {
+    CheckStatus::Match => "ok".to_string(),
+    CheckStatus::Different => format!("DIFFERENT{}", lag),
+    CheckStatus::Missing => format!("MISSING{}", lag),
+    CheckStatus::Unexpected => format!("UNEXPECTED, the record does not exist in Route 53{}", lag),
+    CheckStatus::Error => "ERROR".to_string(),
+  }
+}
+
+// The closest public hosted zone that contains the record.
+async fn find_hosted_zone(route53_client: &aws_sdk_route53::Client, record_name: &str) -> String {
+  let response = route53_client
+    .list_hosted_zones()
+    .send()
+    .await
+    .expect("could not list hosted zones");
+  if response.is_truncated() {
+    panic!("you have a lot of hosted zones and this program does not paginate yet, please use --hosted-zone-id");
+  }
+  let mut search_name = record_name;
+  loop {
+    if let Some(zone) = utils::get_hosted_zone(
+      response
+        .hosted_zones()
+        .iter()
+        .filter(|zone| zone.name() == search_name)
+        .collect(),
+      types::HostedZoneType::Public,
+    ) {
+      let hosted_zone_id = utils::normalize_hosted_zone_id(zone.id());
+      eprintln!("Found hosted zone: {} ({})", hosted_zone_id, zone.name());
+      return hosted_zone_id;
+    }
+    match search_name.split_once('.') {
+      Some((_, parent)) if !parent.is_empty() => search_name = parent,
+      _ => panic!("could not find the public hosted zone for: {}", record_name),
+    }
+  }
+}
+
+// Compares the records in Route 53 with the answers from the resolvers, and checks that the resolvers see the Route 53 name servers for the zone.
+// Returns true if all of the resolvers agree with Route 53.
+pub async fn check(
+  route53_client: &aws_sdk_route53::Client,
+  hosted_zone_id: Option<&str>,
+  record_name: &str,
+  record_type: Option<&RrType>,
+  resolvers: &[(String, IpAddr)],
+) -> bool {
+  let record_name = if record_name.ends_with('.') {
+    record_name.to_string()
+  } else {
+    format!("{}.", record_name)
+  };
+  let hosted_zone_id = match hosted_zone_id {
+    Some(id) => id.to_string(),
+    None => find_hosted_zone(route53_client, &record_name).await,
+  };
+  let zone = route53_client
+    .get_hosted_zone()
+    .id(hosted_zone_id.trim_start_matches("/hostedzone/"))
+    .send()
+    .await
+    .expect("could not get the hosted zone");
+  let hosted_zone = zone.hosted_zone().expect("missing hosted zone in response");
+  let private_zone = hosted_zone.config().is_some_and(|c| c.private_zone);
+  if private_zone {
+    eprintln!(
+      "Warning: {} is a private hosted zone, only resolvers in one of its VPCs can see the records (use --resolver)",
+      hosted_zone.name()
+    );
+  }
+  let mut consistent = true;
+
+  // After changing the name servers at the registrar, the resolvers may still be using the name servers of the previous DNS provider
+  if let Some(delegation_set) = zone.delegation_set().filter(|_| !private_zone) {
+    let expected = vec![delegation_set.name_servers().to_vec()];
+    for (name, ip) in resolvers {
+      let answer = query(*ip, hosted_zone.name(), &RrType::Ns).await;
+      let status = compare(&RrType::Ns, &expected, &answer);
+      if status != CheckStatus::Match {
+        consistent = false;
+        println!(
+          "{} ({}): NS {} {} {}",
+          name,
+          ip,
+          hosted_zone.name(),
+          describe_answer(&answer),
+          describe_status(status, &answer)
+        );
+      }
+    }
+  }
+
+  let record_sets: Vec<ResourceRecordSet> = route53_client
+    .list_resource_record_sets()
+    .hosted_zone_id(&hosted_zone_id)
+    .start_record_name(&record_name)
+    .send()
+    .await
+    .expect("could not list record sets")
+    .resource_record_sets
+    .into_iter()
+    .filter(|r| r.name().eq_ignore_ascii_case(&record_name))
+    .collect();
+
+  let record_types = match record_type {
+    Some(record_type) => vec![record_type.clone()],
+    None => {
+      let mut record_types: Vec<RrType> = Vec::new();
+      for r in &record_sets {
+        if !record_types.contains(r.r#type()) {
+          record_types.push(r.r#type().clone());
+        }
+      }
+      if record_types.is_empty() {
+        record_types.push(RrType::A);
+      }
+      record_types
+    }
+  };
+
+  for record_type in &record_types {
+    let matching: Vec<&ResourceRecordSet> = record_sets
+      .iter()
+      .filter(|r| r.r#type() == record_type)
+      .collect();
+    let is_alias = matching.iter().any(|r| r.alias_target().is_some());
+    let expected: Vec<Vec<String>> = matching
+      .iter()
+      .map(|r| utils::get_record_values(r))
+      .collect();
+    if matching.is_empty() {
+      println!(
+        "Route 53: {} {} does not exist",
+        record_type.as_str(),
+        record_name
+      );
+    } else if is_alias {
+      println!(
+        "Route 53: {} {} is an alias, the answers can not be compared",
+        record_type.as_str(),
+        record_name
+      );
+    } else {
+      for r in &matching {
+        println!(
+          "Route 53: {} {} {} (TTL {}){}",
+          record_type.as_str(),
+          record_name,
+          utils::get_record_values(r).join(","),
+          r.ttl().unwrap_or_default(),
+          r.set_identifier()
+            .map(|id| format!(" [{}]", id))
+            .unwrap_or_default()
+        );
+      }
+    }
+
+    for (name, ip) in resolvers {
+      let answer = query(*ip, &record_name, record_type).await;
+      if is_alias {
+        println!("{} ({}): {}", name, ip, describe_answer(&answer));
+        continue;
+      }
+      let status = compare(record_type, &expected, &answer);
+      if status != CheckStatus::Match {
+        consistent = false;
+      }
+      println!(
+        "{} ({}): {} {}",
+        name,
+        ip,
+        describe_answer(&answer),
+        describe_status(status, &answer)
+      );
+    }
+  }
+  consistent
+}
+
+// Polls the resolvers until all of them return the values. Panics if it takes longer than the timeout.
+// The resolvers keep returning the cached answer until it expires, so this can take up to the TTL of the previous value.
+pub async fn wait_for_resolvers(
+  resolvers: &[IpAddr],
+  record_name: &str,
+  record_type: &RrType,
+  values: &[String],
+  timeout: Duration,
+) {
+  let expected = vec![values.to_vec()];
+  let mut pending = resolvers.to_vec();
+  let start = Instant::now();
+  loop {
+    let mut still_pending = Vec::new();
+    for resolver in pending {
+      let answer = query(resolver, record_name, record_type).await;
+      let status = compare(record_type, &expected, &answer);
+      eprintln!(
+        "Resolver {} returns {} (elapsed: {}s)",
+        resolver,
+        describe_answer(&answer),
+        start.elapsed().as_secs()
+      );
+      if status != CheckStatus::Match {
+        still_pending.push(resolver);
+      }
+    }
+    pending = still_pending;
+    if pending.is_empty() {
+      break;
+    }
+    if start.elapsed() >= timeout {
+      panic!(
+        "timed out waiting for the resolvers to return the new value: {}",
+        pending
+          .iter()
+          .map(|ip| ip.to_string())
+          .collect::<Vec<_>>()
+          .join(", ")
+      );
+    }
+    tokio::time::sleep(Duration::from_secs(5)).await;
+  }
+}
+
+// Resolvers cache a negative answer for the TTL of the SOA record or the SOA minimum field, whichever is lower (RFC 2308).
+// The SOA value looks like: ns-2048.awsdns-64.net. awsdns-hostmaster.amazon.com. 1 7200 900 1209600 86400
+// --require-reachable
+pub async fn is_reachable(
+  address: IpAddr,
+  check: &types::Reachability,
+  timeout: Duration,
+) -> Result<(), String> {
+  match check {
+    types::Reachability::Tcp(port) => {
+      let socket_address = SocketAddr::new(address, *port);
+      match tokio::time::timeout(timeout, tokio::net::TcpStream::connect(socket_address)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(err)) => Err(format!("could not connect to {}: {}", socket_address, err)),
+        Err(_) => Err(format!("timed out connecting to {}", socket_address)),
+      }
+    }
+  }
+}
+
+// Returns the values that are reachable, the others are printed and left out.
+pub async fn filter_reachable(
+  values: Vec<String>,
+  check: &types::Reachability,
+  timeout: Duration,
+) -> Vec<String> {
+  let mut reachable = Vec::new();
+  for value in values {
+    let Ok(address) = value.parse::<IpAddr>() else {
+      eprintln!("Not publishing {}, it is not an IP address", value);
+      continue;
+    };
+    match is_reachable(address, check, timeout).await {
+      Ok(()) => {
+        eprintln!("{} is reachable with {}", value, check);
+        reachable.push(value);
+      }
+      Err(err) => eprintln!("Not publishing {}: {}", value, err),
+    }
+  }
+  reachable
+}
+
+pub fn get_soa_negative_ttl(soa_value: &str, soa_ttl: i64) -> Option<i64> {
+  let minimum: i64 = soa_value.split_whitespace().nth(6)?.parse().ok()?;
+  Some(minimum.min(soa_ttl))
+}
+
+// Returns the negative TTL of the hosted zone, i.e. the maximum time that resolvers return NXDOMAIN after a record is created.
+pub async fn get_negative_ttl(
+  route53_client: &aws_sdk_route53::Client,
+  hosted_zone_id: &str,
+) -> Option<i64> {
+  let zone = route53_client
+    .get_hosted_zone()
+    .id(hosted_zone_id.trim_start_matches("/hostedzone/"))
+    .send()
+    .await
+    .ok()?;
+  let zone_name = zone.hosted_zone()?.name().to_string();
+  let response = route53_client
+    .list_resource_record_sets()
+    .hosted_zone_id(hosted_zone_id)
+    .start_record_name(&zone_name)
+    .start_record_type(RrType::Soa)
+    .max_items(1)
+    .send()
+    .await
+    .ok()?;
+  let soa = response
+    .resource_record_sets()
+    .iter()
+    .find(|r| r.name() == zone_name && r.r#type() == &RrType::Soa)?;
+  get_soa_negative_ttl(utils::get_record_values(soa).first()?, soa.ttl()?)
+}
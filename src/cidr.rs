@@ -0,0 +1,188 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::utils;
+
+use aws_sdk_route53::types::{CidrCollectionChange, CidrCollectionChangeAction};
+use ipnet::IpNet;
+use std::collections::{BTreeMap, BTreeSet};
+
+// The limits of a single ChangeCidrCollection request.
+const MAX_CHANGES_PER_REQUEST: usize = 50;
+const MAX_CIDRS_PER_CHANGE: usize = 1000;
+
+pub type CidrLocations = BTreeMap<String, BTreeSet<String>>;
+
+// Parses a file with one location and CIDR block per line, e.g. "office 192.0.2.0/24". Empty lines and lines starting with # are ignored.
+// A line can list multiple CIDR blocks for the same location.
+pub fn parse_cidr_file(contents: &str) -> Result<CidrLocations, String> {
+  let mut locations = CidrLocations::new();
+  for (i, line) in contents.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let mut fields = line.split_whitespace();
+    let location = fields.next().unwrap();
+    if location.len() > 16
+      || !location
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+      return Err(format!(
+        "line {}: invalid location name (up to 16 letters, digits, - and _): {}",
+        i + 1,
+        location
+      ));
+    }
+    let cidrs = locations.entry(location.to_string()).or_default();
+    let mut empty = true;
+    for cidr in fields {
+      let net = cidr
+        .parse::<IpNet>()
+        .map_err(|_| format!("line {}: invalid CIDR block: {}", i + 1, cidr))?;
+      // Route 53 stores the network address, so host bits are cleared to make the comparison with the existing blocks work
+      cidrs.insert(net.trunc().to_string());
+      empty = false;
+    }
+    if empty {
+      return Err(format!(
+        "line {}: missing CIDR block for {}",
+        i + 1,
+        location
+      ));
+    }
+  }
+  Ok(locations)
+}
+
+// Returns the changes needed to go from the existing locations to the desired locations. Locations that are not in the file are removed.
+// A CIDR block can only be in one location at a time, so all of the deletions come before the additions to make it possible to move a block to another location.
+pub fn get_cidr_collection_changes(
+  existing: &CidrLocations,
+  desired: &CidrLocations,
+) -> Vec<CidrCollectionChange> {
+  let empty = BTreeSet::new();
+  let mut changes = Vec::new();
+  for (location, cidrs) in existing {
+    let desired_cidrs = desired.get(location).unwrap_or(&empty);
+    let removed: Vec<String> = cidrs.difference(desired_cidrs).cloned().collect();
+    changes.extend(build_changes(
+      location,
+      CidrCollectionChangeAction::DeleteIfExists,
+      removed,
+    ));
+  }
+  for (location, cidrs) in desired {
+    let existing_cidrs = existing.get(location).unwrap_or(&empty);
+    let added: Vec<String> = cidrs.difference(existing_cidrs).cloned().collect();
+    changes.extend(build_changes(
+      location,
+      CidrCollectionChangeAction::Put,
+      added,
+    ));
+  }
+  changes
+}
+
+fn build_changes(
+  location: &str,
+  action: CidrCollectionChangeAction,
+  cidrs: Vec<String>,
+) -> Vec<CidrCollectionChange> {
+  cidrs
+    .chunks(MAX_CIDRS_PER_CHANGE)
+    .map(|chunk| {
+      CidrCollectionChange::builder()
+        .location_name(location)
+        .action(action.clone())
+        .set_cidr_list(Some(chunk.to_vec()))
+        .build()
+        .expect("error building CIDR collection change")
+    })
+    .collect()
+}
+
+// Creates the CIDR collection if it does not exist, and makes its locations match the given locations. Returns the collection ID.
+pub async fn sync_cidr_collection(
+  route53_client: &aws_sdk_route53::Client,
+  name: &str,
+  locations: &CidrLocations,
+) -> String {
+  let collections: Vec<_> = route53_client
+    .list_cidr_collections()
+    .into_paginator()
+    .items()
+    .send()
+    .collect::<Result<_, _>>()
+    .await
+    .expect("could not list CIDR collections");
+  let collection_id = match collections.iter().find(|c| c.name() == Some(name)) {
+    Some(collection) => {
+      let id = collection.id().unwrap().to_string();
+      eprintln!("Found CIDR collection: {} ({})", id, name);
+      id
+    }
+    None => {
+      let response = route53_client
+        .create_cidr_collection()
+        .name(name)
+        .caller_reference(format!("route53-update-{}", utils::get_timestamp()))
+        .send()
+        .await
+        .expect("could not create the CIDR collection");
+      let id = response.collection().unwrap().id().unwrap().to_string();
+      eprintln!("Created CIDR collection: {} ({})", id, name);
+      id
+    }
+  };
+
+  let blocks: Vec<_> = route53_client
+    .list_cidr_blocks()
+    .collection_id(&collection_id)
+    .into_paginator()
+    .items()
+    .send()
+    .collect::<Result<_, _>>()
+    .await
+    .expect("could not list the CIDR blocks");
+  let mut existing = CidrLocations::new();
+  for block in blocks {
+    if let (Some(location), Some(cidr)) = (block.location_name(), block.cidr_block()) {
+      existing
+        .entry(location.to_string())
+        .or_default()
+        .insert(cidr.to_string());
+    }
+  }
+
+  let changes = get_cidr_collection_changes(&existing, locations);
+  if changes.is_empty() {
+    eprintln!("The CIDR collection is already up to date");
+  }
+  // The deletions are sent in requests of their own, so that they have been made before a moved block is added to its new location
+  let (deletions, additions): (Vec<_>, Vec<_>) = changes
+    .into_iter()
+    .partition(|change| change.action() == &CidrCollectionChangeAction::DeleteIfExists);
+  for chunk in deletions
+    .chunks(MAX_CHANGES_PER_REQUEST)
+    .chain(additions.chunks(MAX_CHANGES_PER_REQUEST))
+  {
+    for change in chunk {
+      eprintln!(
+        "{} {}: {}",
+        change.action().as_str(),
+        change.location_name(),
+        change.cidr_list().join(", ")
+      );
+    }
+    route53_client
+      .change_cidr_collection()
+      .id(&collection_id)
+      .set_changes(Some(chunk.to_vec()))
+      .send()
+      .await
+      .expect("could not change the CIDR collection");
+  }
+  collection_id
+}
@@ -0,0 +1,87 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::types;
+
+use aws_sdk_route53::types::RrType;
+
+// Cloud Map only stores one address per instance, so only the first value is registered.
+// Registering an instance ID that already exists replaces its attributes, so the instance is only registered again if the value has changed.
+pub async fn register_instance(
+  client: &aws_sdk_servicediscovery::Client,
+  service_id: &str,
+  instance_id: &str,
+  record_type: &RrType,
+  values: &[String],
+) -> types::CloudMapRegistration {
+  let attribute = match record_type {
+    RrType::A => "AWS_INSTANCE_IPV4",
+    RrType::Aaaa => "AWS_INSTANCE_IPV6",
+    RrType::Cname => "AWS_INSTANCE_CNAME",
+    t => panic!(
+      "Cloud Map only supports A, AAAA, and CNAME, not {}",
+      t.as_str()
+    ),
+  };
+  let registration = types::CloudMapRegistration {
+    service_id: service_id.to_string(),
+    instance_id: instance_id.to_string(),
+    client: client.clone(),
+    operation_id: None,
+  };
+  // If the instance can't be read (e.g. missing servicediscovery:GetInstance) then it is registered anyway
+  if let Ok(response) = client
+    .get_instance()
+    .service_id(service_id)
+    .instance_id(instance_id)
+    .send()
+    .await
+  {
+    let current_value = response
+      .instance()
+      .and_then(|instance| instance.attributes())
+      .and_then(|attributes| attributes.get(attribute));
+    if current_value == Some(&values[0]) {
+      eprintln!(
+        "Cloud Map instance {} in service {} is already registered with {}={}",
+        instance_id, service_id, attribute, values[0]
+      );
+      return registration;
+    }
+  }
+  let response = client
+    .register_instance()
+    .service_id(service_id)
+    .instance_id(instance_id)
+    .attributes(attribute, &values[0])
+    .send()
+    .await
+    .expect("could not register the Cloud Map instance");
+  eprintln!(
+    "Registered Cloud Map instance {} in service {} with {}={} (operation: {})",
+    instance_id,
+    service_id,
+    attribute,
+    values[0],
+    response.operation_id().unwrap_or_default()
+  );
+  types::CloudMapRegistration {
+    operation_id: response.operation_id().map(|id| id.to_string()),
+    ..registration
+  }
+}
+
+pub async fn deregister_instance(registration: &types::CloudMapRegistration) {
+  eprintln!(
+    "Deregistering Cloud Map instance {} from service {}",
+    registration.instance_id, registration.service_id
+  );
+  registration
+    .client
+    .deregister_instance()
+    .service_id(&registration.service_id)
+    .instance_id(&registration.instance_id)
+    .send()
+    .await
+    .expect("could not deregister the Cloud Map instance");
+}
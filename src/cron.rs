@@ -0,0 +1,159 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::utils;
+
+use std::{fmt, str::FromStr};
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime, Time};
+
+// A standard five field cron expression (minute, hour, day of month, month, day of week), evaluated in UTC.
+// Supports *, numbers, ranges (1-5), steps (*/5, 0-30/10) and lists (1,15). Names like MON and JAN are not supported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+  expression: String,
+  minutes: u64,
+  hours: u64,
+  days_of_month: u64,
+  months: u64,
+  days_of_week: u64,
+  // When both the day of month and the day of week are restricted, a day matches if either of them matches
+  day_of_month_restricted: bool,
+  day_of_week_restricted: bool,
+}
+
+impl FromStr for Schedule {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let fields: Vec<&str> = s.split_whitespace().collect();
+    let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+      return Err(format!(
+        "expected 5 fields (minute hour day-of-month month day-of-week), got {}",
+        fields.len()
+      ));
+    };
+    let mut days_of_week = parse_field(day_of_week, 0, 7, "day of week")?;
+    // Both 0 and 7 are Sunday
+    if days_of_week & (1 << 7) != 0 {
+      days_of_week = (days_of_week | 1) & !(1 << 7);
+    }
+    Ok(Schedule {
+      expression: fields.join(" "),
+      minutes: parse_field(minute, 0, 59, "minute")?,
+      hours: parse_field(hour, 0, 23, "hour")?,
+      days_of_month: parse_field(day_of_month, 1, 31, "day of month")?,
+      months: parse_field(month, 1, 12, "month")?,
+      days_of_week,
+      day_of_month_restricted: !day_of_month.starts_with('*'),
+      day_of_week_restricted: !day_of_week.starts_with('*'),
+    })
+  }
+}
+
+impl fmt::Display for Schedule {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.expression)
+  }
+}
+
+fn parse_field(field: &str, min: u8, max: u8, name: &str) -> Result<u64, String> {
+  let mut bits = 0;
+  for part in field.split(',') {
+    let (range, step) = match part.split_once('/') {
+      Some((range, step)) => match step.parse::<u8>() {
+        Ok(step) if step > 0 => (range, step),
+        _ => return Err(format!("invalid step in the {} field: {}", name, part)),
+      },
+      None => (part, 1),
+    };
+    let parse = |v: &str| {
+      v.parse::<u8>()
+        .ok()
+        .filter(|v| (min..=max).contains(v))
+        .ok_or_else(|| {
+          format!(
+            "invalid value in the {} field: {} (expected {}-{})",
+            name, part, min, max
+          )
+        })
+    };
+    let (start, end) = match range {
+      "*" => (min, max),
+      _ => match range.split_once('-') {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        // 5/10 means starting at 5, every 10
+        None if step > 1 => (parse(range)?, max),
+        None => (parse(range)?, parse(range)?),
+      },
+    };
+    if start > end {
+      return Err(format!("invalid range in the {} field: {}", name, part));
+    }
+    for v in (start..=end).step_by(usize::from(step)) {
+      bits |= 1 << v;
+    }
+  }
+  Ok(bits)
+}
+
+impl Schedule {
+  fn matches_day(&self, t: OffsetDateTime) -> bool {
+    let day_of_month = self.days_of_month & (1 << t.day()) != 0;
+    let day_of_week = self.days_of_week & (1 << t.weekday().number_days_from_sunday()) != 0;
+    self.months & (1 << u8::from(t.month())) != 0
+      && match (self.day_of_month_restricted, self.day_of_week_restricted) {
+        (true, true) => day_of_month || day_of_week,
+        _ => day_of_month && day_of_week,
+      }
+  }
+
+  // Returns the first time that matches the schedule strictly after the given time, or None if there is none within the next five years (e.g. February 30).
+  pub fn next_after(&self, t: OffsetDateTime) -> Option<OffsetDateTime> {
+    let mut t = t.replace_second(0).ok()?.replace_nanosecond(0).ok()? + Duration::minutes(1);
+    let limit = t + Duration::days(5 * 366);
+    while t < limit {
+      if !self.matches_day(t) {
+        t = t.replace_time(Time::MIDNIGHT) + Duration::days(1);
+        continue;
+      }
+      if self.hours & (1 << t.hour()) == 0 {
+        t = t.replace_minute(0).ok()? + Duration::hours(1);
+        continue;
+      }
+      if self.minutes & (1 << t.minute()) == 0 {
+        t += Duration::minutes(1);
+        continue;
+      }
+      return Some(t);
+    }
+    None
+  }
+
+  // Returns the next scheduled time after now, and how long to sleep until it including the jitter.
+  pub fn next_run(
+    &self,
+    now: OffsetDateTime,
+    jitter: std::time::Duration,
+  ) -> Option<(OffsetDateTime, std::time::Duration)> {
+    let next = self.next_after(now)?;
+    Some((next, (next - now).unsigned_abs() + jitter))
+  }
+
+  // Like next_run, counted from the current time and with the scheduled time formatted for the log.
+  pub fn next_run_from_now(
+    &self,
+    jitter: std::time::Duration,
+  ) -> Option<(String, std::time::Duration)> {
+    let (next, delay) = self.next_run(OffsetDateTime::now_utc(), jitter)?;
+    let next = next.format(&Rfc3339).expect("error formatting timestamp");
+    Some((next, delay))
+  }
+}
+
+// Spreads the runs of many hosts with the same schedule over up to max_seconds, so that they don't all call the APIs at the same time.
+pub fn get_jitter(seed: &str, max_seconds: u64) -> std::time::Duration {
+  if max_seconds == 0 {
+    return std::time::Duration::ZERO;
+  }
+  std::time::Duration::from_secs(utils::stable_hash(seed) % (max_seconds + 1))
+}
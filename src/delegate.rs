@@ -0,0 +1,120 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::{types, utils};
+
+use aws_sdk_route53::types::{Change, ChangeAction, ChangeBatch, RrType};
+
+// NS records in the parent zone are usually long-lived.
+pub const DEFAULT_NS_TTL: i64 = 172800;
+
+// Upserts NS records in the parent zone that point to the name servers of the child zone. Returns the change ID.
+// The clients can be for different accounts if the parent zone is owned by another account.
+pub async fn delegate(
+  child_route53_client: &aws_sdk_route53::Client,
+  parent_route53_client: &aws_sdk_route53::Client,
+  child_zone_id: &str,
+  parent_zone_id: Option<&str>,
+  ttl: i64,
+) -> String {
+  let child = child_route53_client
+    .get_hosted_zone()
+    .id(child_zone_id.trim_start_matches("/hostedzone/"))
+    .send()
+    .await
+    .expect("could not get the child hosted zone");
+  let child_zone = child
+    .hosted_zone()
+    .expect("missing hosted zone in response");
+  let child_name = child_zone.name().to_string();
+  if child_zone.config().is_some_and(|c| c.private_zone) {
+    panic!(
+      "{} is a private hosted zone and can not be delegated",
+      child_name
+    );
+  }
+  let name_servers: Vec<String> = child
+    .delegation_set()
+    .expect("the child hosted zone does not have a delegation set")
+    .name_servers()
+    .iter()
+    .map(|ns| {
+      if ns.ends_with('.') {
+        ns.clone()
+      } else {
+        format!("{}.", ns)
+      }
+    })
+    .collect();
+  eprintln!(
+    "Name servers of {}: {}",
+    child_name,
+    name_servers.join(", ")
+  );
+
+  let parent_zone_id = match parent_zone_id {
+    Some(id) => id.to_string(),
+    None => find_parent_zone(parent_route53_client, &child_name).await,
+  };
+
+  let rrs = utils::build_record_set(
+    &child_name,
+    &RrType::Ns,
+    Some(ttl),
+    &name_servers,
+    &types::RecordRouting::default(),
+  );
+  let change = Change::builder()
+    .action(ChangeAction::Upsert)
+    .resource_record_set(rrs)
+    .build()
+    .expect("error building change set");
+  let change_batch = ChangeBatch::builder()
+    .changes(change)
+    .comment(format!("Delegate {} to {}", child_name, child_zone_id))
+    .build()
+    .expect("error building change batch");
+  eprintln!("{:?}", change_batch);
+  let response = parent_route53_client
+    .change_resource_record_sets()
+    .hosted_zone_id(parent_zone_id)
+    .change_batch(change_batch)
+    .send()
+    .await
+    .expect("could not create the NS records in the parent zone");
+  println!("{:?}", response);
+  response.change_info().unwrap().id().to_string()
+}
+
+// The parent zone is the closest public zone above the child zone.
+async fn find_parent_zone(route53_client: &aws_sdk_route53::Client, child_name: &str) -> String {
+  let response = route53_client
+    .list_hosted_zones()
+    .send()
+    .await
+    .expect("could not list hosted zones");
+  if response.is_truncated() {
+    panic!("you have a lot of hosted zones and this program does not paginate yet, please use --parent-zone-id");
+  }
+  let mut search_name = child_name;
+  while let Some((_, parent)) = search_name.split_once('.') {
+    search_name = parent;
+    if let Some(zone) = utils::get_hosted_zone(
+      response
+        .hosted_zones()
+        .iter()
+        .filter(|zone| zone.name() == search_name)
+        .collect(),
+      types::HostedZoneType::Public,
+    ) {
+      let hosted_zone_id = utils::normalize_hosted_zone_id(zone.id());
+      eprintln!(
+        "Found parent hosted zone: {} ({})",
+        hosted_zone_id,
+        zone.name()
+      );
+      return hosted_zone_id;
+    }
+  }
+  panic!("could not find the parent hosted zone for: {}", child_name);
+}
@@ -0,0 +1,51 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+// --dkim-selector and --dkim-public-key-file
+// Publishes a DKIM public key in the TXT record <selector>._domainkey.<domain> (RFC 6376 section 3.6).
+
+use base64::Engine;
+
+// The DER encoding of an Ed25519 SubjectPublicKeyInfo is this prefix followed by the 32 byte key
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+  0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+pub fn get_record_name(selector: &str, domain: &str) -> String {
+  format!("{}._domainkey.{}", selector, domain)
+}
+
+// Returns the DER bytes of a PEM encoded public key, e.g. from openssl rsa -in private.pem -pubout.
+pub fn parse_public_key_pem(pem: &str) -> Result<Vec<u8>, String> {
+  if pem.contains("PRIVATE KEY-----") {
+    return Err("the file contains a private key, give the public key instead (e.g. openssl rsa -in private.pem -pubout -out public.pem)".to_string());
+  }
+  let body: String = pem
+    .lines()
+    .map(str::trim)
+    .skip_while(|line| *line != "-----BEGIN PUBLIC KEY-----")
+    .skip(1)
+    .take_while(|line| *line != "-----END PUBLIC KEY-----")
+    .collect();
+  if body.is_empty() {
+    return Err(
+      "the file does not contain a PEM encoded public key (-----BEGIN PUBLIC KEY-----)".to_string(),
+    );
+  }
+  base64::engine::general_purpose::STANDARD
+    .decode(body)
+    .map_err(|err| format!("invalid public key: {}", err))
+}
+
+// RSA keys are published as the DER encoded SubjectPublicKeyInfo, and Ed25519 keys as the raw key (RFC 8463).
+pub fn build_dkim_value(public_key: &[u8]) -> String {
+  let (key_type, key) = match public_key.strip_prefix(&ED25519_SPKI_PREFIX[..]) {
+    Some(key) if key.len() == 32 => ("ed25519", key),
+    _ => ("rsa", public_key),
+  };
+  format!(
+    "v=DKIM1; k={}; p={}",
+    key_type,
+    base64::engine::general_purpose::STANDARD.encode(key)
+  )
+}
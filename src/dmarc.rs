@@ -0,0 +1,75 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+// --dmarc-policy
+// Publishes a DMARC policy in the TXT record _dmarc.<domain> (RFC 7489 section 6.3).
+
+use crate::types::DmarcPolicy;
+
+pub fn get_record_name(domain: &str) -> String {
+  format!("_dmarc.{}", domain)
+}
+
+// A report URI is a mailto: URI, optionally followed by a maximum report size like !10m.
+pub fn validate_report_uri(uri: &str) -> Result<(), String> {
+  let invalid = |reason: &str| Err(format!("invalid DMARC report URI {}: {}", uri, reason));
+  let Some(address) = uri
+    .get(..7)
+    .filter(|scheme| scheme.eq_ignore_ascii_case("mailto:"))
+    .map(|_| &uri[7..])
+  else {
+    return invalid("it must start with mailto:");
+  };
+  let address = match address.split_once('!') {
+    Some((address, size)) => {
+      let digits = size.trim_end_matches(['k', 'm', 'g', 't']);
+      if digits.is_empty()
+        || !digits.chars().all(|c| c.is_ascii_digit())
+        || size.len() - digits.len() > 1
+      {
+        return invalid("the size limit must be a number with an optional unit, e.g. !10m");
+      }
+      address
+    }
+    None => address,
+  };
+  if address
+    .chars()
+    .any(|c| c.is_whitespace() || c == ',' || c == ';')
+  {
+    return invalid("it can not contain whitespace, commas or semicolons");
+  }
+  match address.split_once('@') {
+    Some((local, domain)) if !local.is_empty() && domain.contains('.') && !domain.contains('@') => {
+      Ok(())
+    }
+    _ => invalid("it must contain an email address"),
+  }
+}
+
+pub fn build_dmarc_value(
+  policy: DmarcPolicy,
+  subdomain_policy: Option<DmarcPolicy>,
+  percent: Option<u8>,
+  rua: &[String],
+  ruf: &[String],
+) -> Result<String, String> {
+  for uri in rua.iter().chain(ruf) {
+    validate_report_uri(uri)?;
+  }
+  // v and p must be the first tags
+  let mut tags = vec!["v=DMARC1".to_string(), format!("p={}", policy.as_str())];
+  if let Some(subdomain_policy) = subdomain_policy {
+    tags.push(format!("sp={}", subdomain_policy.as_str()));
+  }
+  if let Some(percent) = percent {
+    tags.push(format!("pct={}", percent));
+  }
+  if !rua.is_empty() {
+    tags.push(format!("rua={}", rua.join(",")));
+  }
+  if !ruf.is_empty() {
+    tags.push(format!("ruf={}", ruf.join(",")));
+  }
+  Ok(tags.join("; "))
+}
@@ -0,0 +1,81 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::operation::get_dnssec::GetDnssecOutput;
+
+const BROKEN_STATUSES: [&str; 2] = ["ACTION_NEEDED", "INTERNAL_FAILURE"];
+
+pub async fn get_dnssec(
+  route53_client: &aws_sdk_route53::Client,
+  hosted_zone_id: &str,
+) -> Result<GetDnssecOutput, String> {
+  route53_client
+    .get_dnssec()
+    .hosted_zone_id(hosted_zone_id.trim_start_matches("/hostedzone/"))
+    .send()
+    .await
+    .map_err(|err| format!("could not get the DNSSEC status: {}", err))
+}
+
+// Returns a description of each part of the signing that is in a broken state.
+pub fn get_dnssec_problems(dnssec: &GetDnssecOutput) -> Vec<String> {
+  let mut problems = Vec::new();
+  if let Some(status) = dnssec.status() {
+    let serve_signature = status.serve_signature().unwrap_or_default();
+    if BROKEN_STATUSES.contains(&serve_signature) {
+      problems.push(format!(
+        "DNSSEC signing is {}: {}",
+        serve_signature,
+        status.status_message().unwrap_or_default()
+      ));
+    }
+  }
+  for ksk in dnssec.key_signing_keys() {
+    let status = ksk.status().unwrap_or_default();
+    if BROKEN_STATUSES.contains(&status) {
+      problems.push(format!(
+        "key-signing key {} is {}: {}",
+        ksk.name().unwrap_or_default(),
+        status,
+        ksk.status_message().unwrap_or_default()
+      ));
+    }
+  }
+  problems
+}
+
+// The DS records have to be added to the parent zone to establish the chain of trust.
+pub fn get_ds_records(dnssec: &GetDnssecOutput) -> Vec<String> {
+  dnssec
+    .key_signing_keys()
+    .iter()
+    .filter(|ksk| ksk.status() == Some("ACTIVE"))
+    .filter_map(|ksk| ksk.ds_record())
+    .map(|ds| ds.to_string())
+    .collect()
+}
+
+pub fn print_dnssec_status(dnssec: &GetDnssecOutput) {
+  if let Some(status) = dnssec.status() {
+    println!(
+      "Signing: {}{}",
+      status.serve_signature().unwrap_or("unknown"),
+      status
+        .status_message()
+        .map(|m| format!(" ({})", m))
+        .unwrap_or_default()
+    );
+  }
+  for ksk in dnssec.key_signing_keys() {
+    println!(
+      "Key-signing key {}: {} (key tag {}, {})",
+      ksk.name().unwrap_or_default(),
+      ksk.status().unwrap_or("unknown"),
+      ksk.key_tag(),
+      ksk.signing_algorithm_mnemonic().unwrap_or_default()
+    );
+  }
+  for ds in get_ds_records(dnssec) {
+    println!("DS record for the parent zone: {}", ds);
+  }
+}
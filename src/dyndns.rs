@@ -0,0 +1,150 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::{types, utils};
+
+use base64::Engine;
+use hyper::{
+  header,
+  server::conn::AddrStream,
+  service::{make_service_fn, service_fn},
+  Body, Request, Response, StatusCode,
+};
+use std::{
+  convert::Infallible,
+  net::{IpAddr, SocketAddr},
+  sync::Arc,
+};
+
+pub struct DynDnsOptions {
+  pub username: String,
+  pub password: String,
+  pub allowed_record_names: Vec<String>,
+}
+
+// Implements the DynDNS2 update protocol that is supported by a lot of routers and NAS devices.
+// https://help.dyn.com/remote-access-api/perform-update/
+// https://help.dyn.com/remote-access-api/return-codes/
+pub async fn serve(listen: SocketAddr, options: DynDnsOptions, handler: types::UpdateHandler) {
+  let options = Arc::new(options);
+  let make_service = make_service_fn(move |conn: &AddrStream| {
+    let remote_addr = conn.remote_addr();
+    let options = options.clone();
+    let handler = handler.clone();
+    async move {
+      Ok::<_, Infallible>(service_fn(move |req| {
+        handle_request(req, remote_addr, options.clone(), handler.clone())
+      }))
+    }
+  });
+
+  eprintln!(
+    "Listening for DynDNS updates on http://{}/nic/update",
+    listen
+  );
+  hyper::Server::bind(&listen)
+    .serve(make_service)
+    .await
+    .expect("server error");
+}
+
+async fn handle_request(
+  req: Request<Body>,
+  remote_addr: SocketAddr,
+  options: Arc<DynDnsOptions>,
+  handler: types::UpdateHandler,
+) -> Result<Response<Body>, Infallible> {
+  if req.uri().path() != "/nic/update" {
+    return Ok(response(StatusCode::NOT_FOUND, "not found"));
+  }
+  if !is_authorized(&req, &options) {
+    return Ok(
+      Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(header::WWW_AUTHENTICATE, "Basic realm=\"route53-update\"")
+        .body(Body::from("badauth"))
+        .unwrap(),
+    );
+  }
+
+  let query = req.uri().query().unwrap_or_default();
+  let params: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes())
+    .into_owned()
+    .collect();
+  let param = |name: &str| {
+    params
+      .iter()
+      .find(|(k, _)| k == name)
+      .map(|(_, v)| v.clone())
+  };
+
+  let Some(hostnames) = param("hostname") else {
+    return Ok(response(StatusCode::OK, "notfqdn"));
+  };
+  // Clients that don't send myip want the address that the request came from
+  let myip = param("myip").unwrap_or_else(|| remote_addr.ip().to_string());
+  // Anything else than an address would otherwise be published as a TXT record
+  let Ok(myip) = myip.parse::<IpAddr>() else {
+    eprintln!("Invalid myip: {}", myip);
+    let results = vec!["dnserr"; hostnames.split(',').count()];
+    return Ok(response(StatusCode::OK, &results.join("\n")));
+  };
+  let myip = myip.to_string();
+
+  // Multiple hostnames can be updated in one request, and one return code is returned per hostname
+  let mut results = Vec::new();
+  for hostname in hostnames.split(',') {
+    if !hostname.contains('.') {
+      results.push("notfqdn".to_string());
+      continue;
+    }
+    if !utils::matches_record_name_pattern(hostname, &options.allowed_record_names) {
+      results.push("nohost".to_string());
+      continue;
+    }
+    let request = types::UpdateRequest {
+      record_name: hostname.to_string(),
+      record_type: None,
+      value_source: Some(types::ValueSource::Values(vec![myip.clone()])),
+      alias_target: None,
+      ttl: None,
+    };
+    match handler(request).await {
+      Ok(result) if result.is_changed() => results.push(format!("good {}", myip)),
+      Ok(_) => results.push(format!("nochg {}", myip)),
+      Err(err) => {
+        eprintln!("Error updating {}: {}", hostname, err);
+        results.push("911".to_string());
+      }
+    }
+  }
+
+  Ok(response(StatusCode::OK, &results.join("\n")))
+}
+
+fn is_authorized(req: &Request<Body>, options: &DynDnsOptions) -> bool {
+  let Some(credentials) = req
+    .headers()
+    .get(header::AUTHORIZATION)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.strip_prefix("Basic "))
+    .and_then(|v| base64::engine::general_purpose::STANDARD.decode(v).ok())
+    .and_then(|v| String::from_utf8(v).ok())
+  else {
+    return false;
+  };
+  let Some((username, password)) = credentials.split_once(':') else {
+    return false;
+  };
+  // Both are compared so that the time taken does not tell whether the username was right
+  utils::constant_time_eq(username.as_bytes(), options.username.as_bytes())
+    & utils::constant_time_eq(password.as_bytes(), options.password.as_bytes())
+}
+
+fn response(status: StatusCode, body: &str) -> Response<Body> {
+  Response::builder()
+    .status(status)
+    .header(header::CONTENT_TYPE, "text/plain")
+    .body(Body::from(body.to_string()))
+    .unwrap()
+}
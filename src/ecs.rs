@@ -0,0 +1,139 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::{fleet, types};
+
+use aws_sdk_ecs::types::{DesiredStatus, Task};
+use std::collections::BTreeSet;
+
+// DescribeTasks and DescribeContainerInstances accept up to 100 ARNs per request.
+const MAX_DESCRIBE: usize = 100;
+
+// Returns the network interface ID and the private IPv4 address of a task in awsvpc network mode.
+pub fn get_task_eni(task: &Task) -> Option<(String, String)> {
+  let attachment = task
+    .attachments()
+    .iter()
+    .find(|attachment| attachment.r#type() == Some("ElasticNetworkInterface"))?;
+  let detail = |name: &str| {
+    attachment
+      .details()
+      .iter()
+      .find(|detail| detail.name() == Some(name))
+      .and_then(|detail| detail.value())
+      .map(|value| value.to_string())
+  };
+  Some((detail("networkInterfaceId")?, detail("privateIPv4Address")?))
+}
+
+// Returns the addresses of the running tasks of the service. Tasks in awsvpc network mode use the address of their network interface, and tasks in bridge and host network mode use the address of their container instance.
+pub async fn list_service_addresses(
+  ecs_client: &aws_sdk_ecs::Client,
+  ec2_client: &aws_sdk_ec2::Client,
+  cluster: &str,
+  service: &str,
+  ip_address_type: types::IPAddressType,
+) -> Vec<String> {
+  let task_arns: Vec<String> = ecs_client
+    .list_tasks()
+    .cluster(cluster)
+    .service_name(service)
+    .desired_status(DesiredStatus::Running)
+    .into_paginator()
+    .items()
+    .send()
+    .collect::<Result<_, _>>()
+    .await
+    .expect("could not list the ECS tasks");
+  let mut tasks = Vec::new();
+  for chunk in task_arns.chunks(MAX_DESCRIBE) {
+    let response = ecs_client
+      .describe_tasks()
+      .cluster(cluster)
+      .set_tasks(Some(chunk.to_vec()))
+      .send()
+      .await
+      .expect("could not describe the ECS tasks");
+    tasks.extend(
+      response
+        .tasks
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|task| task.last_status() == Some("RUNNING")),
+    );
+  }
+  eprintln!("Found {} running tasks in {}", tasks.len(), service);
+
+  let mut addresses = BTreeSet::new();
+  let mut eni_ids = Vec::new();
+  let mut container_instance_arns = BTreeSet::new();
+  for task in &tasks {
+    match get_task_eni(task) {
+      Some((eni_id, private_address)) => match ip_address_type {
+        types::IPAddressType::Private => {
+          addresses.insert(private_address);
+        }
+        types::IPAddressType::Public => eni_ids.push(eni_id),
+      },
+      None => {
+        if let Some(arn) = task.container_instance_arn() {
+          container_instance_arns.insert(arn.to_string());
+        }
+      }
+    }
+  }
+
+  // The public addresses of the network interfaces are only available from EC2
+  if !eni_ids.is_empty() {
+    let response = ec2_client
+      .describe_network_interfaces()
+      .set_network_interface_ids(Some(eni_ids))
+      .send()
+      .await
+      .expect("could not describe the network interfaces of the tasks");
+    addresses.extend(
+      response
+        .network_interfaces()
+        .iter()
+        .filter_map(|eni| eni.association()?.public_ip())
+        .map(|address| address.to_string()),
+    );
+  }
+
+  if !container_instance_arns.is_empty() {
+    let container_instance_arns: Vec<String> = container_instance_arns.into_iter().collect();
+    let mut instance_ids = Vec::new();
+    for chunk in container_instance_arns.chunks(MAX_DESCRIBE) {
+      let response = ecs_client
+        .describe_container_instances()
+        .cluster(cluster)
+        .set_container_instances(Some(chunk.to_vec()))
+        .send()
+        .await
+        .expect("could not describe the ECS container instances");
+      instance_ids.extend(
+        response
+          .container_instances()
+          .iter()
+          .filter_map(|container_instance| container_instance.ec2_instance_id())
+          .map(|id| id.to_string()),
+      );
+    }
+    let reservations: Vec<_> = ec2_client
+      .describe_instances()
+      .set_instance_ids(Some(instance_ids))
+      .into_paginator()
+      .items()
+      .send()
+      .collect::<Result<_, _>>()
+      .await
+      .expect("could not describe the EC2 instances");
+    addresses.extend(
+      reservations
+        .iter()
+        .flat_map(|reservation| reservation.instances())
+        .filter_map(|instance| fleet::get_instance_address(instance, ip_address_type)),
+    );
+  }
+  addresses.into_iter().collect()
+}
@@ -0,0 +1,51 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::{logging, utils};
+
+use serde_json::{json, Value};
+use std::{
+  fs::File,
+  io::Write,
+  sync::{Mutex, OnceLock},
+};
+
+// --events
+// The events are written as one JSON object per line, with the event name in "event". The names and fields are stable so that other programs can rely on them:
+// zone_found, value_detected, change_submitted, change_insync, verified, error
+static EVENTS: OnceLock<Mutex<File>> = OnceLock::new();
+
+// The path can be a regular file, or e.g. /dev/fd/3 or /dev/stderr to write to a file descriptor.
+pub fn init(path: &str) -> std::io::Result<()> {
+  let file = std::fs::OpenOptions::new()
+    .create(true)
+    .append(true)
+    .open(path)?;
+  let _ = EVENTS.set(Mutex::new(file));
+  Ok(())
+}
+
+pub fn event_line(event: &str, timestamp: &str, fields: Value) -> String {
+  let mut line = json!({
+    "event": event,
+    "timestamp": timestamp,
+  });
+  if let Value::Object(fields) = fields {
+    line.as_object_mut().unwrap().extend(fields);
+  }
+  line.to_string()
+}
+
+// Does nothing unless --events or --log-target journald was given.
+pub fn emit(event: &str, fields: Value) {
+  logging::log_event(event, &fields);
+  let Some(file) = EVENTS.get() else {
+    return;
+  };
+  let line = event_line(event, &utils::get_timestamp(), fields);
+  let mut file = file.lock().unwrap_or_else(|err| err.into_inner());
+  // The events must not make the update fail
+  if let Err(err) = writeln!(file, "{}", line).and_then(|_| file.flush()) {
+    eprintln!("Warning: could not write event: {}", err);
+  }
+}
@@ -0,0 +1,211 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+// The bootstrap-failover command
+// Creates a health check for each endpoint, and a primary and a secondary failover record set that use them.
+
+use crate::{backend::DnsBackend, types::HealthCheckProtocol, utils};
+
+use aws_sdk_route53::types::{
+  HealthCheckConfig, HealthCheckType, ResourceRecord, ResourceRecordSet, ResourceRecordSetFailover,
+  RrType, Tag, TagResourceType,
+};
+use std::net::IpAddr;
+
+// Failover records should expire quickly so that resolvers pick up the secondary soon after the primary fails
+pub const DEFAULT_TTL: i64 = 60;
+const REQUEST_INTERVAL: i32 = 30;
+const FAILURE_THRESHOLD: i32 = 3;
+
+pub fn build_health_check_config(
+  record_name: &str,
+  endpoint: &str,
+  protocol: HealthCheckProtocol,
+  port: Option<u16>,
+  path: &str,
+) -> Result<HealthCheckConfig, String> {
+  let (health_check_type, default_port) = match protocol {
+    HealthCheckProtocol::Http => (HealthCheckType::Http, Some(80)),
+    HealthCheckProtocol::Https => (HealthCheckType::Https, Some(443)),
+    HealthCheckProtocol::Tcp => (HealthCheckType::Tcp, None),
+  };
+  let port = port
+    .or(default_port)
+    .ok_or_else(|| "--port is required for TCP health checks".to_string())?;
+  let mut config = HealthCheckConfig::builder()
+    .r#type(health_check_type)
+    .port(i32::from(port))
+    .request_interval(REQUEST_INTERVAL)
+    .failure_threshold(FAILURE_THRESHOLD);
+  config = if endpoint.parse::<IpAddr>().is_ok() {
+    // The record name is sent in the Host header and with SNI, since that is the name that the endpoint serves
+    config
+      .ip_address(endpoint)
+      .fully_qualified_domain_name(record_name.trim_end_matches('.'))
+  } else {
+    config.fully_qualified_domain_name(endpoint)
+  };
+  if protocol != HealthCheckProtocol::Tcp {
+    config = config.resource_path(path);
+  }
+  if protocol == HealthCheckProtocol::Https {
+    config = config.enable_sni(true);
+  }
+  config.build().map_err(|err| err.to_string())
+}
+
+// Route 53 returns the existing health check when the caller reference and the configuration are the same, so that the command can be run again.
+// The caller reference can be at most 64 characters, so it is a hash of the fields that the command sets.
+pub fn get_caller_reference(record_name: &str, role: &str, config: &HealthCheckConfig) -> String {
+  let fields = [
+    record_name,
+    role,
+    config.r#type().as_str(),
+    config.ip_address().unwrap_or_default(),
+    config.fully_qualified_domain_name().unwrap_or_default(),
+    &config
+      .port()
+      .map(|port| port.to_string())
+      .unwrap_or_default(),
+    config.resource_path().unwrap_or_default(),
+  ];
+  format!(
+    "route53-update-failover-{:016x}",
+    utils::stable_hash(&fields.join("\n"))
+  )
+}
+
+// Endpoints that are IP addresses give A or AAAA records, and hostnames give CNAME records (which are not allowed at the zone apex).
+pub fn get_record_type(primary: &str, secondary: &str) -> Result<RrType, String> {
+  let record_type = |endpoint: &str| match endpoint.parse::<IpAddr>() {
+    Ok(IpAddr::V4(_)) => RrType::A,
+    Ok(IpAddr::V6(_)) => RrType::Aaaa,
+    Err(_) => RrType::Cname,
+  };
+  let primary_type = record_type(primary);
+  if primary_type != record_type(secondary) {
+    return Err(format!(
+      "the primary and secondary endpoints must be of the same kind ({} is {} and {} is {})",
+      primary,
+      primary_type.as_str(),
+      secondary,
+      record_type(secondary).as_str()
+    ));
+  }
+  Ok(primary_type)
+}
+
+pub fn build_failover_record_set(
+  record_name: &str,
+  record_type: &RrType,
+  ttl: i64,
+  endpoint: &str,
+  failover: ResourceRecordSetFailover,
+  health_check_id: &str,
+) -> ResourceRecordSet {
+  ResourceRecordSet::builder()
+    .name(record_name)
+    .r#type(record_type.clone())
+    .ttl(ttl)
+    .set_identifier(failover.as_str().to_lowercase())
+    .failover(failover)
+    .health_check_id(health_check_id)
+    .resource_records(
+      ResourceRecord::builder()
+        .value(endpoint)
+        .build()
+        .expect("error building resource record"),
+    )
+    .build()
+    .expect("error building resource record set")
+}
+
+async fn create_health_check(
+  route53_client: &aws_sdk_route53::Client,
+  record_name: &str,
+  role: &str,
+  config: HealthCheckConfig,
+) -> String {
+  let response = route53_client
+    .create_health_check()
+    .caller_reference(get_caller_reference(record_name, role, &config))
+    .health_check_config(config)
+    .send()
+    .await
+    .unwrap_or_else(|err| panic!("could not create the {} health check: {}", role, err));
+  let id = response.health_check().unwrap().id().to_string();
+  eprintln!("Health check for the {}: {}", role, id);
+
+  // The Name tag is shown in the console
+  route53_client
+    .change_tags_for_resource()
+    .resource_type(TagResourceType::Healthcheck)
+    .resource_id(&id)
+    .add_tags(
+      Tag::builder()
+        .key("Name")
+        .value(format!("{} {}", record_name.trim_end_matches('.'), role))
+        .build(),
+    )
+    .send()
+    .await
+    .unwrap_or_else(|err| panic!("could not tag the {} health check: {}", role, err));
+  id
+}
+
+// Creates the health checks and the failover record sets. Returns the change ID.
+#[allow(clippy::too_many_arguments)]
+pub async fn bootstrap_failover(
+  route53_client: &aws_sdk_route53::Client,
+  backend: &dyn DnsBackend,
+  hosted_zone_id: &str,
+  record_name: &str,
+  primary: &str,
+  secondary: &str,
+  protocol: HealthCheckProtocol,
+  port: Option<u16>,
+  path: &str,
+  ttl: Option<i64>,
+) -> String {
+  let record_name = if record_name.ends_with('.') {
+    record_name.to_string()
+  } else {
+    format!("{}.", record_name)
+  };
+  let record_type = get_record_type(primary, secondary).unwrap_or_else(|err| panic!("{}", err));
+
+  let mut record_sets = Vec::new();
+  for (endpoint, failover) in [
+    (primary, ResourceRecordSetFailover::Primary),
+    (secondary, ResourceRecordSetFailover::Secondary),
+  ] {
+    let role = failover.as_str().to_lowercase();
+    let config = build_health_check_config(&record_name, endpoint, protocol, port, path)
+      .unwrap_or_else(|err| panic!("{}", err));
+    let health_check_id = create_health_check(route53_client, &record_name, &role, config).await;
+    record_sets.push(build_failover_record_set(
+      &record_name,
+      &record_type,
+      ttl.unwrap_or(DEFAULT_TTL),
+      endpoint,
+      failover,
+      &health_check_id,
+    ));
+  }
+
+  eprintln!(
+    "Creating the failover records {} {}: primary {}, secondary {}",
+    record_name,
+    record_type.as_str(),
+    primary,
+    secondary
+  );
+  backend
+    .upsert(
+      hosted_zone_id,
+      record_sets,
+      Some(format!("Failover from {} to {}", primary, secondary)),
+    )
+    .await
+    .unwrap_or_else(|err| panic!("could not create the failover records: {}", err))
+}
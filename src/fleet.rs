@@ -0,0 +1,144 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::types;
+
+use aws_sdk_ec2::{
+  error::DisplayErrorContext,
+  types::{Filter, Instance},
+};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InventoryEntry {
+  // An instance ID, or the Name tag of the instance (e.g. the host name used with SSH)
+  pub host: String,
+  pub record_name: String,
+}
+
+// One entry per line with the host and the record name separated by whitespace, e.g.:
+// i-0123456789abcdef0 web1.example.com
+// web2 web2.example.com
+// Empty lines and comments starting with # are ignored.
+pub fn parse_inventory(contents: &str) -> Result<Vec<InventoryEntry>, String> {
+  let mut entries = Vec::new();
+  for (i, line) in contents.lines().enumerate() {
+    let line = line.split('#').next().unwrap().trim();
+    if line.is_empty() {
+      continue;
+    }
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [host, record_name] = fields[..] else {
+      return Err(format!(
+        "line {}: expected a host and a record name, got {:?}",
+        i + 1,
+        line
+      ));
+    };
+    entries.push(InventoryEntry {
+      host: host.to_string(),
+      record_name: record_name.to_string(),
+    });
+  }
+  Ok(entries)
+}
+
+pub fn is_instance_id(host: &str) -> bool {
+  host
+    .strip_prefix("i-")
+    .is_some_and(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+pub fn get_instance_address(
+  instance: &Instance,
+  ip_address_type: types::IPAddressType,
+) -> Option<String> {
+  match ip_address_type {
+    types::IPAddressType::Public => instance.public_ip_address(),
+    types::IPAddressType::Private => instance.private_ip_address(),
+  }
+  .map(|address| address.to_string())
+}
+
+// Returns the address of each host that was found, keyed by the host as it was written in the inventory.
+// Fails if an instance ID does not exist, since DescribeInstances rejects the whole request in that case.
+pub async fn get_instance_addresses(
+  ec2_client: &aws_sdk_ec2::Client,
+  entries: &[InventoryEntry],
+  ip_address_type: types::IPAddressType,
+) -> Result<HashMap<String, String>, String> {
+  let (instance_ids, names): (Vec<&str>, Vec<&str>) = entries
+    .iter()
+    .map(|entry| entry.host.as_str())
+    .partition(|host| is_instance_id(host));
+  let mut instances = Vec::new();
+  if !instance_ids.is_empty() {
+    let reservations: Vec<_> = ec2_client
+      .describe_instances()
+      .set_instance_ids(Some(instance_ids.iter().map(|id| id.to_string()).collect()))
+      .into_paginator()
+      .items()
+      .send()
+      .collect::<Result<_, _>>()
+      .await
+      .map_err(|err| {
+        format!(
+          "could not describe the EC2 instances: {}",
+          DisplayErrorContext(&err)
+        )
+      })?;
+    instances.extend(
+      reservations
+        .into_iter()
+        .flat_map(|r| r.instances.unwrap_or_default()),
+    );
+  }
+  if !names.is_empty() {
+    let reservations: Vec<_> = ec2_client
+      .describe_instances()
+      .filters(
+        Filter::builder()
+          .name("tag:Name")
+          .set_values(Some(names.iter().map(|name| name.to_string()).collect()))
+          .build(),
+      )
+      .filters(
+        Filter::builder()
+          .name("instance-state-name")
+          .values("running")
+          .build(),
+      )
+      .into_paginator()
+      .items()
+      .send()
+      .collect::<Result<_, _>>()
+      .await
+      .map_err(|err| {
+        format!(
+          "could not describe the EC2 instances: {}",
+          DisplayErrorContext(&err)
+        )
+      })?;
+    instances.extend(
+      reservations
+        .into_iter()
+        .flat_map(|r| r.instances.unwrap_or_default()),
+    );
+  }
+
+  let mut addresses = HashMap::new();
+  for instance in &instances {
+    let Some(address) = get_instance_address(instance, ip_address_type) else {
+      continue;
+    };
+    let name = instance
+      .tags()
+      .iter()
+      .find(|tag| tag.key() == Some("Name"))
+      .and_then(|tag| tag.value());
+    for host in instance.instance_id().into_iter().chain(name) {
+      addresses.insert(host.to_string(), address.clone());
+    }
+  }
+  Ok(addresses)
+}
@@ -0,0 +1,215 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::types;
+
+use aws_sdk_ec2::types::Filter;
+use aws_sdk_route53::types::{Change, ChangeAction, ChangeBatch, ResourceRecordSet};
+use std::collections::HashSet;
+
+// Keep the change batches well below the Route 53 limit of 1000 changes per request.
+const MAX_CHANGES_PER_BATCH: usize = 100;
+
+// Returns the instance ID in the record name if it matches the pattern.
+// The pattern has a * (or the {instance_id} placeholder) in the label that contains the instance ID, e.g. i-*.fleet.example.com.
+pub fn get_instance_id(pattern: &str, record_name: &str) -> Option<String> {
+  let pattern = pattern
+    .trim_end_matches('.')
+    .replace("{instance_id}", "*")
+    .to_lowercase();
+  let record_name = record_name.trim_end_matches('.').to_lowercase();
+  let pattern_labels: Vec<&str> = pattern.split('.').collect();
+  let record_labels: Vec<&str> = record_name.split('.').collect();
+  if pattern_labels.len() != record_labels.len() {
+    return None;
+  }
+  let mut instance_id = None;
+  for (pattern_label, record_label) in pattern_labels.iter().zip(record_labels) {
+    match pattern_label.split_once('*') {
+      Some((prefix, suffix)) => {
+        if record_label.len() < prefix.len() + suffix.len()
+          || !record_label.starts_with(prefix)
+          || !record_label.ends_with(suffix)
+        {
+          return None;
+        }
+        instance_id = find_instance_id(record_label);
+      }
+      None if *pattern_label == record_label => {}
+      None => return None,
+    }
+  }
+  instance_id
+}
+
+// Finds an instance ID like i-0123456789abcdef0 in the label.
+fn find_instance_id(label: &str) -> Option<String> {
+  let start = label.find("i-")?;
+  let id: String = label[start + 2..]
+    .chars()
+    .take_while(|c| c.is_ascii_hexdigit())
+    .collect();
+  if id.len() < 8 {
+    return None;
+  }
+  Some(format!("i-{}", id))
+}
+
+// The part of the pattern after the label with the wildcard, e.g. fleet.example.com. for i-*.fleet.example.com
+pub fn get_pattern_suffix(pattern: &str) -> String {
+  let pattern = pattern.replace("{instance_id}", "*");
+  let suffix = match pattern.rsplit_once('*') {
+    Some((_, rest)) => rest.split_once('.').map(|(_, suffix)| suffix).unwrap_or(""),
+    None => &pattern,
+  };
+  if suffix.ends_with('.') {
+    suffix.to_string()
+  } else {
+    format!("{}.", suffix)
+  }
+}
+
+// Returns the IDs of the instances that still exist (i.e. are not shutting down or terminated) and have all of the tags.
+pub async fn list_instance_ids(
+  ec2_client: &aws_sdk_ec2::Client,
+  tags: &[types::Tag],
+) -> HashSet<String> {
+  let mut request = ec2_client.describe_instances().filters(
+    Filter::builder()
+      .name("instance-state-name")
+      .values("pending")
+      .values("running")
+      .values("stopping")
+      .values("stopped")
+      .build(),
+  );
+  for tag in tags {
+    request = request.filters(
+      Filter::builder()
+        .name(format!("tag:{}", tag.key))
+        .values(&tag.value)
+        .build(),
+    );
+  }
+  let reservations: Vec<_> = request
+    .into_paginator()
+    .items()
+    .send()
+    .collect::<Result<_, _>>()
+    .await
+    .expect("could not describe the EC2 instances");
+  reservations
+    .iter()
+    .flat_map(|reservation| reservation.instances())
+    .filter_map(|instance| instance.instance_id())
+    .map(|id| id.to_string())
+    .collect()
+}
+
+// Returns the record sets in the hosted zone that match the pattern, along with the instance ID in their name.
+pub async fn list_instance_records(
+  route53_client: &aws_sdk_route53::Client,
+  hosted_zone_id: &str,
+  pattern: &str,
+) -> Vec<(String, ResourceRecordSet)> {
+  let suffix = get_pattern_suffix(pattern);
+  let mut records = Vec::new();
+  // The record sets are sorted by name with the labels reversed, so the records below the suffix are listed together
+  let mut next_record_name = Some(suffix.clone());
+  let mut next_record_type = None;
+  let mut next_record_identifier = None;
+  while let Some(start_record_name) = next_record_name {
+    let response = route53_client
+      .list_resource_record_sets()
+      .hosted_zone_id(hosted_zone_id)
+      .start_record_name(start_record_name)
+      .set_start_record_type(next_record_type)
+      .set_start_record_identifier(next_record_identifier)
+      .send()
+      .await
+      .expect("could not list record sets");
+    let mut done = !response.is_truncated();
+    for record_set in response.resource_record_sets() {
+      if record_set.name() != suffix && !record_set.name().ends_with(&format!(".{}", suffix)) {
+        done = true;
+        break;
+      }
+      if let Some(instance_id) = get_instance_id(pattern, record_set.name()) {
+        records.push((instance_id, record_set.clone()));
+      }
+    }
+    if done {
+      break;
+    }
+    next_record_name = response.next_record_name().map(|name| name.to_string());
+    next_record_type = response.next_record_type().cloned();
+    next_record_identifier = response.next_record_identifier().map(|id| id.to_string());
+  }
+  records
+}
+
+// Deletes the records of instances that no longer exist. Returns the number of records that were (or would be) deleted.
+pub async fn gc(
+  route53_client: &aws_sdk_route53::Client,
+  ec2_client: &aws_sdk_ec2::Client,
+  hosted_zone_id: &str,
+  pattern: &str,
+  tags: &[types::Tag],
+  dry_run: bool,
+) -> usize {
+  let records = list_instance_records(route53_client, hosted_zone_id, pattern).await;
+  eprintln!("Found {} records matching {}", records.len(), pattern);
+  if records.is_empty() {
+    return 0;
+  }
+  let instance_ids = list_instance_ids(ec2_client, tags).await;
+  eprintln!("Found {} instances", instance_ids.len());
+  if instance_ids.is_empty() {
+    panic!(
+      "no instances were found, refusing to delete all of the records (check --region and --tag)"
+    );
+  }
+
+  let stale: Vec<&ResourceRecordSet> = records
+    .iter()
+    .filter(|(instance_id, _)| !instance_ids.contains(instance_id))
+    .map(|(_, record_set)| record_set)
+    .collect();
+  for record_set in &stale {
+    eprintln!(
+      "{} {} {}",
+      if dry_run { "Would delete" } else { "Deleting" },
+      record_set.r#type().as_str(),
+      record_set.name()
+    );
+  }
+  if dry_run {
+    return stale.len();
+  }
+
+  for chunk in stale.chunks(MAX_CHANGES_PER_BATCH) {
+    let mut change_batch_builder =
+      ChangeBatch::builder().comment("Garbage collection of stale instance records");
+    for record_set in chunk {
+      let change = Change::builder()
+        .action(ChangeAction::Delete)
+        .resource_record_set((*record_set).clone())
+        .build()
+        .expect("error building change set");
+      change_batch_builder = change_batch_builder.changes(change);
+    }
+    let response = route53_client
+      .change_resource_record_sets()
+      .hosted_zone_id(hosted_zone_id)
+      .change_batch(
+        change_batch_builder
+          .build()
+          .expect("error building change batch"),
+      )
+      .send()
+      .await
+      .expect("could not delete the stale records");
+    println!("{:?}", response);
+  }
+  stale.len()
+}
@@ -0,0 +1,76 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::types::RrType;
+use std::env;
+
+// dhclient sets $reason, and NetworkManager passes the action as the second argument to dispatcher scripts.
+// https://manpages.debian.org/bookworm/isc-dhcp-client/dhclient-script.8.en.html
+// https://networkmanager.dev/docs/api/latest/NetworkManager-dispatcher.html
+const DHCLIENT_REASONS: [&str; 8] = [
+  "BOUND", "RENEW", "REBIND", "REBOOT", "BOUND6", "RENEW6", "REBIND6", "REBOOT6",
+];
+const NETWORKMANAGER_ACTIONS: [&str; 4] =
+  ["up", "dhcp4-change", "dhcp6-change", "connectivity-change"];
+
+// Returns the new address if the hook was invoked for an event that assigned one, otherwise None.
+// Events for other interfaces than only_interface are ignored.
+pub fn get_hook_values(
+  interface: Option<&str>,
+  action: Option<&str>,
+  only_interface: Option<&str>,
+  record_type: Option<&RrType>,
+) -> Option<Vec<String>> {
+  let ipv6 = record_type == Some(&RrType::Aaaa);
+
+  if let Ok(reason) = env::var("reason") {
+    // dhclient
+    if !DHCLIENT_REASONS.contains(&reason.as_str()) {
+      eprintln!("Ignoring dhclient reason: {}", reason);
+      return None;
+    }
+    if let (Some(interface), Ok(hook_interface)) =
+      (only_interface.or(interface), env::var("interface"))
+    {
+      if interface != hook_interface {
+        eprintln!("Ignoring interface: {}", hook_interface);
+        return None;
+      }
+    }
+    let name = if ipv6 {
+      "new_ip6_address"
+    } else {
+      "new_ip_address"
+    };
+    return env::var(name).ok().map(|address| vec![address]);
+  }
+
+  // NetworkManager
+  let hook_interface = interface
+    .map(|interface| interface.to_string())
+    .or_else(|| env::var("DEVICE_IFACE").ok());
+  if let (Some(interface), Some(hook_interface)) = (only_interface, &hook_interface) {
+    if interface != hook_interface {
+      eprintln!("Ignoring interface: {}", hook_interface);
+      return None;
+    }
+  }
+  if let Some(action) = action {
+    if !NETWORKMANAGER_ACTIONS.contains(&action) {
+      eprintln!("Ignoring NetworkManager action: {}", action);
+      return None;
+    }
+  }
+  let names = if ipv6 {
+    ["DHCP6_IP6_ADDRESS", "IP6_ADDRESS_0"]
+  } else {
+    ["DHCP4_IP_ADDRESS", "IP4_ADDRESS_0"]
+  };
+  names
+    .iter()
+    .find_map(|name| env::var(name).ok())
+    // IP4_ADDRESS_0 has the format "address/prefix gateway"
+    .and_then(|value| value.split(['/', ' ']).next().map(|v| v.to_string()))
+    .filter(|address| !address.is_empty())
+    .map(|address| vec![address])
+}
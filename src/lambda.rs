@@ -0,0 +1,25 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use serde_json::Value;
+
+// https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/monitoring-instance-state-changes.html
+const INSTANCE_STATE_CHANGE: &str = "EC2 Instance State-change Notification";
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct InstanceStateChange {
+  pub instance_id: String,
+  pub state: String,
+}
+
+// Returns None for other events, which are ignored by the Lambda handler.
+pub fn parse_instance_state_change(event: &Value) -> Option<InstanceStateChange> {
+  if event.get("detail-type")?.as_str()? != INSTANCE_STATE_CHANGE {
+    return None;
+  }
+  let detail = event.get("detail")?;
+  Some(InstanceStateChange {
+    instance_id: detail.get("instance-id")?.as_str()?.to_string(),
+    state: detail.get("state")?.as_str()?.to_string(),
+  })
+}
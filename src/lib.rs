@@ -0,0 +1,44 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+pub mod alias;
+pub mod api;
+pub mod api_calls;
+pub mod audit;
+pub mod backend;
+pub mod canary;
+pub mod cfn;
+pub mod check;
+pub mod cidr;
+pub mod cloudmap;
+pub mod cron;
+pub mod delegate;
+pub mod dkim;
+pub mod dmarc;
+pub mod dnssec;
+pub mod dyndns;
+pub mod ecs;
+pub mod events;
+pub mod failover;
+pub mod fleet;
+pub mod gc;
+pub mod hook;
+pub mod lambda;
+pub mod lock;
+pub mod logging;
+pub mod mirror;
+pub mod reconcile;
+pub mod self_update;
+#[cfg(windows)]
+pub mod service;
+pub mod snapshot;
+pub mod sources;
+pub mod spf;
+pub mod ssm;
+pub mod traffic_policy;
+pub mod types;
+pub mod update;
+pub mod upnp;
+pub mod utils;
+pub mod weights;
+pub mod worker;
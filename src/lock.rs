@@ -0,0 +1,84 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+// --lock-file
+// The lock is held with flock until the process exits, so it is released even if the process is killed. The file contains a description of the process that holds the lock.
+
+use crate::utils;
+
+use std::{fmt, fs::File};
+
+pub struct Lock {
+  // The lock is released when the file is closed
+  _file: File,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LockError {
+  // Contains the description of the process that holds the lock, if it could be read
+  Held(Option<String>),
+  Other(String),
+}
+
+impl fmt::Display for LockError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LockError::Held(Some(holder)) => write!(f, "the lock is held by {}", holder),
+      LockError::Held(None) => write!(f, "the lock is held by another process"),
+      LockError::Other(err) => write!(f, "{}", err),
+    }
+  }
+}
+
+pub fn describe_holder(pid: u32, hostname: &str, timestamp: &str) -> String {
+  format!("pid {} on {} (since {})", pid, hostname, timestamp)
+}
+
+#[cfg(unix)]
+pub fn acquire(path: &str) -> Result<Lock, LockError> {
+  use std::io::{Read, Seek, Write};
+  use std::os::fd::AsRawFd;
+
+  let mut file = std::fs::OpenOptions::new()
+    .read(true)
+    .write(true)
+    .create(true)
+    .truncate(false)
+    .open(path)
+    .map_err(|err| LockError::Other(format!("could not open {}: {}", path, err)))?;
+  // SAFETY: the file descriptor is open for as long as the file
+  if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+    let err = std::io::Error::last_os_error();
+    if err.kind() != std::io::ErrorKind::WouldBlock {
+      return Err(LockError::Other(format!(
+        "could not lock {}: {}",
+        path, err
+      )));
+    }
+    let mut holder = String::new();
+    let holder = match file.read_to_string(&mut holder) {
+      Ok(_) if !holder.trim().is_empty() => Some(holder.trim().to_string()),
+      _ => None,
+    };
+    return Err(LockError::Held(holder));
+  }
+
+  let holder = describe_holder(
+    std::process::id(),
+    &utils::get_hostname(),
+    &utils::get_timestamp(),
+  );
+  file
+    .set_len(0)
+    .and_then(|_| file.rewind())
+    .and_then(|_| writeln!(file, "{}", holder))
+    .map_err(|err| LockError::Other(format!("could not write {}: {}", path, err)))?;
+  Ok(Lock { _file: file })
+}
+
+#[cfg(not(unix))]
+pub fn acquire(_path: &str) -> Result<Lock, LockError> {
+  Err(LockError::Other(
+    "--lock-file is only supported on Unix".to_string(),
+  ))
+}
@@ -0,0 +1,251 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+// --log-target
+// The program logs with eprintln!, so to send the log to syslog or journald, stderr is replaced with a pipe and a thread forwards each line that is written to it.
+// With journald, the events (see events.rs) are also sent as structured entries.
+
+use crate::types::LogTarget;
+
+use serde_json::Value;
+
+pub const IDENTIFIER: &str = "route53-update";
+
+pub const SYSLOG_SOCKET: &str = "/dev/log";
+pub const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+// The syslog facility
+const LOG_DAEMON: u8 = 3;
+
+// The syslog severities, which journald also uses for PRIORITY
+pub const SEVERITY_ERROR: u8 = 3;
+pub const SEVERITY_WARNING: u8 = 4;
+pub const SEVERITY_INFO: u8 = 6;
+
+pub fn get_severity(line: &str) -> u8 {
+  if line.starts_with("Error") || line.contains("panicked at") {
+    SEVERITY_ERROR
+  } else if line.starts_with("Warning") {
+    SEVERITY_WARNING
+  } else {
+    SEVERITY_INFO
+  }
+}
+
+// The BSD syslog format without the timestamp and hostname, which the local syslog daemon adds.
+pub fn syslog_message(pid: u32, severity: u8, message: &str) -> String {
+  format!(
+    "<{}>{}[{}]: {}",
+    LOG_DAEMON * 8 + severity,
+    IDENTIFIER,
+    pid,
+    message
+  )
+}
+
+// The native journald protocol: KEY=value lines, or for values that contain newlines, the key followed by the length of the value as a little-endian 64-bit integer.
+// https://systemd.io/JOURNAL_NATIVE_PROTOCOL/
+pub fn journal_message(fields: &[(String, String)]) -> Vec<u8> {
+  let mut message = Vec::new();
+  for (key, value) in fields {
+    message.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+      message.push(b'\n');
+      message.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+      message.push(b'=');
+    }
+    message.extend_from_slice(value.as_bytes());
+    message.push(b'\n');
+  }
+  message
+}
+
+// Journal field names may only contain uppercase letters, digits and underscores, and may not start with an underscore or a digit.
+pub fn journal_field_name(name: &str) -> String {
+  let name: String = name
+    .chars()
+    .map(|c| match c {
+      'a'..='z' => c.to_ascii_uppercase(),
+      'A'..='Z' | '0'..='9' => c,
+      _ => '_',
+    })
+    .collect();
+  format!("ROUTE53_UPDATE_{}", name)
+}
+
+pub fn event_journal_fields(event: &str, fields: &Value) -> Vec<(String, String)> {
+  let mut journal_fields = vec![
+    ("MESSAGE".to_string(), format!("{} {}", event, fields)),
+    (
+      "PRIORITY".to_string(),
+      if event == "error" {
+        SEVERITY_ERROR
+      } else {
+        SEVERITY_INFO
+      }
+      .to_string(),
+    ),
+    ("SYSLOG_IDENTIFIER".to_string(), IDENTIFIER.to_string()),
+    ("ROUTE53_UPDATE_EVENT".to_string(), event.to_string()),
+  ];
+  if let Value::Object(fields) = fields {
+    for (key, value) in fields {
+      let value = match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+      };
+      journal_fields.push((journal_field_name(key), value));
+    }
+  }
+  journal_fields
+}
+
+#[cfg(unix)]
+mod forward {
+  use super::*;
+
+  use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    os::{
+      fd::{AsRawFd, FromRawFd, OwnedFd},
+      unix::net::UnixDatagram,
+    },
+    sync::{Mutex, OnceLock},
+    thread::JoinHandle,
+  };
+
+  struct Forwarder {
+    target: LogTarget,
+    socket: UnixDatagram,
+    // A copy of the original stderr, which is used if the socket fails and restored when the program exits
+    stderr: OwnedFd,
+    thread: Mutex<Option<JoinHandle<()>>>,
+  }
+
+  static FORWARDER: OnceLock<Forwarder> = OnceLock::new();
+
+  impl Forwarder {
+    fn send_line(&self, line: &str) {
+      let severity = get_severity(line);
+      let result = match self.target {
+        LogTarget::Journald => self.socket.send(&journal_message(&[
+          ("MESSAGE".to_string(), line.to_string()),
+          ("PRIORITY".to_string(), severity.to_string()),
+          ("SYSLOG_IDENTIFIER".to_string(), IDENTIFIER.to_string()),
+        ])),
+        _ => self
+          .socket
+          .send(syslog_message(std::process::id(), severity, line).as_bytes()),
+      };
+      if result.is_err() {
+        self.write_stderr(line);
+      }
+    }
+
+    fn write_stderr(&self, line: &str) {
+      if let Ok(fd) = self.stderr.try_clone() {
+        let _ = writeln!(File::from(fd), "{}", line);
+      }
+    }
+  }
+
+  pub fn init(target: LogTarget) -> Result<(), String> {
+    let path = match target {
+      LogTarget::Stderr => return Ok(()),
+      LogTarget::Syslog => SYSLOG_SOCKET,
+      LogTarget::Journald => JOURNALD_SOCKET,
+    };
+    let socket = UnixDatagram::unbound().map_err(|err| err.to_string())?;
+    socket
+      .connect(path)
+      .map_err(|err| format!("could not connect to {}: {}", path, err))?;
+
+    // SAFETY: pipe, dup and dup2 are called with file descriptors that are open, and the new file descriptors are owned by OwnedFd
+    let (reader, writer, stderr) = unsafe {
+      let mut fds = [0; 2];
+      if libc::pipe(fds.as_mut_ptr()) < 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+      }
+      let (reader, writer) = (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]));
+      let stderr = libc::dup(libc::STDERR_FILENO);
+      if stderr < 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+      }
+      let stderr = OwnedFd::from_raw_fd(stderr);
+      if libc::dup2(writer.as_raw_fd(), libc::STDERR_FILENO) < 0 {
+        return Err(std::io::Error::last_os_error().to_string());
+      }
+      (reader, writer, stderr)
+    };
+    // stderr is now the only write end of the pipe, so the thread stops when it is restored
+    drop(writer);
+
+    let forwarder = Forwarder {
+      target,
+      socket,
+      stderr,
+      thread: Mutex::new(None),
+    };
+    if FORWARDER.set(forwarder).is_err() {
+      return Err("the log target is already initialized".to_string());
+    }
+    let thread = std::thread::spawn(move || {
+      let forwarder = FORWARDER.get().unwrap();
+      for line in BufReader::new(File::from(reader)).lines() {
+        match line {
+          Ok(line) => forwarder.send_line(&line),
+          Err(_) => break,
+        }
+      }
+    });
+    *FORWARDER.get().unwrap().thread.lock().unwrap() = Some(thread);
+
+    // SAFETY: flush_at_exit is a function without arguments that does not unwind
+    unsafe {
+      libc::atexit(flush_at_exit);
+    }
+    Ok(())
+  }
+
+  // Restores stderr and waits for the thread to forward the lines that are left in the pipe, so that e.g. the panic message is not lost when the program exits.
+  extern "C" fn flush_at_exit() {
+    let Some(forwarder) = FORWARDER.get() else {
+      return;
+    };
+    let Some(thread) = forwarder.thread.lock().ok().and_then(|mut t| t.take()) else {
+      return;
+    };
+    // SAFETY: the copy of the original stderr is open for as long as the forwarder exists
+    unsafe {
+      libc::dup2(forwarder.stderr.as_raw_fd(), libc::STDERR_FILENO);
+    }
+    let _ = thread.join();
+  }
+
+  pub fn log_event(event: &str, fields: &Value) {
+    let Some(forwarder) = FORWARDER.get() else {
+      return;
+    };
+    if forwarder.target == LogTarget::Journald {
+      let _ = forwarder
+        .socket
+        .send(&journal_message(&event_journal_fields(event, fields)));
+    }
+  }
+}
+
+#[cfg(unix)]
+pub use forward::{init, log_event};
+
+#[cfg(not(unix))]
+pub fn init(target: LogTarget) -> Result<(), String> {
+  match target {
+    LogTarget::Stderr => Ok(()),
+    _ => Err("--log-target syslog and journald are only supported on Unix".to_string()),
+  }
+}
+
+#[cfg(not(unix))]
+pub fn log_event(_event: &str, _fields: &Value) {}
@@ -1,25 +1,127 @@
 // Copyright 2023 Stefan Sundin
 // Licensed under GNU GPL v3 or later
 
-pub mod types;
-pub mod utils;
-
 use aws_sdk_route53::types::{
-  Change, ChangeAction, ChangeBatch, ChangeStatus, ResourceRecord, ResourceRecordSet, RrType,
+  CidrRoutingConfig, Coordinates, GeoProximityLocation, ResourceRecord, ResourceRecordSet, RrType,
+};
+use clap::{Parser, Subcommand};
+use ipnet::Ipv6Net;
+#[cfg(feature = "lambda")]
+use route53_update::lambda;
+#[cfg(windows)]
+use route53_update::service;
+use route53_update::{
+  alias, api, api_calls, audit, backend, backend::DnsBackend, canary, cfn, check, cidr, cloudmap,
+  cron, delegate, dkim, dmarc, dnssec, dyndns, ecs, events, failover, fleet, gc, hook, lock,
+  logging, mirror, reconcile, self_update, snapshot, sources, spf, ssm, traffic_policy, types,
+  upnp, utils, weights, worker,
+};
+use serde_json::json;
+use std::{
+  cell::Cell,
+  collections::HashMap,
+  future::Future,
+  net::{IpAddr, Ipv6Addr, SocketAddr},
+  path::PathBuf,
+  sync::{
+    atomic::{AtomicI32, Ordering},
+    Arc, Mutex, OnceLock,
+  },
+  time,
 };
-use clap::Parser;
-use std::{thread, time};
 
 const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 
-#[derive(Parser)]
-#[command(arg_required_else_help(true))]
+// Exit codes (documented in the README). Invalid command line options also exit with 2 when they are rejected by clap.
+const EXIT_ERROR: i32 = 1;
+const EXIT_INVALID_ARGUMENTS: i32 = 2;
+const EXIT_VALUE_ERROR: i32 = 3;
+const EXIT_AWS_ERROR: i32 = 4;
+const EXIT_LOCKED: i32 = 5;
+const EXIT_UPDATE_AVAILABLE: i32 = 6;
+
+// The exit code that is used if the program panics, updated as the program progresses
+static PANIC_EXIT_CODE: AtomicI32 = AtomicI32::new(EXIT_ERROR);
+
+tokio::task_local! {
+  // The exit code that is used if an update panics. The updates of --daemon runs and of update_many run concurrently, so each one has its own.
+  static UPDATE_EXIT_CODE: Cell<i32>;
+}
+
+fn set_panic_exit_code(code: i32) {
+  if UPDATE_EXIT_CODE
+    .try_with(|exit_code| exit_code.set(code))
+    .is_err()
+  {
+    PANIC_EXIT_CODE.store(code, Ordering::SeqCst);
+  }
+}
+
+fn get_panic_exit_code() -> i32 {
+  UPDATE_EXIT_CODE
+    .try_with(Cell::get)
+    .unwrap_or_else(|_| PANIC_EXIT_CODE.load(Ordering::SeqCst))
+}
+
+tokio::task_local! {
+  // Set in the tasks started by catch_panic, where the panic hook does not exit the program
+  static CATCHES_PANICS: ();
+}
+
+// Starts the future in its own task. The task returns the panic message if it panics, instead of exiting the program.
+fn catch_panic<T: Send + 'static>(
+  future: impl Future<Output = T> + Send + 'static,
+) -> impl Future<Output = Result<T, String>> {
+  let task = tokio::spawn(CATCHES_PANICS.scope((), future));
+  async move { task.await.map_err(utils::get_panic_message) }
+}
+
+// Used by --startup-grace, which is counted from when the program started
+static STARTED_AT: OnceLock<time::Instant> = OnceLock::new();
+
+const EXAMPLES: &str = "\
+Examples:
+  Set a record to a fixed value:
+    route53-update --record-name service.example.com --value 192.0.2.1
+
+  Use the public IP address of the EC2 instance:
+    route53-update --record-name service.example.com --value-from ec2-metadata --record-type A
+
+  Use the IP address of the ECS task, with a 60 second TTL:
+    route53-update --record-name '{family}.example.com' --value-from ecs-metadata --record-type A --ttl 60
+
+  Use the public IPv4 and IPv6 address as seen from the internet:
+    route53-update --record-name home.example.com --value-from-url-v4 https://ipv4.icanhazip.com/ --value-from-url-v6 https://ipv6.icanhazip.com/
+
+  Keep running and delete the record when the ECS task stops:
+    route53-update --record-name service.example.com --value-from auto --record-type A --daemon --deregister-on-exit";
+
+#[derive(Parser, Clone)]
+#[command(arg_required_else_help(true), after_help = EXAMPLES)]
 struct Arguments {
+  #[command(subcommand)]
+  command: Option<Command>,
+
+  #[arg(
+    long,
+    help = "Print the version and build information as JSON and exit"
+  )]
+  version_json: bool,
+
+  // -v is --value
+  #[arg(
+    short = 'V',
+    long,
+    help = "Print a summary of the Route 53 API calls at the end of the run, e.g. to tune the options for the API rate limits"
+  )]
+  verbose: bool,
+
   #[arg(
     long,
-    help = "The Hosted Zone ID (optional, will be looked up automatically based on --record-name if omitted)"
+    value_parser = utils::parse_hosted_zone_id,
+    help = "The Hosted Zone ID, with or without the /hostedzone/ prefix (optional, will be looked up automatically based on --record-name if omitted, can be specified multiple times to write the record to several hosted zones)"
   )]
-  hosted_zone_id: Option<String>,
+  hosted_zone_id: Vec<String>,
 
   #[arg(
     long,
@@ -29,17 +131,38 @@ struct Arguments {
 
   #[arg(
     long,
-    help = "Filter the hosted zones based on the type (supported: 'prefer-public', 'public' or 'private')",
+    value_enum,
+    help = "Filter the hosted zones based on the type, useful when a public and a private hosted zone have the same name",
     default_value = "prefer-public"
   )]
   hosted_zone_type: types::HostedZoneType,
 
+  #[arg(
+    long,
+    value_name = "ARN",
+    help = "Assume this role to make the changes, for private hosted zones that are owned by another account"
+  )]
+  zone_owner_role_arn: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "ID",
+    help = "The VPC used to look for private hosted zones that are owned by another account (optional, is looked up in the EC2 instance metadata service)"
+  )]
+  vpc_id: Option<String>,
+
   #[arg(
     long,
     value_name = "NAME",
-    help = "Record name to update (e.g. service.example.com)"
+    help = "Record name to update (required unless using a serve command, e.g. service.example.com, supports the placeholders {instance_id}, {region}, {account_id}, {availability_zone}, {task_id}, {family} and {revision})"
+  )]
+  record_name: Option<String>,
+
+  #[arg(
+    long,
+    help = "The record name is relative to --hosted-zone-name, e.g. --record-name service with --hosted-zone-name example.com is service.example.com (use @ for the zone apex)"
   )]
-  record_name: String,
+  relative: bool,
 
   #[arg(
     long,
@@ -57,137 +180,2939 @@ struct Arguments {
   )]
   value: Vec<String>,
 
-  #[arg(
-    long,
-    value_enum,
-    value_name = "SOURCE",
-    help = "Get the value from a specific source (supported: 'auto', 'ec2-metadata', or 'ecs-metadata')"
-  )]
-  value_from: Option<types::ValueFromSource>,
+  #[arg(
+    long,
+    value_name = "NAME",
+    conflicts_with = "cron",
+    help = "In daemon mode, keep the record in sync with the answers for another name, e.g. a dynamic record at another DNS provider (the name is resolved again when the TTL of the answer expires)"
+  )]
+  mirror: Option<String>,
+
+  #[arg(
+    long,
+    help = "Resolve the values that are hostnames and use their IP addresses instead, e.g. to mirror a record at another DNS provider (uses the IPv6 addresses for AAAA records, otherwise the IPv4 addresses)"
+  )]
+  resolve_value: bool,
+
+  #[arg(
+    long,
+    value_name = "VALUE",
+    help = "Use this value instead of --value in the private hosted zones given by --hosted-zone-id, e.g. the private IP address for split-horizon DNS (can be specified multiple times, requires route53:GetHostedZone)"
+  )]
+  private_value: Vec<String>,
+
+  #[arg(
+    long,
+    help = "When --value is a mix of IPv4 and IPv6 addresses, create an A record with the IPv4 addresses and an AAAA record with the IPv6 addresses"
+  )]
+  split_mixed: bool,
+
+  #[arg(
+    long,
+    value_enum,
+    value_name = "SOURCE",
+    help = "Get the value from a specific source"
+  )]
+  value_from: Option<types::ValueFromSource>,
+
+  #[arg(
+    long,
+    value_name = "NAME",
+    help = "The name of the Lightsail instance to use with --value-from lightsail (the region is given by --region)"
+  )]
+  lightsail_instance_name: Option<String>,
+
+  #[arg(
+    long,
+    value_enum,
+    value_name = "MODE",
+    help = "The network mode of the ECS task, in bridge and host mode the address of the container instance is used instead of the address of the container",
+    default_value = "auto"
+  )]
+  ecs_network_mode: types::EcsNetworkMode,
+
+  #[arg(
+    long,
+    value_name = "CONTAINER:PORT",
+    help = "Create an SRV record that points to the container instance and the host port that is mapped to this container port (for ECS tasks in bridge network mode)"
+  )]
+  srv_from_ecs: Option<types::SrvFromEcs>,
+
+  #[arg(
+    long,
+    value_name = "URL",
+    help = "Get the value from a URL (e.g. https://checkip.amazonaws.com/), also used as a fallback by --value-from auto"
+  )]
+  value_from_url: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "NAME",
+    help = "Get the value from an SSM parameter (a StringList parameter gives one value per item), or from a Secrets Manager secret if the ARN of a secret is given"
+  )]
+  value_from_ssm: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "INTERFACE",
+    help = "Get the value from the global addresses of a network interface, using the ip command (Linux only, the IPv6 addresses are used for AAAA records)"
+  )]
+  value_from_interface: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "COMMAND",
+    help = "Get the value from the output of a shell command, one value per line"
+  )]
+  value_from_command: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "URL",
+    help = "Get the value for an A record from a URL (e.g. https://ipv4.icanhazip.com/), can be combined with --value-from-url-v6"
+  )]
+  value_from_url_v4: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "URL",
+    help = "Get the value for an AAAA record from a URL (e.g. https://ipv6.icanhazip.com/), can be combined with --value-from-url-v4"
+  )]
+  value_from_url_v6: Option<String>,
+
+  #[arg(
+    long,
+    help = "Fail instead of warning when the host is behind carrier-grade NAT (an interface address is in 100.64.0.0/10 and the address from --value-from-url is not assigned to the host)"
+  )]
+  fail_on_cgnat: bool,
+
+  #[arg(
+    long,
+    value_name = "TEMPLATE",
+    help = "Render the value from a template (supports the placeholders {hostname}, {timestamp}, {public_ipv4}, {local_ipv4}, {ipv6}, {ec2.<metadata-path>}, and the instance identity and ECS task placeholders)"
+  )]
+  value_template: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "DNS_NAME",
+    help = "Create an alias record that points to this load balancer, CloudFront distribution, S3 website endpoint or Global Accelerator, or to another record in the same hosted zone"
+  )]
+  alias_target: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "ID",
+    value_parser = utils::parse_hosted_zone_id,
+    help = "The hosted zone ID of the alias target (optional, it is looked up from the DNS name of the AWS endpoints that --alias-target supports, and the hosted zone of the record is used for other names)"
+  )]
+  alias_target_hosted_zone_id: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "REGION",
+    conflicts_with = "alias_target",
+    help = "Create an alias record that points to the S3 website endpoint of this region (the bucket must have the same name as the record)"
+  )]
+  alias_s3_website: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "API_ID",
+    conflicts_with_all = ["alias_target", "alias_s3_website"],
+    help = "Create an alias record that points to the API Gateway custom domain name with the same name as the record, after checking that this API is mapped to it"
+  )]
+  alias_api_gateway: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "DOMAIN",
+    help = "Add an include mechanism to the SPF record, keeping its other mechanisms and the other TXT values with the same name (can be specified multiple times)"
+  )]
+  spf_include: Vec<String>,
+
+  #[arg(
+    long,
+    value_name = "SELECTOR",
+    help = "Publish a DKIM public key in the TXT record <selector>._domainkey.<record-name>, used together with --dkim-public-key-file"
+  )]
+  dkim_selector: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "PATH",
+    help = "The PEM encoded DKIM public key (RSA or Ed25519) to publish, e.g. from openssl rsa -in private.pem -pubout"
+  )]
+  dkim_public_key_file: Option<PathBuf>,
+
+  #[arg(
+    long,
+    value_enum,
+    value_name = "POLICY",
+    help = "Publish a DMARC policy in the TXT record _dmarc.<record-name>"
+  )]
+  dmarc_policy: Option<types::DmarcPolicy>,
+
+  #[arg(
+    long,
+    value_enum,
+    value_name = "POLICY",
+    help = "The DMARC policy for subdomains (optional, --dmarc-policy is used for subdomains if omitted)"
+  )]
+  dmarc_subdomain_policy: Option<types::DmarcPolicy>,
+
+  #[arg(
+    long,
+    value_name = "PERCENT",
+    value_parser = clap::value_parser!(u8).range(0..=100),
+    help = "The percentage of the messages that the DMARC policy is applied to (optional, 100 if omitted)"
+  )]
+  dmarc_percent: Option<u8>,
+
+  #[arg(
+    long,
+    value_name = "URI",
+    help = "Where to send aggregate DMARC reports, e.g. mailto:dmarc@example.com (can be specified multiple times)"
+  )]
+  dmarc_rua: Vec<String>,
+
+  #[arg(
+    long,
+    value_name = "URI",
+    help = "Where to send DMARC failure reports, e.g. mailto:dmarc@example.com (can be specified multiple times)"
+  )]
+  dmarc_ruf: Vec<String>,
+
+  #[arg(
+    long,
+    help = "Make the alias record evaluate the health of its target, e.g. the health of the targets of a load balancer"
+  )]
+  evaluate_target_health: bool,
+
+  #[arg(
+    long,
+    value_enum,
+    value_name = "SOURCES",
+    value_delimiter = ',',
+    help = "The preference order of the sources used by --value-from auto, all sources are queried concurrently",
+    default_value = "ecs,ec2,url"
+  )]
+  value_source_order: Vec<types::ValueSourceKind>,
+
+  #[arg(
+    long,
+    value_name = "FILE",
+    help = "Read the EC2 and ECS metadata from a JSON file instead of the metadata services, useful to try out options and templates before deploying"
+  )]
+  simulate_metadata: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "SECONDS",
+    help = "How long to keep retrying the ECS task metadata endpoint while it is not ready yet (e.g. right after the container started)",
+    default_value_t = 30
+  )]
+  metadata_timeout: u64,
+
+  #[arg(
+    long,
+    value_name = "SECONDS",
+    help = "Keep retrying the metadata services and the initial AWS calls for this long after the program started if the network or the credentials are not available yet, e.g. when started early during boot",
+    default_value_t = 0
+  )]
+  startup_grace: u64,
+
+  #[arg(
+    long,
+    value_enum,
+    value_name = "TYPE",
+    help = "Use a specific IP address type when getting the value from the EC2 instance metadata service (instances without a public IP address have to use private)",
+    default_value = "public"
+  )]
+  ip_address_type: types::IPAddressType,
+
+  #[arg(
+    long,
+    value_name = "N",
+    help = "Use the network interface with this device number when using the EC2 instance metadata service (0 is the primary network interface)"
+  )]
+  eni_index: Option<usize>,
+
+  #[arg(
+    long,
+    value_name = "N",
+    help = "Use the address at this index on the network interface when using the EC2 instance metadata service (0 is the primary address)"
+  )]
+  secondary_ip_index: Option<usize>,
+
+  #[arg(
+    long,
+    value_name = "POLICY",
+    help = "Select a single IPv6 address when --value-from finds multiple (supported: 'stable', 'temporary', 'eui64', or 'prefix:<PREFIX>'; temporary addresses are identified by their flags from the ip command)"
+  )]
+  ipv6_selection: Option<types::Ipv6Selection>,
+
+  #[arg(
+    long,
+    value_name = "CHECK",
+    help = "Only publish the values that this host can connect to, e.g. tcp:443 (values that are not reachable within 5 seconds are left out, and the record is not updated if none of them are reachable)"
+  )]
+  require_reachable: Option<types::Reachability>,
+
+  #[arg(
+    long,
+    value_name = "FILTER",
+    help = "Only publish the values inside (include-cidr:<CIDR>) or outside (exclude-cidr:<CIDR>) a range, e.g. exclude-cidr:10.0.0.0/8 (can be specified multiple times)"
+  )]
+  value_filter: Vec<types::ValueFilter>,
+
+  #[arg(
+    long,
+    value_name = "PREFIX",
+    num_args = 0..=1,
+    help = "Compose the AAAA value from a delegated IPv6 prefix and --ipv6-suffix (the prefix is read from the EC2 instance metadata service if omitted)"
+  )]
+  ipv6_from_prefix: Option<Option<Ipv6Net>>,
+
+  #[arg(
+    long,
+    value_name = "SUFFIX",
+    help = "The interface identifier to combine with --ipv6-from-prefix (e.g. ::1234)"
+  )]
+  ipv6_suffix: Option<Ipv6Addr>,
+
+  #[arg(
+    long,
+    value_parser = utils::parse_ttl,
+    help = "TTL for the DNS record in seconds or as a duration like 5m or 1h (optional, if an existing record exists then its TTL will be copied, --default-ttl or 300 is used as fallback)"
+  )]
+  ttl: Option<i64>,
+
+  #[arg(
+    long,
+    value_name = "[TYPE=]TTL",
+    help = "Default TTL to use when no existing record exists, optionally per record type (e.g. A=60 or TXT=3600, can be specified multiple times)"
+  )]
+  default_ttl: Vec<types::DefaultTtl>,
+
+  #[arg(
+    long,
+    value_name = "PERCENT",
+    value_parser = clap::value_parser!(u8).range(0..=50),
+    help = "Spread the TTL by up to ±PERCENT per host (stable for each host and record), so that the records of a large fleet do not expire in the resolvers at the same time"
+  )]
+  record_ttl_jitter: Option<u8>,
+
+  #[arg(
+    long,
+    value_name = "TTL",
+    value_parser = utils::parse_ttl,
+    help = "Minimum TTL to use when copying an existing TTL or using the default TTL"
+  )]
+  min_ttl: Option<i64>,
+
+  #[arg(
+    long,
+    value_name = "TTL",
+    value_parser = utils::parse_ttl,
+    help = "Maximum TTL to use when copying an existing TTL or using the default TTL"
+  )]
+  max_ttl: Option<i64>,
+
+  #[arg(
+    long,
+    value_name = "ID",
+    help = "Identifies the record among the record sets with the same name and type, required when using a routing policy"
+  )]
+  set_identifier: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "ID",
+    help = "Use CIDR routing with this CIDR collection (requires --cidr-location and --set-identifier, see the cidr-collection command)"
+  )]
+  cidr_collection_id: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "LOCATION",
+    help = "The location in the CIDR collection that this record answers for (use * for the default location)"
+  )]
+  cidr_location: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "REGION",
+    help = "Use geoproximity routing with the location of this AWS region (requires --set-identifier)"
+  )]
+  geoproximity_region: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "LATITUDE,LONGITUDE",
+    help = "Use geoproximity routing with the location at these coordinates, e.g. 47.61,-122.33 (requires --set-identifier)"
+  )]
+  geoproximity_coordinates: Option<types::Coordinates>,
+
+  #[arg(
+    long,
+    value_name = "BIAS",
+    allow_negative_numbers = true,
+    value_parser = clap::value_parser!(i32).range(-99..=99),
+    help = "Expand (positive) or shrink (negative) the geographic region that the geoproximity record answers for, from -99 to 99"
+  )]
+  bias: Option<i32>,
+
+  #[arg(
+    long,
+    value_name = "WEIGHT",
+    value_parser = clap::value_parser!(i64).range(0..=255),
+    help = "Use weighted routing with this weight, from 0 to 255 (requires --set-identifier)"
+  )]
+  weight: Option<i64>,
+
+  #[arg(
+    long,
+    help = "Change batch comment (supports the placeholders {hostname}, {old_value}, {new_value}, {transition}, {timestamp}, {instance_id}, {region}, {account_id}, {availability_zone}, {task_id}, {family}, {revision} and {version})"
+  )]
+  comment: Option<String>,
+
+  #[arg(
+    long,
+    help = "The AWS region to use (optional, the default AWS configuration is used, falling back to the region in the instance identity document or us-east-1)"
+  )]
+  region: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "SECONDS",
+    help = "How long to wait for the connection to the AWS API to be established",
+    default_value_t = 5
+  )]
+  aws_connect_timeout: u64,
+
+  #[arg(
+    long,
+    value_name = "SECONDS",
+    help = "How long to wait for a response from the AWS API before the attempt fails (the SDK retries failed attempts a few times)",
+    default_value_t = 30
+  )]
+  aws_timeout: u64,
+
+  #[arg(
+    long,
+    help = "Use the FIPS endpoints of the AWS services, e.g. in GovCloud or other FIPS-mandated environments"
+  )]
+  aws_use_fips: bool,
+
+  #[arg(
+    long,
+    help = "Use the dual-stack (IPv4 and IPv6) endpoints of the AWS services, needed on IPv6-only hosts"
+  )]
+  aws_use_dualstack: bool,
+
+  #[arg(
+    long,
+    value_name = "PATH",
+    help = "Append a JSON line describing the change to this file, or write it to S3 when given s3://bucket/prefix"
+  )]
+  audit_log: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "PATH",
+    help = "Exit with code 5 if another run holds the lock on this file, e.g. /var/run/route53-update.lock to prevent overlapping cron runs"
+  )]
+  lock_file: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "TARGET",
+    help = "Where to write the log",
+    default_value = "stderr"
+  )]
+  log_target: types::LogTarget,
+
+  #[arg(
+    long,
+    value_name = "PATH",
+    help = "Write progress events as newline-delimited JSON to this file, e.g. /dev/fd/3 to use a file descriptor"
+  )]
+  events: Option<String>,
+
+  #[arg(
+    long,
+    help = "Tag the hosted zone with LastUpdatedBy and LastUpdatedAt after updating the record"
+  )]
+  tag_hosted_zone: bool,
+
+  #[arg(
+    long,
+    value_name = "PATH",
+    help = "Write the change ID to this file (one per line if multiple records are updated), e.g. for a later wait command"
+  )]
+  change_id_file: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "PATH",
+    help = "Write the result to this file as a JSON array with one object per updated record"
+  )]
+  result_file: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "CODE",
+    help = "The exit code to use when the record is already up to date and no change was needed",
+    default_value_t = 0
+  )]
+  unchanged_exit_code: i32,
+
+  #[arg(long, help = "Wait for the change to propagate in Route 53")]
+  wait: bool,
+
+  #[arg(
+    long,
+    value_name = "N",
+    help = "Give up waiting for the change to propagate in Route 53 after this many status checks (one per second)"
+  )]
+  wait_max_attempts: Option<u32>,
+
+  #[arg(
+    long,
+    value_name = "IP",
+    help = "After the update, wait until this resolver returns the new value, e.g. the VPC resolver or an on-premises DNS forwarder (can be specified multiple times)"
+  )]
+  verify_resolver: Vec<IpAddr>,
+
+  #[arg(
+    long,
+    help = "After the update, wait until the public resolvers from Google, Cloudflare, and Quad9 return the new value"
+  )]
+  verify_dns: bool,
+
+  #[arg(
+    long,
+    value_name = "SECONDS",
+    help = "How long to wait for --verify-dns and --verify-resolver before giving up (raised to the negative TTL of the zone when the record is new)",
+    default_value_t = 600
+  )]
+  verify_timeout: u64,
+
+  #[arg(
+    long,
+    value_name = "URL",
+    help = "After the change has propagated in Route 53, probe this URL and roll back to the previous value if it fails (the new address is used directly if the URL uses the record name)"
+  )]
+  verify_http: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "N",
+    help = "How many times to try --verify-http before rolling back",
+    default_value_t = 3
+  )]
+  verify_http_attempts: u32,
+
+  #[arg(
+    long,
+    value_name = "RECORD_NAME",
+    help = "Before overwriting the record, copy the previous value to this record name, which can be used to switch back manually during a migration"
+  )]
+  keep_previous_as: Option<String>,
+
+  #[arg(
+    long,
+    help = "When the record is new, wait for the negative TTL of the zone (from the SOA record) so that resolvers no longer return NXDOMAIN from their cache"
+  )]
+  wait_negative_ttl: bool,
+
+  #[arg(long, help = "Delete potentially conflicting records (A, AAAA, CNAME)")]
+  clear: bool,
+
+  #[arg(
+    long,
+    value_name = "DIR",
+    default_value = ".",
+    help = "The directory to write the restore file to when --clear deletes records"
+  )]
+  restore_dir: PathBuf,
+
+  #[arg(
+    long,
+    help = "Also maintain a TXT record containing the hostname and a timestamp, updated on every run"
+  )]
+  heartbeat: bool,
+
+  #[arg(
+    long,
+    value_name = "NAME",
+    help = "Record name for the heartbeat record (default: _heartbeat.<record-name>)"
+  )]
+  heartbeat_record_name: Option<String>,
+
+  #[arg(
+    long,
+    help = "Update the record even if it is managed by another controller such as external-dns (detected by a TXT record containing heritage=, or by --external-owner-tag)"
+  )]
+  override_external: bool,
+
+  #[arg(
+    long,
+    value_name = "KEY=VALUE",
+    help = "Treat the records in hosted zones that have this tag as managed by another tool, e.g. ManagedBy=terraform (can be specified multiple times)"
+  )]
+  external_owner_tag: Vec<types::Tag>,
+
+  #[arg(
+    long,
+    help = "If the record is managed by a traffic policy, update the traffic policy instance instead (a new version of the traffic policy is created if the value changed)"
+  )]
+  update_traffic_policy: bool,
+
+  #[arg(
+    long,
+    help = "Warn if the record is in a private hosted zone and a Route 53 Resolver forwarding rule for the name is associated with one of its VPCs (requires route53resolver:ListResolverRules and route53resolver:ListResolverRuleAssociations)"
+  )]
+  check_resolver_rules: bool,
+
+  #[arg(
+    long,
+    help = "Warn if DNSSEC signing of the hosted zone is in a broken state before making the change (requires route53:GetDNSSEC)"
+  )]
+  check_dnssec: bool,
+
+  #[arg(
+    long,
+    value_name = "ID",
+    help = "Also register the value as an instance in this AWS Cloud Map service (Cloud Map namespaces reject direct edits to the records that they manage)"
+  )]
+  cloudmap_service_id: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "NAME",
+    help = "Also write the value to this SSM parameter when it changes, e.g. /fleet/{instance_id}/public-ip (supports the same placeholders as --record-name)"
+  )]
+  publish_to_ssm: Option<String>,
+
+  #[arg(
+    long,
+    value_name = "ID",
+    help = "The instance ID to use in Cloud Map [default: the hostname]"
+  )]
+  cloudmap_instance_id: Option<String>,
+
+  #[arg(
+    long,
+    help = "Only register the value in Cloud Map and do not update Route 53 (--record-name is then optional)"
+  )]
+  skip_route53: bool,
+
+  #[arg(
+    long,
+    help = "Do not update the record when the value is an Elastic IP address, since it does not change (requires ec2:DescribeAddresses)"
+  )]
+  skip_elastic_ip: bool,
+
+  #[arg(
+    long,
+    help = "Keep running and update the record whenever the detected value changes"
+  )]
+  daemon: bool,
+
+  #[arg(
+    long,
+    value_name = "SECONDS",
+    help = "How often to check the value in daemon mode",
+    default_value = "60"
+  )]
+  interval: u64,
+
+  #[arg(
+    long,
+    value_name = "SECONDS",
+    help = "In daemon mode, wait at least this long after changing the record before changing it again, so that a rapidly changing value (e.g. PPPoE reconnects) results in one change with the latest value"
+  )]
+  min_update_interval: Option<u64>,
+
+  #[arg(
+    long,
+    value_name = "EXPRESSION",
+    conflicts_with = "daemon",
+    help = "Keep running and update the record on this cron schedule (in UTC), e.g. '*/5 * * * *' to run every five minutes in a container without crond"
+  )]
+  cron: Option<cron::Schedule>,
+
+  #[arg(
+    long,
+    value_name = "SECONDS",
+    help = "Delay the runs of --cron by up to this many seconds, based on the hostname, so that many hosts with the same schedule don't run at the same time",
+    default_value = "30"
+  )]
+  cron_jitter: u64,
+
+  #[arg(
+    long,
+    help = "Delete the record when the daemon receives SIGTERM or SIGINT, or when the ECS task is stopping (the deletion has to finish within the ECS stop timeout)"
+  )]
+  deregister_on_exit: bool,
+
+  #[arg(
+    long,
+    help = "Delete the record and exit when the daemon is running on an EC2 spot instance that receives an interruption notice or a rebalance recommendation"
+  )]
+  handle_spot_interruption: bool,
+
+  #[arg(
+    long,
+    value_enum,
+    help = "What to do with the record when --handle-spot-interruption receives a notice",
+    default_value = "delete"
+  )]
+  spot_interruption_action: types::SpotInterruptionAction,
+
+  #[cfg(windows)]
+  #[arg(
+    long,
+    help = "Run as a Windows service, stopping the daemon (and deregistering with --deregister-on-exit) when the service is stopped"
+  )]
+  windows_service: bool,
+}
+
+#[derive(Subcommand, Clone)]
+enum Command {
+  #[command(
+    about = "Update the record using the address from a dhclient exit hook or a NetworkManager dispatcher script"
+  )]
+  Hook {
+    #[arg(
+      help = "The interface (passed as the first argument to NetworkManager dispatcher scripts, or used to filter dhclient events)"
+    )]
+    interface: Option<String>,
+
+    #[arg(
+      help = "The action (passed as the second argument to NetworkManager dispatcher scripts)"
+    )]
+    action: Option<String>,
+
+    #[arg(
+      long = "interface",
+      value_name = "INTERFACE",
+      help = "Ignore the events for other interfaces than this one"
+    )]
+    only_interface: Option<String>,
+  },
+
+  #[command(about = "Wait for a previously submitted change to propagate in Route 53")]
+  Wait {
+    #[arg(
+      long,
+      value_name = "ID",
+      help = "The change ID (e.g. C2682N5HXP0BZ4 or /change/C2682N5HXP0BZ4)"
+    )]
+    change_id: String,
+  },
+
+  #[command(
+    about = "Create the NS records for a child hosted zone in its parent hosted zone (subdomain delegation, uses --ttl, --wait and --region)"
+  )]
+  Delegate {
+    #[arg(
+      long,
+      value_name = "ID",
+      value_parser = utils::parse_hosted_zone_id,
+      help = "The hosted zone ID of the child zone"
+    )]
+    child_zone_id: String,
+
+    #[arg(
+      long,
+      value_name = "ID",
+      value_parser = utils::parse_hosted_zone_id,
+      help = "The hosted zone ID of the parent zone (optional, the closest public zone above the child zone is used if omitted)"
+    )]
+    parent_zone_id: Option<String>,
+
+    #[arg(
+      long,
+      value_name = "ARN",
+      help = "Assume this role to update the parent zone, if it is owned by another account"
+    )]
+    parent_role_arn: Option<String>,
+  },
+
+  #[command(
+    about = "Create or update a CIDR collection for CIDR routing from a file with one location and CIDR block per line (e.g. \"office 192.0.2.0/24\"), prints the collection ID"
+  )]
+  CidrCollection {
+    #[arg(
+      long,
+      help = "The name of the CIDR collection, it is created if it does not exist"
+    )]
+    name: String,
+
+    #[arg(
+      long,
+      value_name = "PATH",
+      help = "The file with the CIDR blocks, locations that are not in the file are removed from the collection"
+    )]
+    file: String,
+  },
+
+  #[command(
+    about = "Delete the records of EC2 instances that no longer exist, e.g. records created with --record-name '{instance_id}.fleet.example.com' (uses --hosted-zone-id and --region)"
+  )]
+  Gc {
+    #[arg(
+      long,
+      help = "The record names to look at, with a * (or {instance_id}) in place of the instance ID (e.g. i-*.fleet.example.com)"
+    )]
+    pattern: String,
+
+    #[arg(
+      long,
+      value_name = "KEY=VALUE",
+      help = "Only consider the instances with this tag to exist (can be specified multiple times)"
+    )]
+    tag: Vec<types::Tag>,
+
+    #[arg(
+      long,
+      help = "Print the records that would be deleted without deleting them"
+    )]
+    dry_run: bool,
+  },
+
+  #[command(
+    about = "Update the records of many EC2 instances at once from an inventory file, using the addresses from DescribeInstances (uses --ip-address-type and the other options for the records)"
+  )]
+  Fleet {
+    #[arg(
+      long,
+      value_name = "PATH",
+      help = "A file with an instance ID or the Name tag of an instance, and the record name, separated by whitespace on each line"
+    )]
+    inventory: String,
+
+    #[arg(
+      long,
+      value_name = "N",
+      help = "How many records to update at the same time",
+      default_value_t = 10
+    )]
+    concurrency: usize,
+  },
+
+  #[command(
+    about = "Update the records named by a tag on the running EC2 instances, e.g. --tag dns:name (instances with the same name share a record, uses --ip-address-type and the other options for the records)"
+  )]
+  Reconcile {
+    #[arg(
+      long,
+      value_name = "KEY",
+      help = "The tag that has the record name of the instance as its value"
+    )]
+    tag: String,
+
+    #[arg(
+      long,
+      help = "Also delete the records of terminated instances that no other instance uses (requires --hosted-zone-id, EC2 only lists terminated instances for about an hour)"
+    )]
+    delete_terminated: bool,
+
+    #[arg(
+      long,
+      value_name = "N",
+      help = "How many records to update at the same time",
+      default_value_t = 10
+    )]
+    concurrency: usize,
+  },
+
+  #[command(
+    about = "Update --record-name with the addresses of all of the running tasks of an ECS service, e.g. from a scheduled task, for DNS-based service discovery without a sidecar (uses --ip-address-type and the other options for the record)"
+  )]
+  ReconcileEcs {
+    #[arg(long, help = "The name or ARN of the ECS cluster")]
+    cluster: String,
+
+    #[arg(long, help = "The name of the ECS service")]
+    service: String,
+  },
+
+  #[command(
+    about = "Compare the record in Route 53 with the answers from public resolvers, e.g. to follow the propagation after a change or to verify the name servers after changing them at the registrar (uses --hosted-zone-id and --region)"
+  )]
+  Check {
+    #[arg(long, value_name = "NAME", help = "The record name to check")]
+    record_name: String,
+
+    #[arg(
+      long,
+      value_enum,
+      value_name = "TYPE",
+      help = "The record type to check (optional, all of the record types with that name in Route 53 are checked if omitted)"
+    )]
+    record_type: Option<RrType>,
+
+    #[arg(
+      long,
+      value_name = "IP",
+      help = "Query this resolver instead of the public resolvers from Google, Cloudflare, and Quad9 (can be specified multiple times)"
+    )]
+    resolver: Vec<IpAddr>,
+  },
+
+  #[command(
+    about = "Shift a percentage of the weight from one weighted record set to another, e.g. for a gradual blue/green deployment (uses --hosted-zone-id and --wait)"
+  )]
+  ShiftWeight {
+    #[arg(long, value_name = "NAME", help = "The name of the weighted records")]
+    record_name: String,
+
+    #[arg(
+      long,
+      value_enum,
+      value_name = "TYPE",
+      help = "The record type (optional, only needed if there are weighted record sets of different types with the same set identifiers)"
+    )]
+    record_type: Option<RrType>,
+
+    #[arg(
+      long,
+      value_name = "ID",
+      help = "The set identifier of the record set to take the weight from"
+    )]
+    from: String,
+
+    #[arg(
+      long,
+      value_name = "ID",
+      help = "The set identifier of the record set to give the weight to"
+    )]
+    to: String,
+
+    #[arg(
+      long,
+      value_parser = clap::value_parser!(u8).range(1..=100),
+      help = "The percentage of the combined weight of the two record sets to shift"
+    )]
+    percent: u8,
+  },
+
+  #[command(
+    about = "Create a health check for each of two endpoints, and primary and secondary failover record sets that use them, e.g. for the zone apex (uses --hosted-zone-id, --ttl and --wait)"
+  )]
+  BootstrapFailover {
+    #[arg(long, value_name = "NAME", help = "The name of the failover records")]
+    record_name: String,
+
+    #[arg(
+      long,
+      value_name = "ENDPOINT",
+      help = "The IP address or hostname of the endpoint that receives the traffic while it is healthy"
+    )]
+    primary: String,
+
+    #[arg(
+      long,
+      value_name = "ENDPOINT",
+      help = "The IP address or hostname of the endpoint that receives the traffic when the primary is unhealthy"
+    )]
+    secondary: String,
+
+    #[arg(
+      long,
+      value_enum,
+      help = "How the health checks connect to the endpoints",
+      default_value = "https"
+    )]
+    protocol: types::HealthCheckProtocol,
+
+    #[arg(
+      long,
+      help = "The port of the health checks (optional for HTTP and HTTPS) [default: 80 or 443]"
+    )]
+    port: Option<u16>,
+
+    #[arg(
+      long,
+      help = "The path that the HTTP and HTTPS health checks request",
+      default_value = "/"
+    )]
+    path: String,
+  },
+
+  #[command(
+    about = "Print the DNSSEC signing status of the hosted zone given by --hosted-zone-id, and the DS record for the parent zone"
+  )]
+  DnssecStatus,
+
+  #[command(
+    about = "Replace the binary with the latest release from GitHub, after verifying its signature"
+  )]
+  SelfUpdate {
+    #[arg(
+      long,
+      help = "Only check if there is a newer release (exits with 6 if there is one)"
+    )]
+    check: bool,
+  },
+
+  #[command(
+    about = "Run a server that accepts updates using the DynDNS2 protocol (the other options are used as defaults for the updates)"
+  )]
+  ServeDyndns {
+    #[arg(
+      long,
+      value_name = "ADDRESS",
+      help = "The address to listen on",
+      default_value = "0.0.0.0:8080"
+    )]
+    listen: SocketAddr,
+
+    #[arg(
+      long,
+      env = "ROUTE53_UPDATE_DYNDNS_USERNAME",
+      help = "The username that clients must authenticate with"
+    )]
+    username: String,
+
+    #[arg(
+      long,
+      env = "ROUTE53_UPDATE_DYNDNS_PASSWORD",
+      hide_env_values = true,
+      required_unless_present = "password_file",
+      help = "The password that clients must authenticate with (prefer the environment variable or --password-file, since other users can see the command line)"
+    )]
+    password: Option<String>,
+
+    #[arg(
+      long,
+      value_name = "PATH",
+      conflicts_with = "password",
+      help = "Read the password from this file"
+    )]
+    password_file: Option<PathBuf>,
+
+    #[arg(
+      long,
+      value_name = "NAME",
+      required = true,
+      help = "Record names that clients are allowed to update, wildcards like *.example.com are supported (can be specified multiple times)"
+    )]
+    allow_record_name: Vec<String>,
+  },
+
+  #[command(
+    about = "Handle a CloudFormation custom resource request (e.g. in a Lambda function), creating, updating or deleting the record given by its properties and sending the response to CloudFormation"
+  )]
+  CfnCustomResource {
+    #[arg(
+      long,
+      value_name = "PATH",
+      help = "Read the request from this file instead of stdin"
+    )]
+    event: Option<String>,
+  },
+
+  #[cfg(feature = "lambda")]
+  #[command(
+    about = "Run as a Lambda function that updates the records named by a tag when EC2 instances change state, using EC2 Instance State-change Notification events from EventBridge (uses the same options as reconcile)"
+  )]
+  Lambda {
+    #[arg(
+      long,
+      value_name = "KEY",
+      help = "The tag that has the record name of the instance as its value"
+    )]
+    tag: String,
+
+    #[arg(
+      long,
+      help = "Delete the record when the last instance with the name is terminated (requires --hosted-zone-id)"
+    )]
+    delete_terminated: bool,
+  },
+
+  #[command(about = "Recreate the records that --clear deleted, from the restore file it wrote")]
+  Restore {
+    #[arg(long, value_name = "FILE", help = "The restore file")]
+    from: PathBuf,
+  },
+
+  #[command(
+    about = "Apply updates from an SQS queue, so that hosts can request updates without Route 53 permissions (the other options are used as defaults for the updates)"
+  )]
+  Worker {
+    #[arg(long, value_name = "URL", help = "The URL of the SQS queue")]
+    queue_url: String,
+
+    #[arg(
+      long,
+      value_name = "NAME",
+      required = true,
+      help = "Record names that messages are allowed to update, wildcards like *.example.com are supported (can be specified multiple times)"
+    )]
+    allow_record_name: Vec<String>,
+  },
+
+  #[command(
+    about = "Run a server with a REST API that accepts updates (PUT /records/{name}, the other options are used as defaults for the updates)"
+  )]
+  Serve {
+    #[arg(
+      long,
+      value_name = "ADDRESS",
+      help = "The address to listen on",
+      default_value = "0.0.0.0:8053"
+    )]
+    listen: SocketAddr,
+
+    #[arg(
+      long,
+      env = "ROUTE53_UPDATE_API_TOKEN",
+      hide_env_values = true,
+      required_unless_present = "token_file",
+      help = "The bearer token that clients must authenticate with (prefer the environment variable or --token-file, since other users can see the command line)"
+    )]
+    token: Option<String>,
+
+    #[arg(
+      long,
+      value_name = "PATH",
+      conflicts_with = "token",
+      help = "Read the bearer token from this file"
+    )]
+    token_file: Option<PathBuf>,
+
+    #[arg(
+      long,
+      value_name = "NAME",
+      required = true,
+      help = "Record names that clients are allowed to update, wildcards like *.example.com are supported (can be specified multiple times)"
+    )]
+    allow_record_name: Vec<String>,
+  },
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), std::io::Error> {
+  STARTED_AT.get_or_init(time::Instant::now);
+  env_logger::init();
+
+  let mut args = Arguments::parse();
+  // --alias-s3-website is a shortcut for --alias-target
+  if let Some(region) = args.alias_s3_website.take() {
+    args.alias_target = Some(alias::get_s3_website_endpoint(&region));
+  }
+
+  if args.version_json {
+    println!(
+      "{}",
+      serde_json::to_string_pretty(&types::VersionInfo::new()).unwrap()
+    );
+    return Ok(());
+  }
+
+  logging::init(args.log_target)
+    .unwrap_or_else(|err| panic!("could not set up the log target: {}", err));
+
+  let version = VERSION.unwrap_or("unknown");
+  eprintln!("route53-update version {}", version);
+
+  if let Some(path) = &args.events {
+    events::init(path).unwrap_or_else(|err| panic!("could not open {}: {}", path, err));
+  }
+
+  if let Some(Command::Hook {
+    interface,
+    action,
+    only_interface,
+  }) = &args.command
+  {
+    match hook::get_hook_values(
+      interface.as_deref(),
+      action.as_deref(),
+      only_interface.as_deref(),
+      args.record_type.as_ref(),
+    ) {
+      Some(values) => {
+        eprintln!("Hook provided value: {}", values.join(","));
+        args.value = values;
+      }
+      None => {
+        eprintln!("Nothing to do");
+        std::process::exit(args.unchanged_exit_code);
+      }
+    }
+  }
+
+  // The panic hook exits with the exit code for the failure (see the README). The server and worker modes keep running when a request fails, so it is not installed for them.
+  if !matches!(
+    args.command,
+    Some(Command::Serve { .. } | Command::ServeDyndns { .. } | Command::Worker { .. })
+  ) {
+    let default_panic_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+      default_panic_hook(info);
+      if CATCHES_PANICS.try_with(|_| ()).is_ok() {
+        return;
+      }
+      let message = info
+        .payload()
+        .downcast_ref::<String>()
+        .cloned()
+        .or_else(|| info.payload().downcast_ref::<&str>().map(|s| s.to_string()))
+        .unwrap_or_else(|| "unknown error".to_string());
+      events::emit(
+        "error",
+        json!({
+          "message": message,
+          "exit_code": get_panic_exit_code(),
+        }),
+      );
+      std::process::exit(get_panic_exit_code());
+    }));
+  }
+
+  match args.command.clone() {
+    Some(Command::ServeDyndns {
+      listen,
+      username,
+      password,
+      password_file,
+      allow_record_name,
+    }) => {
+      let options = dyndns::DynDnsOptions {
+        username,
+        password: utils::get_secret(password, password_file.as_deref())
+          .unwrap_or_else(|err| panic!("{}", err)),
+        allowed_record_names: allow_record_name,
+      };
+      dyndns::serve(listen, options, update_handler(args)).await;
+      return Ok(());
+    }
+    Some(Command::Wait { change_id }) => {
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let route53_client = utils::route53_client(&shared_config);
+      utils::wait_for_change(&route53_client, &change_id, args.wait_max_attempts).await;
+      return Ok(());
+    }
+    Some(Command::CidrCollection { name, file }) => {
+      let contents = std::fs::read_to_string(&file).expect("could not read the CIDR file");
+      let locations = cidr::parse_cidr_file(&contents).unwrap_or_else(|err| panic!("{}", err));
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let route53_client = utils::route53_client(&shared_config);
+      let collection_id = cidr::sync_cidr_collection(&route53_client, &name, &locations).await;
+      println!("{}", collection_id);
+      return Ok(());
+    }
+    Some(Command::Gc {
+      pattern,
+      tag,
+      dry_run,
+    }) => {
+      let hosted_zone_id = args
+        .hosted_zone_id
+        .first()
+        .cloned()
+        .expect("--hosted-zone-id is required for gc");
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let route53_client = utils::route53_client(&shared_config);
+      let ec2_client = aws_sdk_ec2::Client::new(&shared_config);
+      let count = gc::gc(
+        &route53_client,
+        &ec2_client,
+        &hosted_zone_id,
+        &pattern,
+        &tag,
+        dry_run,
+      )
+      .await;
+      if dry_run {
+        eprintln!("{} stale records would be deleted", count);
+      } else {
+        eprintln!("Deleted {} stale records", count);
+      }
+      return Ok(());
+    }
+    Some(Command::Fleet {
+      inventory,
+      concurrency,
+    }) => {
+      let contents =
+        std::fs::read_to_string(&inventory).expect("could not read the inventory file");
+      let entries = fleet::parse_inventory(&contents).unwrap_or_else(|err| panic!("{}", err));
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let ec2_client = aws_sdk_ec2::Client::new(&shared_config);
+      let addresses = fleet::get_instance_addresses(&ec2_client, &entries, args.ip_address_type)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+      eprintln!(
+        "Found addresses for {} of {} hosts",
+        addresses.len(),
+        entries.len()
+      );
+
+      let records = entries
+        .into_iter()
+        .map(|entry| {
+          let values = addresses
+            .get(&entry.host)
+            .map(|address| vec![address.clone()]);
+          (entry.host, entry.record_name, values)
+        })
+        .collect();
+      update_many(&args, records, concurrency).await;
+      return Ok(());
+    }
+    Some(Command::Reconcile {
+      tag,
+      delete_terminated,
+      concurrency,
+    }) => {
+      let hosted_zone_id = args.hosted_zone_id.first().cloned();
+      if delete_terminated && hosted_zone_id.is_none() {
+        panic!("--delete-terminated requires --hosted-zone-id");
+      }
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let ec2_client = aws_sdk_ec2::Client::new(&shared_config);
+      let instances =
+        reconcile::list_tagged_instances(&ec2_client, &tag, args.ip_address_type).await;
+      let plan = reconcile::plan(&instances);
+      eprintln!(
+        "Found {} instances with the tag {}, {} records to update",
+        instances.len(),
+        tag,
+        plan.upsert.len()
+      );
+
+      if delete_terminated {
+        let hosted_zone_id = hosted_zone_id.unwrap();
+        let backend = route53_backend(&args, utils::route53_client(&shared_config));
+        for record_name in &plan.delete {
+          delete_terminated_record(&args, &backend, &hosted_zone_id, record_name).await;
+        }
+      }
+
+      let records = plan
+        .upsert
+        .into_iter()
+        .map(|(record_name, addresses)| (tag.clone(), record_name, Some(addresses)))
+        .collect();
+      update_many(&args, records, concurrency).await;
+      return Ok(());
+    }
+    Some(Command::ReconcileEcs { cluster, service }) => {
+      let record_name = args
+        .record_name
+        .clone()
+        .expect("--record-name is required for reconcile-ecs");
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let ecs_client = aws_sdk_ecs::Client::new(&shared_config);
+      let ec2_client = aws_sdk_ec2::Client::new(&shared_config);
+      let addresses = ecs::list_service_addresses(
+        &ecs_client,
+        &ec2_client,
+        &cluster,
+        &service,
+        args.ip_address_type,
+      )
+      .await;
+      // The record is left alone instead of being emptied, e.g. while the service is being redeployed
+      if addresses.is_empty() {
+        panic!(
+          "the service {} does not have any running tasks with an address",
+          service
+        );
+      }
+      eprintln!("Addresses: {}", addresses.join(","));
+      update_many(&args, vec![(service, record_name, Some(addresses))], 1).await;
+      return Ok(());
+    }
+    Some(Command::Check {
+      record_name,
+      record_type,
+      resolver,
+    }) => {
+      let resolvers: Vec<(String, IpAddr)> = if resolver.is_empty() {
+        check::PUBLIC_RESOLVERS
+          .iter()
+          .map(|(name, ip)| (name.to_string(), *ip))
+          .collect()
+      } else {
+        resolver
+          .into_iter()
+          .map(|ip| ("Resolver".to_string(), ip))
+          .collect()
+      };
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let route53_client = utils::route53_client(&shared_config);
+      let consistent = check::check(
+        &route53_client,
+        args.hosted_zone_id.first().map(|id| id.as_str()),
+        &record_name,
+        record_type.as_ref(),
+        &resolvers,
+      )
+      .await;
+      if !consistent {
+        std::process::exit(EXIT_ERROR);
+      }
+      return Ok(());
+    }
+    Some(Command::SelfUpdate { check }) => {
+      let new_version = self_update::self_update(check)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+      if check && new_version.is_some() {
+        std::process::exit(EXIT_UPDATE_AVAILABLE);
+      }
+      return Ok(());
+    }
+    Some(Command::ShiftWeight {
+      record_name,
+      record_type,
+      from,
+      to,
+      percent,
+    }) => {
+      let hosted_zone_id = args
+        .hosted_zone_id
+        .first()
+        .cloned()
+        .expect("--hosted-zone-id is required for shift-weight");
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let route53_client = utils::route53_client(&shared_config);
+      let backend = route53_backend(&args, route53_client.clone());
+      let change_id = weights::shift_weight(
+        &backend,
+        &hosted_zone_id,
+        &record_name,
+        record_type.as_ref(),
+        &from,
+        &to,
+        percent,
+      )
+      .await;
+      if args.wait {
+        utils::wait_for_change(&route53_client, &change_id, args.wait_max_attempts).await;
+      }
+      return Ok(());
+    }
+    Some(Command::BootstrapFailover {
+      record_name,
+      primary,
+      secondary,
+      protocol,
+      port,
+      path,
+    }) => {
+      let hosted_zone_id = args
+        .hosted_zone_id
+        .first()
+        .cloned()
+        .expect("--hosted-zone-id is required for bootstrap-failover");
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let route53_client = utils::route53_client(&shared_config);
+      let backend = route53_backend(&args, route53_client.clone());
+      let change_id = failover::bootstrap_failover(
+        &route53_client,
+        &backend,
+        &hosted_zone_id,
+        &record_name,
+        &primary,
+        &secondary,
+        protocol,
+        port,
+        &path,
+        args.ttl,
+      )
+      .await;
+      if args.wait {
+        utils::wait_for_change(&route53_client, &change_id, args.wait_max_attempts).await;
+      }
+      return Ok(());
+    }
+    Some(Command::DnssecStatus) => {
+      let hosted_zone_id = args
+        .hosted_zone_id
+        .first()
+        .cloned()
+        .expect("--hosted-zone-id is required for dnssec-status");
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let route53_client = utils::route53_client(&shared_config);
+      let dnssec = dnssec::get_dnssec(&route53_client, &hosted_zone_id)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+      dnssec::print_dnssec_status(&dnssec);
+      for problem in dnssec::get_dnssec_problems(&dnssec) {
+        eprintln!("Warning: {}", problem);
+      }
+      return Ok(());
+    }
+    Some(Command::Delegate {
+      child_zone_id,
+      parent_zone_id,
+      parent_role_arn,
+    }) => {
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let route53_client = utils::route53_client(&shared_config);
+      let parent_route53_client = match parent_role_arn {
+        Some(role_arn) => {
+          utils::route53_client(&utils::assume_role(&shared_config, &role_arn).await)
+        }
+        None => route53_client.clone(),
+      };
+      let change_id = delegate::delegate(
+        &route53_client,
+        &parent_route53_client,
+        &child_zone_id,
+        parent_zone_id.as_deref(),
+        args.ttl.unwrap_or(delegate::DEFAULT_NS_TTL),
+      )
+      .await;
+      if args.wait {
+        utils::wait_for_change(&parent_route53_client, &change_id, args.wait_max_attempts).await;
+      }
+      return Ok(());
+    }
+    Some(Command::CfnCustomResource { event }) => {
+      let contents = match &event {
+        Some(path) => std::fs::read_to_string(path).expect("could not read the request"),
+        None => std::io::read_to_string(std::io::stdin()).expect("could not read the request"),
+      };
+      let request: cfn::CustomResourceRequest =
+        serde_json::from_str(&contents).expect("could not parse the custom resource request");
+      eprintln!(
+        "{:?} request for {}",
+        request.request_type, request.logical_resource_id
+      );
+      let mut args = args.clone();
+      args.command = None;
+      let properties = request.resource_properties.clone();
+      let request_type = request.request_type;
+      let physical_resource_id = request.physical_resource_id.clone();
+      // Each request runs in its own task so that CloudFormation gets a response even if it fails
+      let result = catch_panic(async move {
+        if let Some(hosted_zone_id) = &properties.hosted_zone_id {
+          args.hosted_zone_id = vec![utils::normalize_hosted_zone_id(hosted_zone_id)];
+        }
+        if request_type == cfn::RequestType::Delete {
+          let physical_resource_id = physical_resource_id.unwrap_or_default();
+          let Some((hosted_zone_id, record_name, record_type)) =
+            cfn::parse_physical_resource_id(&physical_resource_id)
+          else {
+            eprintln!("Nothing to delete for {}", physical_resource_id);
+            return (physical_resource_id, HashMap::new());
+          };
+          let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+          let backend = route53_backend(&args, utils::route53_client(&shared_config));
+          let record_sets: Vec<_> = backend
+            .get(hosted_zone_id, record_name)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err))
+            .into_iter()
+            .filter(|r| {
+              r.r#type().as_str() == record_type
+                && r.set_identifier() == args.set_identifier.as_deref()
+            })
+            .collect();
+          let mut data = HashMap::new();
+          if !record_sets.is_empty() {
+            let change_id = backend
+              .delete(
+                hosted_zone_id,
+                record_sets,
+                Some("Deleted by CloudFormation".to_string()),
+              )
+              .await
+              .unwrap_or_else(|err| panic!("{}", err));
+            if args.wait {
+              backend
+                .wait(&change_id)
+                .await
+                .unwrap_or_else(|err| panic!("{}", err));
+            }
+            data.insert("ChangeId".to_string(), change_id);
+          }
+          return (physical_resource_id, data);
+        }
+
+        args.record_name = Some(properties.record_name);
+        args.value = properties.value.into_vec();
+        if let Some(record_type) = &properties.record_type {
+          args.record_type = Some(RrType::from(record_type.as_str()));
+        }
+        if let Some(ttl) = &properties.ttl {
+          args.ttl = Some(utils::parse_ttl(ttl).unwrap_or_else(|err| panic!("{}", err)));
+        }
+        validate(&args);
+        let result = update(args, None).await;
+        let record_set = &result.record_set;
+        let mut data = HashMap::from([
+          ("RecordName".to_string(), record_set.name().to_string()),
+          (
+            "Value".to_string(),
+            utils::get_record_values(record_set).join(","),
+          ),
+        ]);
+        if let Some(change_id) = &result.change_id {
+          data.insert("ChangeId".to_string(), change_id.clone());
+        }
+        let physical_resource_id = cfn::format_physical_resource_id(
+          result.hosted_zone_id.as_deref().unwrap_or_default(),
+          record_set.name(),
+          record_set.r#type().as_str(),
+        );
+        (physical_resource_id, data)
+      })
+      .await;
+
+      let failed = result.is_err();
+      let response = cfn::build_response(&request, result);
+      cfn::send_response(&request.response_url, &response)
+        .await
+        .unwrap_or_else(|err| panic!("could not send the response to CloudFormation: {}", err));
+      if failed {
+        std::process::exit(EXIT_ERROR);
+      }
+      return Ok(());
+    }
+    #[cfg(feature = "lambda")]
+    Some(Command::Lambda {
+      tag,
+      delete_terminated,
+    }) => {
+      if delete_terminated && args.hosted_zone_id.is_empty() {
+        panic!("--delete-terminated requires --hosted-zone-id");
+      }
+      let handler = lambda_runtime::service_fn(
+        move |event: lambda_runtime::LambdaEvent<serde_json::Value>| {
+          let args = args.clone();
+          let tag = tag.clone();
+          async move {
+            // Each event runs in its own task so that a panic only fails that invocation
+            catch_panic(handle_instance_event(
+              args,
+              tag,
+              delete_terminated,
+              event.payload,
+            ))
+            .await
+            .map_err(lambda_runtime::Error::from)
+          }
+        },
+      );
+      lambda_runtime::run(handler)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+      return Ok(());
+    }
+    Some(Command::Restore { from }) => {
+      let contents = std::fs::read_to_string(&from)
+        .unwrap_or_else(|err| panic!("could not read {}: {}", from.display(), err));
+      let snapshot: snapshot::Snapshot = serde_json::from_str(&contents)
+        .unwrap_or_else(|err| panic!("could not parse {}: {}", from.display(), err));
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let route53_client = utils::route53_client(&shared_config);
+      let change_id = snapshot::restore(&route53_client, &snapshot).await;
+      if args.wait {
+        utils::wait_for_change(&route53_client, &change_id, args.wait_max_attempts).await;
+      }
+      return Ok(());
+    }
+    Some(Command::Worker {
+      queue_url,
+      allow_record_name,
+    }) => {
+      let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+      let sqs_client = aws_sdk_sqs::Client::new(&shared_config);
+      let ec2_client = aws_sdk_ec2::Client::new(&shared_config);
+      let options = worker::WorkerOptions {
+        queue_url,
+        allowed_record_names: allow_record_name,
+        ip_address_type: args.ip_address_type,
+      };
+      worker::run(&sqs_client, &ec2_client, options, update_handler(args)).await;
+      return Ok(());
+    }
+    Some(Command::Serve {
+      listen,
+      token,
+      token_file,
+      allow_record_name,
+    }) => {
+      let options = api::ApiOptions {
+        token: utils::get_secret(token, token_file.as_deref())
+          .unwrap_or_else(|err| panic!("{}", err)),
+        allowed_record_names: allow_record_name,
+      };
+      api::serve(listen, options, update_handler(args)).await;
+      return Ok(());
+    }
+    _ => {}
+  }
+
+  set_panic_exit_code(EXIT_INVALID_ARGUMENTS);
+  let mut runs = expand_runs(args);
+  for args in &runs {
+    validate(args);
+  }
+  set_panic_exit_code(EXIT_ERROR);
+
+  // The lock is held until the process exits
+  let _lock = runs[0]
+    .lock_file
+    .as_ref()
+    .map(|path| match lock::acquire(path) {
+      Ok(lock) => lock,
+      Err(err @ lock::LockError::Held(_)) => {
+        set_panic_exit_code(EXIT_LOCKED);
+        panic!("another run is in progress, {} ({})", err, path);
+      }
+      Err(err) => panic!("{}", err),
+    });
+
+  use_private_values(&mut runs).await;
+
+  if runs.iter().any(|args| args.daemon || args.cron.is_some()) {
+    #[cfg(windows)]
+    if runs[0].windows_service {
+      std::thread::spawn(|| {
+        if let Err(err) = service::start() {
+          eprintln!("Error: {}", err);
+          std::process::exit(EXIT_ERROR);
+        }
+      });
+    }
+    let tasks: Vec<_> = runs
+      .into_iter()
+      .map(|args| tokio::spawn(run_daemon(args)))
+      .collect();
+    for task in tasks {
+      if let Err(err) = task.await {
+        std::panic::resume_unwind(err.into_panic());
+      }
+    }
+    #[cfg(windows)]
+    service::set_stopped();
+  } else {
+    let mut results = Vec::new();
+    for args in &runs {
+      let result = update(args.clone(), None).await;
+      if args.heartbeat {
+        update_heartbeat(args, &result).await;
+      }
+      results.push(result);
+    }
+    set_panic_exit_code(EXIT_ERROR);
+    for result in &results {
+      print_change_summary(result);
+    }
+    write_result_files(&runs[0], &results);
+    if runs[0].verbose {
+      eprintln!("Route 53 API calls: {}", api_calls::get_summary());
+    }
+    if !results.iter().any(|result| result.is_changed()) {
+      std::process::exit(runs[0].unchanged_exit_code);
+    }
+  }
+
+  Ok(())
+}
+
+fn print_change_summary(result: &types::UpdateResult) {
+  if result.is_changed() {
+    eprintln!(
+      "Changed {}",
+      utils::format_transition(
+        result.record_set.name(),
+        result.record_set.r#type(),
+        &result.previous_value,
+        &utils::get_record_values(&result.record_set),
+      )
+    );
+  }
+}
+
+// The files contain all of the records that were updated in this run, since some options result in multiple records being updated.
+fn write_result_files(args: &Arguments, results: &[types::UpdateResult]) {
+  if let Some(path) = &args.change_id_file {
+    let contents: String = results
+      .iter()
+      .filter_map(|result| result.change_id.as_ref())
+      .map(|change_id| format!("{}\n", change_id))
+      .collect();
+    std::fs::write(path, contents).expect("could not write the --change-id-file");
+  }
+  if let Some(path) = &args.result_file {
+    let results: Vec<types::UpdateResultJson> = results.iter().map(Into::into).collect();
+    let contents = serde_json::to_string_pretty(&results).expect("error serializing the result");
+    std::fs::write(path, contents + "\n").expect("could not write the --result-file");
+  }
+}
+
+// Some options result in multiple records being updated, in which case the arguments are split into one set of arguments per record.
+fn expand_runs(args: Arguments) -> Vec<Arguments> {
+  if args.hosted_zone_id.len() > 1 {
+    return args
+      .hosted_zone_id
+      .iter()
+      .flat_map(|hosted_zone_id| {
+        let mut args = args.clone();
+        args.hosted_zone_id = vec![hosted_zone_id.clone()];
+        expand_runs(args)
+      })
+      .collect();
+  }
+  if args.split_mixed && args.record_type.is_none() {
+    if let Some((ipv4, ipv6)) = utils::split_mixed_values(&args.value) {
+      return [(ipv4, RrType::A), (ipv6, RrType::Aaaa)]
+        .into_iter()
+        .map(|(value, record_type)| {
+          let mut args = args.clone();
+          args.value = value;
+          args.record_type = Some(record_type);
+          args
+        })
+        .collect();
+    }
+  }
+  if args.value_from_url_v4.is_none() && args.value_from_url_v6.is_none() {
+    return vec![args];
+  }
+  if args.record_type.is_some() {
+    panic!("--record-type can not be used with --value-from-url-v4 or --value-from-url-v6.");
+  }
+  if args.value_from_url.is_some() {
+    panic!("--value-from-url can not be used with --value-from-url-v4 or --value-from-url-v6.");
+  }
+
+  [
+    (args.value_from_url_v4.clone(), RrType::A),
+    (args.value_from_url_v6.clone(), RrType::Aaaa),
+  ]
+  .into_iter()
+  .filter_map(|(url, record_type)| {
+    let url = url?;
+    let mut args = args.clone();
+    args.value_from_url_v4 = None;
+    args.value_from_url_v6 = None;
+    args.value_from_url = Some(url);
+    args.record_type = Some(record_type);
+    Some(args)
+  })
+  .collect()
+}
+
+// The runs for private hosted zones use --private-value instead of --value.
+async fn use_private_values(runs: &mut [Arguments]) {
+  for args in runs.iter_mut() {
+    if args.private_value.is_empty() || args.hosted_zone_id.is_empty() {
+      continue;
+    }
+    let shared_config = utils::load_aws_config(&aws_options(args), None).await;
+    let route53_client = utils::route53_client(&shared_config);
+    let response = route53_client
+      .get_hosted_zone()
+      .id(&args.hosted_zone_id[0])
+      .send()
+      .await
+      .expect("could not get the hosted zone to check if it is private");
+    if response
+      .hosted_zone()
+      .and_then(|zone| zone.config())
+      .is_some_and(|config| config.private_zone)
+    {
+      eprintln!(
+        "Using --private-value for the private hosted zone {}",
+        args.hosted_zone_id[0]
+      );
+      args.value = args.private_value.clone();
+    }
+  }
+}
+
+fn validate(args: &Arguments) {
+  #[cfg(windows)]
+  if args.windows_service && !args.daemon && args.cron.is_none() {
+    panic!("--windows-service only works with --daemon or --cron");
+  }
+
+  // --alias-s3-website has already been turned into --alias-target
+  let alias = args.alias_target.is_some() || args.alias_api_gateway.is_some();
+  // --value-from auto uses --value-from-url as a fallback, so they can be combined
+  let value_sources = [
+    !args.value.is_empty(),
+    args.value_from.is_some(),
+    args.value_from_url.is_some() && args.value_from != Some(types::ValueFromSource::Auto),
+    args.ipv6_from_prefix.is_some(),
+    args.value_template.is_some(),
+    args.srv_from_ecs.is_some(),
+    args.value_from_interface.is_some(),
+    args.value_from_command.is_some(),
+    args.mirror.is_some(),
+    args.value_from_ssm.is_some(),
+    alias,
+    !args.spf_include.is_empty(),
+    args.dkim_public_key_file.is_some(),
+    args.dmarc_policy.is_some(),
+  ]
+  .into_iter()
+  .filter(|&s| s)
+  .count();
+
+  if args.record_name.is_none() && !args.skip_route53 {
+    panic!("--record-name is required.");
+  } else if args.skip_route53 && args.cloudmap_service_id.is_none() {
+    panic!("--skip-route53 only works with --cloudmap-service-id");
+  } else if args.cloudmap_instance_id.is_some() && args.cloudmap_service_id.is_none() {
+    panic!("--cloudmap-instance-id only works with --cloudmap-service-id");
+  } else if args.skip_route53 && args.heartbeat {
+    panic!("--heartbeat does not work with --skip-route53");
+  } else if !args.private_value.is_empty() && args.value.is_empty() {
+    panic!("--private-value only works with --value");
+  } else if !args.private_value.is_empty() && args.hosted_zone_id.is_empty() {
+    panic!("--private-value only works with --hosted-zone-id");
+  } else if args.keep_previous_as.is_some()
+    && args
+      .keep_previous_as
+      .as_ref()
+      .map(|name| name.trim_end_matches('.').to_lowercase())
+      == args
+        .record_name
+        .as_ref()
+        .map(|name| name.trim_end_matches('.').to_lowercase())
+  {
+    panic!("--keep-previous-as must be a different record name than --record-name.");
+  } else if (args.value_from == Some(types::ValueFromSource::Lightsail))
+    != args.lightsail_instance_name.is_some()
+  {
+    panic!("--value-from lightsail and --lightsail-instance-name must be used together.");
+  } else if args.value_from == Some(types::ValueFromSource::Upnp)
+    && args.record_type.as_ref().is_some_and(|t| t != &RrType::A)
+  {
+    panic!("--value-from upnp only works with A records");
+  } else if args.resolve_value && args.value.is_empty() {
+    panic!("--resolve-value requires --value");
+  } else if args.resolve_value
+    && args
+      .record_type
+      .as_ref()
+      .is_some_and(|t| t != &RrType::A && t != &RrType::Aaaa)
+  {
+    panic!("--resolve-value only works with A and AAAA records");
+  } else if args.mirror.is_some() && !args.daemon {
+    panic!("--mirror only works with --daemon");
+  } else if args.mirror.is_some()
+    && args
+      .record_type
+      .as_ref()
+      .is_some_and(|t| t != &RrType::A && t != &RrType::Aaaa)
+  {
+    panic!("--mirror only works with A and AAAA records");
+  } else if args.alias_target_hosted_zone_id.is_some() && args.alias_target.is_none() {
+    panic!("--alias-target-hosted-zone-id only works with --alias-target");
+  } else if args.evaluate_target_health && !alias {
+    panic!("--evaluate-target-health only works with --alias-target");
+  } else if alias && args.ttl.is_some() {
+    panic!("--ttl does not work with --alias-target (alias records use the TTL of the target)");
+  } else if alias
+    && args
+      .record_type
+      .as_ref()
+      .is_some_and(|t| t != &RrType::A && t != &RrType::Aaaa)
+  {
+    panic!("--alias-target only works with A and AAAA records");
+  } else if !args.spf_include.is_empty()
+    && args.record_type.as_ref().is_some_and(|t| t != &RrType::Txt)
+  {
+    panic!("--spf-include only works with TXT records");
+  } else if args.dkim_selector.is_some() != args.dkim_public_key_file.is_some() {
+    panic!("--dkim-selector and --dkim-public-key-file must be used together.");
+  } else if args.dkim_selector.is_some()
+    && args.record_type.as_ref().is_some_and(|t| t != &RrType::Txt)
+  {
+    panic!("--dkim-selector only works with TXT records");
+  } else if args.dmarc_policy.is_none()
+    && (args.dmarc_subdomain_policy.is_some()
+      || args.dmarc_percent.is_some()
+      || !args.dmarc_rua.is_empty()
+      || !args.dmarc_ruf.is_empty())
+  {
+    panic!("--dmarc-subdomain-policy, --dmarc-percent, --dmarc-rua and --dmarc-ruf only work with --dmarc-policy");
+  } else if args.dmarc_policy.is_some()
+    && args.record_type.as_ref().is_some_and(|t| t != &RrType::Txt)
+  {
+    panic!("--dmarc-policy only works with TXT records");
+  } else if !args.value_filter.is_empty()
+    && args
+      .record_type
+      .as_ref()
+      .is_some_and(|t| !matches!(t, RrType::A | RrType::Aaaa))
+  {
+    panic!("--value-filter only works with A and AAAA records");
+  } else if args.relative && args.hosted_zone_name.is_none() {
+    panic!("--relative requires --hosted-zone-name");
+  } else if !args.hosted_zone_id.is_empty() && args.hosted_zone_name.is_some() {
+    panic!("can only use one of --hosted-zone-id or --hosted-zone-name.");
+  } else if value_sources > 1 {
+    panic!("can only use one of --value, --value-from, --value-from-url, --ipv6-from-prefix, --value-from-ssm, --value-from-interface, --value-from-command, --value-template, --srv-from-ecs, --alias-target, --spf-include, --dkim-public-key-file, --dmarc-policy, or --mirror.");
+  } else if value_sources == 0 {
+    panic!("value must be supplied with either --value, --value-from, --value-from-url, --ipv6-from-prefix, --value-from-ssm, --value-from-interface, --value-from-command, --value-template, --srv-from-ecs, --alias-target, --spf-include, --dkim-public-key-file, --dmarc-policy, or --mirror.");
+  } else if args.ipv6_from_prefix.is_some() != args.ipv6_suffix.is_some() {
+    panic!("--ipv6-from-prefix and --ipv6-suffix must be used together.");
+  } else if args.ipv6_from_prefix.is_some()
+    && args.record_type.is_some()
+    && args.record_type != Some(RrType::Aaaa)
+  {
+    panic!("--ipv6-from-prefix only works with AAAA");
+  } else if args.srv_from_ecs.is_some()
+    && args.record_type.is_some()
+    && args.record_type != Some(RrType::Srv)
+  {
+    panic!("--srv-from-ecs only works with SRV");
+  } else if args.record_type.is_some() && args.record_type == Some(RrType::Txt) && args.clear {
+    panic!("--clear only works with A, AAAA, or CNAME");
+  } else if args.min_ttl.is_some() && args.max_ttl.is_some() && args.min_ttl > args.max_ttl {
+    panic!("--min-ttl can not be greater than --max-ttl");
+  } else if args.deregister_on_exit && !args.daemon && args.cron.is_none() {
+    panic!("--deregister-on-exit only works with --daemon or --cron");
+  } else if args.min_update_interval.is_some() && !args.daemon && args.cron.is_none() {
+    panic!("--min-update-interval only works with --daemon or --cron");
+  } else if args.handle_spot_interruption && !args.daemon && args.cron.is_none() {
+    panic!("--handle-spot-interruption only works with --daemon or --cron");
+  } else if args.spot_interruption_action == types::SpotInterruptionAction::ZeroWeight
+    && args.weight.is_none()
+  {
+    panic!("--spot-interruption-action zero-weight only works with --weight");
+  } else if (args.change_id_file.is_some() || args.result_file.is_some())
+    && (args.daemon || args.cron.is_some())
+  {
+    panic!("--change-id-file and --result-file do not work with --daemon or --cron");
+  } else if let Some(schedule) = args
+    .cron
+    .as_ref()
+    .filter(|schedule| schedule.next_run_from_now(time::Duration::ZERO).is_none())
+  {
+    panic!("the cron schedule {} never runs", schedule);
+  } else if args.heartbeat_record_name.is_some() && !args.heartbeat {
+    panic!("--heartbeat-record-name only works with --heartbeat");
+  } else if let Err(err) = utils::check_value_count(args.record_type.as_ref(), args.value.len()) {
+    panic!("{}", err);
+  } else if args.cidr_collection_id.is_some() != args.cidr_location.is_some() {
+    panic!("--cidr-collection-id and --cidr-location must be used together.");
+  } else if args.geoproximity_region.is_some() && args.geoproximity_coordinates.is_some() {
+    panic!("can only use one of --geoproximity-region or --geoproximity-coordinates.");
+  } else if args.bias.is_some()
+    && args.geoproximity_region.is_none()
+    && args.geoproximity_coordinates.is_none()
+  {
+    panic!("--bias only works with --geoproximity-region or --geoproximity-coordinates");
+  } else if args.cidr_collection_id.is_some()
+    && (args.geoproximity_region.is_some() || args.geoproximity_coordinates.is_some())
+  {
+    panic!("can only use one routing policy.");
+  } else if args.weight.is_some()
+    && (args.cidr_collection_id.is_some()
+      || args.geoproximity_region.is_some()
+      || args.geoproximity_coordinates.is_some())
+  {
+    panic!("can only use one routing policy.");
+  } else if (args.cidr_collection_id.is_some()
+    || args.geoproximity_region.is_some()
+    || args.geoproximity_coordinates.is_some()
+    || args.weight.is_some())
+    && args.set_identifier.is_none()
+  {
+    panic!("--set-identifier is required when using a routing policy");
+  }
+}
+
+// Used by reconcile and the Lambda handler. Deletes the simple record set of the type given by --record-type (A by default).
+async fn delete_terminated_record(
+  args: &Arguments,
+  backend: &backend::Route53Backend,
+  hosted_zone_id: &str,
+  record_name: &str,
+) {
+  let record_type = args.record_type.clone().unwrap_or(RrType::A);
+  let record_sets: Vec<_> = backend
+    .get(hosted_zone_id, record_name)
+    .await
+    .unwrap_or_else(|err| panic!("{}", err))
+    .into_iter()
+    .filter(|r| r.r#type() == &record_type && r.set_identifier().is_none())
+    .collect();
+  if record_sets.is_empty() {
+    return;
+  }
+  eprintln!(
+    "Deleting {} {}, the instance was terminated",
+    record_type.as_str(),
+    record_name
+  );
+  let change_id = backend
+    .delete(
+      hosted_zone_id,
+      record_sets,
+      Some("Delete the record of a terminated instance".to_string()),
+    )
+    .await
+    .unwrap_or_else(|err| panic!("{}", err));
+  if args.wait {
+    backend
+      .wait(&change_id)
+      .await
+      .unwrap_or_else(|err| panic!("{}", err));
+  }
+}
+
+// Used by the Lambda handler. The record of the instance is reconciled with all of the instances that have the same name, so that a record shared by several instances keeps the addresses of the others.
+#[cfg(feature = "lambda")]
+async fn handle_instance_event(
+  args: Arguments,
+  tag: String,
+  delete_terminated: bool,
+  event: serde_json::Value,
+) -> serde_json::Value {
+  let Some(change) = lambda::parse_instance_state_change(&event) else {
+    eprintln!("Ignoring event: {}", event);
+    return json!({ "ignored": true });
+  };
+  eprintln!("Instance {} is {}", change.instance_id, change.state);
+  let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+  let ec2_client = aws_sdk_ec2::Client::new(&shared_config);
+  let instances = reconcile::list_tagged_instances(&ec2_client, &tag, args.ip_address_type).await;
+  let Some(record_name) = instances
+    .iter()
+    .find(|instance| instance.instance_id == change.instance_id)
+    .map(|instance| reconcile::normalize_record_name(&instance.record_name))
+  else {
+    eprintln!("The instance does not have the tag {}", tag);
+    return json!({ "ignored": true });
+  };
+  let instances: Vec<_> = instances
+    .into_iter()
+    .filter(|instance| reconcile::normalize_record_name(&instance.record_name) == record_name)
+    .collect();
+  let plan = reconcile::plan(&instances);
+  if let Some(addresses) = plan.upsert.get(&record_name) {
+    update_many(
+      &args,
+      vec![(
+        change.instance_id,
+        record_name.clone(),
+        Some(addresses.clone()),
+      )],
+      1,
+    )
+    .await;
+  } else if delete_terminated && plan.delete.contains(&record_name) {
+    let backend = route53_backend(&args, utils::route53_client(&shared_config));
+    delete_terminated_record(&args, &backend, &args.hosted_zone_id[0], &record_name).await;
+  }
+  json!({ "record_name": record_name })
+}
+
+// Used by fleet and reconcile. The records are given as (label, record name, values), where the label identifies the source of the record in the output and the values are None if they could not be found.
+// Up to concurrency records are updated at the same time, each in its own task so that a panic only fails that record.
+async fn update_many(
+  args: &Arguments,
+  records: Vec<(String, String, Option<Vec<String>>)>,
+  concurrency: usize,
+) {
+  let mut failed = 0;
+  for chunk in records.chunks(concurrency.max(1)) {
+    let tasks: Vec<_> = chunk
+      .iter()
+      .map(|(label, record_name, values)| {
+        let mut args = args.clone();
+        let label = label.clone();
+        let values = values.clone();
+        args.command = None;
+        args.record_name = Some(record_name.clone());
+        catch_panic(async move {
+          let Some(values) = values else {
+            panic!("could not find the address of {}", label);
+          };
+          args.value = values;
+          validate(&args);
+          update(args, None).await
+        })
+      })
+      .collect();
+    for ((label, record_name, _), task) in chunk.iter().zip(tasks) {
+      match task.await {
+        Ok(result) => eprintln!(
+          "{} {}: {}",
+          label,
+          record_name,
+          if result.is_changed() {
+            "updated"
+          } else {
+            "unchanged"
+          }
+        ),
+        Err(err) => {
+          eprintln!("{} {}: failed: {}", label, record_name, err);
+          failed += 1;
+        }
+      }
+    }
+  }
+  if failed > 0 {
+    panic!(
+      "{} of {} records could not be updated",
+      failed,
+      records.len()
+    );
+  }
+}
+
+// Used by the server modes. Each update runs in its own task so that a panic only fails that request.
+fn update_handler(args: Arguments) -> types::UpdateHandler {
+  let previous: Arc<Mutex<HashMap<String, types::UpdateResult>>> = Default::default();
+  Arc::new(move |request: types::UpdateRequest| {
+    let mut args = args.clone();
+    let previous = previous.clone();
+    Box::pin(async move {
+      args.command = None;
+      // The clients send the full record name
+      args.relative = false;
+      args.record_name = Some(request.record_name.clone());
+      args.value = Vec::new();
+      args.value_from = None;
+      args.value_from_url = None;
+      args.value_from_url_v4 = None;
+      args.value_from_url_v6 = None;
+      args.ipv6_from_prefix = None;
+      args.ipv6_suffix = None;
+      args.value_template = None;
+      args.srv_from_ecs = None;
+      args.value_from_interface = None;
+      args.value_from_command = None;
+      args.value_from_ssm = None;
+      args.mirror = None;
+      args.spf_include = Vec::new();
+      // --dkim-selector and --dmarc-policy would also change the requested record name
+      args.dkim_selector = None;
+      args.dkim_public_key_file = None;
+      args.dmarc_policy = None;
+      args.dmarc_subdomain_policy = None;
+      args.dmarc_percent = None;
+      args.dmarc_rua = Vec::new();
+      args.dmarc_ruf = Vec::new();
+      args.alias_target = request.alias_target;
+      args.alias_target_hosted_zone_id = None;
+      args.alias_api_gateway = None;
+      args.record_type = request.record_type.or(args.record_type);
+      args.ttl = request.ttl.or(args.ttl);
+      match request.value_source {
+        Some(types::ValueSource::Values(values)) => args.value = values,
+        Some(types::ValueSource::Imds) => {
+          args.value_from = Some(types::ValueFromSource::Ec2Metadata)
+        }
+        Some(types::ValueSource::EcsMetadata) => {
+          args.value_from = Some(types::ValueFromSource::EcsMetadata)
+        }
+        Some(types::ValueSource::Url(url)) => args.value_from_url = Some(url),
+        Some(types::ValueSource::Auto) => args.value_from = Some(types::ValueFromSource::Auto),
+        // The arguments can only describe the built-in sources
+        Some(types::ValueSource::Custom(source)) => args.value = source.resolve().await?,
+        None => {}
+      }
+
+      let key = request.record_name.trim_end_matches('.').to_lowercase();
+      let cached = previous.lock().unwrap().get(&key).cloned();
+      let result = catch_panic(async move {
+        validate(&args);
+        update(args, cached.as_ref()).await
+      })
+      .await?;
+      previous.lock().unwrap().insert(key, result.clone());
+      Ok(result)
+    })
+  })
+}
+
+async fn run_daemon(mut args: Arguments) {
+  let clients = metadata_clients(&args);
+  let mut shutdown_signal = utils::ShutdownSignal::new();
+  let mut next_mirror_poll = None;
+  if args.mirror.is_some() {
+    next_mirror_poll = Some(
+      resolve_mirror(&mut args)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err)),
+    );
+  }
+  let mut previous = update_with_heartbeat(&args, None).await;
+  let min_update_interval = args.min_update_interval.map(time::Duration::from_secs);
+  let mut last_change = previous.is_changed().then(time::Instant::now);
+  // Set when a check is skipped by --min-update-interval, so that the latest value is applied as soon as it is allowed
+  let mut deferred_update: Option<tokio::time::Instant> = None;
+
+  let mut update_interval = tokio::time::interval(time::Duration::from_secs(args.interval));
+  // The first tick completes immediately
+  update_interval.tick().await;
+  let cron_jitter = cron::get_jitter(&utils::get_hostname(), args.cron_jitter);
+  // --cron and --mirror decide when to run instead of --interval
+  let mut next_run = args
+    .cron
+    .as_ref()
+    .map(|schedule| schedule_cron_run(schedule, cron_jitter))
+    .or(next_mirror_poll);
+  // Spot interruption notices are given two minutes in advance, so they are checked more often than the value
+  let mut spot_interval = tokio::time::interval(time::Duration::from_secs(5));
+
+  loop {
+    tokio::select! {
+      _ = async {
+        match next_run {
+          Some(next_run) => tokio::time::sleep_until(next_run).await,
+          None => {
+            update_interval.tick().await;
+          }
+        }
+      } => {
+        if let Some(schedule) = &args.cron {
+          eprintln!("Running on the cron schedule {}", schedule);
+          next_run = Some(schedule_cron_run(schedule, cron_jitter));
+        }
+        if args.mirror.is_some() {
+          match resolve_mirror(&mut args).await {
+            Ok(next_poll) => next_run = Some(next_poll),
+            Err(err) => {
+              // Keep the record as it is until the name can be resolved again
+              eprintln!("Error: {}", err);
+              next_run = Some(tokio::time::Instant::now() + mirror::MIN_POLL_INTERVAL);
+              continue;
+            }
+          }
+        }
+        if args.deregister_on_exit {
+          if let Some(ecs_task_metadata) = utils::get_ecs_task_metadata(&clients).await {
+            if ecs_task_metadata.desired_status.as_deref() == Some("STOPPED") {
+              eprintln!("The ECS task is stopping");
+              deregister(&previous).await;
+              return;
+            }
+          }
+        }
+
+        let remaining = last_change
+          .zip(min_update_interval)
+          .and_then(|(last_change, min_update_interval)| {
+            min_update_interval.checked_sub(last_change.elapsed())
+          });
+        if let Some(remaining) = remaining {
+          eprintln!(
+            "The record was changed less than {}s ago, deferring the check for {}s (--min-update-interval)",
+            args.min_update_interval.unwrap(),
+            remaining.as_secs()
+          );
+          deferred_update = Some(tokio::time::Instant::now() + remaining);
+        } else {
+          deferred_update = None;
+          if let Some(result) = try_update_with_heartbeat(&args, &previous).await {
+            previous = result;
+            if previous.is_changed() {
+              last_change = Some(time::Instant::now());
+            }
+          }
+        }
+      }
+      _ = tokio::time::sleep_until(deferred_update.unwrap_or_else(tokio::time::Instant::now)), if deferred_update.is_some() => {
+        eprintln!("Running the check that was deferred by --min-update-interval");
+        deferred_update = None;
+        if let Some(result) = try_update_with_heartbeat(&args, &previous).await {
+          previous = result;
+          if previous.is_changed() {
+            last_change = Some(time::Instant::now());
+          }
+        }
+      }
+      _ = spot_interval.tick(), if args.handle_spot_interruption => {
+        if let Some(notice) = utils::get_spot_interruption_notice(&clients).await {
+          eprintln!("Received spot interruption notice: {}", notice);
+          match args.spot_interruption_action {
+            types::SpotInterruptionAction::Delete => deregister(&previous).await,
+            types::SpotInterruptionAction::ZeroWeight => drain(&previous).await,
+          }
+          return;
+        }
+      }
+      _ = shutdown_signal.recv() => {
+        eprintln!("Received shutdown signal");
+        if args.deregister_on_exit {
+          deregister(&previous).await;
+        }
+        return;
+      }
+    }
+  }
+}
+
+async fn update_with_heartbeat(
+  args: &Arguments,
+  previous: Option<&types::UpdateResult>,
+) -> types::UpdateResult {
+  let result = update(args.clone(), previous).await;
+  print_change_summary(&result);
+  if args.heartbeat {
+    update_heartbeat(args, &result).await;
+  }
+  result
+}
+
+// Used for the periodic updates of the daemon, which keeps running if an update fails (e.g. the metadata service or Route 53 is briefly unavailable).
+// Returns None if the update failed, in which case it is tried again at the next check.
+async fn try_update_with_heartbeat(
+  args: &Arguments,
+  previous: &types::UpdateResult,
+) -> Option<types::UpdateResult> {
+  let args = args.clone();
+  let previous = previous.clone();
+  match catch_panic(async move { update_with_heartbeat(&args, Some(&previous)).await }).await {
+    Ok(result) => Some(result),
+    Err(err) => {
+      eprintln!(
+        "Error: the update failed, trying again at the next check: {}",
+        err
+      );
+      None
+    }
+  }
+}
+
+// Warns when the address from --value-from-url is not the address of the host because it is behind carrier-grade NAT, since inbound connections to the record will not reach the host.
+async fn check_cgnat(args: &Arguments, record_name: &str) {
+  let public_address = &args.value[0];
+  if public_address.parse::<std::net::Ipv4Addr>().is_err() {
+    return;
+  }
+  // The check is skipped where the interfaces can't be listed
+  let Ok(interface_addresses) = sources::get_interface_ipv4_addresses().await else {
+    return;
+  };
+  let default_route_interfaces = sources::get_default_route_interfaces()
+    .await
+    .unwrap_or_default();
+  if let Some(cgnat_address) = utils::get_cgnat_address(
+    &interface_addresses,
+    &default_route_interfaces,
+    public_address,
+  ) {
+    let message = format!("the host is behind carrier-grade NAT (the interface address {} is in 100.64.0.0/10 and the public address is {}), so inbound connections to {} will not reach this host", cgnat_address, public_address, record_name);
+    if args.fail_on_cgnat {
+      set_panic_exit_code(EXIT_VALUE_ERROR);
+      panic!("{}", message);
+    }
+    eprintln!("Warning: {}", message);
+  }
+}
+
+// Resolves the name given by --mirror and uses its values. Returns when to resolve it again.
+async fn resolve_mirror(args: &mut Arguments) -> Result<tokio::time::Instant, String> {
+  let source = args.mirror.as_ref().unwrap();
+  let record_type = args.record_type.clone().unwrap_or(RrType::A);
+  let (values, ttl) = mirror::resolve(source, &record_type).await?;
+  let poll_interval = mirror::get_poll_interval(ttl);
+  eprintln!(
+    "{} returned {} (TTL {}, resolving it again in {}s)",
+    source,
+    values.join(","),
+    ttl,
+    poll_interval.as_secs()
+  );
+  args.value = values;
+  Ok(tokio::time::Instant::now() + poll_interval)
+}
+
+// Returns the instant of the next cron run, including the jitter.
+fn schedule_cron_run(schedule: &cron::Schedule, jitter: time::Duration) -> tokio::time::Instant {
+  let (next, delay) = schedule
+    .next_run_from_now(jitter)
+    .unwrap_or_else(|| panic!("the cron schedule {} never runs", schedule));
+  eprintln!(
+    "Next run at {} (with {}s of jitter)",
+    next,
+    jitter.as_secs()
+  );
+  tokio::time::Instant::now() + delay
+}
+
+// The heartbeat record is updated on every run, even if the value is unchanged, so that monitoring can detect agents that stopped running.
+async fn update_heartbeat(args: &Arguments, result: &types::UpdateResult) {
+  let record_name = match &args.heartbeat_record_name {
+    Some(name) if name.ends_with('.') => name.clone(),
+    Some(name) => format!("{}.", name),
+    None => format!("_heartbeat.{}", result.record_set.name()),
+  };
+  let value = format!(
+    "\"host={} timestamp={}\"",
+    utils::get_hostname(),
+    utils::get_timestamp()
+  );
+  let rrs = ResourceRecordSet::builder()
+    .name(record_name)
+    .r#type(RrType::Txt)
+    .set_ttl(result.record_set.ttl())
+    .resource_records(
+      ResourceRecord::builder()
+        .value(value)
+        .build()
+        .expect("error building resource record"),
+    )
+    .build()
+    .expect("error building resource record set");
+  eprintln!("{:?}", rrs);
+  let Some(hosted_zone_id) = &result.hosted_zone_id else {
+    return;
+  };
+  result
+    .backend
+    .upsert(hosted_zone_id, vec![rrs], None)
+    .await
+    .unwrap_or_else(|err| panic!("{}", err));
+}
+
+async fn deregister(previous: &types::UpdateResult) {
+  if let Some(registration) = &previous.cloudmap {
+    cloudmap::deregister_instance(registration).await;
+  }
+  let Some(hosted_zone_id) = &previous.hosted_zone_id else {
+    return;
+  };
+  eprintln!(
+    "Deleting {} {}",
+    previous.record_set.r#type().as_str(),
+    previous.record_set.name()
+  );
+  previous
+    .backend
+    .delete(hosted_zone_id, vec![previous.record_set.clone()], None)
+    .await
+    .unwrap_or_else(|err| panic!("{}", err));
+}
 
-  #[arg(
-    long,
-    value_name = "URL",
-    help = "Get the value from a URL (e.g. https://checkip.amazonaws.com/)"
-  )]
-  value_from_url: Option<String>,
+// --spot-interruption-action zero-weight
+async fn drain(previous: &types::UpdateResult) {
+  let Some(hosted_zone_id) = &previous.hosted_zone_id else {
+    return;
+  };
+  eprintln!(
+    "Setting the weight of {} {} to 0",
+    previous.record_set.r#type().as_str(),
+    previous.record_set.name()
+  );
+  let mut record_set = previous.record_set.clone();
+  record_set.weight = Some(0);
+  previous
+    .backend
+    .upsert(hosted_zone_id, vec![record_set], None)
+    .await
+    .unwrap_or_else(|err| panic!("{}", err));
+}
 
-  #[arg(
-    long,
-    value_enum,
-    value_name = "TYPE",
-    help = "Use a specific IP address type (supported: 'public' or 'private')",
-    default_value = "public"
-  )]
-  ip_address_type: types::IPAddressType,
+fn record_routing(args: &Arguments) -> types::RecordRouting {
+  let cidr_routing_config = match (&args.cidr_collection_id, &args.cidr_location) {
+    (Some(collection_id), Some(location)) => Some(
+      CidrRoutingConfig::builder()
+        .collection_id(collection_id)
+        .location_name(location)
+        .build()
+        .expect("error building CIDR routing config"),
+    ),
+    _ => None,
+  };
+  let geo_proximity_location =
+    if args.geoproximity_region.is_some() || args.geoproximity_coordinates.is_some() {
+      let coordinates = args.geoproximity_coordinates.as_ref().map(|c| {
+        Coordinates::builder()
+          .latitude(&c.latitude)
+          .longitude(&c.longitude)
+          .build()
+          .expect("error building coordinates")
+      });
+      Some(
+        GeoProximityLocation::builder()
+          .set_aws_region(args.geoproximity_region.clone())
+          .set_coordinates(coordinates)
+          .set_bias(args.bias)
+          .build(),
+      )
+    } else {
+      None
+    };
+  types::RecordRouting {
+    set_identifier: args.set_identifier.clone(),
+    cidr_routing_config,
+    geo_proximity_location,
+    weight: args.weight,
+  }
+}
 
-  #[arg(
-    long,
-    help = "TTL for the DNS record (optional, if an existing record exists then its TTL will be copied, 300 is used as fallback)"
-  )]
-  ttl: Option<i64>,
+fn metadata_clients(args: &Arguments) -> types::MetadataClients {
+  let clients = match &args.simulate_metadata {
+    Some(path) => {
+      let contents =
+        std::fs::read_to_string(path).expect("could not read the --simulate-metadata file");
+      let fixture =
+        serde_json::from_str(&contents).expect("could not parse the --simulate-metadata file");
+      eprintln!("Simulating the metadata services using {}", path);
+      types::MetadataClients::simulated(fixture)
+    }
+    None => types::MetadataClients::new(),
+  };
+  let startup_deadline = startup_deadline(args);
+  let grace_left = startup_deadline
+    .map(|deadline| deadline.saturating_duration_since(time::Instant::now()))
+    .unwrap_or_default();
+  types::MetadataClients {
+    metadata_timeout: time::Duration::from_secs(args.metadata_timeout).max(grace_left),
+    startup_deadline,
+    ..clients
+  }
+}
 
-  #[arg(long, help = "Change batch comment")]
-  comment: Option<String>,
+fn aws_options(args: &Arguments) -> types::AwsOptions {
+  types::AwsOptions {
+    region: args.region.clone(),
+    connect_timeout: time::Duration::from_secs(args.aws_connect_timeout),
+    timeout: time::Duration::from_secs(args.aws_timeout),
+    use_fips: args.aws_use_fips,
+    use_dualstack: args.aws_use_dualstack,
+  }
+}
 
-  #[arg(long, help = "Wait for the change to propagate in Route 53")]
-  wait: bool,
+fn route53_backend(args: &Arguments, client: aws_sdk_route53::Client) -> backend::Route53Backend {
+  backend::Route53Backend {
+    client,
+    max_attempts: args.wait_max_attempts,
+    startup_deadline: startup_deadline(args),
+  }
+}
 
-  #[arg(long, help = "Delete potentially conflicting records (A, AAAA, CNAME)")]
-  clear: bool,
+// None if --startup-grace is not used or the grace period has passed.
+fn startup_deadline(args: &Arguments) -> Option<time::Instant> {
+  let started_at = *STARTED_AT.get_or_init(time::Instant::now);
+  let deadline = started_at + time::Duration::from_secs(args.startup_grace);
+  (deadline > time::Instant::now()).then_some(deadline)
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<(), std::io::Error> {
-  env_logger::init();
+async fn resolve_value_source(source: &dyn sources::ValueSource) -> Vec<String> {
+  source
+    .resolve()
+    .await
+    .unwrap_or_else(|err| panic!("{}", err))
+}
 
-  let mut args = Arguments::parse();
+// Returns the record set that was published. If the value is unchanged since the previous update then nothing is submitted.
+async fn update(args: Arguments, previous: Option<&types::UpdateResult>) -> types::UpdateResult {
+  UPDATE_EXIT_CODE
+    .scope(Cell::new(EXIT_VALUE_ERROR), run_update(args, previous))
+    .await
+}
 
-  let version = VERSION.unwrap_or("unknown");
-  eprintln!("route53-update version {}", version);
+async fn run_update(
+  mut args: Arguments,
+  previous: Option<&types::UpdateResult>,
+) -> types::UpdateResult {
+  let clients = metadata_clients(&args);
+  let routing = record_routing(&args);
+  // The record name is optional with --skip-route53
+  let mut record_name = args.record_name.clone().unwrap_or_default();
 
-  if args.hosted_zone_id.is_some() && args.hosted_zone_name.is_some() {
-    panic!("can only use one of --hosted-zone-id or --hosted-zone-name.");
-  } else if !args.value.is_empty() && args.value_from.is_some()
-    || !args.value.is_empty() && args.value_from_url.is_some()
-    || args.value_from.is_some() && args.value_from_url.is_some()
+  // The instance identity document is only fetched if it is used, since it is not available outside of EC2
+  let templates = [
+    Some(record_name.clone()),
+    args.comment.clone(),
+    args.value_template.clone(),
+    args.publish_to_ssm.clone(),
+  ];
+  let instance_identity = if templates
+    .iter()
+    .flatten()
+    .any(|t| utils::uses_instance_identity(t))
   {
-    panic!("can only use one of --value, --value-from, or --value-from-url.");
-  } else if args.value.is_empty() && args.value_from.is_none() && args.value_from_url.is_none() {
-    panic!("value must be supplied with either --value, --value-from, or --value-from-url.");
-  } else if args.record_type.is_some() && args.record_type == Some(RrType::Txt) && args.clear {
-    panic!("--clear only works with A, AAAA, or CNAME");
+    let document = utils::get_instance_identity_document(&clients)
+      .await
+      .expect(
+        "could not get the instance identity document from the EC2 instance metadata service",
+      );
+    eprintln!("instance_identity: {:?}", document);
+    record_name = utils::render_template(&record_name, &utils::instance_identity_vars(&document));
+    Some(document)
+  } else {
+    None
+  };
+
+  let ecs_task_metadata = if templates
+    .iter()
+    .flatten()
+    .any(|t| utils::uses_ecs_task_metadata(t))
+  {
+    let metadata = utils::get_ecs_task_metadata(&clients)
+      .await
+      .expect("could not get the ECS task metadata (missing ECS environment variables)");
+    record_name = utils::render_template(&record_name, &utils::ecs_task_vars(&metadata));
+    Some(metadata)
+  } else {
+    None
+  };
+
+  if args.relative {
+    record_name =
+      utils::expand_relative_name(&record_name, args.hosted_zone_name.as_ref().unwrap());
+    eprintln!("Record name: {}", record_name);
+  } else if !record_name.ends_with(".") {
+    record_name += ".";
+  }
+  if let Some(selector) = &args.dkim_selector {
+    record_name = dkim::get_record_name(selector, &record_name);
+    eprintln!("DKIM record name: {}", record_name);
   }
+  if args.dmarc_policy.is_some() {
+    record_name = dmarc::get_record_name(&record_name);
+    eprintln!("DMARC record name: {}", record_name);
+  }
+  let keep_previous_as = args.keep_previous_as.as_ref().map(|name| {
+    if args.relative {
+      utils::expand_relative_name(name, args.hosted_zone_name.as_ref().unwrap())
+    } else if name.ends_with(".") {
+      name.clone()
+    } else {
+      format!("{}.", name)
+    }
+  });
 
-  if !args.record_name.ends_with(".") {
-    args.record_name = args.record_name + ".";
-  }
-
-  if args.value_from.is_some() {
-    let source = args.value_from.unwrap();
-
-    // --value-from ecs-metadata
-    if source == types::ValueFromSource::EcsMetadata || source == types::ValueFromSource::Auto {
-      if let Some(ecs_task_metadata) = utils::get_ecs_task_metadata().await {
-        eprintln!("ecs_task_metadata: {:?}", ecs_task_metadata);
-        // This naively grabs the IP for first container in the task, this should perhaps be configurable.
-        // If you use awsvpc networking mode then all the containers will have the same IP.
-        let network = ecs_task_metadata
-          .containers
-          .first()
-          .unwrap()
-          .networks
-          .first()
-          .unwrap();
-        if args.record_type == Some(RrType::A) && network.ipv4_addresses.is_some() {
-          args.value = network
-            .ipv4_addresses
-            .clone()
-            .unwrap()
-            .into_iter()
-            .filter(|address| !address.is_empty()) // The ECS metadata service can annoyingly return "IPv4Addresses": [""]
-            .collect();
-        } else if args.record_type == Some(RrType::Aaaa) && network.ipv6_addresses.is_some() {
-          args.value = network.ipv6_addresses.clone().unwrap();
+  // None for aliases to records in the same hosted zone
+  let mut alias_hosted_zone_id = None;
+  if let Some(source) = args.value_from {
+    let ec2_options = types::Ec2MetadataOptions {
+      ip_address_type: args.ip_address_type,
+      eni_index: args.eni_index,
+      secondary_ip_index: args.secondary_ip_index,
+      all_ipv6: args.ipv6_selection.is_some(),
+    };
+    args.value = match source {
+      types::ValueFromSource::EcsMetadata => {
+        resolve_value_source(&sources::EcsMetadataSource {
+          clients: clients.clone(),
+          record_type: args.record_type.clone(),
+          network_mode: args.ecs_network_mode,
+          ec2_options,
+        })
+        .await
+      }
+      types::ValueFromSource::Ec2Metadata => {
+        resolve_value_source(&sources::Ec2MetadataSource {
+          clients: clients.clone(),
+          record_type: args.record_type.clone(),
+          options: ec2_options,
+        })
+        .await
+      }
+      types::ValueFromSource::Lightsail => {
+        // The Lightsail API uses the region from the AWS configuration, not from the instance metadata
+        let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+        let lightsail_client = aws_sdk_lightsail::Client::new(&shared_config);
+        sources::get_lightsail_values(
+          &lightsail_client,
+          args.lightsail_instance_name.as_ref().unwrap(),
+          args.record_type.as_ref(),
+          args.ip_address_type,
+        )
+        .await
+      }
+      types::ValueFromSource::Upnp => {
+        let address = upnp::get_upnp_value(&clients.http)
+          .await
+          .unwrap_or_else(|err| panic!("{}", err));
+        // The router itself is behind carrier-grade NAT
+        if address
+          .parse()
+          .is_ok_and(|address| utils::is_cgnat_address(&address))
+        {
+          let message = format!("the external address of the router ({}) is in 100.64.0.0/10, so the router is behind carrier-grade NAT and inbound connections to {} will not reach this host", address, record_name);
+          if args.fail_on_cgnat {
+            set_panic_exit_code(EXIT_VALUE_ERROR);
+            panic!("{}", message);
+          }
+          eprintln!("Warning: {}", message);
         }
+        vec![address]
       }
-    }
-
-    // --value-from ec2-metadata
-    if source == types::ValueFromSource::Ec2Metadata
-      || (source == types::ValueFromSource::Auto && args.value.is_empty())
-    {
-      let path = match (args.record_type.clone(), args.ip_address_type) {
-        (Some(RrType::A) | None, types::IPAddressType::Public) => "public-ipv4",
-        (Some(RrType::A) | None, types::IPAddressType::Private) => "local-ipv4",
-        (Some(RrType::Aaaa), _) => "ipv6",
-        _ => panic!("--value-from is only usable with --record-type A or AAAA"),
-      };
-      let imds_client = aws_config::imds::client::Client::builder().build();
-      if let Ok(value) = imds_client
-        .get(format!("/latest/meta-data/{}", path).as_str())
+      types::ValueFromSource::Auto => {
+        sources::get_auto_values(
+          &clients,
+          &args.value_source_order,
+          args.record_type.as_ref(),
+          args.ecs_network_mode,
+          &ec2_options,
+          args.value_from_url.as_deref(),
+        )
         .await
-      {
-        args.value.push(value.as_ref().to_string());
+      }
+    };
+
+    if let Some(selection) = &args.ipv6_selection {
+      if args.record_type == Some(RrType::Aaaa) && !args.value.is_empty() {
+        let flags = match sources::get_ipv6_address_flags().await {
+          Ok(flags) => flags,
+          Err(err) if *selection == types::Ipv6Selection::Temporary => {
+            panic!("could not read the flags of the local IPv6 addresses, which are needed to identify temporary addresses: {}", err)
+          }
+          // Without the flags none of the addresses are known to be temporary
+          Err(_) => HashMap::new(),
+        };
+        args.value = utils::select_ipv6_address(args.value, selection, &flags);
+        if args.value.is_empty() {
+          panic!("no IPv6 address matched --ipv6-selection");
+        }
+        eprintln!("Selected IPv6 address: {}", args.value[0]);
       }
     }
 
     if source == types::ValueFromSource::Auto && args.value.is_empty() {
-      panic!("unable to auto-detect an IP address to use (missing ECS environment variables and unable to connect to the EC2 instance metadata service)");
+      panic!("unable to auto-detect an IP address to use (missing ECS environment variables, unable to connect to the EC2 instance metadata service, and no usable --value-from-url)");
+    }
+  } else if let Some(prefix) = args.ipv6_from_prefix {
+    let prefix = match prefix {
+      Some(prefix) => prefix,
+      None => sources::get_ec2_ipv6_prefix(&clients)
+        .await
+        .expect("could not get the delegated IPv6 prefix from the EC2 instance metadata service"),
+    };
+    let address = utils::compose_ipv6_address(&prefix, &args.ipv6_suffix.unwrap());
+    eprintln!("Composed IPv6 address {} from prefix {}", address, prefix);
+    args.value = vec![address.to_string()];
+  } else if let Some(url) = &args.value_from_url {
+    args.value = resolve_value_source(&sources::UrlSource {
+      clients: clients.clone(),
+      url: url.clone(),
+    })
+    .await;
+    check_cgnat(&args, &record_name).await;
+  } else if let Some(api_id) = &args.alias_api_gateway {
+    let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+    let apigateway_client = aws_sdk_apigatewayv2::Client::new(&shared_config);
+    let (dns_name, hosted_zone_id) =
+      alias::get_api_gateway_target(&apigateway_client, &record_name, api_id)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+    eprintln!(
+      "API Gateway custom domain name: {} ({})",
+      dns_name, hosted_zone_id
+    );
+    alias_hosted_zone_id = Some(hosted_zone_id);
+    args.value = vec![alias::normalize_dns_name(&dns_name)];
+    args.alias_target = Some(dns_name);
+    args.record_type.get_or_insert(RrType::A);
+  } else if let Some(dns_name) = &args.alias_target {
+    alias_hosted_zone_id = match &args.alias_target_hosted_zone_id {
+      Some(hosted_zone_id) => Some(hosted_zone_id.clone()),
+      None => alias::get_alias_hosted_zone_id(dns_name)
+        .unwrap_or_else(|err| panic!("{}", err))
+        .map(str::to_string),
+    };
+    if let Some(hosted_zone_id) = &alias_hosted_zone_id {
+      eprintln!("Alias target hosted zone ID: {}", hosted_zone_id);
     }
-  } else if args.value_from_url.is_some() {
-    let url = args.value_from_url.unwrap();
-    let response = reqwest::get(url.as_str()).await.unwrap();
-    if response.status() != reqwest::StatusCode::OK {
+    args.value = vec![alias::normalize_dns_name(dns_name)];
+    args.record_type.get_or_insert(RrType::A);
+  } else if let Some(path) = &args.dkim_public_key_file {
+    let pem = std::fs::read_to_string(path)
+      .unwrap_or_else(|err| panic!("could not read {}: {}", path.display(), err));
+    let public_key = dkim::parse_public_key_pem(&pem).unwrap_or_else(|err| panic!("{}", err));
+    args.value = vec![dkim::build_dkim_value(&public_key)];
+    args.record_type = Some(RrType::Txt);
+  } else if let Some(policy) = args.dmarc_policy {
+    args.value = vec![dmarc::build_dmarc_value(
+      policy,
+      args.dmarc_subdomain_policy,
+      args.dmarc_percent,
+      &args.dmarc_rua,
+      &args.dmarc_ruf,
+    )
+    .unwrap_or_else(|err| panic!("{}", err))];
+    args.record_type = Some(RrType::Txt);
+  } else if !args.spf_include.is_empty() {
+    // The includes are merged into the existing SPF record once it has been read
+    args.value = vec![spf::format_spf(&spf::merge_spf(
+      Vec::new(),
+      &args.spf_include,
+    ))];
+    args.record_type = Some(RrType::Txt);
+  } else if let Some(name) = &args.value_from_ssm {
+    let shared_config = utils::load_aws_config(&aws_options(&args), None).await;
+    let result = if ssm::is_secret_arn(name) {
+      let secrets_client = aws_sdk_secretsmanager::Client::new(&shared_config);
+      ssm::get_secret_values(&secrets_client, name).await
+    } else {
+      let ssm_client = aws_sdk_ssm::Client::new(&shared_config);
+      ssm::get_parameter_values(&ssm_client, name).await
+    };
+    args.value = result.unwrap_or_else(|err| {
+      set_panic_exit_code(EXIT_AWS_ERROR);
+      panic!("{}", err)
+    });
+    eprintln!("Value from {}: {}", name, args.value.join(","));
+  } else if let Some(template) = &args.value_template {
+    let value = sources::get_template_value(
+      &clients,
+      template,
+      instance_identity.as_ref(),
+      ecs_task_metadata.as_ref(),
+    )
+    .await;
+    eprintln!("Rendered value template: {}", value);
+    args.value = vec![value];
+  } else if let Some(interface) = &args.value_from_interface {
+    let source = sources::InterfaceSource {
+      interface: interface.clone(),
+      record_type: args.record_type.clone(),
+    };
+    args.value = resolve_value_source(&source).await;
+    eprintln!("Value from {}: {}", source.name(), args.value.join(","));
+  } else if let Some(command) = &args.value_from_command {
+    let source = sources::CommandSource {
+      command: command.clone(),
+    };
+    args.value = resolve_value_source(&source).await;
+    eprintln!("Value from {}: {}", source.name(), args.value.join(","));
+  } else if let Some(srv) = &args.srv_from_ecs {
+    let value = sources::get_ecs_srv_value(&clients, srv, args.ip_address_type)
+      .await
+      .unwrap_or_else(|err| panic!("{}", err));
+    eprintln!("SRV record value: {}", value);
+    args.value = vec![value];
+    args.record_type = Some(RrType::Srv);
+  }
+
+  if args.resolve_value {
+    args.value = sources::resolve_values(&args.value, args.record_type.as_ref())
+      .await
+      .unwrap_or_else(|err| panic!("{}", err));
+  }
+
+  if !args.value_filter.is_empty() {
+    let values = utils::filter_values(args.value.clone(), &args.value_filter);
+    if values.is_empty() {
       panic!(
-        "response from {} returned non-200 status code: {}",
-        url,
-        response.status()
-      )
+        "none of the values matched --value-filter: {}",
+        args.value.join(",")
+      );
     }
-    let response_text = response.text().await.unwrap().trim().to_string();
-    eprintln!("{} returned {:?}", url, response_text);
-    args.value = vec![response_text];
+    if values.len() != args.value.len() {
+      eprintln!("Filtered values: {}", values.join(","));
+    }
+    args.value = values;
   }
 
   // Sanity check
@@ -196,7 +3121,8 @@ async fn main() -> Result<(), std::io::Error> {
   }
 
   if args.record_type.is_none() {
-    args.record_type = Some(utils::detect_record_type(args.value.clone()));
+    args.record_type =
+      Some(utils::detect_record_type(args.value.clone()).unwrap_or_else(|err| panic!("{}", err)));
     if args.record_type == Some(RrType::Txt) && args.clear {
       panic!("--clear only works with A, AAAA, or CNAME");
     }
@@ -206,57 +3132,183 @@ async fn main() -> Result<(), std::io::Error> {
   if matches!(args.record_type, Some(RrType::Txt)) {
     args.value = args
       .value
-      .into_iter()
-      .map(|v: String| {
-        if v.starts_with('"') && v.ends_with('"') {
-          v
-        } else {
-          format!("\"{}\"", v)
-        }
-      })
+      .iter()
+      .map(|v| utils::quote_txt_value(v))
       .collect();
   }
 
-  let region_provider =
-    aws_config::meta::region::RegionProviderChain::default_provider().or_else("us-east-1");
-  let shared_config = aws_config::defaults(aws_config::BehaviorVersion::v2023_11_09())
-    .region(region_provider)
-    .load()
-    .await;
-  let route53_config = aws_sdk_route53::config::Builder::from(&shared_config);
-  let route53_client = aws_sdk_route53::client::Client::from_conf(route53_config.build());
+  args.value = utils::normalize_values(args.value);
 
-  if args.hosted_zone_id.is_none() {
-    let response = route53_client
-      .list_hosted_zones()
-      .send()
+  if let Some(check) = &args.require_reachable {
+    if !matches!(args.record_type, Some(RrType::A | RrType::Aaaa)) {
+      panic!("--require-reachable only works with A and AAAA records");
+    }
+    args.value = check::filter_reachable(args.value, check, time::Duration::from_secs(5)).await;
+    if args.value.is_empty() {
+      panic!("none of the values are reachable with {}", check);
+    }
+  }
+
+  // The values from the other sources are only known now
+  if let Err(err) = utils::check_value_count(args.record_type.as_ref(), args.value.len()) {
+    panic!("{}", err);
+  }
+  if let Err(err) = utils::check_value_lengths(args.record_type.as_ref(), &args.value) {
+    panic!("{}", err);
+  }
+  events::emit(
+    "value_detected",
+    json!({
+      "record_name": record_name,
+      "record_type": args.record_type.as_ref().unwrap().as_str(),
+      "value": args.value,
+    }),
+  );
+
+  if let Some(previous) = previous {
+    if previous.record_set.r#type() == args.record_type.as_ref().unwrap()
+      && utils::normalize_values(utils::get_record_values(&previous.record_set)) == args.value
+    {
+      eprintln!("Value is unchanged: {}", args.value.join(","));
+      return types::UpdateResult {
+        change_id: None,
+        cloudmap: previous
+          .cloudmap
+          .clone()
+          .map(|registration| types::CloudMapRegistration {
+            operation_id: None,
+            ..registration
+          }),
+        traffic_policy_instance_id: None,
+        previous_value: args.value.clone(),
+        ..previous.clone()
+      };
+    }
+  }
+
+  set_panic_exit_code(EXIT_AWS_ERROR);
+  let shared_config = utils::load_aws_config(
+    &aws_options(&args),
+    instance_identity
+      .as_ref()
+      .map(|document| document.region.clone()),
+  )
+  .await;
+  let route53_client = utils::route53_client(&shared_config);
+  let backend: Arc<dyn backend::DnsBackend> =
+    Arc::new(route53_backend(&args, route53_client.clone()));
+
+  if args.skip_elastic_ip && args.record_type == Some(RrType::A) {
+    let ec2_client = aws_sdk_ec2::Client::new(&shared_config);
+    let elastic_ips = utils::get_elastic_ips(&ec2_client, &args.value).await;
+    if elastic_ips.len() == args.value.len() {
+      eprintln!(
+        "Skipping the update since {} is an Elastic IP address, which does not change. Consider creating the record once instead of updating it periodically.",
+        elastic_ips.join(",")
+      );
+      return types::UpdateResult {
+        hosted_zone_id: None,
+        record_set: utils::build_record_set(
+          &record_name,
+          args.record_type.as_ref().unwrap(),
+          args.ttl,
+          &args.value,
+          &routing,
+        ),
+        change_id: None,
+        backend,
+        cloudmap: None,
+        traffic_policy_instance_id: None,
+        previous_value: Vec::new(),
+      };
+    }
+  }
+
+  let cloudmap = match &args.cloudmap_service_id {
+    Some(service_id) => {
+      let cloudmap_client = aws_sdk_servicediscovery::Client::new(&shared_config);
+      let instance_id = args
+        .cloudmap_instance_id
+        .clone()
+        .unwrap_or_else(utils::get_hostname);
+      Some(
+        cloudmap::register_instance(
+          &cloudmap_client,
+          service_id,
+          &instance_id,
+          args.record_type.as_ref().unwrap(),
+          &args.value,
+        )
+        .await,
+      )
+    }
+    None => None,
+  };
+
+  if let Some(name) = &args.publish_to_ssm {
+    let mut name = name.clone();
+    if let Some(document) = &instance_identity {
+      name = utils::render_template(&name, &utils::instance_identity_vars(document));
+    }
+    if let Some(metadata) = &ecs_task_metadata {
+      name = utils::render_template(&name, &utils::ecs_task_vars(metadata));
+    }
+    let ssm_client = aws_sdk_ssm::Client::new(&shared_config);
+    ssm::put_parameter(&ssm_client, &name, &args.value)
       .await
-      .expect("could not list hosted zones");
+      .unwrap_or_else(|err| panic!("{}", err));
+  }
+
+  if args.skip_route53 {
+    return types::UpdateResult {
+      hosted_zone_id: None,
+      record_set: utils::build_record_set(
+        &record_name,
+        args.record_type.as_ref().unwrap(),
+        args.ttl,
+        &args.value,
+        &routing,
+      ),
+      change_id: None,
+      backend,
+      cloudmap,
+      traffic_policy_instance_id: None,
+      previous_value: Vec::new(),
+    };
+  }
+
+  let explicit_hosted_zone_id = !args.hosted_zone_id.is_empty();
+  if args.hosted_zone_id.is_empty() {
+    let response = match utils::retry_until_deadline(startup_deadline(&args), || {
+      route53_client.list_hosted_zones().send()
+    })
+    .await
+    {
+      Ok(response) => response,
+      Err(err) if utils::is_access_denied(&err) => panic!(
+        "access denied when listing hosted zones, please use --hosted-zone-id or add route53:ListHostedZones to the IAM policy"
+      ),
+      Err(err) => panic!("could not list hosted zones: {:?}", err),
+    };
     if response.is_truncated() {
       panic!("you have a lot of hosted zones and this program does not paginate yet, please use --hosted-zone-id");
     }
 
     let hosted_zone;
-    if let Some(mut hosted_zone_name) = args.hosted_zone_name {
+    if let Some(mut hosted_zone_name) = args.hosted_zone_name.clone() {
       if !hosted_zone_name.ends_with(".") {
-        hosted_zone_name = hosted_zone_name + ".";
+        hosted_zone_name += ".";
       }
       hosted_zone = utils::get_hosted_zone(
         response
           .hosted_zones()
-          .into_iter()
+          .iter()
           .filter(|zone| zone.name() == hosted_zone_name)
           .collect(),
         args.hosted_zone_type,
       );
-      if hosted_zone.is_none() {
-        panic!(
-          "could not find a hosted zone with name: {}",
-          hosted_zone_name
-        );
-      }
     } else {
-      let mut search_name = args.record_name.clone();
+      let mut search_name = record_name.clone();
       let mut hosted_zone_type = if args.hosted_zone_type == types::HostedZoneType::Public
         || args.hosted_zone_type == types::HostedZoneType::PreferPublic
       {
@@ -268,7 +3320,7 @@ async fn main() -> Result<(), std::io::Error> {
         let zone = utils::get_hosted_zone(
           response
             .hosted_zones()
-            .into_iter()
+            .iter()
             .filter(|zone| zone.name().eq(&search_name))
             .collect(),
           hosted_zone_type,
@@ -277,155 +3329,521 @@ async fn main() -> Result<(), std::io::Error> {
           hosted_zone = zone;
           break;
         } else {
-          let search_split = search_name.split_once(".");
-          if search_split.is_some() {
-            search_name = search_split.unwrap().1.to_string();
+          if let Some((_, parent)) = search_name.split_once(".") {
+            search_name = parent.to_string();
           } else if args.hosted_zone_type == types::HostedZoneType::PreferPublic
             && hosted_zone_type == types::HostedZoneType::Public
           {
             hosted_zone_type = types::HostedZoneType::Private;
           } else {
-            panic!("could not find the hosted zone for: {}", args.record_name);
+            hosted_zone = None;
+            break;
           }
         }
       }
     }
 
     if let Some(zone) = hosted_zone {
-      args.hosted_zone_id = Some(zone.id.to_string());
-      eprintln!("Found hosted zone: {} ({})", zone.id(), zone.name());
+      let hosted_zone_id = utils::normalize_hosted_zone_id(zone.id());
+      eprintln!("Found hosted zone: {} ({})", hosted_zone_id, zone.name());
+      args.hosted_zone_id = vec![hosted_zone_id];
+    } else if args.hosted_zone_type != types::HostedZoneType::Public {
+      // The private hosted zone may be owned by another account and associated with the VPC
+      let vpc_id = match &args.vpc_id {
+        Some(vpc_id) => vpc_id.clone(),
+        None => utils::get_ec2_vpc_id(&clients).await.unwrap_or_else(|| {
+          panic!(
+            "could not find the hosted zone for: {} (use --vpc-id to also look for private hosted zones from other accounts)",
+            record_name
+          )
+        }),
+      };
+      let vpc_region = shared_config
+        .region()
+        .map(|region| region.to_string())
+        .unwrap_or_else(|| {
+          set_panic_exit_code(EXIT_INVALID_ARGUMENTS);
+          panic!("the region of the VPC is unknown, set AWS_REGION or use --region")
+        });
+      let summaries = utils::list_hosted_zones_by_vpc(&route53_client, &vpc_id, &vpc_region).await;
+      let hosted_zone_name = args.hosted_zone_name.as_ref().map(|name| {
+        if name.ends_with('.') {
+          name.clone()
+        } else {
+          format!("{}.", name)
+        }
+      });
+      let zone =
+        utils::find_hosted_zone_summary(&summaries, hosted_zone_name.as_deref(), &record_name)
+          .unwrap_or_else(|| panic!("could not find the hosted zone for: {}", record_name));
+      let owner = zone
+        .owner()
+        .and_then(|owner| owner.owning_account())
+        .unwrap_or("unknown");
+      eprintln!(
+        "Found hosted zone associated with {}: {} ({}) owned by account {}",
+        vpc_id,
+        zone.hosted_zone_id(),
+        zone.name(),
+        owner
+      );
+      if args.zone_owner_role_arn.is_none() {
+        eprintln!("If the hosted zone is owned by another account then use --zone-owner-role-arn to make the change in that account");
+      }
+      args.hosted_zone_id = vec![utils::normalize_hosted_zone_id(zone.hosted_zone_id())];
     } else {
-      panic!("could not find the hosted zone");
+      panic!("could not find the hosted zone for: {}", record_name);
     }
   }
 
-  let hosted_zone_id = args.hosted_zone_id.clone().unwrap();
-  if args.ttl.is_none() || args.clear {
-    let response = route53_client
-      .list_resource_record_sets()
-      .hosted_zone_id(hosted_zone_id.clone())
+  // The hosted zone is owned by another account, so the changes have to be made in that account
+  let route53_client = match &args.zone_owner_role_arn {
+    Some(role_arn) => {
+      let owner_config = utils::assume_role(&shared_config, role_arn).await;
+      utils::route53_client(&owner_config)
+    }
+    None => route53_client,
+  };
+  let backend: Arc<dyn backend::DnsBackend> =
+    Arc::new(route53_backend(&args, route53_client.clone()));
+
+  // There is only one hosted zone per run, see expand_runs
+  let hosted_zone_id = args.hosted_zone_id[0].clone();
+  events::emit(
+    "zone_found",
+    json!({
+      "hosted_zone_id": hosted_zone_id,
+      "record_name": record_name,
+    }),
+  );
+  // Route 53 rejects the change with an unhelpful InvalidChangeBatch error otherwise
+  // The check is skipped if the IAM policy does not allow route53:GetHostedZone
+  if explicit_hosted_zone_id {
+    if let Ok(response) = route53_client
+      .get_hosted_zone()
+      .id(&hosted_zone_id)
       .send()
       .await
-      .expect("could not list record sets");
+    {
+      if let Some(zone) = response.hosted_zone() {
+        for name in std::iter::once(&record_name).chain(keep_previous_as.as_ref()) {
+          if !utils::is_in_zone(name, zone.name()) {
+            panic!(
+              "the record name {} is not in the hosted zone {} ({})",
+              name,
+              hosted_zone_id,
+              zone.name()
+            );
+          }
+        }
+      }
+    }
+  }
+  if args.check_dnssec {
+    match dnssec::get_dnssec(&route53_client, &hosted_zone_id).await {
+      Ok(dnssec) => {
+        for problem in dnssec::get_dnssec_problems(&dnssec) {
+          eprintln!("Warning: {}", problem);
+        }
+      }
+      Err(err) => eprintln!("Warning: {}", err),
+    }
+  }
+  if args.check_resolver_rules {
+    utils::check_resolver_rules(
+      &shared_config,
+      &route53_client,
+      &hosted_zone_id,
+      &record_name,
+    )
+    .await;
+  }
 
-    if response.is_truncated() {
-      eprintln!("This zone has a lot of record sets and this program does not paginate yet, so --clear might clear everything.");
+  let mut old_value: Vec<String> = Vec::new();
+  // If the IAM policy only allows changing records then the defaults are used instead
+  let record_sets = match backend.get(&hosted_zone_id, &record_name).await {
+    Ok(record_sets) => record_sets,
+    // --spf-include would drop the other TXT values if they could not be read
+    Err(err)
+      if err.starts_with(backend::ACCESS_DENIED) && !args.clear && args.spf_include.is_empty() =>
+    {
+      eprintln!("Warning: access denied when listing record sets, unable to copy the TTL or check for conflicting records (add route53:ListResourceRecordSets to the IAM policy to fix this)");
+      Vec::new()
     }
+    Err(err) => panic!("{}", err),
+  };
 
-    if args.ttl.is_none() {
-      args.ttl = response
-        .resource_record_sets()
-        .into_iter()
-        .find(|r| r.name() == &args.record_name && Some(r.r#type()) == args.record_type.as_ref())
-        .map(|r| r.ttl().unwrap());
-      if args.ttl.is_some() {
-        eprintln!("Copied TTL from existing record: {}", args.ttl.unwrap())
-      } else {
-        args.ttl = Some(300);
-        eprintln!("Using default TTL: {}", args.ttl.unwrap())
+  if !args.clear {
+    utils::check_record_conflicts(
+      &record_sets,
+      &record_name,
+      args.record_type.as_ref().unwrap(),
+    );
+  }
+
+  let existing_record = record_sets.iter().find(|r| {
+    r.name() == record_name
+      && Some(r.r#type()) == args.record_type.as_ref()
+      && routing.identifies(r)
+  });
+  if let Some(r) = existing_record {
+    old_value = utils::get_record_values(r);
+    if !args.override_external {
+      if let Some(owner) = utils::find_external_owner(
+        backend.as_ref(),
+        &hosted_zone_id,
+        &record_sets,
+        &record_name,
+        args.record_type.as_ref().unwrap(),
+      )
+      .await
+      {
+        panic!(
+          "the record is managed by another controller ({}), use --override-external to update it anyway",
+          owner
+        );
       }
     }
+  }
+  if !args.override_external && !args.external_owner_tag.is_empty() {
+    if let Some(owner) =
+      utils::find_tag_owner(&route53_client, &hosted_zone_id, &args.external_owner_tag).await
+    {
+      panic!(
+        "the hosted zone is managed by another tool (it has the tag {}), use --override-external to update the record anyway",
+        owner
+      );
+    }
+  }
 
-    if args.clear {
-      // To avoid errors of the following kind, we have to delete records before we UPSERT:
-      // RRSet of type CNAME with DNS name service.example.com. is not permitted as it conflicts with other records with the same DNS name in zone example.com.
+  if !args.spf_include.is_empty() {
+    args.value = spf::merge_spf_values(&old_value, &args.spf_include)
+      .map(utils::normalize_values)
+      .unwrap_or_else(|err| panic!("{}", err));
+    if let Some(terms) = args.value.iter().find_map(|v| spf::parse_spf(v)) {
+      eprintln!("SPF record: {}", spf::format_spf(&terms));
+      let lookups = spf::count_dns_lookups(&terms);
+      if lookups > spf::MAX_DNS_LOOKUPS {
+        eprintln!(
+          "Warning: the SPF record has {} mechanisms that cause DNS lookups, but SPF allows at most {} lookups in total (including the lookups of the included records)",
+          lookups,
+          spf::MAX_DNS_LOOKUPS
+        );
+      }
+    }
+  }
 
-      let mut change_batch_builder = ChangeBatch::builder();
-      for r in response
-        .resource_record_sets()
-        .into_iter()
-        .filter(|r| r.name() == &args.record_name)
-        .filter(|r| {
-          args.record_type == Some(RrType::Cname)
-            || (r.r#type() == &RrType::A
-              || r.r#type() == &RrType::Aaaa
-              || r.r#type() == &RrType::Cname)
-        })
-        .filter(|r| Some(r.r#type()) != args.record_type.as_ref())
-      {
-        let change = Change::builder()
-          .action(ChangeAction::Delete)
-          .resource_record_set(r.clone())
-          .build()
-          .expect("error building change set");
-        change_batch_builder = change_batch_builder.changes(change);
-        eprintln!("Will delete {} {}", r.r#type().as_str(), r.name())
+  let jitter_seed = format!("{} {}", utils::get_hostname(), record_name);
+  if let (Some(ttl), Some(percent)) = (args.ttl, args.record_ttl_jitter) {
+    let jittered_ttl = utils::jitter_ttl(ttl, percent, &jitter_seed);
+    eprintln!("Applied TTL jitter: {}", jittered_ttl);
+    args.ttl = Some(jittered_ttl);
+  }
+  // Alias records do not have a TTL
+  if args.ttl.is_none() && args.alias_target.is_none() {
+    // The copied TTL already has the jitter applied if it was written by this host
+    // An existing alias record does not have a TTL to copy
+    args.ttl = existing_record.and_then(|r| r.ttl());
+    let ttl = if let Some(ttl) = args.ttl {
+      eprintln!("Copied TTL from existing record: {}", ttl);
+      ttl
+    } else {
+      let mut ttl = utils::get_default_ttl(&args.default_ttl, args.record_type.as_ref().unwrap());
+      eprintln!("Using default TTL: {}", ttl);
+      if let Some(percent) = args.record_ttl_jitter {
+        ttl = utils::jitter_ttl(ttl, percent, &jitter_seed);
+        eprintln!("Applied TTL jitter: {}", ttl);
       }
+      ttl
+    };
+    let clamped_ttl = utils::clamp_ttl(ttl, args.min_ttl, args.max_ttl);
+    if clamped_ttl != ttl {
+      eprintln!("Clamped TTL to: {}", clamped_ttl);
+    }
+    args.ttl = Some(clamped_ttl);
+  }
 
-      let change_batch = change_batch_builder
-        .build()
-        .expect("error building change batch");
-      if !change_batch.changes().is_empty() {
-        route53_client
-          .change_resource_record_sets()
-          .hosted_zone_id(hosted_zone_id.clone())
-          .change_batch(change_batch)
-          .send()
-          .await
-          .expect("could not delete DNS records");
+  // --clear may still have conflicting records to delete
+  if let Some(r) = existing_record {
+    if !args.clear
+      && utils::normalize_values(old_value.clone()) == args.value
+      && r.ttl() == args.ttl
+      && r.alias_target().map_or(true, |a| {
+        a.evaluate_target_health() == args.evaluate_target_health
+      })
+      && routing.is_applied_to(r)
+    {
+      eprintln!("The record is already up to date: {}", args.value.join(","));
+      return types::UpdateResult {
+        hosted_zone_id: Some(hosted_zone_id),
+        record_set: r.clone(),
+        change_id: None,
+        backend,
+        cloudmap,
+        traffic_policy_instance_id: None,
+        previous_value: old_value.clone(),
+      };
+    }
+
+    // Route 53 rejects direct changes to record sets that are created by a traffic policy
+    if let Some(instance_id) = r.traffic_policy_instance_id() {
+      if !args.update_traffic_policy {
+        panic!(
+          "the record is managed by the traffic policy instance {}, use --update-traffic-policy to update the traffic policy instance instead",
+          instance_id
+        );
       }
+      eprintln!("Updating traffic policy instance {}", instance_id);
+      traffic_policy::update_traffic_policy_instance(
+        &route53_client,
+        instance_id,
+        args.ttl.unwrap(),
+        &old_value,
+        &args.value,
+      )
+      .await;
+      if args.wait {
+        traffic_policy::wait_for_traffic_policy_instance(&route53_client, instance_id).await;
+      }
+      return types::UpdateResult {
+        hosted_zone_id: Some(hosted_zone_id),
+        record_set: utils::build_record_set(
+          &record_name,
+          args.record_type.as_ref().unwrap(),
+          args.ttl,
+          &args.value,
+          &routing,
+        ),
+        change_id: None,
+        backend,
+        cloudmap,
+        traffic_policy_instance_id: Some(instance_id.to_string()),
+        previous_value: old_value.clone(),
+      };
     }
   }
 
-  let rrs = ResourceRecordSet::builder()
-    .set_ttl(args.ttl)
-    .name(args.record_name.clone())
-    .set_type(args.record_type.clone())
-    .set_resource_records(Some(
-      args
-        .value
-        .into_iter()
-        .map(|v| {
-          ResourceRecord::builder()
-            .value(v)
-            .build()
-            .expect("error building resource record")
-        })
-        .collect(),
-    ))
-    .build()
-    .expect("error building resource record set");
-  let change = Change::builder()
-    .action(ChangeAction::Upsert)
-    .resource_record_set(rrs)
-    .build()
-    .expect("error building change set");
-  let change_batch = ChangeBatch::builder()
-    .changes(change)
-    .set_comment(args.comment)
-    .build()
-    .expect("error building change batch");
+  if args.clear {
+    // To avoid errors of the following kind, we have to delete records before we UPSERT:
+    // RRSet of type CNAME with DNS name service.example.com. is not permitted as it conflicts with other records with the same DNS name in zone example.com.
+
+    let deleted: Vec<ResourceRecordSet> = record_sets
+      .iter()
+      .filter(|r| r.name() == record_name)
+      .filter(|r| {
+        args.record_type == Some(RrType::Cname)
+          || (r.r#type() == &RrType::A
+            || r.r#type() == &RrType::Aaaa
+            || r.r#type() == &RrType::Cname)
+      })
+      .filter(|r| Some(r.r#type()) != args.record_type.as_ref())
+      .cloned()
+      .collect();
+    for r in &deleted {
+      eprintln!("Will delete {} {}", r.r#type().as_str(), r.name())
+    }
+
+    if !deleted.is_empty() {
+      let snapshot = snapshot::Snapshot {
+        hosted_zone_id: hosted_zone_id.clone(),
+        created_at: utils::get_timestamp(),
+        record_sets: deleted
+          .iter()
+          .map(snapshot::RecordSetSnapshot::from)
+          .collect(),
+      };
+      let path = snapshot::write_snapshot(&args.restore_dir, &snapshot)
+        .unwrap_or_else(|err| panic!("{}", err));
+      eprintln!(
+        "Wrote the records that will be deleted to {}, run `route53-update restore --from {}` to recreate them",
+        path.display(),
+        path.display()
+      );
+      backend
+        .delete(&hosted_zone_id, deleted, None)
+        .await
+        .unwrap_or_else(|err| panic!("{}", err));
+    }
+  }
+
+  let comment = match args.comment {
+    Some(comment) => {
+      let mut vars = instance_identity
+        .as_ref()
+        .map(utils::instance_identity_vars)
+        .unwrap_or_default();
+      if let Some(metadata) = &ecs_task_metadata {
+        vars.extend(utils::ecs_task_vars(metadata));
+      }
+      if comment.contains("{hostname}") {
+        vars.insert("hostname", utils::get_hostname());
+      }
+      vars.insert("old_value", old_value.join(","));
+      vars.insert("new_value", args.value.join(","));
+      vars.insert(
+        "transition",
+        utils::format_transition(
+          &record_name,
+          args.record_type.as_ref().unwrap(),
+          &old_value,
+          &args.value,
+        ),
+      );
+      vars.insert("timestamp", utils::get_timestamp());
+      vars.insert("version", types::VersionInfo::new().to_string());
+      Some(utils::render_template(&comment, &vars))
+    }
+    None => None,
+  };
+
+  let rrs = if args.alias_target.is_some() {
+    alias::build_alias_record_set(
+      &record_name,
+      args.record_type.as_ref().unwrap(),
+      alias_hosted_zone_id.as_deref().unwrap_or(&hosted_zone_id),
+      &args.value[0],
+      args.evaluate_target_health,
+      &routing,
+    )
+  } else {
+    utils::build_record_set(
+      &record_name,
+      args.record_type.as_ref().unwrap(),
+      args.ttl,
+      &args.value,
+      &routing,
+    )
+  };
+  let mut upserts = Vec::new();
+  // The copy is in the same change batch, so the previous value can not be lost
+  if let (Some(name), Some(r)) = (&keep_previous_as, existing_record) {
+    eprintln!("Keeping the previous value as: {}", name);
+    let previous = ResourceRecordSet::builder()
+      .name(name)
+      .r#type(r.r#type().clone())
+      .set_ttl(r.ttl())
+      .set_resource_records(r.resource_records.clone())
+      .set_alias_target(r.alias_target.clone())
+      .build()
+      .expect("error building record set");
+    upserts.push(previous);
+  }
+  upserts.push(rrs.clone());
 
-  eprintln!("{:?}", change_batch);
+  eprintln!("{:?}", upserts);
 
-  let response = route53_client
-    .change_resource_record_sets()
-    .set_hosted_zone_id(args.hosted_zone_id)
-    .change_batch(change_batch)
-    .send()
+  let change_id = backend
+    .upsert(&hosted_zone_id, upserts, comment)
     .await
-    .expect("could not update DNS");
+    .unwrap_or_else(|err| panic!("{}", err));
 
-  println!("{:?}", response);
+  events::emit(
+    "change_submitted",
+    json!({
+      "hosted_zone_id": hosted_zone_id,
+      "record_name": record_name,
+      "change_id": change_id,
+      "old_value": old_value,
+      "value": args.value,
+    }),
+  );
 
-  if args.wait {
-    let change_id = response.change_info().unwrap().id();
-
-    loop {
-      thread::sleep(time::Duration::from_millis(1000));
-      let response = route53_client
-        .get_change()
-        .id(change_id)
-        .send()
-        .await
-        .expect("could not poll change status");
-      eprintln!("{:?}", response);
-      let change_status = response.change_info().unwrap().status();
-      if matches!(change_status, ChangeStatus::Insync) {
-        break;
+  if let Some(audit_log) = args.audit_log {
+    let entry = audit::AuditLogEntry {
+      timestamp: utils::get_timestamp(),
+      hostname: utils::get_hostname(),
+      hosted_zone_id: hosted_zone_id.clone(),
+      record_name: record_name.clone(),
+      record_type: args.record_type.as_ref().unwrap().as_str().to_string(),
+      old_value: old_value.clone(),
+      new_value: args.value.clone(),
+      change_id: change_id.clone(),
+    };
+    audit::write_audit_log(&audit_log, &entry, &shared_config).await;
+  }
+
+  if args.tag_hosted_zone {
+    utils::tag_hosted_zone(&route53_client, &hosted_zone_id).await;
+  }
+
+  // --verify-http has to wait for the change to propagate in Route 53 before probing
+  if args.wait || args.verify_http.is_some() {
+    backend
+      .wait(&change_id)
+      .await
+      .unwrap_or_else(|err| panic!("{}", err));
+  }
+
+  if let Some(url) = &args.verify_http {
+    if let Err(err) = canary::probe(
+      url,
+      &record_name,
+      args.record_type.as_ref().unwrap(),
+      &args.value,
+      args.verify_http_attempts,
+    )
+    .await
+    {
+      let change_id =
+        canary::rollback(backend.as_ref(), &hosted_zone_id, existing_record, &rrs).await;
+      if args.wait {
+        backend
+          .wait(&change_id)
+          .await
+          .unwrap_or_else(|err| panic!("{}", err));
+      }
+      panic!(
+        "the verification failed and the change was rolled back: {}",
+        err
+      );
+    }
+    events::emit(
+      "verified",
+      json!({ "record_name": record_name, "method": "http" }),
+    );
+  }
+
+  let mut verify_resolvers = args.verify_resolver.clone();
+  if args.verify_dns {
+    verify_resolvers.extend(check::PUBLIC_RESOLVERS.iter().map(|(_, ip)| *ip));
+  }
+  let mut verify_timeout = args.verify_timeout;
+  // Resolvers that looked up the record before it existed keep returning NXDOMAIN until the negative answer expires
+  if existing_record.is_none() && (!verify_resolvers.is_empty() || args.wait_negative_ttl) {
+    if let Some(negative_ttl) = check::get_negative_ttl(&route53_client, &hosted_zone_id).await {
+      eprintln!(
+        "The record is new, resolvers that cached the nonexistence of the record may return NXDOMAIN for up to {} seconds",
+        negative_ttl
+      );
+      verify_timeout = verify_timeout.max(negative_ttl as u64);
+      if args.wait_negative_ttl {
+        tokio::time::sleep(time::Duration::from_secs(negative_ttl as u64)).await;
       }
     }
   }
 
-  return Ok(());
+  if !verify_resolvers.is_empty() {
+    check::wait_for_resolvers(
+      &verify_resolvers,
+      &record_name,
+      args.record_type.as_ref().unwrap(),
+      &args.value,
+      time::Duration::from_secs(verify_timeout),
+    )
+    .await;
+    events::emit(
+      "verified",
+      json!({ "record_name": record_name, "method": "dns" }),
+    );
+  }
+
+  types::UpdateResult {
+    hosted_zone_id: Some(hosted_zone_id),
+    record_set: rrs,
+    change_id: Some(change_id),
+    backend,
+    cloudmap,
+    traffic_policy_instance_id: None,
+    previous_value: old_value,
+  }
 }
@@ -0,0 +1,32 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+// --mirror
+// The values of the record are copied from another name, e.g. a dynamic record at another DNS provider. The name is resolved again when the answer expires.
+
+use crate::check;
+
+use aws_sdk_route53::types::RrType;
+use std::time::Duration;
+
+// Some dynamic DNS providers use a TTL of 0 or a few seconds, and a very long TTL would delay the update for too long
+pub const MIN_POLL_INTERVAL: Duration = Duration::from_secs(10);
+pub const MAX_POLL_INTERVAL: Duration = Duration::from_secs(3600);
+
+// Returns the values of the name, and how long they are valid according to the TTL of the answer.
+pub async fn resolve(name: &str, record_type: &RrType) -> Result<(Vec<String>, u32), String> {
+  let fqdn = format!("{}.", name.trim_end_matches('.'));
+  match check::query_system(&fqdn, record_type).await {
+    check::Answer::Values { values, ttl } if !values.is_empty() => Ok((values, ttl)),
+    check::Answer::Values { .. } | check::Answer::NotFound { .. } => {
+      Err(format!("{} has no {} records", name, record_type.as_str()))
+    }
+    check::Answer::Error(err) => Err(format!("could not resolve {}: {}", name, err)),
+  }
+}
+
+// The name is resolved again one second after the answer expires, so that a caching resolver returns the new answer.
+pub fn get_poll_interval(ttl: u32) -> Duration {
+  (Duration::from_secs(u64::from(ttl)) + Duration::from_secs(1))
+    .clamp(MIN_POLL_INTERVAL, MAX_POLL_INTERVAL)
+}
@@ -0,0 +1,98 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::{fleet, types};
+
+use aws_sdk_ec2::types::{Filter, InstanceStateName};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TaggedInstance {
+  pub instance_id: String,
+  // The value of the tag
+  pub record_name: String,
+  pub address: Option<String>,
+  pub state: InstanceStateName,
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Plan {
+  // Record name -> the addresses of the running instances with that name
+  pub upsert: BTreeMap<String, Vec<String>>,
+  // The record names of terminated instances that no running instance uses anymore
+  pub delete: BTreeSet<String>,
+}
+
+pub fn normalize_record_name(record_name: &str) -> String {
+  format!("{}.", record_name.trim_end_matches('.').to_lowercase())
+}
+
+// Several running instances with the same name get a record with all of their addresses.
+// Stopped instances keep their records, and terminated instances are only known for a while after they were terminated (about an hour), so their records are only deleted if the reconciliation runs during that time (use gc for records named after the instance ID).
+pub fn plan(instances: &[TaggedInstance]) -> Plan {
+  let mut plan = Plan::default();
+  let mut in_use = BTreeSet::new();
+  for instance in instances {
+    let record_name = normalize_record_name(&instance.record_name);
+    match instance.state {
+      InstanceStateName::Running => {
+        in_use.insert(record_name.clone());
+        if let Some(address) = &instance.address {
+          let addresses = plan.upsert.entry(record_name).or_default();
+          if !addresses.contains(address) {
+            addresses.push(address.clone());
+          }
+        }
+      }
+      InstanceStateName::Pending | InstanceStateName::Stopping | InstanceStateName::Stopped => {
+        in_use.insert(record_name);
+      }
+      InstanceStateName::ShuttingDown | InstanceStateName::Terminated => {
+        plan.delete.insert(record_name);
+      }
+      _ => {}
+    }
+  }
+  plan
+    .delete
+    .retain(|record_name| !in_use.contains(record_name));
+  for addresses in plan.upsert.values_mut() {
+    addresses.sort();
+  }
+  plan
+}
+
+// Returns all of the instances that have the tag, in any state.
+pub async fn list_tagged_instances(
+  ec2_client: &aws_sdk_ec2::Client,
+  tag_key: &str,
+  ip_address_type: types::IPAddressType,
+) -> Vec<TaggedInstance> {
+  let reservations: Vec<_> = ec2_client
+    .describe_instances()
+    .filters(Filter::builder().name("tag-key").values(tag_key).build())
+    .into_paginator()
+    .items()
+    .send()
+    .collect::<Result<_, _>>()
+    .await
+    .expect("could not describe the EC2 instances");
+  reservations
+    .iter()
+    .flat_map(|reservation| reservation.instances())
+    .filter_map(|instance| {
+      let record_name = instance
+        .tags()
+        .iter()
+        .find(|tag| tag.key() == Some(tag_key))
+        .and_then(|tag| tag.value())
+        .filter(|value| !value.is_empty())?;
+      Some(TaggedInstance {
+        instance_id: instance.instance_id()?.to_string(),
+        record_name: record_name.to_string(),
+        address: fleet::get_instance_address(instance, ip_address_type),
+        state: instance.state()?.name()?.clone(),
+      })
+    })
+    .collect()
+}
@@ -0,0 +1,188 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+const LATEST_RELEASE_URL: &str =
+  "https://api.github.com/repos/stefansundin/route53-update/releases/latest";
+
+// The target triple that the binary was built for, set by build.rs.
+pub const TARGET: &str = env!("BUILD_TARGET");
+
+// The minisign public key that the release artifacts are signed with. It is provided when the release artifacts are built, so self-update is only available in binaries that were built for a release.
+pub const PUBLIC_KEY: Option<&str> = option_env!("SELF_UPDATE_PUBLIC_KEY");
+
+#[derive(Debug, Deserialize)]
+pub struct Release {
+  pub tag_name: String,
+  pub assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReleaseAsset {
+  pub name: String,
+  pub browser_download_url: String,
+}
+
+// The release artifacts are named after the target triple, e.g. route53-update-x86_64-unknown-linux-musl, with the signature in route53-update-x86_64-unknown-linux-musl.minisig.
+pub fn get_asset_name(target: &str) -> String {
+  format!("route53-update-{}", target)
+}
+
+// Compares versions like 0.0.2 and v0.1.0.
+pub fn is_newer_version(current: &str, latest: &str) -> bool {
+  let parse = |version: &str| -> Vec<u64> {
+    version
+      .trim_start_matches('v')
+      .split('.')
+      .map(|part| part.parse().unwrap_or(0))
+      .collect()
+  };
+  parse(latest) > parse(current)
+}
+
+fn http_client() -> reqwest::Client {
+  // The GitHub API rejects requests without a user agent
+  reqwest::Client::builder()
+    .user_agent(format!("route53-update/{}", env!("CARGO_PKG_VERSION")))
+    .build()
+    .expect("error building HTTP client")
+}
+
+async fn download(client: &reqwest::Client, url: &str) -> Result<Vec<u8>, String> {
+  let response = client
+    .get(url)
+    .send()
+    .await
+    .map_err(|err| format!("request to {} failed: {}", url, err))?;
+  if !response.status().is_success() {
+    return Err(format!(
+      "response from {} returned non-200 status code: {}",
+      url,
+      response.status()
+    ));
+  }
+  let bytes = response
+    .bytes()
+    .await
+    .map_err(|err| format!("could not read response from {}: {}", url, err))?;
+  Ok(bytes.to_vec())
+}
+
+// The trusted comment of the signature names the artifact and the release, e.g. "route53-update-x86_64-unknown-linux-musl v0.1.0".
+// The signature only covers the binary, so without it a signed binary of an older release or of another target could be served as the latest release.
+pub fn get_trusted_comment(asset_name: &str, tag_name: &str) -> String {
+  format!("{} {}", asset_name, tag_name)
+}
+
+pub fn verify_signature(
+  public_key: &str,
+  binary: &[u8],
+  signature: &str,
+  trusted_comment: &str,
+) -> Result<(), String> {
+  let public_key =
+    PublicKey::from_base64(public_key).map_err(|err| format!("invalid public key: {}", err))?;
+  let signature =
+    Signature::decode(signature).map_err(|err| format!("invalid signature: {}", err))?;
+  public_key
+    .verify(binary, &signature, false)
+    .map_err(|err| format!("signature verification failed: {}", err))?;
+  if signature.trusted_comment() != trusted_comment {
+    return Err(format!(
+      "the signature is for {:?}, expected {:?}",
+      signature.trusted_comment(),
+      trusted_comment
+    ));
+  }
+  Ok(())
+}
+
+// Replaces the running binary with the latest release if it is newer. Returns the new version if the binary was replaced.
+// With check_only the newer version is only reported.
+pub async fn self_update(check_only: bool) -> Result<Option<String>, String> {
+  let current_version = env!("CARGO_PKG_VERSION");
+  let client = http_client();
+  let release: Release = serde_json::from_slice(&download(&client, LATEST_RELEASE_URL).await?)
+    .map_err(|err| format!("could not parse the release: {}", err))?;
+  if !is_newer_version(current_version, &release.tag_name) {
+    eprintln!(
+      "Already up to date: {} (latest release is {})",
+      current_version, release.tag_name
+    );
+    return Ok(None);
+  }
+  eprintln!(
+    "A newer version is available: {} (current version is {})",
+    release.tag_name, current_version
+  );
+  if check_only {
+    return Ok(Some(release.tag_name));
+  }
+
+  let public_key = PUBLIC_KEY.ok_or(
+    "this binary was not built with a public key to verify the release with, please update it manually",
+  )?;
+  let asset_name = get_asset_name(TARGET);
+  let signature_name = format!("{}.minisig", asset_name);
+  let find_asset = |name: &str| {
+    release
+      .assets
+      .iter()
+      .find(|asset| asset.name == name)
+      .ok_or_else(|| format!("the release {} does not have {}", release.tag_name, name))
+  };
+  let asset = find_asset(&asset_name)?;
+  let signature_asset = find_asset(&signature_name)?;
+
+  eprintln!("Downloading {}", asset.browser_download_url);
+  let binary = download(&client, &asset.browser_download_url).await?;
+  let signature = download(&client, &signature_asset.browser_download_url).await?;
+  verify_signature(
+    public_key,
+    &binary,
+    &String::from_utf8_lossy(&signature),
+    &get_trusted_comment(&asset_name, &release.tag_name),
+  )?;
+  eprintln!("Verified the signature of {}", asset_name);
+
+  let current_exe =
+    std::env::current_exe().map_err(|err| format!("could not find the binary: {}", err))?;
+  replace_exe(&current_exe, &binary)?;
+  eprintln!("Updated {} to {}", current_exe.display(), release.tag_name);
+  Ok(Some(release.tag_name))
+}
+
+// The new binary is written next to the current one and then renamed over it, which is atomic and works while the program is running.
+#[cfg(unix)]
+fn replace_exe(current_exe: &Path, binary: &[u8]) -> Result<(), String> {
+  use std::os::unix::fs::PermissionsExt;
+
+  let new_exe = current_exe.with_extension("new");
+  fs::write(&new_exe, binary)
+    .map_err(|err| format!("could not write {}: {}", new_exe.display(), err))?;
+  fs::set_permissions(&new_exe, fs::Permissions::from_mode(0o755))
+    .map_err(|err| format!("could not make {} executable: {}", new_exe.display(), err))?;
+  fs::rename(&new_exe, current_exe)
+    .map_err(|err| format!("could not replace {}: {}", current_exe.display(), err))
+}
+
+// Windows does not allow a running binary to be replaced, but it can be renamed. So the current binary is moved out of the way first, and left behind as .old.exe since it can't be deleted while it is running.
+#[cfg(windows)]
+fn replace_exe(current_exe: &Path, binary: &[u8]) -> Result<(), String> {
+  let new_exe = current_exe.with_extension("new.exe");
+  let old_exe = current_exe.with_extension("old.exe");
+  fs::write(&new_exe, binary)
+    .map_err(|err| format!("could not write {}: {}", new_exe.display(), err))?;
+  // Left behind by the previous update
+  let _ = fs::remove_file(&old_exe);
+  fs::rename(current_exe, &old_exe)
+    .map_err(|err| format!("could not rename {}: {}", current_exe.display(), err))?;
+  fs::rename(&new_exe, current_exe).map_err(|err| {
+    // Put the current binary back so that the program can still be started
+    let _ = fs::rename(&old_exe, current_exe);
+    format!("could not replace {}: {}", current_exe.display(), err)
+  })
+}
@@ -0,0 +1,118 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+// Runs the daemon as a Windows service. The service control manager starts the program with the command line given to sc.exe, and a stop request is delivered to the daemon like ctrl-c so that --deregister-on-exit still works.
+
+use std::{
+  ffi::OsString,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc, Mutex, OnceLock,
+  },
+  time::Duration,
+};
+use tokio::sync::Notify;
+use windows_service::{
+  define_windows_service,
+  service::{
+    ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType,
+  },
+  service_control_handler::{self, ServiceControlHandlerResult, ServiceStatusHandle},
+  service_dispatcher,
+};
+
+pub const SERVICE_NAME: &str = "route53-update";
+
+// How long the service control manager should wait for the daemon to stop, e.g. while the record is deleted by --deregister-on-exit
+const STOP_WAIT_HINT: Duration = Duration::from_secs(30);
+
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+static STOP_NOTIFY: Notify = Notify::const_new();
+static STATUS_HANDLE: OnceLock<ServiceStatusHandle> = OnceLock::new();
+static STOPPED: Mutex<Option<mpsc::Sender<()>>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+// Connects to the service control manager. This blocks until the service has stopped, so it has to run on its own thread.
+// Fails if the program was not started by the service control manager.
+pub fn start() -> Result<(), String> {
+  service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+    .map_err(|err| format!("could not connect to the service control manager: {}", err))
+}
+
+// Resolves when the service control manager asks the service to stop, e.g. when the computer shuts down.
+// Every daemon task is notified when several records are updated by one process.
+pub async fn stop_requested() {
+  let notified = STOP_NOTIFY.notified();
+  if STOP_REQUESTED.load(Ordering::SeqCst) {
+    return;
+  }
+  notified.await;
+}
+
+// Reports to the service control manager that the daemon has finished.
+pub fn set_stopped() {
+  if let Some(tx) = STOPPED.lock().unwrap().take() {
+    let _ = tx.send(());
+  }
+}
+
+fn set_status(state: ServiceState, controls_accepted: ServiceControlAccept, wait_hint: Duration) {
+  let Some(status_handle) = STATUS_HANDLE.get() else {
+    return;
+  };
+  let status = ServiceStatus {
+    service_type: ServiceType::OWN_PROCESS,
+    current_state: state,
+    controls_accepted,
+    exit_code: ServiceExitCode::Win32(0),
+    checkpoint: 0,
+    wait_hint,
+    process_id: None,
+  };
+  if let Err(err) = status_handle.set_service_status(status) {
+    eprintln!("Could not set the service status: {}", err);
+  }
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+  let (tx, rx) = mpsc::channel();
+  *STOPPED.lock().unwrap() = Some(tx);
+
+  let status_handle = match service_control_handler::register(SERVICE_NAME, |control| match control
+  {
+    ServiceControl::Stop | ServiceControl::Shutdown => {
+      eprintln!("Received a stop request from the service control manager");
+      set_status(
+        ServiceState::StopPending,
+        ServiceControlAccept::empty(),
+        STOP_WAIT_HINT,
+      );
+      STOP_REQUESTED.store(true, Ordering::SeqCst);
+      STOP_NOTIFY.notify_waiters();
+      ServiceControlHandlerResult::NoError
+    }
+    ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+    _ => ServiceControlHandlerResult::NotImplemented,
+  }) {
+    Ok(status_handle) => status_handle,
+    Err(err) => {
+      eprintln!("Could not register the service control handler: {}", err);
+      return;
+    }
+  };
+  let _ = STATUS_HANDLE.set(status_handle);
+  set_status(
+    ServiceState::Running,
+    ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+    Duration::ZERO,
+  );
+
+  // Wait for the daemon to finish
+  let _ = rx.recv();
+  set_status(
+    ServiceState::Stopped,
+    ServiceControlAccept::empty(),
+    Duration::ZERO,
+  );
+}
@@ -0,0 +1,329 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::utils;
+
+use aws_sdk_route53::types::{
+  AliasTarget, Change, ChangeAction, ChangeBatch, CidrRoutingConfig, Coordinates, GeoLocation,
+  GeoProximityLocation, ResourceRecord, ResourceRecordSet, ResourceRecordSetFailover,
+  ResourceRecordSetRegion, RrType,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// The record sets that --clear deleted, so that they can be recreated with the restore command.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+  pub hosted_zone_id: String,
+  pub created_at: String,
+  pub record_sets: Vec<RecordSetSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordSetSnapshot {
+  pub name: String,
+  #[serde(rename = "type")]
+  pub record_type: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub ttl: Option<i64>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub values: Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub alias_target: Option<AliasTargetSnapshot>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub set_identifier: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub weight: Option<i64>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub region: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub failover: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub geo_location: Option<GeoLocationSnapshot>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub cidr_routing_config: Option<CidrRoutingConfigSnapshot>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub geo_proximity_location: Option<GeoProximityLocationSnapshot>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub multi_value_answer: Option<bool>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub health_check_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AliasTargetSnapshot {
+  pub hosted_zone_id: String,
+  pub dns_name: String,
+  pub evaluate_target_health: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeoLocationSnapshot {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub continent_code: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub country_code: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub subdivision_code: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CidrRoutingConfigSnapshot {
+  pub collection_id: String,
+  pub location_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeoProximityLocationSnapshot {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub aws_region: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub local_zone_group: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub coordinates: Option<CoordinatesSnapshot>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub bias: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoordinatesSnapshot {
+  pub latitude: String,
+  pub longitude: String,
+}
+
+impl From<&ResourceRecordSet> for RecordSetSnapshot {
+  fn from(r: &ResourceRecordSet) -> Self {
+    RecordSetSnapshot {
+      name: r.name().to_string(),
+      record_type: r.r#type().as_str().to_string(),
+      ttl: r.ttl(),
+      values: utils::get_record_values(r),
+      alias_target: r.alias_target().map(|alias_target| AliasTargetSnapshot {
+        hosted_zone_id: alias_target.hosted_zone_id().to_string(),
+        dns_name: alias_target.dns_name().to_string(),
+        evaluate_target_health: alias_target.evaluate_target_health(),
+      }),
+      set_identifier: r.set_identifier().map(|s| s.to_string()),
+      weight: r.weight(),
+      region: r.region().map(|region| region.as_str().to_string()),
+      failover: r.failover().map(|failover| failover.as_str().to_string()),
+      geo_location: r.geo_location().map(|geo_location| GeoLocationSnapshot {
+        continent_code: geo_location.continent_code().map(|s| s.to_string()),
+        country_code: geo_location.country_code().map(|s| s.to_string()),
+        subdivision_code: geo_location.subdivision_code().map(|s| s.to_string()),
+      }),
+      cidr_routing_config: r
+        .cidr_routing_config()
+        .map(|config| CidrRoutingConfigSnapshot {
+          collection_id: config.collection_id().to_string(),
+          location_name: config.location_name().to_string(),
+        }),
+      geo_proximity_location: r.geo_proximity_location().map(|location| {
+        GeoProximityLocationSnapshot {
+          aws_region: location.aws_region().map(|s| s.to_string()),
+          local_zone_group: location.local_zone_group().map(|s| s.to_string()),
+          coordinates: location
+            .coordinates()
+            .map(|coordinates| CoordinatesSnapshot {
+              latitude: coordinates.latitude().to_string(),
+              longitude: coordinates.longitude().to_string(),
+            }),
+          bias: location.bias(),
+        }
+      }),
+      multi_value_answer: r.multi_value_answer(),
+      health_check_id: r.health_check_id().map(|s| s.to_string()),
+    }
+  }
+}
+
+impl RecordSetSnapshot {
+  pub fn to_resource_record_set(&self) -> Result<ResourceRecordSet, String> {
+    let alias_target = match &self.alias_target {
+      Some(alias_target) => Some(
+        AliasTarget::builder()
+          .hosted_zone_id(&alias_target.hosted_zone_id)
+          .dns_name(&alias_target.dns_name)
+          .evaluate_target_health(alias_target.evaluate_target_health)
+          .build()
+          .map_err(|err| format!("invalid alias target: {}", err))?,
+      ),
+      None => None,
+    };
+    let geo_location = self.geo_location.as_ref().map(|geo_location| {
+      GeoLocation::builder()
+        .set_continent_code(geo_location.continent_code.clone())
+        .set_country_code(geo_location.country_code.clone())
+        .set_subdivision_code(geo_location.subdivision_code.clone())
+        .build()
+    });
+    let cidr_routing_config = match &self.cidr_routing_config {
+      Some(config) => Some(
+        CidrRoutingConfig::builder()
+          .collection_id(&config.collection_id)
+          .location_name(&config.location_name)
+          .build()
+          .map_err(|err| format!("invalid CIDR routing config: {}", err))?,
+      ),
+      None => None,
+    };
+    let geo_proximity_location = match &self.geo_proximity_location {
+      Some(location) => {
+        let coordinates = match &location.coordinates {
+          Some(coordinates) => Some(
+            Coordinates::builder()
+              .latitude(&coordinates.latitude)
+              .longitude(&coordinates.longitude)
+              .build()
+              .map_err(|err| format!("invalid coordinates: {}", err))?,
+          ),
+          None => None,
+        };
+        Some(
+          GeoProximityLocation::builder()
+            .set_aws_region(location.aws_region.clone())
+            .set_local_zone_group(location.local_zone_group.clone())
+            .set_coordinates(coordinates)
+            .set_bias(location.bias)
+            .build(),
+        )
+      }
+      None => None,
+    };
+    let resource_records = if self.values.is_empty() {
+      None
+    } else {
+      Some(
+        self
+          .values
+          .iter()
+          .map(|value| ResourceRecord::builder().value(value).build())
+          .collect::<Result<Vec<_>, _>>()
+          .map_err(|err| format!("invalid value: {}", err))?,
+      )
+    };
+    ResourceRecordSet::builder()
+      .name(&self.name)
+      .r#type(RrType::from(self.record_type.as_str()))
+      .set_ttl(self.ttl)
+      .set_resource_records(resource_records)
+      .set_alias_target(alias_target)
+      .set_set_identifier(self.set_identifier.clone())
+      .set_weight(self.weight)
+      .set_region(self.region.as_deref().map(ResourceRecordSetRegion::from))
+      .set_failover(
+        self
+          .failover
+          .as_deref()
+          .map(ResourceRecordSetFailover::from),
+      )
+      .set_geo_location(geo_location)
+      .set_cidr_routing_config(cidr_routing_config)
+      .set_geo_proximity_location(geo_proximity_location)
+      .set_multi_value_answer(self.multi_value_answer)
+      .set_health_check_id(self.health_check_id.clone())
+      .build()
+      .map_err(|err| format!("invalid record set {}: {}", self.name, err))
+  }
+}
+
+// Writes the snapshot to a file named after the time, and returns its path.
+pub fn write_snapshot(directory: &Path, snapshot: &Snapshot) -> Result<PathBuf, String> {
+  let path = directory.join(format!(
+    "route53-update-restore-{}.json",
+    snapshot.created_at.replace(':', "")
+  ));
+  let contents = serde_json::to_string_pretty(snapshot).expect("error serializing the snapshot");
+  std::fs::write(&path, contents)
+    .map_err(|err| format!("could not write {}: {}", path.display(), err))?;
+  Ok(path)
+}
+
+// Returns the existing record sets that have to be deleted for the restored record sets to be created, i.e. a CNAME record can not coexist with other records with the same name.
+pub fn get_conflicting_record_sets(
+  existing: &[ResourceRecordSet],
+  restored: &[ResourceRecordSet],
+) -> Vec<ResourceRecordSet> {
+  existing
+    .iter()
+    .filter(|r| {
+      restored.iter().any(|s| {
+        s.name() == r.name()
+          && (s.r#type() == &RrType::Cname || r.r#type() == &RrType::Cname)
+          && !(s.r#type() == r.r#type() && s.set_identifier() == r.set_identifier())
+      })
+    })
+    .cloned()
+    .collect()
+}
+
+// Recreates the record sets in one change batch, deleting the records that conflict with them. Returns the change ID.
+pub async fn restore(route53_client: &aws_sdk_route53::Client, snapshot: &Snapshot) -> String {
+  let restored: Vec<ResourceRecordSet> = snapshot
+    .record_sets
+    .iter()
+    .map(|r| r.to_resource_record_set())
+    .collect::<Result<_, _>>()
+    .unwrap_or_else(|err| panic!("{}", err));
+  let mut existing = Vec::new();
+  let mut names: Vec<&str> = restored.iter().map(|r| r.name()).collect();
+  names.dedup();
+  for name in names {
+    let response = route53_client
+      .list_resource_record_sets()
+      .hosted_zone_id(&snapshot.hosted_zone_id)
+      .start_record_name(name)
+      .send()
+      .await
+      .expect("could not list record sets");
+    existing.extend(
+      response
+        .resource_record_sets
+        .into_iter()
+        .filter(|r| r.name() == name),
+    );
+  }
+
+  let mut change_batch_builder = ChangeBatch::builder().comment(format!(
+    "Restore the record sets deleted at {}",
+    snapshot.created_at
+  ));
+  for (action, record_sets) in [
+    (
+      ChangeAction::Delete,
+      get_conflicting_record_sets(&existing, &restored),
+    ),
+    (ChangeAction::Upsert, restored),
+  ] {
+    for record_set in record_sets {
+      eprintln!(
+        "{} {} {}",
+        if action == ChangeAction::Delete {
+          "Deleting"
+        } else {
+          "Restoring"
+        },
+        record_set.r#type().as_str(),
+        record_set.name()
+      );
+      let change = Change::builder()
+        .action(action.clone())
+        .resource_record_set(record_set)
+        .build()
+        .expect("error building change set");
+      change_batch_builder = change_batch_builder.changes(change);
+    }
+  }
+  let change_batch = change_batch_builder
+    .build()
+    .expect("error building change batch");
+  let response = route53_client
+    .change_resource_record_sets()
+    .hosted_zone_id(&snapshot.hosted_zone_id)
+    .change_batch(change_batch)
+    .send()
+    .await
+    .expect("could not restore the record sets");
+  println!("{:?}", response);
+  response.change_info().unwrap().id().to_string()
+}
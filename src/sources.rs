@@ -0,0 +1,603 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::{types, utils};
+
+use aws_sdk_route53::types::RrType;
+use ipnet::Ipv6Net;
+use std::{collections::HashMap, fmt, future::Future, net::Ipv6Addr, pin::Pin};
+
+// --value-from ecs-metadata
+pub async fn get_ecs_values(
+  clients: &types::MetadataClients,
+  record_type: Option<&RrType>,
+  network_mode: types::EcsNetworkMode,
+  ec2_options: &types::Ec2MetadataOptions,
+) -> Vec<String> {
+  let Some(ecs_task_metadata) = utils::get_ecs_task_metadata(clients).await else {
+    return Vec::new();
+  };
+  eprintln!("ecs_task_metadata: {:?}", ecs_task_metadata);
+  let networks: Vec<&types::EcsContainerNetworkMetadata> = ecs_task_metadata
+    .containers
+    .iter()
+    .flat_map(|c| &c.networks)
+    .collect();
+  let network_mode = match network_mode {
+    types::EcsNetworkMode::Auto => match networks.iter().find_map(|n| n.network_mode.as_deref()) {
+      Some("bridge") => types::EcsNetworkMode::Bridge,
+      Some("host") => types::EcsNetworkMode::Host,
+      _ => types::EcsNetworkMode::Awsvpc,
+    },
+    mode => mode,
+  };
+  if network_mode != types::EcsNetworkMode::Awsvpc {
+    // The container addresses are only reachable from the container instance, so the addresses of the instance are used instead
+    eprintln!(
+      "The task uses {:?} network mode, using the address of the container instance",
+      network_mode
+    );
+    return get_ec2_values(clients, record_type, ec2_options).await;
+  }
+  // In awsvpc network mode all the containers share the network interface of the task, so every container lists the same addresses
+  let mut values: Vec<String> = Vec::new();
+  for network in networks {
+    let addresses = match record_type {
+      Some(RrType::A) => network.ipv4_addresses.as_deref(),
+      Some(RrType::Aaaa) => network.ipv6_addresses.as_deref(),
+      _ => None,
+    };
+    for address in addresses.unwrap_or_default() {
+      // The ECS metadata service can annoyingly return "IPv4Addresses": [""]
+      if !address.is_empty() && !values.contains(address) {
+        values.push(address.clone());
+      }
+    }
+  }
+  values
+}
+
+// --value-from ec2-metadata
+pub async fn get_ec2_values(
+  clients: &types::MetadataClients,
+  record_type: Option<&RrType>,
+  options: &types::Ec2MetadataOptions,
+) -> Vec<String> {
+  // The per-interface path and the top-level path that only has the first address
+  let (interface_path, path) = match (record_type, options.ip_address_type) {
+    (Some(RrType::A) | None, types::IPAddressType::Public) => ("public-ipv4s", "public-ipv4"),
+    (Some(RrType::A) | None, types::IPAddressType::Private) => ("local-ipv4s", "local-ipv4"),
+    (Some(RrType::Aaaa), _) => ("ipv6s", "ipv6"),
+    _ => panic!("--value-from is only usable with --record-type A or AAAA"),
+  };
+
+  if options.eni_index.is_some()
+    || options.secondary_ip_index.is_some()
+    || (options.all_ipv6 && record_type == Some(&RrType::Aaaa))
+  {
+    // The top-level paths only return the primary address of the primary network interface, so the per-interface paths have to be used instead
+    let Some(mac) = get_ec2_mac(clients, options.eni_index).await else {
+      return Vec::new();
+    };
+    let Some(value) = clients
+      .get_ec2_metadata(&format!(
+        "/latest/meta-data/network/interfaces/macs/{}{}",
+        mac, interface_path
+      ))
+      .await
+    else {
+      return Vec::new();
+    };
+    let values: Vec<String> = value.lines().map(|v| v.to_string()).collect();
+    return match options.secondary_ip_index {
+      Some(index) => values.get(index).cloned().into_iter().collect(),
+      None if options.all_ipv6 && record_type == Some(&RrType::Aaaa) => values,
+      None => values.into_iter().take(1).collect(),
+    };
+  }
+
+  clients
+    .get_ec2_metadata(&format!("/latest/meta-data/{}", path))
+    .await
+    .into_iter()
+    .collect()
+}
+
+// --value-from lightsail
+pub fn get_lightsail_instance_values(
+  instance: &aws_sdk_lightsail::types::Instance,
+  record_type: Option<&RrType>,
+  ip_address_type: types::IPAddressType,
+) -> Vec<String> {
+  match (record_type, ip_address_type) {
+    (Some(RrType::Aaaa), _) => instance.ipv6_addresses().to_vec(),
+    (Some(RrType::A) | None, types::IPAddressType::Public) => instance
+      .public_ip_address()
+      .map(|ip| ip.to_string())
+      .into_iter()
+      .collect(),
+    (Some(RrType::A) | None, types::IPAddressType::Private) => instance
+      .private_ip_address()
+      .map(|ip| ip.to_string())
+      .into_iter()
+      .collect(),
+    _ => panic!("--value-from is only usable with --record-type A or AAAA"),
+  }
+}
+
+// The public address is the static IP if one is attached to the instance.
+pub async fn get_lightsail_values(
+  lightsail_client: &aws_sdk_lightsail::Client,
+  instance_name: &str,
+  record_type: Option<&RrType>,
+  ip_address_type: types::IPAddressType,
+) -> Vec<String> {
+  let response = lightsail_client
+    .get_instance()
+    .instance_name(instance_name)
+    .send()
+    .await
+    .expect("could not get the Lightsail instance");
+  let Some(instance) = response.instance() else {
+    return Vec::new();
+  };
+  eprintln!(
+    "Lightsail instance {}: static IP: {}",
+    instance_name,
+    instance.is_static_ip().unwrap_or(false)
+  );
+  get_lightsail_instance_values(instance, record_type, ip_address_type)
+}
+
+// Returns the MAC address (with a trailing slash) of the network interface with the given device number, or of the primary network interface.
+async fn get_ec2_mac(clients: &types::MetadataClients, eni_index: Option<usize>) -> Option<String> {
+  let Some(eni_index) = eni_index else {
+    let mac = clients.get_ec2_metadata("/latest/meta-data/mac").await?;
+    return Some(format!("{}/", mac));
+  };
+  let macs = clients
+    .get_ec2_metadata("/latest/meta-data/network/interfaces/macs/")
+    .await?;
+  for mac in macs.lines() {
+    // An interface that is being attached or detached may not have a device number yet, which should not hide the other interfaces
+    let Some(device_number) = clients
+      .get_ec2_metadata(&format!(
+        "/latest/meta-data/network/interfaces/macs/{}device-number",
+        mac
+      ))
+      .await
+    else {
+      eprintln!(
+        "could not get the device number of the network interface {}",
+        mac
+      );
+      continue;
+    };
+    if device_number.trim() == eni_index.to_string() {
+      return Some(mac.to_string());
+    }
+  }
+  eprintln!(
+    "could not find a network interface with device number {}",
+    eni_index
+  );
+  None
+}
+
+// --ipv6-from-prefix without a prefix
+pub async fn get_ec2_ipv6_prefix(clients: &types::MetadataClients) -> Option<Ipv6Net> {
+  let mac = clients.get_ec2_metadata("/latest/meta-data/mac").await?;
+  let value = clients
+    .get_ec2_metadata(&format!(
+      "/latest/meta-data/network/interfaces/macs/{}/ipv6-prefix",
+      mac
+    ))
+    .await?;
+  value.lines().next()?.parse::<Ipv6Net>().ok()
+}
+
+// --srv-from-ecs
+// Bridge mode tasks are usually assigned a dynamic host port, so the SRV record points to the container instance and the host port.
+pub async fn get_ecs_srv_value(
+  clients: &types::MetadataClients,
+  srv: &types::SrvFromEcs,
+  ip_address_type: types::IPAddressType,
+) -> Result<String, String> {
+  let ecs_task_metadata = utils::get_ecs_task_metadata(clients)
+    .await
+    .ok_or("could not get the ECS task metadata (missing ECS environment variables)")?;
+  let container = ecs_task_metadata
+    .containers
+    .iter()
+    .find(|c| c.name.as_deref() == Some(srv.container_name.as_str()))
+    .ok_or_else(|| format!("could not find a container named {}", srv.container_name))?;
+  let host_port = container
+    .ports
+    .iter()
+    .find(|p| p.container_port == srv.container_port)
+    .and_then(|p| p.host_port)
+    .ok_or_else(|| {
+      format!(
+        "container {} does not publish port {} on the host",
+        srv.container_name, srv.container_port
+      )
+    })?;
+  let path = match ip_address_type {
+    types::IPAddressType::Public => "/latest/meta-data/public-hostname",
+    types::IPAddressType::Private => "/latest/meta-data/local-hostname",
+  };
+  let hostname = clients.get_ec2_metadata(path).await.ok_or(
+    "could not get the hostname of the container instance from the EC2 instance metadata service",
+  )?;
+  Ok(format!("0 0 {} {}.", host_port, hostname.trim()))
+}
+
+// --value-from-url
+pub async fn get_url_value(clients: &types::MetadataClients, url: &str) -> Result<String, String> {
+  let response = clients
+    .http
+    .get(url)
+    .send()
+    .await
+    .map_err(|err| format!("request to {} failed: {}", url, err))?;
+  if response.status() != reqwest::StatusCode::OK {
+    return Err(format!(
+      "response from {} returned non-200 status code: {}",
+      url,
+      response.status()
+    ));
+  }
+  let response_text = response
+    .text()
+    .await
+    .map_err(|err| format!("could not read response from {}: {}", url, err))?
+    .trim()
+    .to_string();
+  eprintln!("{} returned {:?}", url, response_text);
+  Ok(response_text)
+}
+
+// --value-from auto
+// All of the sources are queried concurrently and the first source in the preference order that returned a value wins.
+pub async fn get_auto_values(
+  clients: &types::MetadataClients,
+  order: &[types::ValueSourceKind],
+  record_type: Option<&RrType>,
+  ecs_network_mode: types::EcsNetworkMode,
+  ec2_options: &types::Ec2MetadataOptions,
+  url: Option<&str>,
+) -> Vec<String> {
+  let url_future = async {
+    match url {
+      Some(url) => get_url_value(clients, url)
+        .await
+        .map(|v| vec![v])
+        .unwrap_or_else(|err| {
+          eprintln!("{}", err);
+          Vec::new()
+        }),
+      None => Vec::new(),
+    }
+  };
+  let (ecs_values, ec2_values, url_values) = tokio::join!(
+    get_ecs_values(clients, record_type, ecs_network_mode, ec2_options),
+    get_ec2_values(clients, record_type, ec2_options),
+    url_future,
+  );
+
+  for kind in order {
+    let values = match kind {
+      types::ValueSourceKind::Ecs => &ecs_values,
+      types::ValueSourceKind::Ec2 => &ec2_values,
+      types::ValueSourceKind::Url => &url_values,
+    };
+    if !values.is_empty() {
+      eprintln!("Using value from source: {:?}", kind);
+      return values.clone();
+    }
+  }
+  Vec::new()
+}
+
+// --value-template
+// Panics if a placeholder for the EC2 instance metadata service can't be looked up, rather than publishing a partial value.
+pub async fn get_template_value(
+  clients: &types::MetadataClients,
+  template: &str,
+  instance_identity: Option<&types::InstanceIdentityDocument>,
+  ecs_task_metadata: Option<&types::EcsTaskMetadata>,
+) -> String {
+  let placeholders = utils::get_template_placeholders(template);
+  let mut vars = instance_identity
+    .map(utils::instance_identity_vars)
+    .unwrap_or_default();
+  if let Some(metadata) = ecs_task_metadata {
+    vars.extend(utils::ecs_task_vars(metadata));
+  }
+  vars.insert("hostname", utils::get_hostname());
+  vars.insert("timestamp", utils::get_timestamp());
+
+  // The other placeholders are looked up in the EC2 instance metadata service
+  // All of the values are inserted in one pass, so that a value is not expanded again
+  for placeholder in &placeholders {
+    if vars.contains_key(placeholder.as_str()) {
+      continue;
+    }
+    let path = match placeholder.as_str() {
+      "public_ipv4" => "public-ipv4",
+      "local_ipv4" => "local-ipv4",
+      "ipv6" => "ipv6",
+      p => match p.strip_prefix("ec2.") {
+        Some(path) => path,
+        None => continue,
+      },
+    };
+    let value = clients
+      .get_ec2_metadata(&format!("/latest/meta-data/{}", path))
+      .await
+      .unwrap_or_else(|| panic!("could not get {} from the instance metadata service", path));
+    vars.insert(placeholder.as_str(), value);
+  }
+  utils::render_template(template, &vars)
+}
+
+pub type ValueFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<String>, String>> + Send + 'a>>;
+
+// A source of record values. The built-in sources below are used by the command line, and embedders can implement it to use their own source with types::ValueSource::Custom.
+pub trait ValueSource: Send + Sync {
+  fn name(&self) -> String;
+  fn resolve(&self) -> ValueFuture<'_>;
+}
+
+impl fmt::Debug for dyn ValueSource {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "ValueSource({})", self.name())
+  }
+}
+
+fn require_values(source: &str, values: Vec<String>) -> Result<Vec<String>, String> {
+  if values.is_empty() {
+    return Err(format!("{} did not return a value", source));
+  }
+  Ok(values)
+}
+
+pub struct Ec2MetadataSource {
+  pub clients: types::MetadataClients,
+  pub record_type: Option<RrType>,
+  pub options: types::Ec2MetadataOptions,
+}
+impl ValueSource for Ec2MetadataSource {
+  fn name(&self) -> String {
+    "the EC2 instance metadata service".to_string()
+  }
+  fn resolve(&self) -> ValueFuture<'_> {
+    Box::pin(async move {
+      let values = get_ec2_values(&self.clients, self.record_type.as_ref(), &self.options).await;
+      require_values(&self.name(), values)
+    })
+  }
+}
+
+pub struct EcsMetadataSource {
+  pub clients: types::MetadataClients,
+  pub record_type: Option<RrType>,
+  pub network_mode: types::EcsNetworkMode,
+  pub ec2_options: types::Ec2MetadataOptions,
+}
+impl ValueSource for EcsMetadataSource {
+  fn name(&self) -> String {
+    "the ECS task metadata endpoint".to_string()
+  }
+  fn resolve(&self) -> ValueFuture<'_> {
+    Box::pin(async move {
+      let values = get_ecs_values(
+        &self.clients,
+        self.record_type.as_ref(),
+        self.network_mode,
+        &self.ec2_options,
+      )
+      .await;
+      require_values(&self.name(), values)
+    })
+  }
+}
+
+pub struct UrlSource {
+  pub clients: types::MetadataClients,
+  pub url: String,
+}
+impl ValueSource for UrlSource {
+  fn name(&self) -> String {
+    self.url.clone()
+  }
+  fn resolve(&self) -> ValueFuture<'_> {
+    Box::pin(async move { Ok(vec![get_url_value(&self.clients, &self.url).await?]) })
+  }
+}
+
+// The global addresses of a local network interface, read with the ip command (Linux only).
+pub struct InterfaceSource {
+  pub interface: String,
+  pub record_type: Option<RrType>,
+}
+impl ValueSource for InterfaceSource {
+  fn name(&self) -> String {
+    format!("the network interface {}", self.interface)
+  }
+  fn resolve(&self) -> ValueFuture<'_> {
+    Box::pin(async move {
+      let family = match self.record_type {
+        Some(RrType::Aaaa) => "-6",
+        _ => "-4",
+      };
+      let output = run_ip(&[
+        family,
+        "addr",
+        "show",
+        "dev",
+        &self.interface,
+        "scope",
+        "global",
+      ])
+      .await?;
+      let values = parse_ip_addr_output(&output);
+      require_values(&self.name(), values)
+    })
+  }
+}
+
+async fn run_ip(args: &[&str]) -> Result<String, String> {
+  let output = tokio::process::Command::new("ip")
+    .arg("-o")
+    .args(args)
+    .output()
+    .await
+    .map_err(|err| format!("could not run ip: {}", err))?;
+  if !output.status.success() {
+    return Err(format!(
+      "ip failed: {}",
+      String::from_utf8_lossy(&output.stderr).trim()
+    ));
+  }
+  Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// The global IPv4 addresses of all of the network interfaces, with the name of the interface (only on Linux, where the ip command is available).
+pub async fn get_interface_ipv4_addresses() -> Result<Vec<(String, String)>, String> {
+  let output = run_ip(&["-4", "addr", "show", "scope", "global"]).await?;
+  Ok(parse_ip_addr_interfaces(&output))
+}
+
+// The interfaces that the IPv4 default routes go through, i.e. the interfaces that connect the host to the internet.
+pub async fn get_default_route_interfaces() -> Result<Vec<String>, String> {
+  let output = run_ip(&["-4", "route", "show", "default"]).await?;
+  Ok(parse_default_route_interfaces(&output))
+}
+
+// Parses the output of ip route show default, e.g.:
+// default via 192.0.2.1 dev eth0 proto dhcp src 192.0.2.10 metric 100
+pub fn parse_default_route_interfaces(output: &str) -> Vec<String> {
+  output
+    .lines()
+    .filter_map(|line| {
+      let mut fields = line.split_whitespace();
+      fields.find(|field| *field == "dev")?;
+      fields.next().map(|interface| interface.to_string())
+    })
+    .collect()
+}
+
+// --resolve-value
+// Values that are not IP addresses are resolved with the system resolver, e.g. to mirror a record at another DNS provider. AAAA records use the IPv6 addresses, otherwise the IPv4 addresses are used.
+pub async fn resolve_values(
+  values: &[String],
+  record_type: Option<&RrType>,
+) -> Result<Vec<String>, String> {
+  let ipv6 = record_type == Some(&RrType::Aaaa);
+  let mut addresses = Vec::new();
+  for value in values {
+    if value.parse::<std::net::IpAddr>().is_ok() {
+      addresses.push(value.clone());
+      continue;
+    }
+    let resolved: Vec<String> = tokio::net::lookup_host((value.trim_end_matches('.'), 0))
+      .await
+      .map_err(|err| format!("could not resolve {}: {}", value, err))?
+      .map(|addr| addr.ip())
+      .filter(|ip| ip.is_ipv6() == ipv6)
+      .map(|ip| ip.to_string())
+      .collect();
+    if resolved.is_empty() {
+      return Err(format!(
+        "{} has no {} addresses",
+        value,
+        if ipv6 { "IPv6" } else { "IPv4" }
+      ));
+    }
+    eprintln!("Resolved {} to {}", value, resolved.join(","));
+    addresses.extend(resolved);
+  }
+  Ok(utils::normalize_values(addresses))
+}
+
+// Parses the output of ip -o addr show, e.g.:
+// 2: eth0    inet 192.0.2.10/24 brd 192.0.2.255 scope global eth0\       valid_lft forever preferred_lft forever
+pub fn parse_ip_addr_output(output: &str) -> Vec<String> {
+  output
+    .lines()
+    .filter_map(|line| {
+      let mut fields = line.split_whitespace();
+      fields.find(|field| *field == "inet" || *field == "inet6")?;
+      let address = fields.next()?;
+      Some(address.split('/').next().unwrap().to_string())
+    })
+    .collect()
+}
+
+// The flags of the global IPv6 addresses of all of the network interfaces, e.g. temporary, mngtmpaddr and deprecated (Linux only).
+pub async fn get_ipv6_address_flags() -> Result<HashMap<Ipv6Addr, Vec<String>>, String> {
+  let output = run_ip(&["-6", "addr", "show", "scope", "global"]).await?;
+  Ok(parse_ipv6_address_flags(&output))
+}
+
+// The flags come after the scope, e.g.:
+// 2: eth0    inet6 2001:db8::10/64 scope global temporary dynamic \       valid_lft 86400sec preferred_lft 14400sec
+pub fn parse_ipv6_address_flags(output: &str) -> HashMap<Ipv6Addr, Vec<String>> {
+  output
+    .lines()
+    .filter_map(|line| {
+      let mut fields = line.split_whitespace();
+      fields.find(|field| *field == "inet6")?;
+      let address = fields.next()?.split('/').next()?.parse::<Ipv6Addr>().ok()?;
+      fields.find(|field| *field == "scope")?;
+      let flags = fields
+        .skip(1)
+        .take_while(|field| !field.starts_with('\\') && !field.contains("_lft"))
+        .map(|field| field.to_string())
+        .collect();
+      Some((address, flags))
+    })
+    .collect()
+}
+
+// Like parse_ip_addr_output, with the name of the interface that has the address.
+pub fn parse_ip_addr_interfaces(output: &str) -> Vec<(String, String)> {
+  output
+    .lines()
+    .filter_map(|line| {
+      let interface = line.split_whitespace().nth(1)?;
+      let address = parse_ip_addr_output(line).pop()?;
+      Some((interface.to_string(), address))
+    })
+    .collect()
+}
+
+// The output of a shell command, one value per line.
+pub struct CommandSource {
+  pub command: String,
+}
+impl ValueSource for CommandSource {
+  fn name(&self) -> String {
+    format!("the command {:?}", self.command)
+  }
+  fn resolve(&self) -> ValueFuture<'_> {
+    Box::pin(async move {
+      let output = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(&self.command)
+        .output()
+        .await
+        .map_err(|err| format!("could not run {}: {}", self.name(), err))?;
+      if !output.status.success() {
+        return Err(format!("{} failed: {}", self.name(), output.status));
+      }
+      let values = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+      require_values(&self.name(), values)
+    })
+  }
+}
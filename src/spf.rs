@@ -0,0 +1,104 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+// --spf-include
+// Adds include mechanisms to the SPF record, keeping the other mechanisms and the other TXT values with the same name (e.g. verification tokens).
+
+use crate::utils;
+
+// RFC 7208 section 4.6.4
+pub const MAX_DNS_LOOKUPS: usize = 10;
+
+const DEFAULT_ALL: &str = "~all";
+
+// Returns the terms after v=spf1 if the TXT value is an SPF record. The strings of the value are joined, since a long record is split into multiple strings.
+pub fn parse_spf(value: &str) -> Option<Vec<String>> {
+  let text = if value.starts_with('"') {
+    utils::get_txt_strings(value).concat()
+  } else {
+    value.to_string()
+  };
+  let mut terms = text.split_whitespace();
+  if !terms.next()?.eq_ignore_ascii_case("v=spf1") {
+    return None;
+  }
+  Some(terms.map(str::to_string).collect())
+}
+
+fn mechanism_name(term: &str) -> String {
+  term
+    .trim_start_matches(['+', '-', '~', '?'])
+    .split([':', '/', '='])
+    .next()
+    .unwrap_or_default()
+    .to_lowercase()
+}
+
+// Adds the includes that are not already in the record, before the all mechanism or the redirect modifier, which have to be last.
+pub fn merge_spf(mut terms: Vec<String>, includes: &[String]) -> Vec<String> {
+  for include in includes {
+    let term = format!("include:{}", include.trim_end_matches('.'));
+    if terms
+      .iter()
+      .any(|t| t.trim_start_matches('+').eq_ignore_ascii_case(&term))
+    {
+      continue;
+    }
+    let position = terms
+      .iter()
+      .position(|t| matches!(mechanism_name(t).as_str(), "all" | "redirect"))
+      .unwrap_or(terms.len());
+    terms.insert(position, term);
+  }
+  if !terms
+    .iter()
+    .any(|t| matches!(mechanism_name(t).as_str(), "all" | "redirect"))
+  {
+    terms.push(DEFAULT_ALL.to_string());
+  }
+  terms
+}
+
+// The mechanisms and modifiers that cause DNS lookups when the record is evaluated. The lookups of the included records are not counted.
+pub fn count_dns_lookups(terms: &[String]) -> usize {
+  terms
+    .iter()
+    .filter(|t| {
+      matches!(
+        mechanism_name(t).as_str(),
+        "include" | "a" | "mx" | "ptr" | "exists" | "redirect"
+      )
+    })
+    .count()
+}
+
+pub fn format_spf(terms: &[String]) -> String {
+  std::iter::once("v=spf1")
+    .chain(terms.iter().map(String::as_str))
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+// Returns the TXT values with the includes merged into the SPF record, which is added if there is none. The other values are kept as they are.
+pub fn merge_spf_values(values: &[String], includes: &[String]) -> Result<Vec<String>, String> {
+  let spf_values: Vec<&String> = values.iter().filter(|v| parse_spf(v).is_some()).collect();
+  if spf_values.len() > 1 {
+    return Err(format!(
+      "there are {} SPF records, which is invalid (RFC 7208 section 4.5), merge them into one first",
+      spf_values.len()
+    ));
+  }
+  let terms = spf_values
+    .first()
+    .and_then(|v| parse_spf(v))
+    .unwrap_or_default();
+  let spf = utils::quote_txt_value(&format_spf(&merge_spf(terms, includes)));
+  Ok(
+    values
+      .iter()
+      .filter(|v| parse_spf(v).is_none())
+      .cloned()
+      .chain(std::iter::once(spf))
+      .collect(),
+  )
+}
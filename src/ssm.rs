@@ -0,0 +1,99 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+// --value-from-ssm and --publish-to-ssm
+// The value is read from an SSM parameter, or from a Secrets Manager secret when the ARN of a secret is given.
+// The value can also be written to an SSM parameter, so that other automation can read it without DNS.
+
+use aws_sdk_ssm::types::ParameterType;
+
+pub fn is_secret_arn(name: &str) -> bool {
+  name.starts_with("arn:") && name.split(':').nth(2) == Some("secretsmanager")
+}
+
+// A StringList parameter has one value per comma-separated item.
+pub fn parse_parameter_value(value: &str, parameter_type: Option<&ParameterType>) -> Vec<String> {
+  let values: Vec<&str> = match parameter_type {
+    Some(ParameterType::StringList) => value.split(',').collect(),
+    _ => vec![value],
+  };
+  values
+    .into_iter()
+    .map(|v| v.trim().to_string())
+    .filter(|v| !v.is_empty())
+    .collect()
+}
+
+// Multiple values are stored as a StringList parameter. TXT values are stored without the quotes.
+pub fn format_parameter_value(values: &[String]) -> (String, ParameterType) {
+  let values: Vec<&str> = values
+    .iter()
+    .map(|v| {
+      v.strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(v)
+    })
+    .collect();
+  let parameter_type = if values.len() > 1 {
+    ParameterType::StringList
+  } else {
+    ParameterType::String
+  };
+  (values.join(","), parameter_type)
+}
+
+pub async fn get_parameter_values(
+  ssm_client: &aws_sdk_ssm::Client,
+  name: &str,
+) -> Result<Vec<String>, String> {
+  let response = ssm_client
+    .get_parameter()
+    .name(name)
+    .with_decryption(true)
+    .send()
+    .await
+    .map_err(|err| format!("could not get the SSM parameter {}: {}", name, err))?;
+  let parameter = response
+    .parameter()
+    .ok_or_else(|| format!("the SSM parameter {} was not found", name))?;
+  let values = parse_parameter_value(parameter.value().unwrap_or_default(), parameter.r#type());
+  if values.is_empty() {
+    return Err(format!("the SSM parameter {} is empty", name));
+  }
+  Ok(values)
+}
+
+pub async fn get_secret_values(
+  secrets_client: &aws_sdk_secretsmanager::Client,
+  arn: &str,
+) -> Result<Vec<String>, String> {
+  let response = secrets_client
+    .get_secret_value()
+    .secret_id(arn)
+    .send()
+    .await
+    .map_err(|err| format!("could not get the secret {}: {}", arn, err))?;
+  match response.secret_string().map(str::trim) {
+    Some(value) if !value.is_empty() => Ok(vec![value.to_string()]),
+    _ => Err(format!("the secret {} does not have a string value", arn)),
+  }
+}
+
+pub async fn put_parameter(
+  ssm_client: &aws_sdk_ssm::Client,
+  name: &str,
+  values: &[String],
+) -> Result<(), String> {
+  let (value, parameter_type) = format_parameter_value(values);
+  ssm_client
+    .put_parameter()
+    .name(name)
+    .value(&value)
+    .r#type(parameter_type)
+    .overwrite(true)
+    .send()
+    .await
+    .map_err(|err| format!("could not put the SSM parameter {}: {}", name, err))?;
+  eprintln!("Published {} to the SSM parameter {}", value, name);
+  Ok(())
+}
@@ -0,0 +1,150 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use std::time::{Duration, Instant};
+
+// Replaces the values of the "value" endpoints in a traffic policy document. Each old value is replaced by the new value at the same position.
+// https://docs.aws.amazon.com/Route53/latest/APIReference/api-policies-traffic-policy-document-format.html
+pub fn replace_endpoint_values(
+  document: &str,
+  old_values: &[String],
+  new_values: &[String],
+) -> Result<String, String> {
+  if old_values.len() != new_values.len() {
+    return Err(format!(
+      "the traffic policy record has {} value(s), can not replace them with {} value(s)",
+      old_values.len(),
+      new_values.len()
+    ));
+  }
+  let mut document: serde_json::Value = serde_json::from_str(document)
+    .map_err(|err| format!("could not parse the traffic policy document: {}", err))?;
+  let Some(endpoints) = document
+    .get_mut("Endpoints")
+    .and_then(|endpoints| endpoints.as_object_mut())
+  else {
+    return Err("the traffic policy document does not have any endpoints".to_string());
+  };
+  let mut replaced = 0;
+  for endpoint in endpoints.values_mut() {
+    if endpoint.get("Type").and_then(|t| t.as_str()) != Some("value") {
+      continue;
+    }
+    let Some(value) = endpoint.get("Value").and_then(|v| v.as_str()) else {
+      continue;
+    };
+    if let Some(i) = old_values.iter().position(|old| old == value) {
+      endpoint["Value"] = serde_json::Value::String(new_values[i].clone());
+      replaced += 1;
+    }
+  }
+  if replaced == 0 {
+    return Err(format!(
+      "could not find an endpoint with the value {} in the traffic policy document",
+      old_values.join(",")
+    ));
+  }
+  Ok(document.to_string())
+}
+
+// Updates the traffic policy instance that manages the record. If the value changed then a new version of the traffic policy is created with the new value.
+// Note that the new version of the traffic policy is also used by the other instances of the traffic policy if they are updated to the latest version.
+pub async fn update_traffic_policy_instance(
+  route53_client: &aws_sdk_route53::Client,
+  instance_id: &str,
+  ttl: i64,
+  old_values: &[String],
+  new_values: &[String],
+) {
+  let response = route53_client
+    .get_traffic_policy_instance()
+    .id(instance_id)
+    .send()
+    .await
+    .expect("could not get the traffic policy instance");
+  let instance = response
+    .traffic_policy_instance()
+    .expect("missing traffic policy instance in response");
+  let policy_id = instance.traffic_policy_id().to_string();
+  let mut policy_version = instance.traffic_policy_version();
+
+  if old_values != new_values {
+    let response = route53_client
+      .get_traffic_policy()
+      .id(&policy_id)
+      .version(policy_version)
+      .send()
+      .await
+      .expect("could not get the traffic policy");
+    let policy = response
+      .traffic_policy()
+      .expect("missing traffic policy in response");
+    let document = replace_endpoint_values(policy.document(), old_values, new_values)
+      .unwrap_or_else(|err| panic!("{}", err));
+    let response = route53_client
+      .create_traffic_policy_version()
+      .id(&policy_id)
+      .document(document)
+      .comment(format!(
+        "Changed {} to {}",
+        old_values.join(","),
+        new_values.join(",")
+      ))
+      .send()
+      .await
+      .expect("could not create a new version of the traffic policy");
+    policy_version = response
+      .traffic_policy()
+      .expect("missing traffic policy in response")
+      .version();
+    eprintln!(
+      "Created version {} of traffic policy {}",
+      policy_version, policy_id
+    );
+  }
+
+  let response = route53_client
+    .update_traffic_policy_instance()
+    .id(instance_id)
+    .ttl(ttl)
+    .traffic_policy_id(&policy_id)
+    .traffic_policy_version(policy_version)
+    .send()
+    .await
+    .expect("could not update the traffic policy instance");
+  println!("{:?}", response);
+}
+
+// Traffic policy instances do not have a change ID, so the state of the instance is polled instead.
+pub async fn wait_for_traffic_policy_instance(
+  route53_client: &aws_sdk_route53::Client,
+  instance_id: &str,
+) {
+  let start = Instant::now();
+  loop {
+    let response = route53_client
+      .get_traffic_policy_instance()
+      .id(instance_id)
+      .send()
+      .await
+      .expect("could not poll traffic policy instance status");
+    let instance = response
+      .traffic_policy_instance()
+      .expect("missing traffic policy instance in response");
+    eprintln!(
+      "Traffic policy instance {} is {} (elapsed: {}s)",
+      instance_id,
+      instance.state(),
+      start.elapsed().as_secs()
+    );
+    match instance.state() {
+      "Applied" => break,
+      "Failed" => panic!(
+        "the traffic policy instance could not be updated: {}",
+        instance.message()
+      ),
+      _ => {}
+    }
+    tokio::time::sleep(Duration::from_secs(1)).await;
+  }
+}
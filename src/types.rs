@@ -1,74 +1,769 @@
 // Copyright 2023 Stefan Sundin
 // Licensed under GNU GPL v3 or later
 
-use serde::Deserialize;
+use crate::{alias, backend, sources, utils};
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+use aws_config::imds::client::error::ImdsError;
+use aws_sdk_route53::types::{CidrRoutingConfig, GeoProximityLocation, ResourceRecordSet, RrType};
+use clap::ValueEnum;
+use ipnet::{IpNet, Ipv6Net};
+use serde::{Deserialize, Serialize};
+use std::{
+  collections::HashMap,
+  fmt,
+  future::Future,
+  pin::Pin,
+  str::FromStr,
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
 pub enum HostedZoneType {
+  #[value(help = "Use the public hosted zone if there is one, otherwise the private hosted zone")]
   PreferPublic,
+  #[value(help = "Only use public hosted zones")]
   Public,
+  #[value(help = "Only use private hosted zones")]
   Private,
 }
-impl From<&str> for HostedZoneType {
-  fn from(s: &str) -> Self {
-    match s {
-      "prefer-public" => HostedZoneType::PreferPublic,
-      "public" => HostedZoneType::Public,
-      "private" => HostedZoneType::Private,
-      v => panic!("unsupported value: {}", v),
-    }
-  }
-}
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
 pub enum IPAddressType {
   Public,
   Private,
 }
-impl From<&str> for IPAddressType {
-  fn from(s: &str) -> Self {
-    match s {
-      "public" => IPAddressType::Public,
-      "private" => IPAddressType::Private,
-      v => panic!("unsupported value: {}", v),
-    }
-  }
-}
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
 pub enum ValueFromSource {
+  #[value(
+    help = "Query all of the sources concurrently and use the first one that returned a value, in the order given by --value-source-order (--value-from-url is used as a fallback if it is set)"
+  )]
   Auto,
+  #[value(
+    help = "The EC2 instance metadata service (IMDSv2), the address is selected with --ip-address-type, --eni-index, --secondary-ip-index and --ipv6-selection"
+  )]
   Ec2Metadata,
+  #[value(
+    help = "The ECS task metadata endpoint, in bridge and host network mode the address of the container instance is used instead (see --ecs-network-mode)"
+  )]
+  EcsMetadata,
+  #[value(
+    help = "The Lightsail API (GetInstance) for the instance given by --lightsail-instance-name, the address is selected with --ip-address-type"
+  )]
+  Lightsail,
+  #[value(
+    help = "The external IP address of the router, using UPnP IGD (GetExternalIPAddress), only for A records"
+  )]
+  Upnp,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
+pub enum SpotInterruptionAction {
+  #[value(
+    help = "Delete the record (a failover record set that is deleted stops answering, so the secondary is used)"
+  )]
+  Delete,
+  #[value(
+    help = "Set the weight of the weighted record set to 0, so that it stops receiving traffic but can be brought back by the next instance (requires --weight)"
+  )]
+  ZeroWeight,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ec2MetadataOptions {
+  pub ip_address_type: IPAddressType,
+  pub eni_index: Option<usize>,
+  pub secondary_ip_index: Option<usize>,
+  pub all_ipv6: bool,
+}
+
+// The clients used to talk to the metadata services and to fetch URLs.
+// They can be built ahead of time to point them somewhere else, e.g. a mock server in tests.
+#[derive(Debug, Clone)]
+pub struct MetadataClients {
+  pub imds: aws_config::imds::client::Client,
+  pub http: reqwest::Client,
+  // The base URI of the ECS container metadata endpoint, None when not running on ECS
+  pub ecs_metadata_uri: Option<String>,
+  // How long to keep retrying the ECS container metadata endpoint before giving up
+  pub metadata_timeout: Duration,
+  // Until this time, the EC2 instance metadata service is retried while it is unreachable (--startup-grace)
+  pub startup_deadline: Option<Instant>,
+  // When set, the metadata is read from the fixture and the metadata services are never contacted
+  pub fixture: Option<MetadataFixture>,
+}
+
+impl MetadataClients {
+  pub fn new() -> Self {
+    Self {
+      imds: aws_config::imds::client::Client::builder().build(),
+      http: reqwest::Client::new(),
+      ecs_metadata_uri: std::env::var("ECS_CONTAINER_METADATA_URI_V4")
+        .or(std::env::var("ECS_CONTAINER_METADATA_URI"))
+        .ok(),
+      metadata_timeout: Duration::from_secs(30),
+      startup_deadline: None,
+      fixture: None,
+    }
+  }
+
+  // --simulate-metadata
+  pub fn simulated(fixture: MetadataFixture) -> Self {
+    Self {
+      fixture: Some(fixture),
+      ..Self::new()
+    }
+  }
+
+  // Returns None if the path does not exist or if the EC2 instance metadata service is unreachable.
+  pub async fn get_ec2_metadata(&self, path: &str) -> Option<String> {
+    match &self.fixture {
+      Some(fixture) => fixture.ec2.get(path).cloned(),
+      None => {
+        let mut delay = Duration::from_millis(250);
+        loop {
+          let err = match self.imds.get(path).await {
+            Ok(value) => return Some(value.as_ref().to_string()),
+            // The service responded, so the path does not exist
+            Err(ImdsError::ErrorResponse(_)) => return None,
+            Err(err) => err,
+          };
+          if !self
+            .startup_deadline
+            .is_some_and(|deadline| Instant::now() + delay < deadline)
+          {
+            return None;
+          }
+          eprintln!(
+            "EC2 instance metadata service request to {} failed: {}, retrying in {}ms",
+            path,
+            err,
+            delay.as_millis()
+          );
+          tokio::time::sleep(delay).await;
+          delay = (delay * 2).min(Duration::from_secs(5));
+        }
+      }
+    }
+  }
+}
+
+impl Default for MetadataClients {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+// Canned metadata responses for --simulate-metadata.
+// The EC2 responses are keyed by their path, e.g. "/latest/meta-data/public-ipv4", and the ECS response is the task metadata.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MetadataFixture {
+  #[serde(default)]
+  pub ec2: HashMap<String, String>,
+  pub ecs: Option<EcsTaskMetadata>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CloudMapRegistration {
+  pub service_id: String,
+  pub instance_id: String,
+  pub client: aws_sdk_servicediscovery::Client,
+  // None if the instance was already registered with the value, and nothing was changed
+  pub operation_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UpdateResult {
+  // None if Route 53 was skipped (--skip-route53)
+  pub hosted_zone_id: Option<String>,
+  pub record_set: ResourceRecordSet,
+  // None if the value was unchanged or Route 53 was skipped, and no change was submitted
+  pub change_id: Option<String>,
+  // Used to delete the record on shutdown (--deregister-on-exit) and for the heartbeat record
+  pub backend: Arc<dyn backend::DnsBackend>,
+  pub cloudmap: Option<CloudMapRegistration>,
+  // Set if the record is managed by a traffic policy and the traffic policy instance was updated instead of the record set
+  pub traffic_policy_instance_id: Option<String>,
+  // The values of the record before the update, empty if the record did not exist or Route 53 was skipped
+  pub previous_value: Vec<String>,
+}
+impl UpdateResult {
+  // Whether anything was changed by the update. The program exits with --unchanged-exit-code if nothing was changed.
+  pub fn is_changed(&self) -> bool {
+    self.change_id.is_some()
+      || self.traffic_policy_instance_id.is_some()
+      || self
+        .cloudmap
+        .as_ref()
+        .is_some_and(|registration| registration.operation_id.is_some())
+  }
+}
+
+// The JSON representation of an UpdateResult that is returned by the API server and written by --result-file.
+#[derive(Debug, Serialize)]
+pub struct UpdateResultJson {
+  pub hosted_zone_id: Option<String>,
+  pub name: String,
+  #[serde(rename = "type")]
+  pub record_type: String,
+  pub value: Vec<String>,
+  pub previous_value: Vec<String>,
+  pub ttl: Option<i64>,
+  pub changed: bool,
+  pub change_id: Option<String>,
+}
+impl From<&UpdateResult> for UpdateResultJson {
+  fn from(result: &UpdateResult) -> Self {
+    UpdateResultJson {
+      hosted_zone_id: result.hosted_zone_id.clone(),
+      name: result.record_set.name().to_string(),
+      record_type: result.record_set.r#type().as_str().to_string(),
+      value: utils::get_record_values(&result.record_set),
+      previous_value: result.previous_value.clone(),
+      ttl: result.record_set.ttl(),
+      changed: result.is_changed(),
+      change_id: result.change_id.clone(),
+    }
+  }
+}
+
+// The routing policy of a record set. Record sets with a routing policy are told apart by their set identifier.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordRouting {
+  pub set_identifier: Option<String>,
+  pub cidr_routing_config: Option<CidrRoutingConfig>,
+  pub geo_proximity_location: Option<GeoProximityLocation>,
+  pub weight: Option<i64>,
+}
+impl RecordRouting {
+  // Returns true if the record set is the one that this routing policy refers to.
+  pub fn identifies(&self, record_set: &ResourceRecordSet) -> bool {
+    record_set.set_identifier() == self.set_identifier.as_deref()
+  }
+
+  // Returns true if the record set already uses this routing policy.
+  pub fn is_applied_to(&self, record_set: &ResourceRecordSet) -> bool {
+    self.identifies(record_set)
+      && record_set.cidr_routing_config() == self.cidr_routing_config.as_ref()
+      && record_set.geo_proximity_location() == self.geo_proximity_location.as_ref()
+      && record_set.weight() == self.weight
+  }
+}
+
+// --geoproximity-coordinates latitude,longitude
+// The values are kept as strings since that is how Route 53 represents them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coordinates {
+  pub latitude: String,
+  pub longitude: String,
+}
+impl FromStr for Coordinates {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let Some((latitude, longitude)) = s.split_once(',') else {
+      return Err(format!("expected latitude,longitude: {}", s));
+    };
+    let (latitude, longitude) = (latitude.trim(), longitude.trim());
+    match latitude.parse::<f64>() {
+      Ok(v) if (-90.0..=90.0).contains(&v) => {}
+      _ => return Err(format!("invalid latitude: {}", latitude)),
+    }
+    match longitude.parse::<f64>() {
+      Ok(v) if (-180.0..=180.0).contains(&v) => {}
+      _ => return Err(format!("invalid longitude: {}", longitude)),
+    }
+    Ok(Coordinates {
+      latitude: latitude.to_string(),
+      longitude: longitude.to_string(),
+    })
+  }
+}
+
+// Where the value of an UpdateRequest comes from, like --value, --value-from and --value-from-url.
+// Custom sources implement the sources::ValueSource trait.
+#[derive(Debug, Clone)]
+pub enum ValueSource {
+  Values(Vec<String>),
+  Imds,
   EcsMetadata,
+  Url(String),
+  Auto,
+  Custom(Arc<dyn sources::ValueSource>),
 }
-impl From<&str> for ValueFromSource {
-  fn from(s: &str) -> Self {
+
+// Used by the server modes and by embedders to request an update of a record, the remaining settings are taken from the command line arguments.
+// Use UpdateRequest::builder() to get the same validation as the command line.
+#[derive(Debug, Clone)]
+pub struct UpdateRequest {
+  pub record_name: String,
+  pub record_type: Option<RrType>,
+  // None for alias records
+  pub value_source: Option<ValueSource>,
+  // Like --alias-target, the hosted zone ID of the target is looked up from the DNS name
+  pub alias_target: Option<String>,
+  pub ttl: Option<i64>,
+}
+impl UpdateRequest {
+  pub fn builder() -> UpdateRequestBuilder<NoValueSource> {
+    UpdateRequestBuilder {
+      record_name: None,
+      record_type: None,
+      ttl: None,
+      value_source: NoValueSource,
+    }
+  }
+}
+
+// The value source or the alias target can only be set once, so a request with several value sources, or with both a value source and an alias target, does not compile.
+#[derive(Debug, Clone)]
+pub struct NoValueSource;
+impl From<NoValueSource> for Option<ValueSource> {
+  fn from(_: NoValueSource) -> Self {
+    None
+  }
+}
+
+// The DNS name of the alias target
+#[derive(Debug, Clone)]
+pub struct AliasTarget(String);
+
+#[derive(Debug, Clone)]
+pub struct UpdateRequestBuilder<V> {
+  record_name: Option<String>,
+  record_type: Option<RrType>,
+  ttl: Option<i64>,
+  value_source: V,
+}
+impl<V> UpdateRequestBuilder<V> {
+  pub fn record_name(mut self, record_name: impl Into<String>) -> Self {
+    self.record_name = Some(record_name.into());
+    self
+  }
+  pub fn record_type(mut self, record_type: RrType) -> Self {
+    self.record_type = Some(record_type);
+    self
+  }
+  pub fn ttl(mut self, ttl: i64) -> Self {
+    self.ttl = Some(ttl);
+    self
+  }
+  fn with_value_source<W>(self, value_source: W) -> UpdateRequestBuilder<W> {
+    UpdateRequestBuilder {
+      record_name: self.record_name,
+      record_type: self.record_type,
+      ttl: self.ttl,
+      value_source,
+    }
+  }
+
+  // Takes the value source or the alias target out of the state of the builder
+  fn build_request(
+    self,
+    target: impl FnOnce(V) -> (Option<ValueSource>, Option<String>),
+  ) -> Result<UpdateRequest, String> {
+    let (value_source, alias_target) = target(self.value_source);
+    let record_name = match self.record_name {
+      Some(record_name) if !record_name.trim_end_matches('.').is_empty() => record_name,
+      _ => return Err("the record name is required".to_string()),
+    };
+    if let Some(ttl) = self.ttl {
+      if !(0..=utils::MAX_TTL).contains(&ttl) {
+        return Err(format!(
+          "TTL must be between 0 and {}: {}",
+          utils::MAX_TTL,
+          ttl
+        ));
+      }
+    }
+    match (&value_source, &alias_target) {
+      (None, None) => return Err("a value source or an alias target is required".to_string()),
+      (None, Some(dns_name)) => {
+        if self.ttl.is_some() {
+          return Err("alias records do not have a TTL".to_string());
+        }
+        alias::get_alias_hosted_zone_id(dns_name)?;
+      }
+      (Some(ValueSource::Values(values)), _) => {
+        if values.is_empty() {
+          return Err("at least one value is required".to_string());
+        }
+        utils::check_value_count(self.record_type.as_ref(), values.len())?;
+        if self.record_type.is_none() {
+          utils::detect_record_type(values.clone())?;
+        }
+      }
+      (Some(ValueSource::Url(url)), _) => {
+        reqwest::Url::parse(url).map_err(|err| format!("invalid URL {}: {}", url, err))?;
+      }
+      (Some(_), _) => {}
+    }
+    Ok(UpdateRequest {
+      record_name,
+      record_type: self.record_type,
+      value_source,
+      alias_target,
+      ttl: self.ttl,
+    })
+  }
+}
+impl UpdateRequestBuilder<NoValueSource> {
+  pub fn value_source(self, value_source: ValueSource) -> UpdateRequestBuilder<ValueSource> {
+    self.with_value_source(value_source)
+  }
+  pub fn values(self, values: Vec<String>) -> UpdateRequestBuilder<ValueSource> {
+    self.value_source(ValueSource::Values(values))
+  }
+  // Like --alias-target, the hosted zone ID of the target is looked up from the DNS name
+  pub fn alias_target(self, dns_name: impl Into<String>) -> UpdateRequestBuilder<AliasTarget> {
+    self.with_value_source(AliasTarget(dns_name.into()))
+  }
+}
+impl<V: Into<Option<ValueSource>>> UpdateRequestBuilder<V> {
+  pub fn build(self) -> Result<UpdateRequest, String> {
+    self.build_request(|value_source| (value_source.into(), None))
+  }
+}
+impl UpdateRequestBuilder<AliasTarget> {
+  pub fn build(self) -> Result<UpdateRequest, String> {
+    self.build_request(|AliasTarget(dns_name)| (None, Some(dns_name)))
+  }
+}
+
+pub type UpdateHandler = Arc<
+  dyn Fn(UpdateRequest) -> Pin<Box<dyn Future<Output = Result<UpdateResult, String>> + Send>>
+    + Send
+    + Sync,
+>;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
+pub enum LogTarget {
+  #[value(help = "Standard error")]
+  Stderr,
+  #[value(help = "The local syslog daemon (/dev/log)")]
+  Syslog,
+  #[value(help = "The systemd journal, with the progress events as structured fields")]
+  Journald,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
+pub enum DmarcPolicy {
+  #[value(help = "Only report, the messages are delivered as usual")]
+  None,
+  #[value(
+    help = "Treat the messages that fail the check as suspicious, e.g. deliver them to the spam folder"
+  )]
+  Quarantine,
+  #[value(help = "Reject the messages that fail the check")]
+  Reject,
+}
+impl DmarcPolicy {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      DmarcPolicy::None => "none",
+      DmarcPolicy::Quarantine => "quarantine",
+      DmarcPolicy::Reject => "reject",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
+pub enum HealthCheckProtocol {
+  Http,
+  Https,
+  Tcp,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
+pub enum ValueSourceKind {
+  Ecs,
+  Ec2,
+  Url,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ipv6Selection {
+  Stable,
+  Temporary,
+  Eui64,
+  Prefix(Ipv6Net),
+}
+impl FromStr for Ipv6Selection {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
     match s {
-      "auto" => ValueFromSource::Auto,
-      "ec2-metadata" => ValueFromSource::Ec2Metadata,
-      "ecs-metadata" => ValueFromSource::EcsMetadata,
-      v => panic!("unsupported value: {}", v),
+      "stable" => Ok(Ipv6Selection::Stable),
+      "temporary" => Ok(Ipv6Selection::Temporary),
+      "eui64" => Ok(Ipv6Selection::Eui64),
+      v => match v.strip_prefix("prefix:") {
+        Some(prefix) => prefix
+          .parse::<Ipv6Net>()
+          .map(Ipv6Selection::Prefix)
+          .map_err(|_| format!("invalid IPv6 prefix: {}", prefix)),
+        None => Err(format!("unsupported value: {}", v)),
+      },
     }
   }
 }
 
-#[derive(Debug, Deserialize)]
+// --require-reachable tcp:443
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reachability {
+  Tcp(u16),
+}
+impl FromStr for Reachability {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.split_once(':') {
+      Some(("tcp", port)) => port
+        .parse::<u16>()
+        .ok()
+        .filter(|&port| port != 0)
+        .map(Reachability::Tcp)
+        .ok_or_else(|| format!("invalid port: {}", port)),
+      _ => Err(format!("unsupported value: {} (expected tcp:<PORT>)", s)),
+    }
+  }
+}
+impl fmt::Display for Reachability {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Reachability::Tcp(port) => write!(f, "tcp:{}", port),
+    }
+  }
+}
+
+// --value-filter include-cidr:10.0.0.0/8
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueFilter {
+  IncludeCidr(IpNet),
+  ExcludeCidr(IpNet),
+}
+impl FromStr for ValueFilter {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (filter, cidr): (fn(IpNet) -> ValueFilter, &str) = match s.split_once(':') {
+      Some(("include-cidr", cidr)) => (ValueFilter::IncludeCidr, cidr),
+      Some(("exclude-cidr", cidr)) => (ValueFilter::ExcludeCidr, cidr),
+      _ => {
+        return Err(format!(
+          "unsupported value: {} (expected include-cidr:<CIDR> or exclude-cidr:<CIDR>)",
+          s
+        ))
+      }
+    };
+    cidr
+      .parse::<IpNet>()
+      .map(filter)
+      .map_err(|_| format!("invalid CIDR block: {}", cidr))
+  }
+}
+impl fmt::Display for ValueFilter {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ValueFilter::IncludeCidr(net) => write!(f, "include-cidr:{}", net),
+      ValueFilter::ExcludeCidr(net) => write!(f, "exclude-cidr:{}", net),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefaultTtl {
+  pub record_type: Option<RrType>,
+  pub ttl: i64,
+}
+impl FromStr for DefaultTtl {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (record_type, ttl) = match s.split_once('=') {
+      Some((record_type, ttl)) => {
+        let record_type = record_type.to_uppercase();
+        if !RrType::values().contains(&record_type.as_str()) {
+          return Err(format!("unsupported record type: {}", record_type));
+        }
+        (Some(RrType::from(record_type.as_str())), ttl)
+      }
+      None => (None, s),
+    };
+    let ttl = utils::parse_ttl(ttl)?;
+    Ok(DefaultTtl { record_type, ttl })
+  }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
+pub enum EcsNetworkMode {
+  Auto,
+  Awsvpc,
+  Bridge,
+  Host,
+}
+
+// https://docs.aws.amazon.com/AmazonECS/latest/developerguide/task-metadata-endpoint-v4-response.html
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct EcsTaskMetadata {
+  pub cluster: Option<String>,
+  pub desired_status: Option<String>,
+  pub known_status: Option<String>,
+  #[serde(rename = "TaskARN")]
+  pub task_arn: Option<String>,
+  pub family: Option<String>,
+  pub revision: Option<String>,
+  pub availability_zone: Option<String>,
+  pub launch_type: Option<String>,
   pub containers: Vec<EcsContainerMetadata>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct EcsContainerMetadata {
+  pub docker_id: Option<String>,
+  pub name: Option<String>,
+  #[serde(default)]
   pub networks: Vec<EcsContainerNetworkMetadata>,
+  #[serde(default)]
+  pub ports: Vec<EcsPortMapping>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EcsPortMapping {
+  pub container_port: u16,
+  pub host_port: Option<u16>,
+  pub protocol: Option<String>,
+}
+
+// --srv-from-ecs containerName:containerPort
+#[derive(Debug, Clone, PartialEq)]
+pub struct SrvFromEcs {
+  pub container_name: String,
+  pub container_port: u16,
+}
+impl FromStr for SrvFromEcs {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let Some((container_name, container_port)) = s.rsplit_once(':') else {
+      return Err(format!("expected containerName:containerPort: {}", s));
+    };
+    let container_port = container_port
+      .parse::<u16>()
+      .map_err(|_| format!("invalid container port: {}", container_port))?;
+    Ok(SrvFromEcs {
+      container_name: container_name.to_string(),
+      container_port,
+    })
+  }
+}
+
+// --tag Key=Value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+  pub key: String,
+  pub value: String,
+}
+impl FromStr for Tag {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.split_once('=') {
+      Some((key, value)) if !key.is_empty() => Ok(Tag {
+        key: key.to_string(),
+        value: value.to_string(),
+      }),
+      _ => Err(format!("expected Key=Value: {}", s)),
+    }
+  }
 }
 
-#[derive(Debug, Deserialize)]
+// The options that are used to configure the AWS SDK.
+#[derive(Debug, Clone, Default)]
+pub struct AwsOptions {
+  pub region: Option<String>,
+  pub connect_timeout: Duration,
+  // Used for both the read timeout and the timeout of each attempt of an API call
+  pub timeout: Duration,
+  pub use_fips: bool,
+  pub use_dualstack: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct EcsContainerNetworkMetadata {
+  pub network_mode: Option<String>,
   #[serde(rename = "IPv4Addresses")]
   pub ipv4_addresses: Option<Vec<String>>,
   #[serde(rename = "IPv6Addresses")]
   pub ipv6_addresses: Option<Vec<String>>,
+  // The remaining fields are only present in awsvpc network mode
+  pub attachment_index: Option<u32>,
+  #[serde(rename = "MACAddress")]
+  pub mac_address: Option<String>,
+  #[serde(rename = "IPv4SubnetCIDRBlock")]
+  pub ipv4_subnet_cidr_block: Option<String>,
+  #[serde(rename = "IPv6SubnetCIDRBlock")]
+  pub ipv6_subnet_cidr_block: Option<String>,
+  #[serde(rename = "PrivateDNSName")]
+  pub private_dns_name: Option<String>,
+  pub subnet_gateway_ipv4_address: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceIdentityDocument {
+  pub account_id: String,
+  pub availability_zone: String,
+  pub instance_id: String,
+  pub instance_type: String,
+  pub region: String,
+}
+
+// The TLS implementation that was selected with the cargo features.
+pub const TLS: &str = if cfg!(feature = "rustls-webpki-roots") {
+  "rustls-webpki-roots"
+} else if cfg!(feature = "rustls-native-roots") {
+  "rustls-native-roots"
+} else if cfg!(feature = "native-tls") {
+  "native-tls"
+} else {
+  "none"
+};
+
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+  pub version: &'static str,
+  pub git_commit: &'static str,
+  pub build_date: &'static str,
+  pub aws_sdk_route53_version: &'static str,
+  pub tls: &'static str,
+}
+impl VersionInfo {
+  pub fn new() -> Self {
+    VersionInfo {
+      version: env!("CARGO_PKG_VERSION"),
+      git_commit: env!("BUILD_GIT_COMMIT"),
+      build_date: env!("BUILD_DATE"),
+      aws_sdk_route53_version: aws_sdk_route53::meta::PKG_VERSION,
+      tls: TLS,
+    }
+  }
+}
+impl Default for VersionInfo {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+impl fmt::Display for VersionInfo {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "route53-update {} ({}, aws-sdk-route53 {})",
+      self.version, self.git_commit, self.aws_sdk_route53_version
+    )
+  }
 }
@@ -0,0 +1,150 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+// The update for embedders that already know the hosted zone of the record.
+// The command line does more (finding the hosted zone, --clear, verification, etc.), but the values and the record set are handled the same way.
+
+use crate::{alias, backend::DnsBackend, sources, types, utils};
+
+use aws_sdk_route53::types::RrType;
+use std::sync::Arc;
+
+// Returns the values from the value source, using the defaults of the command line for the metadata services (e.g. --ip-address-type public).
+pub async fn resolve_value_source(
+  value_source: &types::ValueSource,
+  clients: &types::MetadataClients,
+  record_type: Option<&RrType>,
+) -> Result<Vec<String>, String> {
+  let ec2_options = types::Ec2MetadataOptions {
+    ip_address_type: types::IPAddressType::Public,
+    eni_index: None,
+    secondary_ip_index: None,
+    all_ipv6: false,
+  };
+  let source: Arc<dyn sources::ValueSource> = match value_source {
+    types::ValueSource::Values(values) => return Ok(values.clone()),
+    types::ValueSource::Imds => Arc::new(sources::Ec2MetadataSource {
+      clients: clients.clone(),
+      record_type: record_type.cloned(),
+      options: ec2_options,
+    }),
+    types::ValueSource::EcsMetadata => Arc::new(sources::EcsMetadataSource {
+      clients: clients.clone(),
+      record_type: record_type.cloned(),
+      network_mode: types::EcsNetworkMode::Auto,
+      ec2_options,
+    }),
+    types::ValueSource::Url(url) => Arc::new(sources::UrlSource {
+      clients: clients.clone(),
+      url: url.clone(),
+    }),
+    types::ValueSource::Auto => {
+      let values = sources::get_auto_values(
+        clients,
+        &[types::ValueSourceKind::Ecs, types::ValueSourceKind::Ec2],
+        record_type,
+        types::EcsNetworkMode::Auto,
+        &ec2_options,
+        None,
+      )
+      .await;
+      if values.is_empty() {
+        return Err("unable to auto-detect an IP address to use (missing ECS environment variables and unable to connect to the EC2 instance metadata service)".to_string());
+      }
+      return Ok(values);
+    }
+    types::ValueSource::Custom(source) => source.clone(),
+  };
+  source.resolve().await
+}
+
+// Publishes the record set of the request in the hosted zone. Nothing is submitted if the record set is already up to date.
+// Like the command line, the TTL of the existing record set is kept if the request does not have a TTL.
+pub async fn update(
+  backend: Arc<dyn DnsBackend>,
+  clients: &types::MetadataClients,
+  hosted_zone_id: &str,
+  request: types::UpdateRequest,
+) -> Result<types::UpdateResult, String> {
+  let record_name = if request.record_name.ends_with('.') {
+    request.record_name.clone()
+  } else {
+    format!("{}.", request.record_name)
+  };
+  let routing = types::RecordRouting::default();
+  let (record_type, values) = match (&request.value_source, &request.alias_target) {
+    (Some(_), Some(_)) => {
+      return Err("an alias target can not be combined with a value source".to_string())
+    }
+    (None, None) => return Err("a value source or an alias target is required".to_string()),
+    (None, Some(dns_name)) => (
+      request.record_type.clone().unwrap_or(RrType::A),
+      vec![alias::normalize_dns_name(dns_name)],
+    ),
+    (Some(value_source), None) => {
+      let values =
+        resolve_value_source(value_source, clients, request.record_type.as_ref()).await?;
+      let record_type = match &request.record_type {
+        Some(record_type) => record_type.clone(),
+        None => utils::detect_record_type(values.clone())?,
+      };
+      // TXT records must be enclosed in quotes
+      let values = if record_type == RrType::Txt {
+        values.iter().map(|v| utils::quote_txt_value(v)).collect()
+      } else {
+        values
+      };
+      (record_type, utils::normalize_values(values))
+    }
+  };
+  if values.is_empty() {
+    return Err("at least one value is required".to_string());
+  }
+  utils::check_value_count(Some(&record_type), values.len())?;
+  utils::check_value_lengths(Some(&record_type), &values)?;
+
+  let record_sets = backend.get(hosted_zone_id, &record_name).await?;
+  let existing_record = record_sets
+    .iter()
+    .find(|r| r.r#type() == &record_type && routing.identifies(r));
+  let previous_value = existing_record
+    .map(utils::get_record_values)
+    .unwrap_or_default();
+  let record_set = match &request.alias_target {
+    Some(dns_name) => alias::build_alias_record_set(
+      &record_name,
+      &record_type,
+      alias::get_alias_hosted_zone_id(dns_name)?.unwrap_or(hosted_zone_id),
+      &values[0],
+      false,
+      &routing,
+    ),
+    None => {
+      let ttl = request
+        .ttl
+        .or(existing_record.and_then(|r| r.ttl()))
+        .unwrap_or_else(|| utils::get_default_ttl(&[], &record_type));
+      utils::build_record_set(&record_name, &record_type, Some(ttl), &values, &routing)
+    }
+  };
+
+  let change_id = if existing_record == Some(&record_set) {
+    eprintln!("The record is already up to date: {}", values.join(","));
+    None
+  } else {
+    Some(
+      backend
+        .upsert(hosted_zone_id, vec![record_set.clone()], None)
+        .await?,
+    )
+  };
+  Ok(types::UpdateResult {
+    hosted_zone_id: Some(hosted_zone_id.to_string()),
+    record_set,
+    change_id,
+    backend,
+    cloudmap: None,
+    traffic_policy_instance_id: None,
+    previous_value,
+  })
+}
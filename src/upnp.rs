@@ -0,0 +1,163 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+// --value-from upnp
+// Asks the router for its external address using UPnP IGD: the router is discovered with SSDP, its device description gives the control URL of the WAN connection service, and GetExternalIPAddress is called with SOAP.
+
+use std::{net::SocketAddr, time::Duration};
+use tokio::net::UdpSocket;
+
+const SSDP_ADDRESS: &str = "239.255.255.250:1900";
+const SEARCH_TARGET: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+// The router has one of these services depending on how it is connected
+const WAN_SERVICE_TYPES: [&str; 3] = [
+  "urn:schemas-upnp-org:service:WANIPConnection:2",
+  "urn:schemas-upnp-org:service:WANIPConnection:1",
+  "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+
+pub fn search_request() -> String {
+  format!(
+    "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+    SSDP_ADDRESS, SEARCH_TARGET
+  )
+}
+
+// Returns the LOCATION header of an SSDP response, which is the URL of the device description.
+pub fn parse_search_response(response: &str) -> Option<String> {
+  response.lines().find_map(|line| {
+    let (name, value) = line.split_once(':')?;
+    name
+      .trim()
+      .eq_ignore_ascii_case("location")
+      .then(|| value.trim().to_string())
+  })
+}
+
+// Returns the text of the first element with the given name, ignoring namespace prefixes.
+fn get_element_text<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+  let mut rest = xml;
+  loop {
+    let start = rest.find('<')?;
+    rest = &rest[start + 1..];
+    let end = rest.find('>')?;
+    let tag = &rest[..end];
+    let tag_name = tag.split_whitespace().next().unwrap_or_default();
+    let local_name = tag_name.rsplit(':').next().unwrap_or_default();
+    if local_name == name && !tag.ends_with('/') {
+      let content = &rest[end + 1..];
+      let close = content.find("</")?;
+      return Some(content[..close].trim());
+    }
+    rest = &rest[end + 1..];
+  }
+}
+
+// Returns the service type and the control URL of the WAN connection service in the device description, with the control URL resolved against the location of the description.
+pub fn parse_device_description(xml: &str, location: &str) -> Result<(String, String), String> {
+  let (service_type, control_url) = xml
+    .split("<service>")
+    .skip(1)
+    .find_map(|service| {
+      let service_type = get_element_text(service, "serviceType")?;
+      if !WAN_SERVICE_TYPES.contains(&service_type) {
+        return None;
+      }
+      Some((service_type, get_element_text(service, "controlURL")?))
+    })
+    .ok_or_else(|| "the router does not have a WAN connection service".to_string())?;
+  let control_url = reqwest::Url::parse(location)
+    .and_then(|base| base.join(control_url))
+    .map_err(|err| format!("invalid control URL {:?}: {}", control_url, err))?;
+  Ok((service_type.to_string(), control_url.to_string()))
+}
+
+pub fn get_external_ip_request(service_type: &str) -> String {
+  format!(
+    r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+  <s:Body>
+    <u:GetExternalIPAddress xmlns:u="{}"/>
+  </s:Body>
+</s:Envelope>"#,
+    service_type
+  )
+}
+
+pub fn parse_external_ip_response(xml: &str) -> Result<String, String> {
+  match get_element_text(xml, "NewExternalIPAddress") {
+    Some(address) if !address.is_empty() => Ok(address.to_string()),
+    // The router returns an empty address when it is not connected
+    Some(_) => Err("the router does not have an external IP address".to_string()),
+    None => Err(match get_element_text(xml, "errorDescription") {
+      Some(description) => format!("GetExternalIPAddress failed: {}", description),
+      None => "invalid response to GetExternalIPAddress".to_string(),
+    }),
+  }
+}
+
+// Returns the URL of the device description of the first router that responds.
+pub async fn discover() -> Result<String, String> {
+  let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 0)))
+    .await
+    .map_err(|err| format!("could not open a UDP socket: {}", err))?;
+  socket
+    .send_to(search_request().as_bytes(), SSDP_ADDRESS)
+    .await
+    .map_err(|err| format!("could not send the SSDP search: {}", err))?;
+  let mut buf = [0; 2048];
+  tokio::time::timeout(DISCOVERY_TIMEOUT, async {
+    loop {
+      let (len, from) = socket
+        .recv_from(&mut buf)
+        .await
+        .map_err(|err| format!("could not receive the SSDP response: {}", err))?;
+      if let Some(location) = parse_search_response(&String::from_utf8_lossy(&buf[..len])) {
+        eprintln!("Found UPnP gateway {} at {}", from.ip(), location);
+        return Ok(location);
+      }
+    }
+  })
+  .await
+  .map_err(|_| "no UPnP gateway responded (is UPnP enabled on the router?)".to_string())?
+}
+
+// Asks the router with the device description at the location for its external address.
+pub async fn get_external_ip(http: &reqwest::Client, location: &str) -> Result<String, String> {
+  let description = http
+    .get(location)
+    .send()
+    .await
+    .and_then(|response| response.error_for_status())
+    .map_err(|err| format!("could not get the device description: {}", err))?
+    .text()
+    .await
+    .map_err(|err| format!("could not read the device description: {}", err))?;
+  let (service_type, control_url) = parse_device_description(&description, location)?;
+
+  // A failed action returns status 500 with a SOAP fault, which parse_external_ip_response reports
+  let response = http
+    .post(&control_url)
+    .header("Content-Type", "text/xml; charset=\"utf-8\"")
+    .header(
+      "SOAPAction",
+      format!("\"{}#GetExternalIPAddress\"", service_type),
+    )
+    .body(get_external_ip_request(&service_type))
+    .send()
+    .await
+    .map_err(|err| format!("request to {} failed: {}", control_url, err))?
+    .text()
+    .await
+    .map_err(|err| format!("could not read the response from {}: {}", control_url, err))?;
+  parse_external_ip_response(&response)
+}
+
+pub async fn get_upnp_value(http: &reqwest::Client) -> Result<String, String> {
+  let location = discover().await?;
+  let address = get_external_ip(http, &location).await?;
+  eprintln!("UPnP gateway external IP address: {}", address);
+  Ok(address)
+}
@@ -1,10 +1,60 @@
 // Copyright 2023 Stefan Sundin
 // Licensed under GNU GPL v3 or later
 
-use crate::types;
+use crate::{api_calls, backend::DnsBackend, events, types};
 
-use aws_sdk_route53::types::{HostedZone, RrType};
-use std::net::IpAddr;
+use aws_config::{meta::region::RegionProviderChain, timeout::TimeoutConfig, Region};
+use aws_sdk_route53::config::SharedCredentialsProvider;
+use aws_sdk_route53::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use aws_sdk_route53::types::{
+  ChangeStatus, HostedZone, HostedZoneSummary, ResourceRecord, ResourceRecordSet, RrType, Tag,
+  TagResourceType, VpcRegion,
+};
+use aws_sdk_route53resolver::types::{ResolverRule, RuleTypeOption};
+use ipnet::{IpNet, Ipv6Net};
+use serde_json::json;
+use std::{
+  collections::HashMap,
+  future::Future,
+  net::{IpAddr, Ipv4Addr, Ipv6Addr},
+  path::Path,
+  time::{Duration, Instant},
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+// The API returns hosted zone IDs with a /hostedzone/ prefix, and IDs copied from there are accepted as well.
+pub fn normalize_hosted_zone_id(id: &str) -> String {
+  id.trim()
+    .trim_start_matches('/')
+    .trim_start_matches("hostedzone/")
+    .to_string()
+}
+
+// Both names have the trailing dot.
+pub fn is_in_zone(record_name: &str, zone_name: &str) -> bool {
+  let record_name = record_name.to_lowercase();
+  let zone_name = zone_name.to_lowercase();
+  record_name == zone_name || record_name.ends_with(&format!(".{}", zone_name))
+}
+
+// --relative
+// The record name is relative to the zone, and @ is the zone apex like in zone files.
+pub fn expand_relative_name(record_name: &str, zone_name: &str) -> String {
+  let zone_name = zone_name.trim_end_matches('.');
+  match record_name.trim_end_matches('.') {
+    "" | "@" => format!("{}.", zone_name),
+    name => format!("{}.{}.", name, zone_name),
+  }
+}
+
+// --hosted-zone-id
+pub fn parse_hosted_zone_id(s: &str) -> Result<String, String> {
+  let id = normalize_hosted_zone_id(s);
+  if id.is_empty() || id.contains('/') {
+    return Err(format!("invalid hosted zone ID: {}", s));
+  }
+  Ok(id)
+}
 
 pub fn get_hosted_zone(
   zones: Vec<&HostedZone>,
@@ -23,38 +73,1181 @@ pub fn get_hosted_zone(
   None
 }
 
-pub fn detect_record_type(v: Vec<String>) -> RrType {
-  let mut addrs = v.into_iter().map(|text| text.parse::<IpAddr>());
-  if addrs.all(|addr| addr.is_ok()) {
-    if addrs.all(|addr| addr.unwrap().is_ipv4()) {
-      return RrType::A;
-    } else if addrs.all(|addr| addr.unwrap().is_ipv6()) {
-      return RrType::Aaaa;
+pub fn build_record_set(
+  record_name: &str,
+  record_type: &RrType,
+  ttl: Option<i64>,
+  values: &[String],
+  routing: &types::RecordRouting,
+) -> ResourceRecordSet {
+  ResourceRecordSet::builder()
+    .set_ttl(ttl)
+    .set_set_identifier(routing.set_identifier.clone())
+    .set_cidr_routing_config(routing.cidr_routing_config.clone())
+    .set_geo_proximity_location(routing.geo_proximity_location.clone())
+    .set_weight(routing.weight)
+    .name(record_name)
+    .r#type(record_type.clone())
+    .set_resource_records(Some(
+      values
+        .iter()
+        .map(|v| {
+          ResourceRecord::builder()
+            .value(v)
+            .build()
+            .expect("error building resource record")
+        })
+        .collect(),
+    ))
+    .build()
+    .expect("error building resource record set")
+}
+
+// Finds the hosted zone with the given name, or the most specific hosted zone that contains the record name.
+pub fn find_hosted_zone_summary<'a>(
+  summaries: &'a [HostedZoneSummary],
+  hosted_zone_name: Option<&str>,
+  record_name: &str,
+) -> Option<&'a HostedZoneSummary> {
+  summaries
+    .iter()
+    .filter(|zone| match hosted_zone_name {
+      Some(name) => zone.name() == name,
+      None => record_name == zone.name() || record_name.ends_with(&format!(".{}", zone.name())),
+    })
+    .max_by_key(|zone| zone.name().len())
+}
+
+// Private hosted zones that are owned by another account are not returned by ListHostedZones, but they are returned by ListHostedZonesByVPC if they are associated with the VPC.
+pub async fn list_hosted_zones_by_vpc(
+  route53_client: &aws_sdk_route53::Client,
+  vpc_id: &str,
+  vpc_region: &str,
+) -> Vec<HostedZoneSummary> {
+  let mut summaries = Vec::new();
+  let mut next_token = None;
+  loop {
+    let response = route53_client
+      .list_hosted_zones_by_vpc()
+      .vpc_id(vpc_id)
+      .vpc_region(VpcRegion::from(vpc_region))
+      .set_next_token(next_token)
+      .send()
+      .await
+      .expect("could not list the hosted zones associated with the VPC");
+    summaries.extend(response.hosted_zone_summaries().iter().cloned());
+    next_token = response.next_token().map(|t| t.to_string());
+    if next_token.is_none() {
+      return summaries;
+    }
+  }
+}
+
+// Returns a copy of the config that uses the credentials from assuming the role.
+pub async fn assume_role(
+  shared_config: &aws_config::SdkConfig,
+  role_arn: &str,
+) -> aws_config::SdkConfig {
+  eprintln!("Assuming role: {}", role_arn);
+  let provider = aws_config::sts::AssumeRoleProvider::builder(role_arn)
+    .session_name("route53-update")
+    .configure(shared_config)
+    .build()
+    .await;
+  shared_config
+    .to_builder()
+    .credentials_provider(SharedCredentialsProvider::new(provider))
+    .build()
+}
+
+// Describes a change for the log, e.g. "service.example.com. A: 192.0.2.1 -> 192.0.2.2".
+pub fn format_transition(
+  record_name: &str,
+  record_type: &RrType,
+  old_value: &[String],
+  new_value: &[String],
+) -> String {
+  let old_value = match old_value {
+    [] => "(none)".to_string(),
+    values => values.join(","),
+  };
+  format!(
+    "{} {}: {} -> {}",
+    record_name,
+    record_type.as_str(),
+    old_value,
+    new_value.join(",")
+  )
+}
+
+// The order of the values in a record set does not matter, and Route 53 rejects record sets with duplicate values.
+pub fn normalize_values(mut values: Vec<String>) -> Vec<String> {
+  values.sort();
+  values.dedup();
+  values
+}
+
+// The value of an alias record is the DNS name of its target.
+pub fn get_record_values(record_set: &ResourceRecordSet) -> Vec<String> {
+  if let Some(alias_target) = record_set.alias_target() {
+    return vec![alias_target.dns_name().to_string()];
+  }
+  record_set
+    .resource_records()
+    .iter()
+    .map(|rr| rr.value().to_string())
+    .collect()
+}
+
+// Route 53 does not allow a CNAME record to coexist with other records with the same name, and rejects the change with an unhelpful error message.
+pub fn check_record_conflicts(
+  record_sets: &[ResourceRecordSet],
+  record_name: &str,
+  record_type: &RrType,
+) {
+  for r in record_sets.iter().filter(|r| r.name() == record_name) {
+    if r.r#type() == record_type {
+      continue;
+    }
+    if r.r#type() == &RrType::Cname || record_type == &RrType::Cname {
+      panic!(
+        "a {} record already exists for {} which conflicts with the {} record, use --clear to delete it first",
+        r.r#type().as_str(),
+        record_name,
+        record_type.as_str()
+      );
+    }
+  }
+}
+
+// Returns the ownership marker if the record is managed by another controller, e.g. "heritage=external-dns,external-dns/owner=default".
+// external-dns creates a TXT record with the same name as the record, or with the record type as a prefix in newer versions (e.g. a-service.example.com).
+pub fn get_external_owner(
+  record_sets: &[ResourceRecordSet],
+  record_name: &str,
+  record_type: &RrType,
+) -> Option<String> {
+  let owner_record_names = [
+    record_name.to_string(),
+    format!("{}-{}", record_type.as_str().to_lowercase(), record_name),
+  ];
+  record_sets
+    .iter()
+    .filter(|r| {
+      r.r#type() == &RrType::Txt && owner_record_names.iter().any(|name| name == r.name())
+    })
+    .flat_map(get_record_values)
+    .map(|value| value.trim_matches('"').to_string())
+    .find(|value| value.contains("heritage="))
+}
+
+// The prefixed TXT record is not next to the record in the listing, so it has to be looked up separately.
+pub async fn find_external_owner(
+  backend: &dyn DnsBackend,
+  hosted_zone_id: &str,
+  record_sets: &[ResourceRecordSet],
+  record_name: &str,
+  record_type: &RrType,
+) -> Option<String> {
+  if let Some(owner) = get_external_owner(record_sets, record_name, record_type) {
+    return Some(owner);
+  }
+  let prefixed_name = format!("{}-{}", record_type.as_str().to_lowercase(), record_name);
+  match backend.get(hosted_zone_id, &prefixed_name).await {
+    Ok(record_sets) => get_external_owner(&record_sets, record_name, record_type),
+    Err(err) => {
+      eprintln!(
+        "Warning: could not check if the record is managed by another controller: {}",
+        err
+      );
+      None
+    }
+  }
+}
+
+// Terraform and CloudFormation don't leave markers next to the records, so hosted zones that they manage can be tagged instead, e.g. ManagedBy=terraform.
+// Returns the first of the owner tags that the hosted zone has.
+pub fn get_tag_owner(tags: &[Tag], owner_tags: &[types::Tag]) -> Option<String> {
+  owner_tags
+    .iter()
+    .find(|owner_tag| {
+      tags.iter().any(|tag| {
+        tag.key() == Some(owner_tag.key.as_str()) && tag.value() == Some(owner_tag.value.as_str())
+      })
+    })
+    .map(|owner_tag| format!("{}={}", owner_tag.key, owner_tag.value))
+}
+
+pub async fn find_tag_owner(
+  route53_client: &aws_sdk_route53::Client,
+  hosted_zone_id: &str,
+  owner_tags: &[types::Tag],
+) -> Option<String> {
+  match route53_client
+    .list_tags_for_resource()
+    .resource_type(TagResourceType::Hostedzone)
+    .resource_id(hosted_zone_id.trim_start_matches("/hostedzone/"))
+    .send()
+    .await
+  {
+    Ok(response) => get_tag_owner(
+      response
+        .resource_tag_set()
+        .map(|set| set.tags())
+        .unwrap_or_default(),
+      owner_tags,
+    ),
+    Err(err) => {
+      eprintln!(
+        "Warning: could not check if the hosted zone is managed by another tool: {}",
+        err
+      );
+      None
     }
-    // else {
-    //   TODO: Support a mix of IPv4 and IPv6 and set both A and AAAA records
-    // }
   }
-  RrType::Txt
+}
+
+// Values that are all IPv4 or all IPv6 addresses are A or AAAA records, and anything else falls back to TXT.
+// A mix of IPv4 and IPv6 addresses has to be split into separate record sets (see split_mixed_values).
+pub fn detect_record_type(v: Vec<String>) -> Result<RrType, String> {
+  let addrs: Vec<IpAddr> = match v.iter().map(|text| text.parse::<IpAddr>()).collect() {
+    Ok(addrs) => addrs,
+    Err(_) => return Ok(RrType::Txt),
+  };
+  if addrs.iter().all(|addr| addr.is_ipv4()) {
+    Ok(RrType::A)
+  } else if addrs.iter().all(|addr| addr.is_ipv6()) {
+    Ok(RrType::Aaaa)
+  } else {
+    Err("the values contain both IPv4 and IPv6 addresses, use --record-type to pick one or --split-mixed to create both an A and an AAAA record".to_string())
+  }
+}
+
+// Route 53 accepts up to 400 values in a record set, and CNAME records can only have one value.
+const MAX_VALUES_PER_RECORD_SET: usize = 400;
+
+pub fn check_value_count(record_type: Option<&RrType>, count: usize) -> Result<(), String> {
+  if record_type == Some(&RrType::Cname) && count > 1 {
+    return Err(format!(
+      "a CNAME record can only have one value, got {}",
+      count
+    ));
+  }
+  if count > MAX_VALUES_PER_RECORD_SET {
+    return Err(format!(
+      "a record set can have at most {} values, got {} (consider multivalue answer routing with one record set per value instead)",
+      MAX_VALUES_PER_RECORD_SET, count
+    ));
+  }
+  Ok(())
+}
+
+// Route 53 limits the length of each value, and of all of the values in a change. A string in a TXT value can be at most 255 bytes, so longer texts are split into multiple strings.
+const MAX_VALUE_LENGTH: usize = 4000;
+const MAX_TOTAL_VALUE_LENGTH: usize = 32000;
+pub const MAX_TXT_STRING_LENGTH: usize = 255;
+
+// Escapes a character for a quoted TXT string in the presentation format (RFC 1035 section 5.1). Quotes and backslashes are escaped with a backslash, and the bytes outside of printable ASCII are written as a backslash and three octal digits, which is the form that Route 53 uses.
+pub fn escape_txt_char(c: char) -> String {
+  match c {
+    '"' | '\\' => format!("\\{}", c),
+    ' '..='~' => c.to_string(),
+    _ => c
+      .to_string()
+      .bytes()
+      .map(|b| format!("\\{:03o}", b))
+      .collect(),
+  }
+}
+
+// Encloses a TXT value in quotes with the special characters escaped, splitting it into strings of at most 255 bytes, e.g. "abc" "def". Values that are already quoted are used as they are.
+pub fn quote_txt_value(value: &str) -> String {
+  if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+    return value.to_string();
+  }
+  // The escaped strings and their lengths in bytes
+  let mut strings = vec![(String::new(), 0)];
+  for c in value.chars() {
+    // Characters are not split between strings
+    if strings.last().unwrap().1 + c.len_utf8() > MAX_TXT_STRING_LENGTH {
+      strings.push((String::new(), 0));
+    }
+    let (s, length) = strings.last_mut().unwrap();
+    s.push_str(&escape_txt_char(c));
+    *length += c.len_utf8();
+  }
+  strings
+    .iter()
+    .map(|(s, _)| format!("\"{}\"", s))
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+// Returns the strings in a quoted TXT value, without the quotes. Escaped characters are kept as they are.
+pub fn get_txt_strings(value: &str) -> Vec<String> {
+  let mut strings = Vec::new();
+  let mut string: Option<String> = None;
+  let mut chars = value.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '"' => match string.take() {
+        Some(s) => strings.push(s),
+        None => string = Some(String::new()),
+      },
+      '\\' if string.is_some() => {
+        let s = string.as_mut().unwrap();
+        s.push(c);
+        s.extend(chars.next());
+      }
+      _ => {
+        if let Some(s) = &mut string {
+          s.push(c);
+        }
+      }
+    }
+  }
+  strings.extend(string);
+  strings
+}
+
+// The length of a TXT string is counted after the escapes are decoded, e.g. \" is one byte.
+fn get_txt_string_length(s: &str) -> usize {
+  let mut length = 0;
+  let mut chars = s.chars().peekable();
+  while let Some(c) = chars.next() {
+    if c == '\\' {
+      let digits: String = std::iter::from_fn(|| chars.next_if(|c| c.is_ascii_digit()))
+        .take(3)
+        .collect();
+      if digits.is_empty() {
+        length += chars.next().map_or(0, |c| c.len_utf8());
+      } else {
+        length += 1;
+      }
+    } else {
+      length += c.len_utf8();
+    }
+  }
+  length
+}
+
+pub fn check_value_lengths(record_type: Option<&RrType>, values: &[String]) -> Result<(), String> {
+  let preview = |value: &str| value.chars().take(40).collect::<String>();
+  for value in values {
+    if value.len() > MAX_VALUE_LENGTH {
+      return Err(format!(
+        "a value can be at most {} characters, got {} (the value starts with {})",
+        MAX_VALUE_LENGTH,
+        value.len(),
+        preview(value)
+      ));
+    }
+    if record_type == Some(&RrType::Txt) {
+      if let Some(s) = get_txt_strings(value)
+        .into_iter()
+        .find(|s| get_txt_string_length(s) > MAX_TXT_STRING_LENGTH)
+      {
+        return Err(format!(
+          "a string in a TXT value can be at most {} bytes, got {} (split it into multiple quoted strings like \"abc\" \"def\", or give the value without quotes to have it split automatically): \"{}...",
+          MAX_TXT_STRING_LENGTH,
+          get_txt_string_length(&s),
+          preview(&s)
+        ));
+      }
+    }
+  }
+  let total: usize = values.iter().map(|v| v.len()).sum();
+  if total > MAX_TOTAL_VALUE_LENGTH {
+    return Err(format!(
+      "the values can be at most {} characters in total, got {}",
+      MAX_TOTAL_VALUE_LENGTH, total
+    ));
+  }
+  Ok(())
+}
+
+// Returns the IPv4 and IPv6 addresses separately if the values are a mix of both.
+pub fn split_mixed_values(values: &[String]) -> Option<(Vec<String>, Vec<String>)> {
+  let addrs: Vec<IpAddr> = values
+    .iter()
+    .map(|text| text.parse::<IpAddr>())
+    .collect::<Result<_, _>>()
+    .ok()?;
+  let (ipv4, ipv6): (Vec<IpAddr>, Vec<IpAddr>) = addrs.into_iter().partition(|addr| addr.is_ipv4());
+  if ipv4.is_empty() || ipv6.is_empty() {
+    return None;
+  }
+  let to_strings = |addrs: Vec<IpAddr>| addrs.iter().map(|addr| addr.to_string()).collect();
+  Some((to_strings(ipv4), to_strings(ipv6)))
+}
+
+// EUI-64 interface identifiers are derived from the MAC address and have ff:fe in the middle.
+fn is_eui64(addr: &Ipv6Addr) -> bool {
+  let octets = addr.octets();
+  octets[11] == 0xff && octets[12] == 0xfe
+}
+
+fn is_global_unicast(addr: &Ipv6Addr) -> bool {
+  addr.segments()[0] & 0xe000 == 0x2000
+}
+
+// Picks a single IPv6 address according to the selection policy. The addresses are sorted first so that the result is deterministic.
+// Temporary (privacy) addresses can't be identified from the address alone (RFC 7217 stable addresses are not EUI-64 either), so the flags that the kernel has set on the local addresses are used, see sources::get_ipv6_address_flags.
+pub fn select_ipv6_address(
+  values: Vec<String>,
+  selection: &types::Ipv6Selection,
+  flags: &HashMap<Ipv6Addr, Vec<String>>,
+) -> Vec<String> {
+  let mut addrs: Vec<Ipv6Addr> = values
+    .iter()
+    .filter_map(|v| v.parse::<Ipv6Addr>().ok())
+    .collect();
+  addrs.sort();
+  let has_flag =
+    |addr: &Ipv6Addr, flag: &str| flags.get(addr).is_some_and(|f| f.iter().any(|f| f == flag));
+  let selected = match selection {
+    // Addresses that are being phased out are only used if there is nothing else
+    types::Ipv6Selection::Stable => addrs
+      .iter()
+      .filter(|a| is_global_unicast(a) && !has_flag(a, "temporary"))
+      .min_by_key(|a| has_flag(a, "deprecated")),
+    types::Ipv6Selection::Temporary => addrs
+      .iter()
+      .find(|a| is_global_unicast(a) && has_flag(a, "temporary") && !has_flag(a, "deprecated")),
+    types::Ipv6Selection::Eui64 => addrs.iter().find(|a| is_eui64(a)),
+    types::Ipv6Selection::Prefix(prefix) => addrs.iter().find(|a| prefix.contains(*a)),
+  };
+  selected.map(|a| vec![a.to_string()]).unwrap_or_default()
+}
+
+// Keeps the values that are inside one of the include-cidr ranges (if there are any) and outside all of the exclude-cidr ranges.
+// Values that are not IP addresses are not inside any range.
+pub fn filter_values(values: Vec<String>, filters: &[types::ValueFilter]) -> Vec<String> {
+  let has_includes = filters
+    .iter()
+    .any(|f| matches!(f, types::ValueFilter::IncludeCidr(_)));
+  values
+    .into_iter()
+    .filter(|v| {
+      let addr = v.parse::<IpAddr>().ok();
+      let contains = |net: &IpNet| addr.is_some_and(|a| net.contains(&a));
+      let included = !has_includes
+        || filters.iter().any(|f| match f {
+          types::ValueFilter::IncludeCidr(net) => contains(net),
+          types::ValueFilter::ExcludeCidr(_) => false,
+        });
+      let excluded = filters.iter().any(|f| match f {
+        types::ValueFilter::ExcludeCidr(net) => contains(net),
+        types::ValueFilter::IncludeCidr(_) => false,
+      });
+      included && !excluded
+    })
+    .collect()
+}
+
+// The shared address space that ISPs use for carrier-grade NAT (RFC 6598).
+pub fn is_cgnat_address(address: &Ipv4Addr) -> bool {
+  let octets = address.octets();
+  octets[0] == 100 && octets[1] & 0xc0 == 64
+}
+
+// Returns the interface address that shows that the host is behind carrier-grade NAT, i.e. an address in 100.64.0.0/10 when the public address is not assigned to any of the interfaces.
+// interface_addresses has the interface name and the address. Only the interfaces with a default route (if they are known) connect the host to the ISP.
+// VPNs like Tailscale use 100.64.0.0/10 for their own addresses, so their interfaces are never considered.
+pub fn get_cgnat_address(
+  interface_addresses: &[(String, String)],
+  default_route_interfaces: &[String],
+  public_address: &str,
+) -> Option<String> {
+  if interface_addresses.iter().any(|(_, a)| a == public_address) {
+    return None;
+  }
+  interface_addresses
+    .iter()
+    .filter(|(interface, _)| {
+      default_route_interfaces.is_empty() || default_route_interfaces.contains(interface)
+    })
+    .filter(|(interface, _)| !interface.starts_with("tailscale") && !interface.starts_with("wg"))
+    .map(|(_, address)| address)
+    .find(|a| a.parse().is_ok_and(|a| is_cgnat_address(&a)))
+    .cloned()
+}
+
+// Combines the network bits of the prefix with the host bits of the suffix.
+pub fn compose_ipv6_address(prefix: &Ipv6Net, suffix: &Ipv6Addr) -> Ipv6Addr {
+  let network = u128::from(prefix.network());
+  let host = u128::from(*suffix) & u128::from(prefix.hostmask());
+  Ipv6Addr::from(network | host)
+}
+
+pub fn is_access_denied(err: &impl ProvideErrorMetadata) -> bool {
+  err.code() == Some("AccessDenied")
+}
+
+// Retries the AWS call with backoff while it fails without a response from the service (e.g. the network or the credentials are not available yet during boot), until the deadline has passed.
+pub async fn retry_until_deadline<T, E, R, F, Fut>(
+  deadline: Option<Instant>,
+  mut call: F,
+) -> Result<T, SdkError<E, R>>
+where
+  F: FnMut() -> Fut,
+  Fut: Future<Output = Result<T, SdkError<E, R>>>,
+  E: std::error::Error + 'static,
+  R: std::fmt::Debug + 'static,
+{
+  let mut delay = Duration::from_millis(250);
+  loop {
+    match call().await {
+      Err(err)
+        if err.as_service_error().is_none()
+          && deadline.is_some_and(|deadline| Instant::now() + delay < deadline) =>
+      {
+        eprintln!(
+          "{}, retrying in {}ms",
+          DisplayErrorContext(&err),
+          delay.as_millis()
+        );
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(Duration::from_secs(5));
+      }
+      result => return result,
+    }
+  }
+}
+
+// The SDK does not have a read timeout by default, so a connection to an unreachable endpoint could hang for a long time.
+pub async fn load_aws_config(
+  options: &types::AwsOptions,
+  fallback_region: Option<String>,
+) -> aws_config::SdkConfig {
+  let region_provider = RegionProviderChain::first_try(options.region.clone().map(Region::new))
+    .or_default_provider()
+    .or_else(fallback_region.map(Region::new))
+    .or_else("us-east-1");
+  let timeout_config = TimeoutConfig::builder()
+    .connect_timeout(options.connect_timeout)
+    .read_timeout(options.timeout)
+    .operation_attempt_timeout(options.timeout)
+    .build();
+  let mut loader = aws_config::defaults(aws_config::BehaviorVersion::v2026_01_12())
+    .region(region_provider)
+    .timeout_config(timeout_config);
+  // Only override the settings when the flags are used, so that AWS_USE_FIPS_ENDPOINT and AWS_USE_DUALSTACK_ENDPOINT still work
+  if options.use_fips {
+    loader = loader.use_fips(true);
+  }
+  if options.use_dualstack {
+    loader = loader.use_dual_stack(true);
+  }
+  #[cfg(feature = "rustls-webpki-roots")]
+  {
+    use aws_smithy_http_client::tls::{self, rustls_provider::CryptoMode, TlsContext, TrustStore};
+    let trust_store = webpki_root_certs::TLS_SERVER_ROOT_CERTS.iter().fold(
+      TrustStore::empty().with_native_roots(false),
+      |store, cert| store.with_pem_certificate(der_to_pem(cert.as_ref()).as_bytes()),
+    );
+    let http_client = aws_smithy_http_client::Builder::new()
+      .tls_provider(tls::Provider::Rustls(CryptoMode::AwsLc))
+      .tls_context(
+        TlsContext::builder()
+          .with_trust_store(trust_store)
+          .build()
+          .expect("error building TLS context"),
+      )
+      .build_https();
+    loader = loader.http_client(http_client);
+  }
+  loader.load().await
+}
+
+// The trust store of the AWS SDK only accepts certificates in PEM format.
+pub fn der_to_pem(der: &[u8]) -> String {
+  use base64::Engine;
+  let encoded = base64::engine::general_purpose::STANDARD.encode(der);
+  let lines: Vec<&str> = encoded
+    .as_bytes()
+    .chunks(64)
+    .map(|chunk| std::str::from_utf8(chunk).unwrap())
+    .collect();
+  format!(
+    "-----BEGIN CERTIFICATE-----\n{}\n-----END CERTIFICATE-----\n",
+    lines.join("\n")
+  )
+}
+
+// All of the Route 53 calls go through clients from here, so that they are counted for the summary printed by --verbose.
+pub fn route53_client(config: &aws_config::SdkConfig) -> aws_sdk_route53::Client {
+  let route53_config = aws_sdk_route53::config::Builder::from(config)
+    .interceptor(api_calls::ApiCallCounter)
+    .build();
+  aws_sdk_route53::Client::from_conf(route53_config)
+}
+
+// Resolver picks the rule with the most specific domain name, and forwarding rules take precedence over private hosted zones.
+// Returns the forwarding rule that shadows the record name, if any.
+pub fn get_shadowing_resolver_rule<'a>(
+  rules: &'a [ResolverRule],
+  record_name: &str,
+) -> Option<&'a ResolverRule> {
+  let record_name = record_name.trim_end_matches('.');
+  rules
+    .iter()
+    .filter(|rule| {
+      let Some(domain_name) = rule.domain_name() else {
+        return false;
+      };
+      let domain_name = domain_name.trim_end_matches('.');
+      domain_name.is_empty()
+        || record_name == domain_name
+        || record_name.ends_with(&format!(".{}", domain_name))
+    })
+    .max_by_key(|rule| rule.domain_name().unwrap().trim_end_matches('.').len())
+    .filter(|rule| rule.rule_type() == Some(&RuleTypeOption::Forward))
+}
+
+// Only rules that are associated with one of the VPCs of the private hosted zone are considered.
+pub async fn check_resolver_rules(
+  shared_config: &aws_config::SdkConfig,
+  route53_client: &aws_sdk_route53::Client,
+  hosted_zone_id: &str,
+  record_name: &str,
+) {
+  let zone = match route53_client
+    .get_hosted_zone()
+    .id(hosted_zone_id.trim_start_matches("/hostedzone/"))
+    .send()
+    .await
+  {
+    Ok(zone) => zone,
+    Err(err) => {
+      eprintln!(
+        "Warning: could not get the hosted zone to check the resolver rules: {}",
+        err
+      );
+      return;
+    }
+  };
+  if !zone
+    .hosted_zone()
+    .and_then(|z| z.config())
+    .is_some_and(|c| c.private_zone)
+  {
+    return;
+  }
+  let vpc_ids: Vec<&str> = zone.vpcs().iter().filter_map(|vpc| vpc.vpc_id()).collect();
+
+  let resolver_client = aws_sdk_route53resolver::Client::new(shared_config);
+  let rules = resolver_client
+    .list_resolver_rules()
+    .into_paginator()
+    .items()
+    .send()
+    .collect::<Result<Vec<_>, _>>()
+    .await;
+  let associations = resolver_client
+    .list_resolver_rule_associations()
+    .into_paginator()
+    .items()
+    .send()
+    .collect::<Result<Vec<_>, _>>()
+    .await;
+  let (rules, associations) = match (rules, associations) {
+    (Ok(rules), Ok(associations)) => (rules, associations),
+    (Err(err), _) => {
+      eprintln!("Warning: could not list the resolver rules: {}", err);
+      return;
+    }
+    (_, Err(err)) => {
+      eprintln!(
+        "Warning: could not list the resolver rule associations: {}",
+        err
+      );
+      return;
+    }
+  };
+
+  for vpc_id in vpc_ids {
+    let vpc_rules: Vec<ResolverRule> = rules
+      .iter()
+      .filter(|rule| {
+        associations
+          .iter()
+          .any(|a| a.vpc_id() == Some(vpc_id) && a.resolver_rule_id() == rule.id())
+      })
+      .cloned()
+      .collect();
+    if let Some(rule) = get_shadowing_resolver_rule(&vpc_rules, record_name) {
+      eprintln!(
+        "Warning: the resolver rule {} ({}) forwards queries for {} in {}, so the record in the private hosted zone will not be used there",
+        rule.id().unwrap_or_default(),
+        rule.domain_name().unwrap_or_default(),
+        record_name,
+        vpc_id
+      );
+    }
+  }
+}
+
+pub async fn tag_hosted_zone(route53_client: &aws_sdk_route53::Client, hosted_zone_id: &str) {
+  let tags = [
+    ("LastUpdatedBy", get_hostname()),
+    ("LastUpdatedAt", get_timestamp()),
+  ]
+  .into_iter()
+  .map(|(key, value)| Tag::builder().key(key).value(value).build())
+  .collect();
+  route53_client
+    .change_tags_for_resource()
+    .resource_type(TagResourceType::Hostedzone)
+    .resource_id(hosted_zone_id.trim_start_matches("/hostedzone/"))
+    .set_add_tags(Some(tags))
+    .send()
+    .await
+    .expect("could not tag the hosted zone");
+}
+
+// --skip-elastic-ip
+// Returns the addresses that are Elastic IP addresses in the account. The filter is used instead of PublicIps since that fails if an address is not an Elastic IP.
+pub async fn get_elastic_ips(
+  ec2_client: &aws_sdk_ec2::Client,
+  addresses: &[String],
+) -> Vec<String> {
+  let response = ec2_client
+    .describe_addresses()
+    .filters(
+      aws_sdk_ec2::types::Filter::builder()
+        .name("public-ip")
+        .set_values(Some(addresses.to_vec()))
+        .build(),
+    )
+    .send()
+    .await
+    .expect("could not describe the Elastic IP addresses");
+  response
+    .addresses()
+    .iter()
+    .filter_map(|address| address.public_ip())
+    .map(|address| address.to_string())
+    .collect()
+}
+
+// Reported to the callback of wait_for_change_with_progress after each poll.
+#[derive(Debug)]
+pub struct ChangeProgress<'a> {
+  pub change_id: &'a str,
+  pub status: &'a ChangeStatus,
+  pub attempt: u32,
+  pub elapsed: Duration,
+}
+
+// Polls GetChange until the change is INSYNC. With max_attempts it gives up with an error after that many polls.
+pub async fn wait_for_change_with_progress<F: FnMut(&ChangeProgress)>(
+  route53_client: &aws_sdk_route53::Client,
+  change_id: &str,
+  max_attempts: Option<u32>,
+  mut on_progress: F,
+) -> Result<(), String> {
+  let change_id = change_id.trim_start_matches("/change/");
+  let start = Instant::now();
+  let mut attempt = 0;
+  loop {
+    attempt += 1;
+    let response = route53_client
+      .get_change()
+      .id(change_id)
+      .send()
+      .await
+      .map_err(|err| {
+        format!(
+          "could not poll change status: {}",
+          DisplayErrorContext(&err)
+        )
+      })?;
+    let change_status = response.change_info().unwrap().status();
+    on_progress(&ChangeProgress {
+      change_id,
+      status: change_status,
+      attempt,
+      elapsed: start.elapsed(),
+    });
+    if matches!(change_status, ChangeStatus::Insync) {
+      events::emit("change_insync", json!({ "change_id": change_id }));
+      return Ok(());
+    }
+    if max_attempts.is_some_and(|max_attempts| attempt >= max_attempts) {
+      return Err(format!(
+        "change {} is still {} after {} attempts",
+        change_id,
+        change_status.as_str(),
+        attempt
+      ));
+    }
+    tokio::time::sleep(Duration::from_secs(1)).await;
+  }
+}
+
+pub fn print_change_progress(progress: &ChangeProgress) {
+  eprintln!(
+    "Change {} is {} (elapsed: {}s)",
+    progress.change_id,
+    progress.status.as_str(),
+    progress.elapsed.as_secs()
+  );
+}
+
+// --wait
+pub async fn wait_for_change(
+  route53_client: &aws_sdk_route53::Client,
+  change_id: &str,
+  max_attempts: Option<u32>,
+) {
+  wait_for_change_with_progress(
+    route53_client,
+    change_id,
+    max_attempts,
+    print_change_progress,
+  )
+  .await
+  .unwrap_or_else(|err| panic!("{}", err));
+}
+
+// Route 53 accepts TTLs between 0 and 2147483647 seconds.
+pub const MAX_TTL: i64 = 2147483647;
+
+// Parses a TTL in seconds (e.g. 300) or a duration with units (e.g. 30s, 5m, 1h30m, or 1d).
+pub fn parse_ttl(s: &str) -> Result<i64, String> {
+  if s.is_empty() {
+    return Err("the TTL can not be empty".to_string());
+  }
+  let ttl = if let Ok(ttl) = s.parse::<i64>() {
+    ttl
+  } else {
+    let mut ttl: i64 = 0;
+    let mut number = String::new();
+    for c in s.chars() {
+      if c.is_ascii_digit() {
+        number.push(c);
+        continue;
+      }
+      let multiplier = match c {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return Err(format!("invalid TTL: {}", s)),
+      };
+      let n = number
+        .parse::<i64>()
+        .map_err(|_| format!("invalid TTL: {}", s))?;
+      ttl = n
+        .checked_mul(multiplier)
+        .and_then(|n| ttl.checked_add(n))
+        .ok_or_else(|| format!("TTL is too large: {}", s))?;
+      number.clear();
+    }
+    if !number.is_empty() {
+      return Err(format!("invalid TTL (missing unit): {}", s));
+    }
+    ttl
+  };
+  if !(0..=MAX_TTL).contains(&ttl) {
+    return Err(format!("TTL must be between 0 and {}: {}", MAX_TTL, s));
+  }
+  Ok(ttl)
+}
+
+// A default TTL for a specific record type takes precedence over a default TTL without a type.
+pub fn get_default_ttl(default_ttls: &[types::DefaultTtl], record_type: &RrType) -> i64 {
+  default_ttls
+    .iter()
+    .rev()
+    .find(|d| d.record_type.as_ref() == Some(record_type))
+    .or_else(|| default_ttls.iter().rev().find(|d| d.record_type.is_none()))
+    .map(|d| d.ttl)
+    .unwrap_or(300)
+}
+
+pub fn clamp_ttl(ttl: i64, min_ttl: Option<i64>, max_ttl: Option<i64>) -> i64 {
+  let ttl = min_ttl.map_or(ttl, |min| ttl.max(min));
+  max_ttl.map_or(ttl, |max| ttl.min(max))
+}
+
+// FNV-1a. Unlike DefaultHasher the result is the same in every build, so the values that are derived from a seed (like the hostname) stay the same for a host when the program is upgraded.
+pub fn stable_hash(data: &str) -> u64 {
+  data.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+    (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+  })
+}
+
+// Spreads the TTL by up to ±percent, so that the records of a large fleet do not expire in the resolvers at the same time.
+// A host has to get the same TTL on every run, otherwise the record would be updated each time.
+pub fn jitter_ttl(ttl: i64, percent: u8, seed: &str) -> i64 {
+  let range = ttl * i64::from(percent) / 100;
+  if range == 0 {
+    return ttl;
+  }
+  let offset = (stable_hash(seed) % (2 * range as u64 + 1)) as i64 - range;
+  (ttl + offset).clamp(0, MAX_TTL)
 }
 
 // The data that is retrieved so far exists in the same location in both the V3 and V4 endpoints.
 // https://docs.aws.amazon.com/AmazonECS/latest/developerguide/task-metadata-endpoint.html
-pub async fn get_ecs_task_metadata() -> Option<types::EcsTaskMetadata> {
-  if let Ok(ecs_container_metadata_uri) =
-    std::env::var("ECS_CONTAINER_METADATA_URI_V4").or(std::env::var("ECS_CONTAINER_METADATA_URI"))
-  {
-    let url = format!("{}/task", ecs_container_metadata_uri);
-    let response = reqwest::get(url.as_str()).await.unwrap();
-    if response.status() != reqwest::StatusCode::OK {
-      panic!(
+pub async fn get_ecs_task_metadata(
+  clients: &types::MetadataClients,
+) -> Option<types::EcsTaskMetadata> {
+  if let Some(fixture) = &clients.fixture {
+    return fixture.ecs.clone();
+  }
+  let ecs_container_metadata_uri = clients.ecs_metadata_uri.as_ref()?;
+  let url = format!("{}/task", ecs_container_metadata_uri);
+  // The endpoint can refuse connections or return errors for a short while after the container has started
+  let start = Instant::now();
+  let mut delay = Duration::from_millis(250);
+  loop {
+    let err = match clients.http.get(url.as_str()).send().await {
+      Ok(response) if response.status() == reqwest::StatusCode::OK => {
+        return Some(
+          response
+            .json::<types::EcsTaskMetadata>()
+            .await
+            .expect("could not parse the ECS task metadata"),
+        );
+      }
+      Ok(response) => format!(
         "response from {} returned non-200 status code: {}",
         url,
         response.status()
-      )
+      ),
+      Err(err) => format!("request to {} failed: {}", url, err),
+    };
+    if start.elapsed() + delay > clients.metadata_timeout {
+      panic!("{}", err);
     }
-    Some(response.json::<types::EcsTaskMetadata>().await.unwrap())
-  } else {
-    None
+    eprintln!("{}, retrying in {}ms", err, delay.as_millis());
+    tokio::time::sleep(delay).await;
+    delay = (delay * 2).min(Duration::from_secs(5));
+  }
+}
+
+pub async fn get_ec2_vpc_id(clients: &types::MetadataClients) -> Option<String> {
+  let mac = clients.get_ec2_metadata("/latest/meta-data/mac").await?;
+  clients
+    .get_ec2_metadata(&format!(
+      "/latest/meta-data/network/interfaces/macs/{}/vpc-id",
+      mac
+    ))
+    .await
+}
+
+pub async fn get_instance_identity_document(
+  clients: &types::MetadataClients,
+) -> Option<types::InstanceIdentityDocument> {
+  let document = clients
+    .get_ec2_metadata("/latest/dynamic/instance-identity/document")
+    .await?;
+  serde_json::from_str(&document).ok()
+}
+
+// Both of these paths return 404 until there is a notice.
+// https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/spot-instance-termination-notices.html
+pub async fn get_spot_interruption_notice(clients: &types::MetadataClients) -> Option<String> {
+  if let Some(instance_action) = clients
+    .get_ec2_metadata("/latest/meta-data/spot/instance-action")
+    .await
+  {
+    return Some(instance_action);
+  }
+  clients
+    .get_ec2_metadata("/latest/meta-data/events/recommendations/rebalance")
+    .await
+}
+
+const INSTANCE_IDENTITY_PLACEHOLDERS: [&str; 4] = [
+  "{instance_id}",
+  "{region}",
+  "{account_id}",
+  "{availability_zone}",
+];
+
+pub fn uses_instance_identity(template: &str) -> bool {
+  INSTANCE_IDENTITY_PLACEHOLDERS
+    .iter()
+    .any(|placeholder| template.contains(placeholder))
+}
+
+pub fn instance_identity_vars(
+  document: &types::InstanceIdentityDocument,
+) -> HashMap<&'static str, String> {
+  HashMap::from([
+    ("instance_id", document.instance_id.clone()),
+    ("region", document.region.clone()),
+    ("account_id", document.account_id.clone()),
+    ("availability_zone", document.availability_zone.clone()),
+  ])
+}
+
+const ECS_TASK_PLACEHOLDERS: [&str; 3] = ["{task_id}", "{family}", "{revision}"];
+
+pub fn uses_ecs_task_metadata(template: &str) -> bool {
+  ECS_TASK_PLACEHOLDERS
+    .iter()
+    .any(|placeholder| template.contains(placeholder))
+}
+
+pub fn ecs_task_vars(metadata: &types::EcsTaskMetadata) -> HashMap<&'static str, String> {
+  // The task ID is the last part of the task ARN, e.g. arn:aws:ecs:us-west-2:123456789012:task/cluster-name/task-id
+  let task_id = metadata
+    .task_arn
+    .as_deref()
+    .and_then(|arn| arn.rsplit('/').next())
+    .unwrap_or_default();
+  HashMap::from([
+    ("task_id", task_id.to_string()),
+    ("family", metadata.family.clone().unwrap_or_default()),
+    ("revision", metadata.revision.clone().unwrap_or_default()),
+  ])
+}
+
+// Patterns can either be an exact name or a wildcard like *.example.com that matches any subdomain.
+pub fn matches_record_name_pattern(name: &str, patterns: &[String]) -> bool {
+  let name = name.trim_end_matches('.').to_lowercase();
+  patterns.iter().any(|pattern| {
+    let pattern = pattern.trim_end_matches('.').to_lowercase();
+    match pattern.strip_prefix("*.") {
+      Some(suffix) => name.ends_with(&format!(".{}", suffix)),
+      None => name == pattern,
+    }
+  })
+}
+
+pub fn get_panic_message(err: tokio::task::JoinError) -> String {
+  match err.try_into_panic() {
+    Ok(panic) => panic
+      .downcast_ref::<String>()
+      .cloned()
+      .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+      .unwrap_or_else(|| "unknown error".to_string()),
+    Err(err) => err.to_string(),
+  }
+}
+
+pub fn get_hostname() -> String {
+  gethostname::gethostname().to_string_lossy().to_string()
+}
+
+pub fn get_timestamp() -> String {
+  OffsetDateTime::now_utc()
+    .format(&Rfc3339)
+    .expect("error formatting timestamp")
+}
+
+pub fn get_template_placeholders(template: &str) -> Vec<String> {
+  let mut placeholders = Vec::new();
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    rest = &rest[start + 1..];
+    if let Some(end) = rest.find('}') {
+      placeholders.push(rest[..end].to_string());
+      rest = &rest[end + 1..];
+    }
+  }
+  placeholders
+}
+
+// Replaces {placeholder} in the template with the corresponding value. Unknown placeholders are left as is.
+// Values are inserted as is, so a value containing {placeholder} is not expanded again.
+pub fn render_template(template: &str, vars: &HashMap<&str, String>) -> String {
+  let mut result = String::with_capacity(template.len());
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    result.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let Some(end) = rest[1..].find(['{', '}']).map(|i| i + 1) else {
+      break;
+    };
+    if rest.as_bytes()[end] == b'{' {
+      // The first brace does not start a placeholder, e.g. in "{{name}"
+      result.push_str(&rest[..end]);
+      rest = &rest[end..];
+      continue;
+    }
+    match vars.get(&rest[1..end]) {
+      Some(value) => result.push_str(value),
+      None => result.push_str(&rest[..=end]),
+    }
+    rest = &rest[end + 1..];
+  }
+  result.push_str(rest);
+  result
+}
+
+// A password or token given directly (on the command line or in an environment variable) or in a file. A trailing newline in the file is not part of the secret.
+pub fn get_secret(value: Option<String>, file: Option<&Path>) -> Result<String, String> {
+  let secret = match (value, file) {
+    (Some(value), _) => value,
+    (None, Some(path)) => std::fs::read_to_string(path)
+      .map_err(|err| format!("could not read {}: {}", path.display(), err))?
+      .trim_end_matches(['\r', '\n'])
+      .to_string(),
+    (None, None) => return Err("no secret was given".to_string()),
+  };
+  if secret.is_empty() {
+    return Err("the secret can not be empty".to_string());
+  }
+  Ok(secret)
+}
+
+// Compares the client's credentials without returning early at the first difference, so that the response time does not reveal how much of a guess was right.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  if a.len() != b.len() {
+    return false;
+  }
+  a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Resolves when SIGTERM (sent by ECS and Docker when stopping a container) or SIGINT is received.
+pub struct ShutdownSignal {
+  #[cfg(unix)]
+  sigterm: tokio::signal::unix::Signal,
+}
+
+impl Default for ShutdownSignal {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ShutdownSignal {
+  pub fn new() -> Self {
+    ShutdownSignal {
+      #[cfg(unix)]
+      sigterm: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("could not listen for SIGTERM"),
+    }
+  }
+
+  #[cfg(unix)]
+  pub async fn recv(&mut self) {
+    tokio::select! {
+      _ = self.sigterm.recv() => {}
+      _ = tokio::signal::ctrl_c() => {}
+    }
+  }
+
+  // A stop request from the service control manager is handled like ctrl-c
+  #[cfg(windows)]
+  pub async fn recv(&mut self) {
+    tokio::select! {
+      result = tokio::signal::ctrl_c() => result.expect("could not listen for ctrl-c"),
+      _ = crate::service::stop_requested() => {}
+    }
+  }
+
+  #[cfg(not(any(unix, windows)))]
+  pub async fn recv(&mut self) {
+    tokio::signal::ctrl_c()
+      .await
+      .expect("could not listen for ctrl-c");
   }
 }
@@ -0,0 +1,116 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::backend::DnsBackend;
+
+use aws_sdk_route53::types::{ResourceRecordSet, RrType};
+
+// Route 53 accepts weights between 0 and 255.
+const MAX_WEIGHT: i64 = 255;
+
+// Moves the percentage of the combined weight from one record set to the other, e.g. 90/10 becomes 80/20 when shifting 10%.
+// Returns the new weights of the record sets in the same order.
+pub fn shift_weights(from_weight: i64, to_weight: i64, percent: u8) -> Result<(i64, i64), String> {
+  let total = from_weight + to_weight;
+  if total == 0 {
+    return Err("both record sets have weight 0, there is no traffic to shift".to_string());
+  }
+  // Rounded to the nearest integer, but at least 1 so that small shifts make progress
+  let delta = ((total * i64::from(percent) + 50) / 100)
+    .max(1)
+    .min(from_weight);
+  let (from_weight, to_weight) = (from_weight - delta, to_weight + delta);
+  if to_weight > MAX_WEIGHT {
+    return Err(format!("the weight can not be greater than {}", MAX_WEIGHT));
+  }
+  Ok((from_weight, to_weight))
+}
+
+fn find_weighted_record_set<'a>(
+  record_sets: &'a [ResourceRecordSet],
+  record_name: &str,
+  record_type: Option<&RrType>,
+  set_identifier: &str,
+) -> &'a ResourceRecordSet {
+  let record_set = record_sets
+    .iter()
+    .find(|r| {
+      r.name() == record_name
+        && r.set_identifier() == Some(set_identifier)
+        && record_type.map_or(true, |t| r.r#type() == t)
+    })
+    .unwrap_or_else(|| {
+      panic!(
+        "could not find the record set {} with the set identifier {}",
+        record_name, set_identifier
+      )
+    });
+  if record_set.weight().is_none() {
+    panic!(
+      "the record set {} with the set identifier {} is not a weighted record set",
+      record_name, set_identifier
+    );
+  }
+  record_set
+}
+
+// Updates the weights of both record sets in the same change batch, so that the shift is atomic. Returns the change ID.
+pub async fn shift_weight(
+  backend: &dyn DnsBackend,
+  hosted_zone_id: &str,
+  record_name: &str,
+  record_type: Option<&RrType>,
+  from: &str,
+  to: &str,
+  percent: u8,
+) -> String {
+  let record_name = if record_name.ends_with('.') {
+    record_name.to_string()
+  } else {
+    format!("{}.", record_name)
+  };
+  let record_sets = backend
+    .get(hosted_zone_id, &record_name)
+    .await
+    .unwrap_or_else(|err| panic!("{}", err));
+  let from_record_set = find_weighted_record_set(&record_sets, &record_name, record_type, from);
+  let to_record_set = find_weighted_record_set(
+    &record_sets,
+    &record_name,
+    Some(from_record_set.r#type()),
+    to,
+  );
+
+  let (from_weight, to_weight) = shift_weights(
+    from_record_set.weight().unwrap(),
+    to_record_set.weight().unwrap(),
+    percent,
+  )
+  .unwrap_or_else(|err| panic!("{}", err));
+  eprintln!(
+    "Shifting {}% from {} to {}: {} {} -> {}, {} {} -> {}",
+    percent,
+    from,
+    to,
+    from,
+    from_record_set.weight().unwrap(),
+    from_weight,
+    to,
+    to_record_set.weight().unwrap(),
+    to_weight
+  );
+
+  let record_sets = [(from_record_set, from_weight), (to_record_set, to_weight)]
+    .into_iter()
+    .map(|(record_set, weight)| {
+      let mut record_set = record_set.clone();
+      record_set.weight = Some(weight);
+      record_set
+    })
+    .collect();
+  let comment = format!("Shift {}% of the weight from {} to {}", percent, from, to);
+  backend
+    .upsert(hosted_zone_id, record_sets, Some(comment))
+    .await
+    .unwrap_or_else(|err| panic!("could not update the weights: {}", err))
+}
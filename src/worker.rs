@@ -0,0 +1,192 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use crate::{fleet, types, utils};
+
+use aws_sdk_route53::types::RrType;
+use aws_sdk_sqs::error::DisplayErrorContext;
+use serde::Deserialize;
+use std::time::Duration;
+
+const RECEIVE_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECEIVE_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+pub struct WorkerOptions {
+  pub queue_url: String,
+  pub allowed_record_names: Vec<String>,
+  pub ip_address_type: types::IPAddressType,
+}
+
+// The body of a message, either with the values or with the ID of an EC2 instance to get the address of, e.g.:
+// {"record_name": "web1.example.com", "value": ["192.0.2.1"]}
+// {"record_name": "web1.example.com", "instance_id": "i-0123456789abcdef0"}
+#[derive(Debug, Deserialize)]
+pub struct WorkerRequest {
+  pub record_name: String,
+  #[serde(rename = "type")]
+  pub record_type: Option<String>,
+  #[serde(default)]
+  pub value: Vec<String>,
+  pub instance_id: Option<String>,
+  pub ttl: Option<i64>,
+}
+
+pub fn parse_request(body: &str, allowed_record_names: &[String]) -> Result<WorkerRequest, String> {
+  let request: WorkerRequest =
+    serde_json::from_str(body).map_err(|err| format!("invalid request: {}", err))?;
+  if !request.record_name.contains('.') {
+    return Err("the record name must be fully qualified".to_string());
+  }
+  if !utils::matches_record_name_pattern(&request.record_name, allowed_record_names) {
+    return Err(format!("updating {} is not allowed", request.record_name));
+  }
+  match (&request.instance_id, request.value.is_empty()) {
+    (Some(_), false) => return Err("can only use one of value or instance_id".to_string()),
+    (None, true) => return Err("value or instance_id is required".to_string()),
+    (Some(instance_id), true) if !fleet::is_instance_id(instance_id) => {
+      return Err(format!("invalid instance ID: {}", instance_id))
+    }
+    _ => {}
+  }
+  if let Some(record_type) = &request.record_type {
+    if !RrType::values().contains(&record_type.to_uppercase().as_str()) {
+      return Err(format!("unsupported record type: {}", record_type));
+    }
+  }
+  Ok(request)
+}
+
+async fn handle_message(
+  ec2_client: &aws_sdk_ec2::Client,
+  options: &WorkerOptions,
+  handler: &types::UpdateHandler,
+  request: WorkerRequest,
+) -> Result<types::UpdateResult, String> {
+  let values = match &request.instance_id {
+    Some(instance_id) => {
+      let entry = fleet::InventoryEntry {
+        host: instance_id.clone(),
+        record_name: request.record_name.clone(),
+      };
+      let addresses =
+        fleet::get_instance_addresses(ec2_client, &[entry], options.ip_address_type).await?;
+      let address = addresses
+        .get(instance_id)
+        .ok_or_else(|| format!("could not find the address of {}", instance_id))?;
+      vec![address.clone()]
+    }
+    None => request.value,
+  };
+  handler(types::UpdateRequest {
+    record_name: request.record_name,
+    record_type: request
+      .record_type
+      .map(|t| RrType::from(t.to_uppercase().as_str())),
+    value_source: Some(types::ValueSource::Values(values)),
+    alias_target: None,
+    ttl: request.ttl,
+  })
+  .await
+}
+
+async fn delete_message(
+  sqs_client: &aws_sdk_sqs::Client,
+  queue_url: &str,
+  message: &aws_sdk_sqs::types::Message,
+) {
+  let Some(receipt_handle) = message.receipt_handle() else {
+    return;
+  };
+  // The message is received again after the visibility timeout, which is harmless since the update is idempotent
+  if let Err(err) = sqs_client
+    .delete_message()
+    .queue_url(queue_url)
+    .receipt_handle(receipt_handle)
+    .send()
+    .await
+  {
+    eprintln!(
+      "Warning: could not delete message {} from the queue: {}",
+      message.message_id().unwrap_or_default(),
+      DisplayErrorContext(&err)
+    );
+  }
+}
+
+// Long-polls the queue until SIGTERM or Ctrl-C. Messages are deleted after the update succeeded, so failed messages are retried after the visibility timeout (configure a dead-letter queue to stop retrying eventually).
+// Messages that can't be parsed would never succeed, so they are deleted right away.
+pub async fn run(
+  sqs_client: &aws_sdk_sqs::Client,
+  ec2_client: &aws_sdk_ec2::Client,
+  options: WorkerOptions,
+  handler: types::UpdateHandler,
+) {
+  eprintln!("Waiting for messages from {}", options.queue_url);
+  let mut shutdown_signal = utils::ShutdownSignal::new();
+  let mut backoff = RECEIVE_RETRY_DELAY;
+  loop {
+    let response = tokio::select! {
+      response = sqs_client
+        .receive_message()
+        .queue_url(&options.queue_url)
+        .max_number_of_messages(10)
+        .wait_time_seconds(20)
+        .send() => response,
+      _ = shutdown_signal.recv() => {
+        eprintln!("Shutting down");
+        return;
+      }
+    };
+    let response = match response {
+      Ok(response) => {
+        backoff = RECEIVE_RETRY_DELAY;
+        response
+      }
+      Err(err) => {
+        // E.g. expired credentials or a network outage, which should not stop the worker for good
+        eprintln!(
+          "Could not receive messages from the queue, retrying in {}s: {}",
+          backoff.as_secs(),
+          DisplayErrorContext(&err)
+        );
+        tokio::select! {
+          _ = tokio::time::sleep(backoff) => {}
+          _ = shutdown_signal.recv() => {
+            eprintln!("Shutting down");
+            return;
+          }
+        }
+        backoff = (backoff * 2).min(MAX_RECEIVE_RETRY_DELAY);
+        continue;
+      }
+    };
+    for message in response.messages() {
+      let body = message.body().unwrap_or_default();
+      let message_id = message.message_id().unwrap_or_default();
+      let request = match parse_request(body, &options.allowed_record_names) {
+        Ok(request) => request,
+        Err(err) => {
+          eprintln!("Message {} is invalid, deleting it: {}", message_id, err);
+          delete_message(sqs_client, &options.queue_url, message).await;
+          continue;
+        }
+      };
+      match handle_message(ec2_client, &options, &handler, request).await {
+        Ok(result) => {
+          eprintln!(
+            "Message {}: {} {}",
+            message_id,
+            result.record_set.name(),
+            if result.is_changed() {
+              "updated"
+            } else {
+              "unchanged"
+            }
+          );
+          delete_message(sqs_client, &options.queue_url, message).await;
+        }
+        Err(err) => eprintln!("Message {} failed: {}", message_id, err),
+      }
+    }
+  }
+}
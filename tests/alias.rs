@@ -0,0 +1,154 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::types::RrType;
+use route53_update::{
+  alias::{self, AliasService},
+  types::RecordRouting,
+};
+
+#[test]
+fn get_alias_service() {
+  assert_eq!(
+    alias::get_alias_service("my-alb-1234567890.us-east-1.elb.amazonaws.com"),
+    Some((AliasService::LoadBalancer, Some("us-east-1".to_string())))
+  );
+  assert_eq!(
+    alias::get_alias_service("dualstack.internal-my-alb-1234567890.eu-west-1.elb.amazonaws.com."),
+    Some((AliasService::LoadBalancer, Some("eu-west-1".to_string())))
+  );
+  assert_eq!(
+    alias::get_alias_service("my-nlb-1234567890abcdef.elb.us-west-2.amazonaws.com"),
+    Some((
+      AliasService::NetworkLoadBalancer,
+      Some("us-west-2".to_string())
+    ))
+  );
+  assert_eq!(
+    alias::get_alias_service("D111111ABCDEF8.CloudFront.net"),
+    Some((AliasService::CloudFront, None))
+  );
+  assert_eq!(
+    alias::get_alias_service("a1234567890abcdef.awsglobalaccelerator.com"),
+    Some((AliasService::GlobalAccelerator, None))
+  );
+  assert_eq!(
+    alias::get_alias_service("example.com.s3-website-us-east-1.amazonaws.com"),
+    Some((AliasService::S3Website, Some("us-east-1".to_string())))
+  );
+  assert_eq!(
+    alias::get_alias_service("s3-website.eu-central-1.amazonaws.com"),
+    Some((AliasService::S3Website, Some("eu-central-1".to_string())))
+  );
+  assert_eq!(
+    alias::get_alias_service("d-abc123def4.execute-api.eu-west-1.amazonaws.com"),
+    Some((AliasService::ApiGateway, Some("eu-west-1".to_string())))
+  );
+  assert_eq!(alias::get_alias_service("www.example.com"), None);
+  assert_eq!(alias::get_alias_service("s3.amazonaws.com"), None);
+}
+
+#[test]
+fn get_alias_hosted_zone_id() {
+  assert_eq!(
+    alias::get_alias_hosted_zone_id("my-alb-1234567890.us-east-1.elb.amazonaws.com"),
+    Ok(Some("Z35SXDOTRQ7X7K"))
+  );
+  assert_eq!(
+    alias::get_alias_hosted_zone_id("my-nlb-1234567890abcdef.elb.us-east-1.amazonaws.com"),
+    Ok(Some("Z26RNL4JYFTOTI"))
+  );
+  assert_eq!(
+    alias::get_alias_hosted_zone_id("d111111abcdef8.cloudfront.net"),
+    Ok(Some(alias::CLOUDFRONT_HOSTED_ZONE_ID))
+  );
+  assert_eq!(
+    alias::get_alias_hosted_zone_id("example.com.s3-website-us-west-2.amazonaws.com"),
+    Ok(Some("Z3BJ6K6RIION7M"))
+  );
+  assert_eq!(
+    alias::get_alias_hosted_zone_id("d-abc123def4.execute-api.us-east-1.amazonaws.com"),
+    Ok(Some("Z1UJRXOUMOOFQ8"))
+  );
+  assert_eq!(alias::get_alias_hosted_zone_id("www.example.com"), Ok(None));
+  assert!(
+    alias::get_alias_hosted_zone_id("my-alb-1234567890.xx-nowhere-1.elb.amazonaws.com").is_err()
+  );
+}
+
+#[test]
+fn regional_hosted_zone_ids() {
+  for (region, alb, nlb, s3_website, api_gateway) in alias::REGIONAL_HOSTED_ZONE_IDS {
+    let ids = [alb, nlb, s3_website, api_gateway];
+    for (i, id) in ids.iter().enumerate() {
+      if region == "ap-northeast-3" && i == 3 {
+        // Osaka uses the same hosted zone ID for S3 website endpoints and API Gateway
+        assert_eq!(*id, s3_website);
+        continue;
+      }
+      assert!(
+        !ids[..i].contains(id),
+        "{} is repeated in the row of {}",
+        id,
+        region
+      );
+    }
+  }
+}
+
+#[test]
+fn build_alias_record_set() {
+  let record_set = alias::build_alias_record_set(
+    "example.com.",
+    &RrType::A,
+    "Z35SXDOTRQ7X7K",
+    "my-alb-1234567890.us-east-1.elb.amazonaws.com.",
+    true,
+    &RecordRouting::default(),
+  );
+  let alias_target = record_set.alias_target().unwrap();
+  assert_eq!(alias_target.hosted_zone_id(), "Z35SXDOTRQ7X7K");
+  assert_eq!(
+    alias_target.dns_name(),
+    "my-alb-1234567890.us-east-1.elb.amazonaws.com."
+  );
+  assert!(alias_target.evaluate_target_health());
+  assert_eq!(record_set.ttl(), None);
+  assert!(record_set.resource_records().is_empty());
+}
+
+#[test]
+fn get_record_values() {
+  let record_set = alias::build_alias_record_set(
+    "www.example.com.",
+    &RrType::A,
+    "Z1234567890ABC",
+    "example.com.",
+    false,
+    &RecordRouting::default(),
+  );
+  assert_eq!(
+    route53_update::utils::get_record_values(&record_set),
+    vec!["example.com."]
+  );
+}
+
+#[test]
+fn get_s3_website_endpoint() {
+  assert_eq!(
+    alias::get_s3_website_endpoint("us-east-1"),
+    "s3-website-us-east-1.amazonaws.com"
+  );
+  assert_eq!(
+    alias::get_s3_website_endpoint("eu-central-1"),
+    "s3-website.eu-central-1.amazonaws.com"
+  );
+  // Both forms are recognized by --alias-target
+  for region in ["us-west-2", "eu-central-1"] {
+    assert!(
+      alias::get_alias_hosted_zone_id(&alias::get_s3_website_endpoint(region))
+        .unwrap()
+        .is_some()
+    );
+  }
+}
@@ -0,0 +1,13 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::api_calls;
+
+#[test]
+fn summary() {
+  assert_eq!(api_calls::get_summary(), "none");
+  api_calls::record_api_call("ListHostedZones");
+  api_calls::record_api_call("GetChange");
+  api_calls::record_api_call("GetChange");
+  assert_eq!(api_calls::get_summary(), "ListHostedZones x1, GetChange x2");
+}
@@ -0,0 +1,94 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::types::{ResourceRecord, ResourceRecordSet, RrType};
+use route53_update::{
+  backend::{DnsBackend, MemoryBackend},
+  canary, weights,
+};
+use std::collections::HashMap;
+
+fn record_set(value: &str, set_identifier: Option<&str>, weight: Option<i64>) -> ResourceRecordSet {
+  ResourceRecordSet::builder()
+    .name("service.example.com.")
+    .r#type(RrType::A)
+    .ttl(60)
+    .resource_records(ResourceRecord::builder().value(value).build().unwrap())
+    .set_set_identifier(set_identifier.map(|s| s.to_string()))
+    .set_weight(weight)
+    .build()
+    .unwrap()
+}
+
+fn memory_backend(record_sets: Vec<ResourceRecordSet>) -> MemoryBackend {
+  MemoryBackend::new(HashMap::from([("Z0123456789".to_string(), record_sets)]))
+}
+
+#[tokio::test]
+async fn shift_weight() {
+  let backend = memory_backend(vec![
+    record_set("192.0.2.1", Some("blue"), Some(90)),
+    record_set("192.0.2.2", Some("green"), Some(10)),
+  ]);
+  weights::shift_weight(
+    &backend,
+    "Z0123456789",
+    "service.example.com",
+    None,
+    "blue",
+    "green",
+    10,
+  )
+  .await;
+  let record_sets = backend
+    .get("Z0123456789", "service.example.com.")
+    .await
+    .unwrap();
+  let weight = |set_identifier: &str| {
+    record_sets
+      .iter()
+      .find(|r| r.set_identifier() == Some(set_identifier))
+      .and_then(|r| r.weight())
+  };
+  assert_eq!(weight("blue"), Some(80));
+  assert_eq!(weight("green"), Some(20));
+}
+
+#[tokio::test]
+async fn rollback() {
+  let previous = record_set("192.0.2.1", None, None);
+  let new = record_set("192.0.2.2", None, None);
+  let backend = memory_backend(vec![new.clone()]);
+  canary::rollback(&backend, "Z0123456789", Some(&previous), &new).await;
+  assert_eq!(
+    backend
+      .get("Z0123456789", "service.example.com.")
+      .await
+      .unwrap(),
+    vec![previous]
+  );
+
+  // The record did not exist before
+  let backend = memory_backend(vec![new.clone()]);
+  canary::rollback(&backend, "Z0123456789", None, &new).await;
+  assert_eq!(
+    backend
+      .get("Z0123456789", "service.example.com.")
+      .await
+      .unwrap(),
+    Vec::new()
+  );
+}
+
+#[tokio::test]
+async fn delete_missing_record_set() {
+  let backend = memory_backend(Vec::new());
+  assert!(backend
+    .delete(
+      "Z0123456789",
+      vec![record_set("192.0.2.1", None, None)],
+      None
+    )
+    .await
+    .is_err());
+}
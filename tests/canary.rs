@@ -0,0 +1,25 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::types::RrType;
+use route53_update::canary;
+
+#[test]
+fn get_probe_address() {
+  let url = reqwest::Url::parse("https://service.example.com/health").unwrap();
+  let values = vec!["192.0.2.1".to_string()];
+  assert_eq!(
+    canary::get_probe_address(&url, "service.example.com.", &RrType::A, &values),
+    Some("192.0.2.1:0".parse().unwrap())
+  );
+  // The URL does not use the record name
+  assert_eq!(
+    canary::get_probe_address(&url, "other.example.com.", &RrType::A, &values),
+    None
+  );
+  let values = vec!["target.example.com.".to_string()];
+  assert_eq!(
+    canary::get_probe_address(&url, "service.example.com.", &RrType::Cname, &values),
+    None
+  );
+}
@@ -0,0 +1,74 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::cfn;
+use std::collections::HashMap;
+
+fn custom_resource_request(
+  request_type: &str,
+  physical_resource_id: Option<&str>,
+) -> cfn::CustomResourceRequest {
+  let mut request = serde_json::json!({
+    "RequestType": request_type,
+    "ResponseURL": "https://cloudformation-custom-resource-response.s3.amazonaws.com/response",
+    "StackId": "arn:aws:cloudformation:us-east-1:123456789012:stack/my-stack/guid",
+    "RequestId": "request-id",
+    "ResourceType": "Custom::Route53Record",
+    "LogicalResourceId": "Record",
+    "ResourceProperties": {
+      "ServiceToken": "arn:aws:lambda:us-east-1:123456789012:function:route53-update",
+      "RecordName": "service.example.com",
+      "Value": "192.0.2.1",
+      "TTL": "60"
+    }
+  });
+  if let Some(id) = physical_resource_id {
+    request["PhysicalResourceId"] = id.into();
+  }
+  serde_json::from_value(request).unwrap()
+}
+
+#[test]
+fn parse_request() {
+  let request = custom_resource_request("Create", None);
+  assert_eq!(request.request_type, cfn::RequestType::Create);
+  assert_eq!(
+    request.resource_properties.record_name,
+    "service.example.com"
+  );
+  assert_eq!(
+    request.resource_properties.value.into_vec(),
+    vec!["192.0.2.1"]
+  );
+  assert_eq!(request.resource_properties.ttl.as_deref(), Some("60"));
+}
+
+#[test]
+fn physical_resource_id() {
+  let id = cfn::format_physical_resource_id("Z0123456789", "service.example.com.", "A");
+  assert_eq!(
+    cfn::parse_physical_resource_id(&id),
+    Some(("Z0123456789", "service.example.com.", "A"))
+  );
+  assert_eq!(cfn::parse_physical_resource_id("request-id"), None);
+}
+
+#[test]
+fn build_response() {
+  let request = custom_resource_request("Create", None);
+  let response = cfn::build_response(&request, Err("could not update DNS".to_string()));
+  assert_eq!(response.status, "FAILED");
+  assert_eq!(response.reason.as_deref(), Some("could not update DNS"));
+  assert_eq!(response.physical_resource_id, "request-id");
+
+  let request = custom_resource_request("Delete", Some("Z0123456789|service.example.com.|A"));
+  let response = cfn::build_response(
+    &request,
+    Ok((
+      "Z0123456789|service.example.com.|A".to_string(),
+      HashMap::new(),
+    )),
+  );
+  assert_eq!(response.status, "SUCCESS");
+  assert_eq!(response.logical_resource_id, "Record");
+}
@@ -0,0 +1,131 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::types::RrType;
+use route53_update::check::{self, Answer, CheckStatus};
+use route53_update::types;
+use std::time::Duration;
+
+fn values(values: &[&str]) -> Answer {
+  Answer::Values {
+    values: values.iter().map(|v| v.to_string()).collect(),
+    ttl: 60,
+  }
+}
+
+#[test]
+fn normalize_value() {
+  assert_eq!(
+    check::normalize_value(&RrType::Cname, "Target.Example.com."),
+    "target.example.com"
+  );
+  assert_eq!(
+    check::normalize_value(&RrType::Txt, "\"v=spf1 -all\""),
+    "v=spf1 -all"
+  );
+  assert_eq!(
+    check::normalize_value(&RrType::Txt, "\"foo\" \"bar\""),
+    "foobar"
+  );
+  assert_eq!(check::normalize_value(&RrType::Txt, "Foo"), "Foo");
+}
+
+#[test]
+fn compare() {
+  let expected = vec![vec!["192.0.2.1".to_string(), "192.0.2.2".to_string()]];
+  assert_eq!(
+    check::compare(&RrType::A, &expected, &values(&["192.0.2.2", "192.0.2.1"])),
+    CheckStatus::Match
+  );
+  assert_eq!(
+    check::compare(&RrType::A, &expected, &values(&["192.0.2.1"])),
+    CheckStatus::Different
+  );
+  assert_eq!(
+    check::compare(
+      &RrType::A,
+      &expected,
+      &Answer::NotFound {
+        negative_ttl: Some(900)
+      }
+    ),
+    CheckStatus::Missing
+  );
+  assert_eq!(
+    check::compare(&RrType::A, &[], &values(&["192.0.2.1"])),
+    CheckStatus::Unexpected
+  );
+  assert_eq!(
+    check::compare(&RrType::A, &[], &Answer::NotFound { negative_ttl: None }),
+    CheckStatus::Match
+  );
+  assert_eq!(
+    check::compare(&RrType::A, &expected, &Answer::Error("timeout".to_string())),
+    CheckStatus::Error
+  );
+}
+
+#[test]
+fn compare_routing_policy() {
+  // Each record set of a weighted or geoproximity record is a valid answer
+  let expected = vec![vec!["192.0.2.1".to_string()], vec!["192.0.2.2".to_string()]];
+  assert_eq!(
+    check::compare(&RrType::A, &expected, &values(&["192.0.2.2"])),
+    CheckStatus::Match
+  );
+  assert_eq!(
+    check::compare(&RrType::A, &expected, &values(&["192.0.2.1", "192.0.2.2"])),
+    CheckStatus::Different
+  );
+}
+
+#[test]
+fn compare_name_servers() {
+  let expected = vec![vec![
+    "ns-1.awsdns-01.org".to_string(),
+    "ns-2.awsdns-02.com".to_string(),
+  ]];
+  assert_eq!(
+    check::compare(
+      &RrType::Ns,
+      &expected,
+      &values(&["ns-2.awsdns-02.com.", "ns-1.awsdns-01.org."])
+    ),
+    CheckStatus::Match
+  );
+}
+
+#[test]
+fn get_soa_negative_ttl() {
+  let soa = "ns-2048.awsdns-64.net. awsdns-hostmaster.amazon.com. 1 7200 900 1209600 86400";
+  assert_eq!(check::get_soa_negative_ttl(soa, 900), Some(900));
+  assert_eq!(check::get_soa_negative_ttl(soa, 172800), Some(86400));
+  assert_eq!(check::get_soa_negative_ttl("invalid", 900), None);
+}
+
+#[test]
+fn parse_reachability() {
+  assert_eq!(
+    "tcp:443".parse::<types::Reachability>(),
+    Ok(types::Reachability::Tcp(443))
+  );
+  assert!("tcp:0".parse::<types::Reachability>().is_err());
+  assert!("udp:53".parse::<types::Reachability>().is_err());
+}
+
+#[tokio::test]
+async fn filter_reachable() {
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let check = types::Reachability::Tcp(listener.local_addr().unwrap().port());
+  let values = vec!["127.0.0.1".to_string(), "not-an-address".to_string()];
+  assert_eq!(
+    check::filter_reachable(values, &check, Duration::from_secs(5)).await,
+    vec!["127.0.0.1"]
+  );
+  drop(listener);
+  assert!(
+    check::is_reachable("127.0.0.1".parse().unwrap(), &check, Duration::from_secs(5))
+      .await
+      .is_err()
+  );
+}
@@ -0,0 +1,115 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::types::CidrCollectionChangeAction;
+use route53_update::cidr;
+
+#[test]
+fn parse_cidr_file() {
+  let locations = cidr::parse_cidr_file(
+    "# Offices
+office 192.0.2.0/24 2001:db8::/48
+
+office 198.51.100.7/24
+vpn 203.0.113.0/28
+",
+  )
+  .unwrap();
+  assert_eq!(locations.len(), 2);
+  assert_eq!(
+    locations["office"].iter().collect::<Vec<_>>(),
+    ["192.0.2.0/24", "198.51.100.0/24", "2001:db8::/48"]
+  );
+  assert_eq!(
+    locations["vpn"].iter().collect::<Vec<_>>(),
+    ["203.0.113.0/28"]
+  );
+
+  assert!(cidr::parse_cidr_file("office").is_err());
+  assert!(cidr::parse_cidr_file("office 192.0.2.0/33").is_err());
+  assert!(cidr::parse_cidr_file("a.very.long.location 192.0.2.0/24").is_err());
+}
+
+#[test]
+fn cidr_collection_changes() {
+  let existing = cidr::parse_cidr_file(
+    "office 192.0.2.0/24 198.51.100.0/24
+old 203.0.113.0/24",
+  )
+  .unwrap();
+  let desired = cidr::parse_cidr_file("office 192.0.2.0/24 2001:db8::/48").unwrap();
+  let changes = cidr::get_cidr_collection_changes(&existing, &desired);
+  let changes: Vec<_> = changes
+    .iter()
+    .map(|c| {
+      (
+        c.action().clone(),
+        c.location_name(),
+        c.cidr_list().to_vec(),
+      )
+    })
+    .collect();
+  assert_eq!(
+    changes,
+    [
+      (
+        CidrCollectionChangeAction::DeleteIfExists,
+        "office",
+        vec!["198.51.100.0/24".to_string()]
+      ),
+      (
+        CidrCollectionChangeAction::DeleteIfExists,
+        "old",
+        vec!["203.0.113.0/24".to_string()]
+      ),
+      (
+        CidrCollectionChangeAction::Put,
+        "office",
+        vec!["2001:db8::/48".to_string()]
+      ),
+    ]
+  );
+
+  assert!(cidr::get_cidr_collection_changes(&desired, &desired).is_empty());
+}
+
+#[test]
+fn move_cidr_block_to_another_location() {
+  let existing = cidr::parse_cidr_file(
+    "office 192.0.2.0/24 198.51.100.0/24
+home 203.0.113.0/24",
+  )
+  .unwrap();
+  let desired = cidr::parse_cidr_file(
+    "office 192.0.2.0/24
+home 198.51.100.0/24 203.0.113.0/24",
+  )
+  .unwrap();
+  let changes = cidr::get_cidr_collection_changes(&existing, &desired);
+  let changes: Vec<_> = changes
+    .iter()
+    .map(|c| {
+      (
+        c.action().clone(),
+        c.location_name(),
+        c.cidr_list().to_vec(),
+      )
+    })
+    .collect();
+  // The block has to be removed from the old location before it can be added to the new one
+  assert_eq!(
+    changes,
+    [
+      (
+        CidrCollectionChangeAction::DeleteIfExists,
+        "office",
+        vec!["198.51.100.0/24".to_string()]
+      ),
+      (
+        CidrCollectionChangeAction::Put,
+        "home",
+        vec!["198.51.100.0/24".to_string()]
+      ),
+    ]
+  );
+}
@@ -0,0 +1,31 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+mod common;
+
+use aws_sdk_route53::types::RrType;
+use aws_sdk_servicediscovery::config::{BehaviorVersion, Credentials, Region};
+use route53_update::cloudmap;
+
+#[tokio::test]
+async fn register_instance() {
+  let url = common::mock_server(&[("POST /", r#"{"OperationId":"op-123"}"#)]);
+  let config = aws_sdk_servicediscovery::Config::builder()
+    .behavior_version(BehaviorVersion::latest())
+    .region(Region::new("us-east-1"))
+    .credentials_provider(Credentials::for_tests())
+    .endpoint_url(&url)
+    .build();
+  let client = aws_sdk_servicediscovery::Client::from_conf(config);
+  let registration = cloudmap::register_instance(
+    &client,
+    "srv-0123456789",
+    "web-1",
+    &RrType::A,
+    &["192.0.2.1".to_string()],
+  )
+  .await;
+  assert_eq!(registration.service_id, "srv-0123456789");
+  assert_eq!(registration.instance_id, "web-1");
+  assert_eq!(registration.operation_id.as_deref(), Some("op-123"));
+}
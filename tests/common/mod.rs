@@ -0,0 +1,59 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+#![allow(dead_code)]
+
+use hyper::{
+  service::{make_service_fn, service_fn},
+  Body, Response, Server, StatusCode,
+};
+use route53_update::types;
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc};
+
+// Starts a server on a random port that responds to "METHOD /path" with the given body, and with 404 to everything else.
+// Returns the base URL of the server.
+pub fn mock_server(routes: &[(&str, &str)]) -> String {
+  let routes: Arc<HashMap<String, String>> = Arc::new(
+    routes
+      .iter()
+      .map(|(route, body)| (route.to_string(), body.to_string()))
+      .collect(),
+  );
+  let make_svc = make_service_fn(move |_| {
+    let routes = routes.clone();
+    async move {
+      Ok::<_, Infallible>(service_fn(move |req| {
+        let key = format!("{} {}", req.method(), req.uri().path());
+        let response = match routes.get(&key) {
+          Some(body) => Response::builder()
+            // Required by the IMDSv2 token endpoint
+            .header("x-aws-ec2-metadata-token-ttl-seconds", "21600")
+            .body(Body::from(body.clone())),
+          None => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+        };
+        async move { Ok::<_, Infallible>(response.unwrap()) }
+      }))
+    }
+  });
+  let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_svc);
+  let url = format!("http://{}", server.local_addr());
+  tokio::spawn(server);
+  url
+}
+
+// Clients that talk to the mock server instead of the real metadata services.
+pub fn mock_clients(url: &str) -> types::MetadataClients {
+  types::MetadataClients {
+    imds: aws_config::imds::client::Client::builder()
+      .endpoint(url)
+      .unwrap()
+      .build(),
+    http: reqwest::Client::new(),
+    ecs_metadata_uri: Some(url.to_string()),
+    metadata_timeout: std::time::Duration::ZERO,
+    startup_deadline: None,
+    fixture: None,
+  }
+}
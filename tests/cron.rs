@@ -0,0 +1,121 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::cron::{self, Schedule};
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
+
+fn utc(year: i32, month: Month, day: u8, hour: u8, minute: u8, second: u8) -> OffsetDateTime {
+  PrimitiveDateTime::new(
+    Date::from_calendar_date(year, month, day).unwrap(),
+    Time::from_hms(hour, minute, second).unwrap(),
+  )
+  .assume_utc()
+}
+
+fn next_after(expression: &str, t: OffsetDateTime) -> Option<OffsetDateTime> {
+  expression.parse::<Schedule>().unwrap().next_after(t)
+}
+
+#[test]
+fn parse_schedule() {
+  assert!("*/5 * * * *".parse::<Schedule>().is_ok());
+  assert!("0,30 8-18 * * 1-5".parse::<Schedule>().is_ok());
+  assert_eq!(
+    "  */5  *   * * * ".parse::<Schedule>().unwrap().to_string(),
+    "*/5 * * * *"
+  );
+  assert_eq!(
+    "* * * *".parse::<Schedule>(),
+    Err("expected 5 fields (minute hour day-of-month month day-of-week), got 4".to_string())
+  );
+  assert_eq!(
+    "60 * * * *".parse::<Schedule>(),
+    Err("invalid value in the minute field: 60 (expected 0-59)".to_string())
+  );
+  assert_eq!(
+    "*/0 * * * *".parse::<Schedule>(),
+    Err("invalid step in the minute field: */0".to_string())
+  );
+  assert_eq!(
+    "* 18-8 * * *".parse::<Schedule>(),
+    Err("invalid range in the hour field: 18-8".to_string())
+  );
+  assert!("* * * * MON".parse::<Schedule>().is_err());
+}
+
+#[test]
+fn next_run_time() {
+  let t = utc(2024, Month::January, 1, 12, 3, 27);
+  assert_eq!(
+    next_after("*/5 * * * *", t),
+    Some(utc(2024, Month::January, 1, 12, 5, 0))
+  );
+  assert_eq!(
+    next_after("* * * * *", t),
+    Some(utc(2024, Month::January, 1, 12, 4, 0))
+  );
+  // The current minute is never returned
+  assert_eq!(
+    next_after("5 12 * * *", utc(2024, Month::January, 1, 12, 5, 0)),
+    Some(utc(2024, Month::January, 2, 12, 5, 0))
+  );
+  assert_eq!(
+    next_after("10/20 * * * *", t),
+    Some(utc(2024, Month::January, 1, 12, 10, 0))
+  );
+  assert_eq!(
+    next_after("0 0 1 * *", t),
+    Some(utc(2024, Month::February, 1, 0, 0, 0))
+  );
+  assert_eq!(
+    next_after("0 0 29 2 *", t),
+    Some(utc(2024, Month::February, 29, 0, 0, 0))
+  );
+  assert_eq!(next_after("0 0 30 2 *", t), None);
+}
+
+#[test]
+fn next_run_day_of_week() {
+  // 2024-01-01 is a Monday
+  let t = utc(2024, Month::January, 1, 12, 0, 0);
+  assert_eq!(
+    next_after("0 9 * * 0", t),
+    Some(utc(2024, Month::January, 7, 9, 0, 0))
+  );
+  // 7 is also Sunday
+  assert_eq!(
+    next_after("0 9 * * 7", t),
+    Some(utc(2024, Month::January, 7, 9, 0, 0))
+  );
+  assert_eq!(
+    next_after("0 9 * * 1-5", t),
+    Some(utc(2024, Month::January, 2, 9, 0, 0))
+  );
+  // When both the day of month and the day of week are given, either of them matches
+  assert_eq!(
+    next_after("0 9 15 * 5", t),
+    Some(utc(2024, Month::January, 5, 9, 0, 0))
+  );
+}
+
+#[test]
+fn next_run_with_jitter() {
+  let schedule: Schedule = "*/5 * * * *".parse().unwrap();
+  let jitter = std::time::Duration::from_secs(7);
+  assert_eq!(
+    schedule.next_run(utc(2024, Month::January, 1, 12, 3, 30), jitter),
+    Some((
+      utc(2024, Month::January, 1, 12, 5, 0),
+      std::time::Duration::from_secs(90 + 7)
+    ))
+  );
+}
+
+#[test]
+fn jitter() {
+  assert_eq!(cron::get_jitter("host1", 0), std::time::Duration::ZERO);
+  assert_eq!(cron::get_jitter("host1", 30), cron::get_jitter("host1", 30));
+  for host in ["host1", "host2", "host3"] {
+    assert!(cron::get_jitter(host, 30) <= std::time::Duration::from_secs(30));
+  }
+}
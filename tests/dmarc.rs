@@ -0,0 +1,57 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::{dmarc, types::DmarcPolicy};
+
+#[test]
+fn get_record_name() {
+  assert_eq!(
+    dmarc::get_record_name("example.com."),
+    "_dmarc.example.com."
+  );
+}
+
+#[test]
+fn build_dmarc_value() {
+  assert_eq!(
+    dmarc::build_dmarc_value(DmarcPolicy::None, None, None, &[], &[]).unwrap(),
+    "v=DMARC1; p=none"
+  );
+  assert_eq!(
+    dmarc::build_dmarc_value(
+      DmarcPolicy::Quarantine,
+      Some(DmarcPolicy::Reject),
+      Some(50),
+      &[
+        "mailto:dmarc@example.com".to_string(),
+        "mailto:reports@example.net!10m".to_string()
+      ],
+      &["mailto:forensic@example.com".to_string()],
+    )
+    .unwrap(),
+    "v=DMARC1; p=quarantine; sp=reject; pct=50; rua=mailto:dmarc@example.com,mailto:reports@example.net!10m; ruf=mailto:forensic@example.com"
+  );
+  assert!(dmarc::build_dmarc_value(
+    DmarcPolicy::Reject,
+    None,
+    None,
+    &["dmarc@example.com".to_string()],
+    &[]
+  )
+  .is_err());
+}
+
+#[test]
+fn validate_report_uri() {
+  assert!(dmarc::validate_report_uri("mailto:dmarc@example.com").is_ok());
+  assert!(dmarc::validate_report_uri("MAILTO:dmarc@example.com").is_ok());
+  assert!(dmarc::validate_report_uri("mailto:dmarc@example.com!10m").is_ok());
+  assert!(dmarc::validate_report_uri("mailto:dmarc@example.com!500").is_ok());
+  assert!(dmarc::validate_report_uri("https://example.com/dmarc").is_err());
+  assert!(dmarc::validate_report_uri("mailto:").is_err());
+  assert!(dmarc::validate_report_uri("mailto:dmarc").is_err());
+  assert!(dmarc::validate_report_uri("mailto:dmarc@example.com!").is_err());
+  assert!(dmarc::validate_report_uri("mailto:dmarc@example.com!10mb").is_err());
+  assert!(dmarc::validate_report_uri("mailto:a@example.com,mailto:b@example.com").is_err());
+  assert!(dmarc::validate_report_uri("mailto:dmarc@example.com; p=none").is_err());
+}
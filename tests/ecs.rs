@@ -0,0 +1,26 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_ecs::types::{Attachment, KeyValuePair, Task};
+use route53_update::ecs;
+
+#[test]
+fn get_task_eni() {
+  let detail = |name: &str, value: &str| KeyValuePair::builder().name(name).value(value).build();
+  let task = Task::builder()
+    .attachments(
+      Attachment::builder()
+        .r#type("ElasticNetworkInterface")
+        .details(detail("subnetId", "subnet-0123456789abcdef0"))
+        .details(detail("networkInterfaceId", "eni-0123456789abcdef0"))
+        .details(detail("privateIPv4Address", "10.0.0.10"))
+        .build(),
+    )
+    .build();
+  assert_eq!(
+    ecs::get_task_eni(&task),
+    Some(("eni-0123456789abcdef0".to_string(), "10.0.0.10".to_string()))
+  );
+  // Tasks in bridge and host network mode do not have a network interface
+  assert_eq!(ecs::get_task_eni(&Task::builder().build()), None);
+}
@@ -0,0 +1,17 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::events;
+use serde_json::json;
+
+#[test]
+fn event_line() {
+  assert_eq!(
+    events::event_line(
+      "change_insync",
+      "2024-01-01T00:00:00Z",
+      json!({ "change_id": "C0123456789" })
+    ),
+    r#"{"change_id":"C0123456789","event":"change_insync","timestamp":"2024-01-01T00:00:00Z"}"#
+  );
+}
@@ -0,0 +1,134 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::types::{HealthCheckType, ResourceRecordSetFailover, RrType};
+use route53_update::{failover, types::HealthCheckProtocol};
+
+#[test]
+fn build_health_check_config() {
+  let config = failover::build_health_check_config(
+    "example.com.",
+    "192.0.2.10",
+    HealthCheckProtocol::Https,
+    None,
+    "/health",
+  )
+  .unwrap();
+  assert_eq!(config.r#type(), &HealthCheckType::Https);
+  assert_eq!(config.ip_address(), Some("192.0.2.10"));
+  assert_eq!(config.fully_qualified_domain_name(), Some("example.com"));
+  assert_eq!(config.port(), Some(443));
+  assert_eq!(config.resource_path(), Some("/health"));
+  assert_eq!(config.enable_sni(), Some(true));
+
+  let config = failover::build_health_check_config(
+    "www.example.com.",
+    "primary.example.net",
+    HealthCheckProtocol::Http,
+    Some(8080),
+    "/",
+  )
+  .unwrap();
+  assert_eq!(config.ip_address(), None);
+  assert_eq!(
+    config.fully_qualified_domain_name(),
+    Some("primary.example.net")
+  );
+  assert_eq!(config.port(), Some(8080));
+
+  let config = failover::build_health_check_config(
+    "example.com.",
+    "192.0.2.10",
+    HealthCheckProtocol::Tcp,
+    Some(22),
+    "/",
+  )
+  .unwrap();
+  assert_eq!(config.resource_path(), None);
+  assert!(failover::build_health_check_config(
+    "example.com.",
+    "192.0.2.10",
+    HealthCheckProtocol::Tcp,
+    None,
+    "/"
+  )
+  .is_err());
+}
+
+#[test]
+fn get_caller_reference() {
+  let config = |endpoint| {
+    failover::build_health_check_config(
+      "example.com.",
+      endpoint,
+      HealthCheckProtocol::Https,
+      None,
+      "/",
+    )
+    .unwrap()
+  };
+  let reference = failover::get_caller_reference("example.com.", "primary", &config("192.0.2.10"));
+  assert!(reference.len() <= 64);
+  assert_eq!(
+    reference,
+    failover::get_caller_reference("example.com.", "primary", &config("192.0.2.10"))
+  );
+  assert_ne!(
+    reference,
+    failover::get_caller_reference("example.com.", "primary", &config("192.0.2.11"))
+  );
+  assert_ne!(
+    reference,
+    failover::get_caller_reference("example.com.", "secondary", &config("192.0.2.10"))
+  );
+  // The reference must not change between releases, otherwise a second run would create new health checks
+  assert_eq!(
+    reference,
+    format!(
+      "route53-update-failover-{:016x}",
+      route53_update::utils::stable_hash(
+        "example.com.\nprimary\nHTTPS\n192.0.2.10\nexample.com\n443\n/"
+      )
+    )
+  );
+}
+
+#[test]
+fn get_record_type() {
+  assert_eq!(
+    failover::get_record_type("192.0.2.10", "198.51.100.20"),
+    Ok(RrType::A)
+  );
+  assert_eq!(
+    failover::get_record_type("2001:db8::10", "2001:db8::20"),
+    Ok(RrType::Aaaa)
+  );
+  assert_eq!(
+    failover::get_record_type("primary.example.net", "secondary.example.net"),
+    Ok(RrType::Cname)
+  );
+  assert!(failover::get_record_type("192.0.2.10", "secondary.example.net").is_err());
+}
+
+#[test]
+fn build_failover_record_set() {
+  let record_set = failover::build_failover_record_set(
+    "example.com.",
+    &RrType::A,
+    60,
+    "192.0.2.10",
+    ResourceRecordSetFailover::Primary,
+    "abcdef11-2222-3333-4444-555555fedcba",
+  );
+  assert_eq!(record_set.set_identifier(), Some("primary"));
+  assert_eq!(
+    record_set.failover(),
+    Some(&ResourceRecordSetFailover::Primary)
+  );
+  assert_eq!(
+    record_set.health_check_id(),
+    Some("abcdef11-2222-3333-4444-555555fedcba")
+  );
+  assert_eq!(record_set.ttl(), Some(60));
+  assert_eq!(record_set.resource_records()[0].value(), "192.0.2.10");
+}
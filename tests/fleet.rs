@@ -0,0 +1,85 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+mod common;
+
+use aws_sdk_ec2::{
+  config::{BehaviorVersion, Credentials, Region},
+  types::Instance,
+};
+use route53_update::{fleet, types};
+
+#[test]
+fn parse_inventory() {
+  let contents = "# host record name
+i-0123456789abcdef0 web1.example.com
+
+web2\tweb2.example.com # the second web server
+";
+  assert_eq!(
+    fleet::parse_inventory(contents),
+    Ok(vec![
+      fleet::InventoryEntry {
+        host: "i-0123456789abcdef0".to_string(),
+        record_name: "web1.example.com".to_string(),
+      },
+      fleet::InventoryEntry {
+        host: "web2".to_string(),
+        record_name: "web2.example.com".to_string(),
+      },
+    ])
+  );
+  assert_eq!(
+    fleet::parse_inventory("web1\n"),
+    Err("line 1: expected a host and a record name, got \"web1\"".to_string())
+  );
+}
+
+#[test]
+fn is_instance_id() {
+  assert!(fleet::is_instance_id("i-0123456789abcdef0"));
+  assert!(!fleet::is_instance_id("i-"));
+  assert!(!fleet::is_instance_id("web1"));
+  assert!(!fleet::is_instance_id("i-web1"));
+}
+
+#[test]
+fn get_instance_address() {
+  let instance = Instance::builder()
+    .public_ip_address("198.51.100.1")
+    .private_ip_address("10.0.0.1")
+    .build();
+  assert_eq!(
+    fleet::get_instance_address(&instance, types::IPAddressType::Public),
+    Some("198.51.100.1".to_string())
+  );
+  assert_eq!(
+    fleet::get_instance_address(&instance, types::IPAddressType::Private),
+    Some("10.0.0.1".to_string())
+  );
+  assert_eq!(
+    fleet::get_instance_address(&Instance::builder().build(), types::IPAddressType::Public),
+    None
+  );
+}
+
+#[tokio::test]
+async fn get_instance_addresses_error() {
+  // The mock server responds with 404 to DescribeInstances
+  let url = common::mock_server(&[]);
+  let config = aws_sdk_ec2::Config::builder()
+    .behavior_version(BehaviorVersion::latest())
+    .region(Region::new("us-east-1"))
+    .credentials_provider(Credentials::for_tests())
+    .endpoint_url(&url)
+    .build();
+  let client = aws_sdk_ec2::Client::from_conf(config);
+  let entries = [fleet::InventoryEntry {
+    host: "i-0123456789abcdef0".to_string(),
+    record_name: "web1.example.com".to_string(),
+  }];
+  let err = fleet::get_instance_addresses(&client, &entries, types::IPAddressType::Public)
+    .await
+    .unwrap_err();
+  assert!(err.starts_with("could not describe the EC2 instances: "));
+}
@@ -0,0 +1,52 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::gc;
+
+#[test]
+fn get_instance_id() {
+  assert_eq!(
+    gc::get_instance_id(
+      "i-*.fleet.example.com",
+      "i-0123456789abcdef0.fleet.example.com."
+    ),
+    Some("i-0123456789abcdef0".to_string())
+  );
+  assert_eq!(
+    gc::get_instance_id(
+      "web-{instance_id}.example.com.",
+      "web-i-0123abcd.example.com."
+    ),
+    Some("i-0123abcd".to_string())
+  );
+  assert_eq!(
+    gc::get_instance_id(
+      "i-*.fleet.example.com",
+      "i-0123456789abcdef0.other.example.com."
+    ),
+    None
+  );
+  assert_eq!(
+    gc::get_instance_id(
+      "i-*.fleet.example.com",
+      "a.i-0123456789abcdef0.fleet.example.com."
+    ),
+    None
+  );
+  assert_eq!(
+    gc::get_instance_id("*.fleet.example.com", "www.fleet.example.com."),
+    None
+  );
+}
+
+#[test]
+fn get_pattern_suffix() {
+  assert_eq!(
+    gc::get_pattern_suffix("i-*.fleet.example.com"),
+    "fleet.example.com."
+  );
+  assert_eq!(
+    gc::get_pattern_suffix("{instance_id}.example.com."),
+    "example.com."
+  );
+}
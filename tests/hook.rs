@@ -0,0 +1,28 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::types::RrType;
+use route53_update::hook;
+
+// The hooks are configured with environment variables, so everything is in one test to avoid running them concurrently.
+#[test]
+fn networkmanager_interface_filter() {
+  std::env::remove_var("reason");
+  std::env::set_var("IP4_ADDRESS_0", "192.0.2.10/24 192.0.2.1");
+  assert_eq!(
+    hook::get_hook_values(Some("eth0"), Some("up"), Some("eth0"), Some(&RrType::A)),
+    Some(vec!["192.0.2.10".to_string()])
+  );
+  assert_eq!(
+    hook::get_hook_values(Some("wlan0"), Some("up"), Some("eth0"), Some(&RrType::A)),
+    None
+  );
+  assert_eq!(
+    hook::get_hook_values(Some("wlan0"), Some("up"), None, Some(&RrType::A)),
+    Some(vec!["192.0.2.10".to_string()])
+  );
+  assert_eq!(
+    hook::get_hook_values(Some("eth0"), Some("down"), Some("eth0"), Some(&RrType::A)),
+    None
+  );
+}
@@ -0,0 +1,34 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::lambda;
+use serde_json::json;
+
+#[test]
+fn parse_instance_state_change() {
+  let event = json!({
+    "version": "0",
+    "id": "7bf73129-1428-4cd3-a780-95db273d1602",
+    "detail-type": "EC2 Instance State-change Notification",
+    "source": "aws.ec2",
+    "account": "123456789012",
+    "time": "2024-01-01T00:00:00Z",
+    "region": "us-east-1",
+    "resources": ["arn:aws:ec2:us-east-1:123456789012:instance/i-0123456789abcdef0"],
+    "detail": {
+      "instance-id": "i-0123456789abcdef0",
+      "state": "running"
+    }
+  });
+  assert_eq!(
+    lambda::parse_instance_state_change(&event),
+    Some(lambda::InstanceStateChange {
+      instance_id: "i-0123456789abcdef0".to_string(),
+      state: "running".to_string(),
+    })
+  );
+  assert_eq!(
+    lambda::parse_instance_state_change(&json!({ "detail-type": "Scheduled Event", "detail": {} })),
+    None
+  );
+}
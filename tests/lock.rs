@@ -0,0 +1,41 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::lock::{self, LockError};
+
+#[test]
+fn describe_holder() {
+  assert_eq!(
+    lock::describe_holder(1234, "host1", "2024-01-01T00:00:00Z"),
+    "pid 1234 on host1 (since 2024-01-01T00:00:00Z)"
+  );
+  assert_eq!(
+    LockError::Held(Some(
+      "pid 1234 on host1 (since 2024-01-01T00:00:00Z)".to_string()
+    ))
+    .to_string(),
+    "the lock is held by pid 1234 on host1 (since 2024-01-01T00:00:00Z)"
+  );
+}
+
+#[cfg(unix)]
+#[test]
+fn acquire_lock() {
+  let path = std::env::temp_dir().join(format!("route53-update-test-{}.lock", std::process::id()));
+  let path = path.to_str().unwrap();
+
+  let lock = lock::acquire(path).unwrap();
+  let holder = std::fs::read_to_string(path).unwrap();
+  assert!(holder.starts_with(&format!("pid {} on ", std::process::id())));
+
+  // flock locks belong to the open file, so the lock is held even within the same process
+  match lock::acquire(path) {
+    Err(LockError::Held(Some(h))) => assert_eq!(h, holder.trim()),
+    _ => panic!("expected the lock to be held"),
+  }
+
+  drop(lock);
+  let lock = lock::acquire(path).unwrap();
+  drop(lock);
+  std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,75 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::logging;
+use serde_json::json;
+
+#[test]
+fn severity() {
+  assert_eq!(logging::get_severity("Found hosted zone: Z0123456789"), 6);
+  assert_eq!(
+    logging::get_severity("Warning: could not write event: broken pipe"),
+    4
+  );
+  assert_eq!(logging::get_severity("Error: invalid value"), 3);
+  assert_eq!(
+    logging::get_severity("thread 'main' panicked at src/main.rs:10:5:"),
+    3
+  );
+}
+
+#[test]
+fn syslog_message() {
+  assert_eq!(
+    logging::syslog_message(1234, 3, "Error: invalid value"),
+    "<27>route53-update[1234]: Error: invalid value"
+  );
+}
+
+#[test]
+fn journal_message() {
+  let message = logging::journal_message(&[
+    ("MESSAGE".to_string(), "hello".to_string()),
+    ("DETAILS".to_string(), "a\nb".to_string()),
+  ]);
+  let mut expected = b"MESSAGE=hello\nDETAILS\n".to_vec();
+  expected.extend_from_slice(&3u64.to_le_bytes());
+  expected.extend_from_slice(b"a\nb\n");
+  assert_eq!(message, expected);
+}
+
+#[test]
+fn event_journal_fields() {
+  let fields = logging::event_journal_fields(
+    "change_submitted",
+    &json!({
+      "change_id": "C0123456789",
+      "record-name": "service.example.com.",
+      "values": ["192.0.2.1"],
+    }),
+  );
+  assert_eq!(
+    fields,
+    vec![
+      (
+        "MESSAGE".to_string(),
+        r#"change_submitted {"change_id":"C0123456789","record-name":"service.example.com.","values":["192.0.2.1"]}"#.to_string()
+      ),
+      ("PRIORITY".to_string(), "6".to_string()),
+      ("SYSLOG_IDENTIFIER".to_string(), "route53-update".to_string()),
+      ("ROUTE53_UPDATE_EVENT".to_string(), "change_submitted".to_string()),
+      (
+        "ROUTE53_UPDATE_CHANGE_ID".to_string(),
+        "C0123456789".to_string()
+      ),
+      (
+        "ROUTE53_UPDATE_RECORD_NAME".to_string(),
+        "service.example.com.".to_string()
+      ),
+      (
+        "ROUTE53_UPDATE_VALUES".to_string(),
+        r#"["192.0.2.1"]"#.to_string()
+      ),
+    ]
+  );
+}
@@ -0,0 +1,14 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::mirror::{self, MAX_POLL_INTERVAL, MIN_POLL_INTERVAL};
+use std::time::Duration;
+
+#[test]
+fn get_poll_interval() {
+  assert_eq!(mirror::get_poll_interval(60), Duration::from_secs(61));
+  assert_eq!(mirror::get_poll_interval(300), Duration::from_secs(301));
+  assert_eq!(mirror::get_poll_interval(0), MIN_POLL_INTERVAL);
+  assert_eq!(mirror::get_poll_interval(5), MIN_POLL_INTERVAL);
+  assert_eq!(mirror::get_poll_interval(86400), MAX_POLL_INTERVAL);
+}
@@ -0,0 +1,63 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_ec2::types::InstanceStateName;
+use route53_update::reconcile;
+use std::collections::{BTreeMap, BTreeSet};
+
+fn instance(
+  instance_id: &str,
+  record_name: &str,
+  address: Option<&str>,
+  state: InstanceStateName,
+) -> reconcile::TaggedInstance {
+  reconcile::TaggedInstance {
+    instance_id: instance_id.to_string(),
+    record_name: record_name.to_string(),
+    address: address.map(|address| address.to_string()),
+    state,
+  }
+}
+
+#[test]
+fn plan() {
+  let instances = vec![
+    instance(
+      "i-1",
+      "web.example.com",
+      Some("192.0.2.2"),
+      InstanceStateName::Running,
+    ),
+    instance(
+      "i-2",
+      "Web.example.com.",
+      Some("192.0.2.1"),
+      InstanceStateName::Running,
+    ),
+    instance("i-3", "db.example.com", None, InstanceStateName::Stopped),
+    // The name is still used by a running instance
+    instance(
+      "i-4",
+      "web.example.com",
+      None,
+      InstanceStateName::Terminated,
+    ),
+    instance("i-5", "db.example.com", None, InstanceStateName::Terminated),
+    instance(
+      "i-6",
+      "old.example.com",
+      None,
+      InstanceStateName::ShuttingDown,
+    ),
+  ];
+  assert_eq!(
+    reconcile::plan(&instances),
+    reconcile::Plan {
+      upsert: BTreeMap::from([(
+        "web.example.com.".to_string(),
+        vec!["192.0.2.1".to_string(), "192.0.2.2".to_string()]
+      )]),
+      delete: BTreeSet::from(["old.example.com.".to_string()]),
+    }
+  );
+}
@@ -0,0 +1,140 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+mod common;
+
+use aws_sdk_route53::config::{BehaviorVersion, Credentials, Region};
+use route53_update::{delegate, dnssec, utils};
+
+// A Route 53 client that talks to the given endpoint, e.g. a mock server or LocalStack.
+fn route53_client(endpoint_url: &str) -> aws_sdk_route53::Client {
+  let config = aws_sdk_route53::Config::builder()
+    .behavior_version(BehaviorVersion::latest())
+    .region(Region::new("us-east-1"))
+    .credentials_provider(Credentials::for_tests())
+    .endpoint_url(endpoint_url)
+    .build();
+  aws_sdk_route53::Client::from_conf(config)
+}
+
+#[tokio::test]
+async fn wait_for_insync_change() {
+  let url = common::mock_server(&[(
+    "GET /2013-04-01/change/C0123456789",
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<GetChangeResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+  <ChangeInfo>
+    <Id>/change/C0123456789</Id>
+    <Status>INSYNC</Status>
+    <SubmittedAt>2024-01-01T00:00:00.000Z</SubmittedAt>
+  </ChangeInfo>
+</GetChangeResponse>"#,
+  )]);
+  let route53_client = route53_client(&url);
+  utils::wait_for_change(&route53_client, "/change/C0123456789", None).await;
+}
+
+#[tokio::test]
+async fn wait_for_change_progress() {
+  let url = common::mock_server(&[(
+    "GET /2013-04-01/change/C0123456789",
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<GetChangeResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+  <ChangeInfo>
+    <Id>/change/C0123456789</Id>
+    <Status>PENDING</Status>
+    <SubmittedAt>2024-01-01T00:00:00.000Z</SubmittedAt>
+  </ChangeInfo>
+</GetChangeResponse>"#,
+  )]);
+  let route53_client = route53_client(&url);
+  let mut attempts = Vec::new();
+  let result =
+    utils::wait_for_change_with_progress(&route53_client, "C0123456789", Some(2), |progress| {
+      assert_eq!(progress.change_id, "C0123456789");
+      assert_eq!(progress.status.as_str(), "PENDING");
+      attempts.push(progress.attempt);
+    })
+    .await;
+  assert_eq!(
+    result,
+    Err("change C0123456789 is still PENDING after 2 attempts".to_string())
+  );
+  assert_eq!(attempts, vec![1, 2]);
+}
+
+#[tokio::test]
+async fn delegate_to_parent_zone() {
+  let url = common::mock_server(&[
+    (
+      "GET /2013-04-01/hostedzone/ZCHILD",
+      r#"<?xml version="1.0" encoding="UTF-8"?>
+<GetHostedZoneResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+  <HostedZone>
+    <Id>/hostedzone/ZCHILD</Id>
+    <Name>dev.example.com.</Name>
+    <CallerReference>ref</CallerReference>
+    <Config><PrivateZone>false</PrivateZone></Config>
+  </HostedZone>
+  <DelegationSet>
+    <NameServers>
+      <NameServer>ns-1.awsdns-01.org</NameServer>
+      <NameServer>ns-2.awsdns-02.com</NameServer>
+    </NameServers>
+  </DelegationSet>
+</GetHostedZoneResponse>"#,
+    ),
+    (
+      "POST /2013-04-01/hostedzone/ZPARENT/rrset",
+      r#"<?xml version="1.0" encoding="UTF-8"?>
+<ChangeResourceRecordSetsResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+  <ChangeInfo>
+    <Id>/change/C0000000000001</Id>
+    <Status>PENDING</Status>
+    <SubmittedAt>2024-01-01T00:00:00.000Z</SubmittedAt>
+  </ChangeInfo>
+</ChangeResourceRecordSetsResponse>"#,
+    ),
+  ]);
+  let route53_client = route53_client(&url);
+  let change_id = delegate::delegate(
+    &route53_client,
+    &route53_client,
+    "ZCHILD",
+    Some("ZPARENT"),
+    delegate::DEFAULT_NS_TTL,
+  )
+  .await;
+  assert_eq!(change_id, "/change/C0000000000001");
+}
+
+#[tokio::test]
+async fn dnssec_action_needed() {
+  let url = common::mock_server(&[(
+    "GET /2013-04-01/hostedzone/Z0123456789/dnssec",
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<GetDNSSECResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+  <Status>
+    <ServeSignature>ACTION_NEEDED</ServeSignature>
+    <StatusMessage>The KMS key is disabled</StatusMessage>
+  </Status>
+  <KeySigningKeys>
+    <member>
+      <Name>ksk1</Name>
+      <Status>ACTIVE</Status>
+      <KeyTag>12345</KeyTag>
+      <DSRecord>12345 13 2 ABCDEF</DSRecord>
+    </member>
+  </KeySigningKeys>
+</GetDNSSECResponse>"#,
+  )]);
+  let route53_client = route53_client(&url);
+  let dnssec = dnssec::get_dnssec(&route53_client, "/hostedzone/Z0123456789")
+    .await
+    .unwrap();
+  assert_eq!(
+    dnssec::get_dnssec_problems(&dnssec),
+    vec!["DNSSEC signing is ACTION_NEEDED: The KMS key is disabled"]
+  );
+  assert_eq!(dnssec::get_ds_records(&dnssec), vec!["12345 13 2 ABCDEF"]);
+}
@@ -0,0 +1,71 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::self_update;
+
+#[test]
+fn is_newer_version() {
+  assert!(self_update::is_newer_version("0.0.2", "v0.0.3"));
+  assert!(self_update::is_newer_version("0.0.2", "v0.1.0"));
+  assert!(self_update::is_newer_version("0.9.0", "v0.10.0"));
+  assert!(!self_update::is_newer_version("0.0.2", "v0.0.2"));
+  assert!(!self_update::is_newer_version("0.1.0", "v0.0.9"));
+}
+
+#[test]
+fn get_asset_name() {
+  assert_eq!(
+    self_update::get_asset_name("aarch64-unknown-linux-musl"),
+    "route53-update-aarch64-unknown-linux-musl"
+  );
+}
+
+#[test]
+fn get_trusted_comment() {
+  assert_eq!(
+    self_update::get_trusted_comment("route53-update-x86_64-unknown-linux-musl", "v0.1.0"),
+    "route53-update-x86_64-unknown-linux-musl v0.1.0"
+  );
+}
+
+// Signed with a throwaway key
+const PUBLIC_KEY: &str = "RWQZpNBnI4cxlc5J0bx8CTos7Q25poMI7paHYT4/w6W0V74/Fnlii5yW";
+const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQZpNBnI4cxlcaYRmkVQ5I3S5DSCoP5sGEOYGSMDC+vhKf8DJGYuA33l+RXA1yBDfQj59jrg/+cRLh4QF7p1fGgld4/zwQJVQY=
+trusted comment: route53-update-x86_64-unknown-linux-musl v0.1.0
+ZtqxwBMv9EfvAarcDkmEz4IbzJIpsJ2e+zoVNPqWYGoVg8L4R+W3UDESqRVOyeFmC2SJxwCTztq4ms+dauNEAw==
+";
+
+#[test]
+fn verify_signature() {
+  let trusted_comment = "route53-update-x86_64-unknown-linux-musl v0.1.0";
+  assert_eq!(
+    self_update::verify_signature(PUBLIC_KEY, b"binary", SIGNATURE, trusted_comment),
+    Ok(())
+  );
+  assert!(
+    self_update::verify_signature(PUBLIC_KEY, b"other binary", SIGNATURE, trusted_comment).is_err()
+  );
+  assert!(self_update::verify_signature("invalid", b"binary", "invalid", trusted_comment).is_err());
+}
+
+#[test]
+fn verify_signature_of_other_release() {
+  // E.g. an older release served as the latest release
+  assert_eq!(
+    self_update::verify_signature(
+      PUBLIC_KEY,
+      b"binary",
+      SIGNATURE,
+      "route53-update-x86_64-unknown-linux-musl v0.2.0"
+    ),
+    Err("the signature is for \"route53-update-x86_64-unknown-linux-musl v0.1.0\", expected \"route53-update-x86_64-unknown-linux-musl v0.2.0\"".to_string())
+  );
+  assert!(self_update::verify_signature(
+    PUBLIC_KEY,
+    b"binary",
+    SIGNATURE,
+    "route53-update-aarch64-unknown-linux-musl v0.1.0"
+  )
+  .is_err());
+}
@@ -0,0 +1,199 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::types::{
+  AliasTarget, CidrRoutingConfig, Coordinates, GeoLocation, GeoProximityLocation, ResourceRecord,
+  ResourceRecordSet, RrType,
+};
+use route53_update::snapshot::{self, RecordSetSnapshot, Snapshot};
+
+fn record_set(record_type: RrType, value: &str) -> ResourceRecordSet {
+  ResourceRecordSet::builder()
+    .name("service.example.com.")
+    .r#type(record_type)
+    .ttl(300)
+    .resource_records(ResourceRecord::builder().value(value).build().unwrap())
+    .build()
+    .unwrap()
+}
+
+#[test]
+fn record_set_round_trip() {
+  let r = ResourceRecordSet::builder()
+    .name("service.example.com.")
+    .r#type(RrType::Cname)
+    .ttl(300)
+    .resource_records(
+      ResourceRecord::builder()
+        .value("lb.example.com")
+        .build()
+        .unwrap(),
+    )
+    .set_identifier("blue")
+    .weight(90)
+    .build()
+    .unwrap();
+  let snapshot = RecordSetSnapshot::from(&r);
+  assert_eq!(snapshot.record_type, "CNAME");
+  assert_eq!(snapshot.values, vec!["lb.example.com"]);
+  assert_eq!(snapshot.to_resource_record_set().unwrap(), r);
+
+  let json = serde_json::to_string(&snapshot).unwrap();
+  assert_eq!(
+    json,
+    r#"{"name":"service.example.com.","type":"CNAME","ttl":300,"values":["lb.example.com"],"set_identifier":"blue","weight":90}"#
+  );
+  let parsed: RecordSetSnapshot = serde_json::from_str(&json).unwrap();
+  assert_eq!(parsed, snapshot);
+}
+
+#[test]
+fn alias_record_set_round_trip() {
+  let r = ResourceRecordSet::builder()
+    .name("service.example.com.")
+    .r#type(RrType::A)
+    .alias_target(
+      AliasTarget::builder()
+        .hosted_zone_id("Z35SXDOTRQ7X7K")
+        .dns_name("lb-123.us-east-1.elb.amazonaws.com.")
+        .evaluate_target_health(true)
+        .build()
+        .unwrap(),
+    )
+    .build()
+    .unwrap();
+  let snapshot = RecordSetSnapshot::from(&r);
+  assert!(snapshot.values.is_empty());
+  assert_eq!(snapshot.to_resource_record_set().unwrap(), r);
+}
+
+#[test]
+fn cidr_record_set_restore() {
+  let r = ResourceRecordSet::builder()
+    .name("service.example.com.")
+    .r#type(RrType::A)
+    .ttl(60)
+    .resource_records(
+      ResourceRecord::builder()
+        .value("192.0.2.1")
+        .build()
+        .unwrap(),
+    )
+    .set_identifier("office")
+    .cidr_routing_config(
+      CidrRoutingConfig::builder()
+        .collection_id("c8c02a84-aaaa-bbbb-cccc-000000000000")
+        .location_name("office")
+        .build()
+        .unwrap(),
+    )
+    .build()
+    .unwrap();
+  // The routing config has to survive the snapshot file, otherwise the restored record would answer for every client
+  let json = serde_json::to_string(&RecordSetSnapshot::from(&r)).unwrap();
+  let parsed: RecordSetSnapshot = serde_json::from_str(&json).unwrap();
+  assert_eq!(parsed.to_resource_record_set().unwrap(), r);
+}
+
+#[test]
+fn geo_record_sets_round_trip() {
+  let geo_location = ResourceRecordSet::builder()
+    .name("service.example.com.")
+    .r#type(RrType::A)
+    .ttl(60)
+    .resource_records(
+      ResourceRecord::builder()
+        .value("192.0.2.1")
+        .build()
+        .unwrap(),
+    )
+    .set_identifier("sweden")
+    .geo_location(GeoLocation::builder().country_code("SE").build())
+    .build()
+    .unwrap();
+  let geo_proximity = ResourceRecordSet::builder()
+    .name("service.example.com.")
+    .r#type(RrType::A)
+    .ttl(60)
+    .resource_records(
+      ResourceRecord::builder()
+        .value("192.0.2.2")
+        .build()
+        .unwrap(),
+    )
+    .set_identifier("seattle")
+    .geo_proximity_location(
+      GeoProximityLocation::builder()
+        .coordinates(
+          Coordinates::builder()
+            .latitude("47.61")
+            .longitude("-122.33")
+            .build()
+            .unwrap(),
+        )
+        .bias(20)
+        .build(),
+    )
+    .build()
+    .unwrap();
+  for r in [geo_location, geo_proximity] {
+    let json = serde_json::to_string(&RecordSetSnapshot::from(&r)).unwrap();
+    let parsed: RecordSetSnapshot = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.to_resource_record_set().unwrap(), r);
+  }
+}
+
+#[test]
+fn write_snapshot() {
+  let snapshot = Snapshot {
+    hosted_zone_id: "Z0123456789".to_string(),
+    created_at: "2024-01-01T00:00:00Z".to_string(),
+    record_sets: vec![RecordSetSnapshot::from(&record_set(
+      RrType::Cname,
+      "lb.example.com",
+    ))],
+  };
+  let directory = std::env::temp_dir();
+  let path = snapshot::write_snapshot(&directory, &snapshot).unwrap();
+  assert_eq!(
+    path,
+    directory.join("route53-update-restore-2024-01-01T000000Z.json")
+  );
+  let parsed: Snapshot = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+  std::fs::remove_file(&path).unwrap();
+  assert_eq!(parsed.hosted_zone_id, "Z0123456789");
+  assert_eq!(parsed.record_sets, snapshot.record_sets);
+}
+
+#[test]
+fn conflicting_record_sets() {
+  let a = record_set(RrType::A, "192.0.2.1");
+  let aaaa = record_set(RrType::Aaaa, "2001:db8::1");
+  let cname = record_set(RrType::Cname, "lb.example.com");
+  let txt = record_set(RrType::Txt, "\"hello\"");
+
+  // Restoring a CNAME record requires deleting the other records with the same name
+  assert_eq!(
+    snapshot::get_conflicting_record_sets(
+      &[a.clone(), aaaa.clone(), txt.clone()],
+      std::slice::from_ref(&cname)
+    ),
+    vec![a.clone(), aaaa.clone(), txt.clone()]
+  );
+  // Restoring an A record only conflicts with a CNAME record
+  assert_eq!(
+    snapshot::get_conflicting_record_sets(
+      &[aaaa.clone(), cname.clone(), txt.clone()],
+      std::slice::from_ref(&a)
+    ),
+    vec![cname.clone()]
+  );
+  // The record being restored is replaced by the UPSERT
+  assert_eq!(
+    snapshot::get_conflicting_record_sets(
+      std::slice::from_ref(&cname),
+      std::slice::from_ref(&cname)
+    ),
+    vec![]
+  );
+}
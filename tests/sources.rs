@@ -0,0 +1,442 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+mod common;
+
+use aws_sdk_route53::types::RrType;
+use route53_update::{sources, types, utils};
+
+const IMDS_TOKEN: (&str, &str) = ("PUT /latest/api/token", "token");
+
+fn ec2_options() -> types::Ec2MetadataOptions {
+  types::Ec2MetadataOptions {
+    ip_address_type: types::IPAddressType::Public,
+    eni_index: None,
+    secondary_ip_index: None,
+    all_ipv6: false,
+  }
+}
+
+#[tokio::test]
+async fn ec2_public_ipv4() {
+  let url = common::mock_server(&[
+    IMDS_TOKEN,
+    ("GET /latest/meta-data/public-ipv4", "203.0.113.10"),
+  ]);
+  let clients = common::mock_clients(&url);
+  let values = sources::get_ec2_values(&clients, Some(&RrType::A), &ec2_options()).await;
+  assert_eq!(values, vec!["203.0.113.10"]);
+}
+
+#[tokio::test]
+async fn ec2_secondary_ip_on_eni() {
+  let url = common::mock_server(&[
+    IMDS_TOKEN,
+    (
+      "GET /latest/meta-data/network/interfaces/macs/",
+      "0a:00:00:00:00:01/\n0a:00:00:00:00:02/",
+    ),
+    (
+      "GET /latest/meta-data/network/interfaces/macs/0a:00:00:00:00:01/device-number",
+      "0",
+    ),
+    (
+      "GET /latest/meta-data/network/interfaces/macs/0a:00:00:00:00:02/device-number",
+      "1",
+    ),
+    (
+      "GET /latest/meta-data/network/interfaces/macs/0a:00:00:00:00:02/local-ipv4s",
+      "10.0.1.5\n10.0.1.6",
+    ),
+  ]);
+  let clients = common::mock_clients(&url);
+  let options = types::Ec2MetadataOptions {
+    ip_address_type: types::IPAddressType::Private,
+    eni_index: Some(1),
+    secondary_ip_index: Some(1),
+    all_ipv6: false,
+  };
+  let values = sources::get_ec2_values(&clients, Some(&RrType::A), &options).await;
+  assert_eq!(values, vec!["10.0.1.6"]);
+}
+
+#[tokio::test]
+async fn ec2_eni_after_interface_without_device_number() {
+  let url = common::mock_server(&[
+    IMDS_TOKEN,
+    (
+      "GET /latest/meta-data/network/interfaces/macs/",
+      "0a:00:00:00:00:01/\n0a:00:00:00:00:02/",
+    ),
+    (
+      "GET /latest/meta-data/network/interfaces/macs/0a:00:00:00:00:02/device-number",
+      "1",
+    ),
+    (
+      "GET /latest/meta-data/network/interfaces/macs/0a:00:00:00:00:02/local-ipv4s",
+      "10.0.1.5",
+    ),
+  ]);
+  let clients = common::mock_clients(&url);
+  let options = types::Ec2MetadataOptions {
+    ip_address_type: types::IPAddressType::Private,
+    eni_index: Some(1),
+    secondary_ip_index: None,
+    all_ipv6: false,
+  };
+  let values = sources::get_ec2_values(&clients, Some(&RrType::A), &options).await;
+  assert_eq!(values, vec!["10.0.1.5"]);
+}
+
+#[tokio::test]
+async fn ecs_task_metadata() {
+  let url = common::mock_server(&[(
+    "GET /task",
+    r#"{"TaskARN":"arn:aws:ecs:us-west-2:123456789012:task/default/abc","Family":"web","Revision":"3","Containers":[{"Networks":[{"IPv4Addresses":["10.0.2.7"]}]}]}"#,
+  )]);
+  let clients = common::mock_clients(&url);
+  let values = sources::get_ecs_values(
+    &clients,
+    Some(&RrType::A),
+    types::EcsNetworkMode::Auto,
+    &ec2_options(),
+  )
+  .await;
+  assert_eq!(values, vec!["10.0.2.7"]);
+
+  let metadata = utils::get_ecs_task_metadata(&clients).await.unwrap();
+  assert_eq!(metadata.family.as_deref(), Some("web"));
+}
+
+#[tokio::test]
+async fn url_value() {
+  let url = common::mock_server(&[("GET /ip", "198.51.100.4\n")]);
+  let clients = common::mock_clients(&url);
+  assert_eq!(
+    sources::get_url_value(&clients, &format!("{}/ip", url)).await,
+    Ok("198.51.100.4".to_string())
+  );
+  assert!(
+    sources::get_url_value(&clients, &format!("{}/missing", url))
+      .await
+      .is_err()
+  );
+}
+
+#[tokio::test]
+async fn auto_values_preference_order() {
+  let url = common::mock_server(&[
+    IMDS_TOKEN,
+    ("GET /latest/meta-data/public-ipv4", "203.0.113.10"),
+    ("GET /ip", "198.51.100.4"),
+  ]);
+  let mut clients = common::mock_clients(&url);
+  clients.ecs_metadata_uri = None;
+  let values = sources::get_auto_values(
+    &clients,
+    &[
+      types::ValueSourceKind::Url,
+      types::ValueSourceKind::Ecs,
+      types::ValueSourceKind::Ec2,
+    ],
+    Some(&RrType::A),
+    types::EcsNetworkMode::Auto,
+    &ec2_options(),
+    Some(&format!("{}/ip", url)),
+  )
+  .await;
+  assert_eq!(values, vec!["198.51.100.4"]);
+}
+
+#[tokio::test]
+async fn template_value() {
+  let url = common::mock_server(&[
+    IMDS_TOKEN,
+    ("GET /latest/meta-data/local-ipv4", "10.0.0.8"),
+    (
+      "GET /latest/meta-data/placement/availability-zone",
+      "us-west-2a",
+    ),
+  ]);
+  let clients = common::mock_clients(&url);
+  let value = sources::get_template_value(
+    &clients,
+    "{local_ipv4} {ec2.placement/availability-zone}",
+    None,
+    None,
+  )
+  .await;
+  assert_eq!(value, "10.0.0.8 us-west-2a");
+}
+
+#[tokio::test]
+async fn template_value_is_not_expanded_again() {
+  let url = common::mock_server(&[
+    IMDS_TOKEN,
+    ("GET /latest/meta-data/tags/instance/Name", "{local_ipv4}"),
+    ("GET /latest/meta-data/local-ipv4", "10.0.0.8"),
+  ]);
+  let clients = common::mock_clients(&url);
+  let value = sources::get_template_value(&clients, "{ec2.tags/instance/Name}", None, None).await;
+  assert_eq!(value, "{local_ipv4}");
+}
+
+#[tokio::test]
+#[should_panic(expected = "could not get placement/typo from the instance metadata service")]
+async fn template_value_missing_metadata() {
+  let url = common::mock_server(&[IMDS_TOKEN]);
+  let clients = common::mock_clients(&url);
+  sources::get_template_value(&clients, "{ec2.placement/typo}", None, None).await;
+}
+
+#[tokio::test]
+async fn simulated_metadata() {
+  let fixture: types::MetadataFixture = serde_json::from_str(
+    r#"{
+      "ec2": {
+        "/latest/meta-data/public-ipv4": "203.0.113.20",
+        "/latest/meta-data/local-ipv4": "10.0.0.20"
+      },
+      "ecs": {"Family": "web", "Containers": [{"Networks": [{"IPv4Addresses": ["10.0.3.9"]}]}]}
+    }"#,
+  )
+  .unwrap();
+  let clients = types::MetadataClients::simulated(fixture);
+  let values = sources::get_ec2_values(&clients, Some(&RrType::A), &ec2_options()).await;
+  assert_eq!(values, vec!["203.0.113.20"]);
+  let values = sources::get_ecs_values(
+    &clients,
+    Some(&RrType::A),
+    types::EcsNetworkMode::Auto,
+    &ec2_options(),
+  )
+  .await;
+  assert_eq!(values, vec!["10.0.3.9"]);
+  // Paths that are missing from the fixture behave like they are missing from the metadata service
+  assert_eq!(
+    clients.get_ec2_metadata("/latest/meta-data/ipv6").await,
+    None
+  );
+}
+
+#[tokio::test]
+#[should_panic(expected = "returned non-200 status code: 404")]
+async fn ecs_task_metadata_not_ready() {
+  let url = common::mock_server(&[]);
+  let clients = common::mock_clients(&url);
+  utils::get_ecs_task_metadata(&clients).await;
+}
+
+#[tokio::test]
+async fn ecs_bridge_network_mode_uses_instance_address() {
+  let fixture: types::MetadataFixture = serde_json::from_str(
+    r#"{
+      "ec2": {"/latest/meta-data/public-ipv4": "203.0.113.30"},
+      "ecs": {"Containers": [{"Networks": [{"NetworkMode": "bridge", "IPv4Addresses": ["172.17.0.2"]}]}]}
+    }"#,
+  )
+  .unwrap();
+  let clients = types::MetadataClients::simulated(fixture);
+  let values = sources::get_ecs_values(
+    &clients,
+    Some(&RrType::A),
+    types::EcsNetworkMode::Auto,
+    &ec2_options(),
+  )
+  .await;
+  assert_eq!(values, vec!["203.0.113.30"]);
+  let values = sources::get_ecs_values(
+    &clients,
+    Some(&RrType::A),
+    types::EcsNetworkMode::Awsvpc,
+    &ec2_options(),
+  )
+  .await;
+  assert_eq!(values, vec!["172.17.0.2"]);
+}
+
+#[tokio::test]
+async fn ecs_awsvpc_network_mode_collapses_container_addresses() {
+  let fixture: types::MetadataFixture = serde_json::from_str(
+    r#"{
+      "ecs": {"Containers": [
+        {"Name": "web", "Networks": [{"NetworkMode": "awsvpc", "IPv4Addresses": ["10.0.4.12"], "IPv6Addresses": ["2001:db8::12"]}]},
+        {"Name": "envoy", "Networks": [{"NetworkMode": "awsvpc", "IPv4Addresses": ["10.0.4.12"], "IPv6Addresses": ["2001:db8::12"]}]},
+        {"Name": "init"}
+      ]}
+    }"#,
+  )
+  .unwrap();
+  let clients = types::MetadataClients::simulated(fixture);
+  let values = sources::get_ecs_values(
+    &clients,
+    Some(&RrType::A),
+    types::EcsNetworkMode::Auto,
+    &ec2_options(),
+  )
+  .await;
+  assert_eq!(values, vec!["10.0.4.12"]);
+  let values = sources::get_ecs_values(
+    &clients,
+    Some(&RrType::Aaaa),
+    types::EcsNetworkMode::Auto,
+    &ec2_options(),
+  )
+  .await;
+  assert_eq!(values, vec!["2001:db8::12"]);
+}
+
+#[tokio::test]
+async fn ecs_srv_value() {
+  let fixture: types::MetadataFixture = serde_json::from_str(
+    r#"{
+      "ec2": {"/latest/meta-data/local-hostname": "ip-10-0-0-5.us-west-2.compute.internal"},
+      "ecs": {"Containers": [
+        {"Name": "sidecar", "Ports": [{"ContainerPort": 9000, "HostPort": 32770}]},
+        {"Name": "web", "Ports": [{"ContainerPort": 80, "Protocol": "tcp", "HostPort": 32768}]}
+      ]}
+    }"#,
+  )
+  .unwrap();
+  let clients = types::MetadataClients::simulated(fixture);
+  let srv = "web:80".parse::<types::SrvFromEcs>().unwrap();
+  assert_eq!(
+    sources::get_ecs_srv_value(&clients, &srv, types::IPAddressType::Private).await,
+    Ok("0 0 32768 ip-10-0-0-5.us-west-2.compute.internal.".to_string())
+  );
+  let srv = "web:443".parse::<types::SrvFromEcs>().unwrap();
+  assert!(
+    sources::get_ecs_srv_value(&clients, &srv, types::IPAddressType::Private)
+      .await
+      .is_err()
+  );
+}
+
+#[test]
+fn parse_ip_addr_output() {
+  let output = "2: eth0    inet 192.0.2.10/24 brd 192.0.2.255 scope global eth0\\       valid_lft forever preferred_lft forever
+2: eth0    inet6 2001:db8::10/64 scope global dynamic mngtmpaddr \\       valid_lft 86400sec preferred_lft 14400sec
+";
+  assert_eq!(
+    sources::parse_ip_addr_output(output),
+    vec!["192.0.2.10", "2001:db8::10"]
+  );
+}
+
+#[test]
+fn parse_ip_addr_interfaces() {
+  let output = "2: eth0    inet 192.168.1.10/24 brd 192.168.1.255 scope global eth0\\       valid_lft forever preferred_lft forever
+4: tailscale0    inet 100.101.102.103/32 scope global tailscale0\\       valid_lft forever preferred_lft forever
+";
+  assert_eq!(
+    sources::parse_ip_addr_interfaces(output),
+    vec![
+      ("eth0".to_string(), "192.168.1.10".to_string()),
+      ("tailscale0".to_string(), "100.101.102.103".to_string())
+    ]
+  );
+}
+
+#[test]
+fn parse_default_route_interfaces() {
+  let output = "default via 192.168.1.1 dev eth0 proto dhcp src 192.168.1.10 metric 100\n";
+  assert_eq!(
+    sources::parse_default_route_interfaces(output),
+    vec!["eth0"]
+  );
+}
+
+#[test]
+fn parse_ipv6_address_flags() {
+  let output = "2: eth0    inet6 2001:db8::10/64 scope global dynamic mngtmpaddr \\       valid_lft 86400sec preferred_lft 14400sec
+2: eth0    inet6 2001:db8::a1b2/64 scope global temporary dynamic \\       valid_lft 86400sec preferred_lft 14400sec
+2: eth0    inet6 2001:db8::20/64 scope global \\       valid_lft forever preferred_lft forever
+";
+  let flags = sources::parse_ipv6_address_flags(output);
+  assert_eq!(
+    flags[&"2001:db8::10".parse::<std::net::Ipv6Addr>().unwrap()],
+    vec!["dynamic", "mngtmpaddr"]
+  );
+  assert_eq!(
+    flags[&"2001:db8::a1b2".parse::<std::net::Ipv6Addr>().unwrap()],
+    vec!["temporary", "dynamic"]
+  );
+  assert!(flags[&"2001:db8::20".parse::<std::net::Ipv6Addr>().unwrap()].is_empty());
+}
+
+#[tokio::test]
+async fn command_source() {
+  use sources::ValueSource;
+  let source = sources::CommandSource {
+    command: "echo 192.0.2.1; echo 192.0.2.2".to_string(),
+  };
+  assert_eq!(
+    source.resolve().await,
+    Ok(vec!["192.0.2.1".to_string(), "192.0.2.2".to_string()])
+  );
+  let source = sources::CommandSource {
+    command: "true".to_string(),
+  };
+  assert!(source.resolve().await.is_err());
+}
+
+#[tokio::test]
+async fn url_source() {
+  use sources::ValueSource;
+  let url = common::mock_server(&[("GET /ip", "192.0.2.1\n")]);
+  let source = sources::UrlSource {
+    clients: common::mock_clients(&url),
+    url: format!("{}/ip", url),
+  };
+  assert_eq!(source.resolve().await, Ok(vec!["192.0.2.1".to_string()]));
+}
+
+#[test]
+fn get_lightsail_instance_values() {
+  let instance = aws_sdk_lightsail::types::Instance::builder()
+    .public_ip_address("198.51.100.1")
+    .private_ip_address("172.26.0.10")
+    .ipv6_addresses("2001:db8::10")
+    .build();
+  assert_eq!(
+    sources::get_lightsail_instance_values(&instance, None, types::IPAddressType::Public),
+    vec!["198.51.100.1"]
+  );
+  assert_eq!(
+    sources::get_lightsail_instance_values(
+      &instance,
+      Some(&RrType::A),
+      types::IPAddressType::Private
+    ),
+    vec!["172.26.0.10"]
+  );
+  assert_eq!(
+    sources::get_lightsail_instance_values(
+      &instance,
+      Some(&RrType::Aaaa),
+      types::IPAddressType::Public
+    ),
+    vec!["2001:db8::10"]
+  );
+}
+
+#[tokio::test]
+async fn resolve_values() {
+  assert_eq!(
+    sources::resolve_values(
+      &["192.0.2.2".to_string(), "localhost".to_string()],
+      Some(&RrType::A)
+    )
+    .await,
+    Ok(vec!["127.0.0.1".to_string(), "192.0.2.2".to_string()])
+  );
+  assert_eq!(
+    sources::resolve_values(&["2001:db8::1".to_string()], Some(&RrType::Aaaa)).await,
+    Ok(vec!["2001:db8::1".to_string()])
+  );
+  assert!(
+    sources::resolve_values(&["nonexistent.invalid".to_string()], None)
+      .await
+      .is_err()
+  );
+}
@@ -0,0 +1,91 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::spf;
+
+fn terms(s: &str) -> Vec<String> {
+  s.split_whitespace().map(str::to_string).collect()
+}
+
+#[test]
+fn parse_spf() {
+  assert_eq!(
+    spf::parse_spf("\"v=spf1 include:_spf.google.com ~all\""),
+    Some(terms("include:_spf.google.com ~all"))
+  );
+  // Long records are split into multiple strings
+  assert_eq!(
+    spf::parse_spf("\"v=spf1 include:a.example.com \" \"include:b.example.com -all\""),
+    Some(terms("include:a.example.com include:b.example.com -all"))
+  );
+  assert_eq!(spf::parse_spf("\"google-site-verification=abc\""), None);
+  assert_eq!(spf::parse_spf("\"v=spf10\""), None);
+}
+
+#[test]
+fn merge_spf() {
+  assert_eq!(
+    spf::merge_spf(
+      terms("include:_spf.google.com -all"),
+      &["spf.protection.outlook.com".to_string()]
+    ),
+    terms("include:_spf.google.com include:spf.protection.outlook.com -all")
+  );
+  // Includes that are already in the record are not added again
+  assert_eq!(
+    spf::merge_spf(
+      terms("include:_spf.google.com ~all"),
+      &["_SPF.google.com".to_string()]
+    ),
+    terms("include:_spf.google.com ~all")
+  );
+  assert_eq!(
+    spf::merge_spf(
+      terms("mx redirect=_spf.example.com"),
+      &["spf.example.net".to_string()]
+    ),
+    terms("mx include:spf.example.net redirect=_spf.example.com")
+  );
+  assert_eq!(
+    spf::merge_spf(Vec::new(), &["spf.example.net".to_string()]),
+    terms("include:spf.example.net ~all")
+  );
+}
+
+#[test]
+fn count_dns_lookups() {
+  assert_eq!(
+    spf::count_dns_lookups(&terms(
+      "a mx ip4:192.0.2.0/24 include:a.example.com ?exists:%{i}.example.com ptr -all"
+    )),
+    5
+  );
+  assert_eq!(
+    spf::count_dns_lookups(&terms("ip6:2001:db8::/32 redirect=_spf.example.com")),
+    1
+  );
+}
+
+#[test]
+fn merge_spf_values() {
+  let values = vec![
+    "\"google-site-verification=abc\"".to_string(),
+    "\"v=spf1 include:_spf.google.com ~all\"".to_string(),
+  ];
+  assert_eq!(
+    spf::merge_spf_values(&values, &["spf.protection.outlook.com".to_string()]),
+    Ok(vec![
+      "\"google-site-verification=abc\"".to_string(),
+      "\"v=spf1 include:_spf.google.com include:spf.protection.outlook.com ~all\"".to_string(),
+    ])
+  );
+  assert_eq!(
+    spf::merge_spf_values(&[], &["spf.example.net".to_string()]),
+    Ok(vec!["\"v=spf1 include:spf.example.net ~all\"".to_string()])
+  );
+  let values = vec![
+    "\"v=spf1 -all\"".to_string(),
+    "\"v=spf1 mx -all\"".to_string(),
+  ];
+  assert!(spf::merge_spf_values(&values, &["spf.example.net".to_string()]).is_err());
+}
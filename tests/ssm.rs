@@ -0,0 +1,53 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_ssm::types::ParameterType;
+use route53_update::ssm;
+
+#[test]
+fn is_secret_arn() {
+  assert!(ssm::is_secret_arn(
+    "arn:aws:secretsmanager:us-west-2:123456789012:secret:verification-AbCdEf"
+  ));
+  assert!(!ssm::is_secret_arn("/route53-update/verification"));
+  assert!(!ssm::is_secret_arn(
+    "arn:aws:ssm:us-west-2:123456789012:parameter/route53-update/verification"
+  ));
+}
+
+#[test]
+fn parse_parameter_value() {
+  assert_eq!(
+    ssm::parse_parameter_value(
+      "google-site-verification=abc\n",
+      Some(&ParameterType::String)
+    ),
+    vec!["google-site-verification=abc"]
+  );
+  assert_eq!(
+    ssm::parse_parameter_value("192.0.2.1, 192.0.2.2", Some(&ParameterType::StringList)),
+    vec!["192.0.2.1", "192.0.2.2"]
+  );
+  // Commas are only separators in StringList parameters
+  assert_eq!(
+    ssm::parse_parameter_value("v=spf1 a,mx -all", Some(&ParameterType::SecureString)),
+    vec!["v=spf1 a,mx -all"]
+  );
+  assert!(ssm::parse_parameter_value(" ", None).is_empty());
+}
+
+#[test]
+fn format_parameter_value() {
+  assert_eq!(
+    ssm::format_parameter_value(&["192.0.2.1".to_string()]),
+    ("192.0.2.1".to_string(), ParameterType::String)
+  );
+  assert_eq!(
+    ssm::format_parameter_value(&["192.0.2.1".to_string(), "192.0.2.2".to_string()]),
+    ("192.0.2.1,192.0.2.2".to_string(), ParameterType::StringList)
+  );
+  assert_eq!(
+    ssm::format_parameter_value(&["\"verification=abc\"".to_string()]),
+    ("verification=abc".to_string(), ParameterType::String)
+  );
+}
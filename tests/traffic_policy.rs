@@ -0,0 +1,49 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::traffic_policy;
+
+const DOCUMENT: &str = r#"{
+  "AWSPolicyFormatVersion": "2015-10-01",
+  "RecordType": "A",
+  "StartRule": "failover",
+  "Endpoints": {
+    "primary": {"Type": "value", "Value": "192.0.2.1"},
+    "secondary": {"Type": "value", "Value": "192.0.2.2"},
+    "bucket": {"Type": "s3-website", "Region": "us-east-1", "Value": "www.example.com"}
+  },
+  "Rules": {
+    "failover": {
+      "RuleType": "failover",
+      "Primary": {"EndpointReference": "primary"},
+      "Secondary": {"EndpointReference": "secondary"}
+    }
+  }
+}"#;
+
+#[test]
+fn replace_endpoint_values() {
+  let document = traffic_policy::replace_endpoint_values(
+    DOCUMENT,
+    &["192.0.2.1".to_string()],
+    &["198.51.100.1".to_string()],
+  )
+  .unwrap();
+  let document: serde_json::Value = serde_json::from_str(&document).unwrap();
+  assert_eq!(document["Endpoints"]["primary"]["Value"], "198.51.100.1");
+  assert_eq!(document["Endpoints"]["secondary"]["Value"], "192.0.2.2");
+  assert_eq!(document["Rules"]["failover"]["RuleType"], "failover");
+
+  assert!(traffic_policy::replace_endpoint_values(
+    DOCUMENT,
+    &["203.0.113.1".to_string()],
+    &["198.51.100.1".to_string()],
+  )
+  .is_err());
+  assert!(traffic_policy::replace_endpoint_values(
+    DOCUMENT,
+    &["192.0.2.1".to_string()],
+    &["198.51.100.1".to_string(), "198.51.100.2".to_string()],
+  )
+  .is_err());
+}
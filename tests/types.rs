@@ -0,0 +1,101 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::types::RrType;
+use route53_update::types::{UpdateRequest, ValueSource};
+
+#[test]
+fn build_update_request() {
+  let request = UpdateRequest::builder()
+    .record_name("service.example.com")
+    .ttl(60)
+    .value_source(ValueSource::Imds)
+    .build()
+    .unwrap();
+  assert_eq!(request.record_name, "service.example.com");
+  assert!(matches!(request.value_source, Some(ValueSource::Imds)));
+  assert_eq!(request.ttl, Some(60));
+
+  let request = UpdateRequest::builder()
+    .values(vec!["192.0.2.1".to_string()])
+    .record_name("service.example.com")
+    .record_type(RrType::A)
+    .build()
+    .unwrap();
+  assert!(
+    matches!(request.value_source, Some(ValueSource::Values(values)) if values == vec!["192.0.2.1".to_string()])
+  );
+}
+
+#[test]
+fn build_invalid_update_request() {
+  assert_eq!(
+    UpdateRequest::builder()
+      .value_source(ValueSource::Imds)
+      .build()
+      .unwrap_err(),
+    "the record name is required"
+  );
+  assert_eq!(
+    UpdateRequest::builder()
+      .record_name("service.example.com")
+      .ttl(-1)
+      .value_source(ValueSource::Imds)
+      .build()
+      .unwrap_err(),
+    "TTL must be between 0 and 2147483647: -1"
+  );
+  assert_eq!(
+    UpdateRequest::builder()
+      .record_name("service.example.com")
+      .record_type(RrType::Cname)
+      .values(vec![
+        "a.example.com".to_string(),
+        "b.example.com".to_string()
+      ])
+      .build()
+      .unwrap_err(),
+    "a CNAME record can only have one value, got 2"
+  );
+  assert!(UpdateRequest::builder()
+    .record_name("service.example.com")
+    .values(vec!["192.0.2.1".to_string(), "2001:db8::1".to_string()])
+    .build()
+    .is_err());
+  assert!(UpdateRequest::builder()
+    .record_name("service.example.com")
+    .value_source(ValueSource::Url("not a url".to_string()))
+    .build()
+    .is_err());
+}
+
+#[test]
+fn build_alias_update_request() {
+  let request = UpdateRequest::builder()
+    .record_name("www.example.com")
+    .alias_target("d111111abcdef8.cloudfront.net")
+    .build()
+    .unwrap();
+  assert!(request.value_source.is_none());
+  assert_eq!(
+    request.alias_target.as_deref(),
+    Some("d111111abcdef8.cloudfront.net")
+  );
+
+  assert_eq!(
+    UpdateRequest::builder()
+      .record_name("www.example.com")
+      .build()
+      .unwrap_err(),
+    "a value source or an alias target is required"
+  );
+  assert_eq!(
+    UpdateRequest::builder()
+      .record_name("www.example.com")
+      .alias_target("d111111abcdef8.cloudfront.net")
+      .ttl(300)
+      .build()
+      .unwrap_err(),
+    "alias records do not have a TTL"
+  );
+}
@@ -0,0 +1,130 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+mod common;
+
+use aws_sdk_route53::types::{ResourceRecordSet, RrType};
+use route53_update::{
+  backend::{DnsBackend, MemoryBackend},
+  types::{self, UpdateRequest, ValueSource},
+  update, utils,
+};
+use std::{collections::HashMap, sync::Arc};
+
+const HOSTED_ZONE_ID: &str = "Z0123456789";
+
+fn memory_backend(record_sets: Vec<ResourceRecordSet>) -> Arc<MemoryBackend> {
+  Arc::new(MemoryBackend::new(HashMap::from([(
+    HOSTED_ZONE_ID.to_string(),
+    record_sets,
+  )])))
+}
+
+fn record_set(value: &str, ttl: i64) -> ResourceRecordSet {
+  utils::build_record_set(
+    "service.example.com.",
+    &RrType::A,
+    Some(ttl),
+    &[value.to_string()],
+    &types::RecordRouting::default(),
+  )
+}
+
+#[tokio::test]
+async fn update_record() {
+  let backend = memory_backend(Vec::new());
+  let clients = types::MetadataClients::new();
+  let request = UpdateRequest::builder()
+    .record_name("service.example.com")
+    .values(vec!["192.0.2.1".to_string()])
+    .build()
+    .unwrap();
+
+  let result = update::update(backend.clone(), &clients, HOSTED_ZONE_ID, request.clone())
+    .await
+    .unwrap();
+  assert!(result.is_changed());
+  assert_eq!(result.previous_value, Vec::<String>::new());
+  assert_eq!(result.record_set, record_set("192.0.2.1", 300));
+  assert_eq!(
+    backend
+      .get(HOSTED_ZONE_ID, "service.example.com.")
+      .await
+      .unwrap(),
+    vec![record_set("192.0.2.1", 300)]
+  );
+
+  // Nothing is submitted when the record is already up to date
+  let result = update::update(backend.clone(), &clients, HOSTED_ZONE_ID, request)
+    .await
+    .unwrap();
+  assert!(!result.is_changed());
+}
+
+#[tokio::test]
+async fn update_record_keeps_ttl() {
+  let backend = memory_backend(vec![record_set("192.0.2.1", 60)]);
+  let request = UpdateRequest::builder()
+    .record_name("service.example.com.")
+    .values(vec!["192.0.2.2".to_string()])
+    .build()
+    .unwrap();
+  let result = update::update(
+    backend.clone(),
+    &types::MetadataClients::new(),
+    HOSTED_ZONE_ID,
+    request,
+  )
+  .await
+  .unwrap();
+  assert!(result.is_changed());
+  assert_eq!(result.previous_value, vec!["192.0.2.1"]);
+  assert_eq!(
+    backend
+      .get(HOSTED_ZONE_ID, "service.example.com.")
+      .await
+      .unwrap(),
+    vec![record_set("192.0.2.2", 60)]
+  );
+}
+
+#[tokio::test]
+async fn update_record_from_imds() {
+  let url = common::mock_server(&[
+    ("PUT /latest/api/token", "token"),
+    ("GET /latest/meta-data/public-ipv4", "203.0.113.10"),
+  ]);
+  let backend = memory_backend(Vec::new());
+  let request = UpdateRequest::builder()
+    .record_name("service.example.com")
+    .record_type(RrType::A)
+    .value_source(ValueSource::Imds)
+    .build()
+    .unwrap();
+  let result = update::update(
+    backend.clone(),
+    &common::mock_clients(&url),
+    HOSTED_ZONE_ID,
+    request,
+  )
+  .await
+  .unwrap();
+  assert_eq!(result.record_set, record_set("203.0.113.10", 300));
+}
+
+#[tokio::test]
+async fn update_record_in_unknown_hosted_zone() {
+  let request = UpdateRequest::builder()
+    .record_name("service.example.com")
+    .values(vec!["192.0.2.1".to_string()])
+    .build()
+    .unwrap();
+  assert!(update::update(
+    memory_backend(Vec::new()),
+    &types::MetadataClients::new(),
+    "Z9999999999",
+    request,
+  )
+  .await
+  .is_err());
+}
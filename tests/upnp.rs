@@ -0,0 +1,97 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+mod common;
+
+use route53_update::upnp;
+
+const DEVICE_DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <device>
+    <deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+    <serviceList>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>
+        <controlURL>/ctl/L3F</controlURL>
+      </service>
+    </serviceList>
+    <deviceList>
+      <device>
+        <deviceType>urn:schemas-upnp-org:device:WANConnectionDevice:1</deviceType>
+        <serviceList>
+          <service>
+            <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+            <serviceId>urn:upnp-org:serviceId:WANIPConn1</serviceId>
+            <controlURL>/ctl/IPConn</controlURL>
+          </service>
+        </serviceList>
+      </device>
+    </deviceList>
+  </device>
+</root>"#;
+
+const EXTERNAL_IP_RESPONSE: &str = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/">
+  <s:Body>
+    <u:GetExternalIPAddressResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">
+      <NewExternalIPAddress>203.0.113.5</NewExternalIPAddress>
+    </u:GetExternalIPAddressResponse>
+  </s:Body>
+</s:Envelope>"#;
+
+#[test]
+fn parse_search_response() {
+  assert_eq!(
+    upnp::parse_search_response(
+      "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=120\r\nST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\nLocation: http://192.168.1.1:5000/rootDesc.xml\r\n\r\n"
+    ),
+    Some("http://192.168.1.1:5000/rootDesc.xml".to_string())
+  );
+  assert_eq!(upnp::parse_search_response("HTTP/1.1 200 OK\r\n\r\n"), None);
+}
+
+#[test]
+fn parse_device_description() {
+  assert_eq!(
+    upnp::parse_device_description(DEVICE_DESCRIPTION, "http://192.168.1.1:5000/rootDesc.xml"),
+    Ok((
+      "urn:schemas-upnp-org:service:WANIPConnection:1".to_string(),
+      "http://192.168.1.1:5000/ctl/IPConn".to_string()
+    ))
+  );
+  assert_eq!(
+    upnp::parse_device_description("<root></root>", "http://192.168.1.1:5000/rootDesc.xml"),
+    Err("the router does not have a WAN connection service".to_string())
+  );
+}
+
+#[test]
+fn parse_external_ip_response() {
+  assert_eq!(
+    upnp::parse_external_ip_response(EXTERNAL_IP_RESPONSE),
+    Ok("203.0.113.5".to_string())
+  );
+  assert_eq!(
+    upnp::parse_external_ip_response(
+      "<s:Envelope><s:Body><u:GetExternalIPAddressResponse><NewExternalIPAddress></NewExternalIPAddress></u:GetExternalIPAddressResponse></s:Body></s:Envelope>"
+    ),
+    Err("the router does not have an external IP address".to_string())
+  );
+  assert_eq!(
+    upnp::parse_external_ip_response(
+      "<s:Envelope><s:Body><s:Fault><detail><UPnPError><errorCode>401</errorCode><errorDescription>Invalid Action</errorDescription></UPnPError></detail></s:Fault></s:Body></s:Envelope>"
+    ),
+    Err("GetExternalIPAddress failed: Invalid Action".to_string())
+  );
+}
+
+#[tokio::test]
+async fn get_external_ip() {
+  let url = common::mock_server(&[
+    ("GET /rootDesc.xml", DEVICE_DESCRIPTION),
+    ("POST /ctl/IPConn", EXTERNAL_IP_RESPONSE),
+  ]);
+  let address =
+    upnp::get_external_ip(&reqwest::Client::new(), &format!("{}/rootDesc.xml", url)).await;
+  assert_eq!(address, Ok("203.0.113.5".to_string()));
+}
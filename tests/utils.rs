@@ -0,0 +1,624 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use aws_sdk_route53::types::{HostedZoneSummary, RrType};
+use aws_sdk_route53resolver::types::{ResolverRule, RuleTypeOption};
+use route53_update::{types, utils};
+
+fn resolver_rule(id: &str, domain_name: &str, rule_type: RuleTypeOption) -> ResolverRule {
+  ResolverRule::builder()
+    .id(id)
+    .domain_name(domain_name)
+    .rule_type(rule_type)
+    .build()
+}
+
+#[test]
+fn shadowing_resolver_rule() {
+  let rules = vec![
+    resolver_rule("rslvr-rr-internet", ".", RuleTypeOption::Recursive),
+    resolver_rule(
+      "rslvr-rr-corp",
+      "corp.example.com.",
+      RuleTypeOption::Forward,
+    ),
+    resolver_rule(
+      "rslvr-rr-aws",
+      "aws.corp.example.com.",
+      RuleTypeOption::System,
+    ),
+  ];
+  let rule = utils::get_shadowing_resolver_rule(&rules, "db.corp.example.com.");
+  assert_eq!(rule.and_then(|r| r.id()), Some("rslvr-rr-corp"));
+  // A more specific system rule sends the query back to the private hosted zone
+  assert!(utils::get_shadowing_resolver_rule(&rules, "db.aws.corp.example.com.").is_none());
+  // The domain name has to match on a label boundary
+  assert!(utils::get_shadowing_resolver_rule(&rules, "db.notcorp.example.com.").is_none());
+}
+
+#[test]
+fn find_hosted_zone_summary() {
+  let summaries: Vec<HostedZoneSummary> = [
+    ("/hostedzone/Z1", "example.com."),
+    ("/hostedzone/Z2", "internal.example.com."),
+  ]
+  .into_iter()
+  .map(|(id, name)| {
+    HostedZoneSummary::builder()
+      .hosted_zone_id(id)
+      .name(name)
+      .build()
+      .unwrap()
+  })
+  .collect();
+  let zone = utils::find_hosted_zone_summary(&summaries, None, "db.internal.example.com.");
+  assert_eq!(zone.map(|z| z.hosted_zone_id()), Some("/hostedzone/Z2"));
+  let zone =
+    utils::find_hosted_zone_summary(&summaries, Some("example.com."), "db.internal.example.com.");
+  assert_eq!(zone.map(|z| z.hosted_zone_id()), Some("/hostedzone/Z1"));
+  assert!(utils::find_hosted_zone_summary(&summaries, None, "db.example.org.").is_none());
+}
+
+#[test]
+fn parse_coordinates() {
+  let coordinates: types::Coordinates = "47.61, -122.33".parse().unwrap();
+  assert_eq!(coordinates.latitude, "47.61");
+  assert_eq!(coordinates.longitude, "-122.33");
+  assert!("47.61".parse::<types::Coordinates>().is_err());
+  assert!("91,0".parse::<types::Coordinates>().is_err());
+  assert!("0,east".parse::<types::Coordinates>().is_err());
+}
+
+#[test]
+fn external_owner() {
+  let routing = types::RecordRouting::default();
+  let record = utils::build_record_set(
+    "service.example.com.",
+    &RrType::A,
+    Some(300),
+    &["192.0.2.1".to_string()],
+    &routing,
+  );
+  let marker = utils::build_record_set(
+    "a-service.example.com.",
+    &RrType::Txt,
+    Some(300),
+    &["\"heritage=external-dns,external-dns/owner=default\"".to_string()],
+    &routing,
+  );
+  let other = utils::build_record_set(
+    "service.example.com.",
+    &RrType::Txt,
+    Some(300),
+    &["\"v=spf1 -all\"".to_string()],
+    &routing,
+  );
+
+  assert_eq!(
+    utils::get_external_owner(
+      &[record.clone(), marker.clone(), other.clone()],
+      "service.example.com.",
+      &RrType::A
+    ),
+    Some("heritage=external-dns,external-dns/owner=default".to_string())
+  );
+  assert_eq!(
+    utils::get_external_owner(
+      &[record.clone(), marker],
+      "service.example.com.",
+      &RrType::Aaaa
+    ),
+    None
+  );
+  assert_eq!(
+    utils::get_external_owner(&[record, other], "service.example.com.", &RrType::A),
+    None
+  );
+}
+
+#[test]
+fn weighted_routing() {
+  let routing = types::RecordRouting {
+    set_identifier: Some("spot".to_string()),
+    weight: Some(10),
+    ..Default::default()
+  };
+  let mut record = utils::build_record_set(
+    "example.com.",
+    &RrType::A,
+    Some(60),
+    &["192.0.2.1".to_string()],
+    &routing,
+  );
+  assert_eq!(record.weight(), Some(10));
+  assert!(routing.is_applied_to(&record));
+  record.weight = Some(0);
+  assert!(!routing.is_applied_to(&record));
+}
+
+#[test]
+fn tag_owner() {
+  let tags = [
+    aws_sdk_route53::types::Tag::builder()
+      .key("ManagedBy")
+      .value("terraform")
+      .build(),
+    aws_sdk_route53::types::Tag::builder()
+      .key("Team")
+      .value("web")
+      .build(),
+  ];
+  let owner_tags: Vec<types::Tag> = vec![
+    "ManagedBy=cloudformation".parse().unwrap(),
+    "ManagedBy=terraform".parse().unwrap(),
+  ];
+  assert_eq!(
+    utils::get_tag_owner(&tags, &owner_tags),
+    Some("ManagedBy=terraform".to_string())
+  );
+  assert_eq!(utils::get_tag_owner(&tags[1..], &owner_tags), None);
+}
+
+#[test]
+fn der_to_pem() {
+  let pem = utils::der_to_pem(&[0; 60]);
+  assert_eq!(
+    pem,
+    "-----BEGIN CERTIFICATE-----\nAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\nAAAAAAAAAAAAAAAA\n-----END CERTIFICATE-----\n"
+  );
+}
+
+#[test]
+fn stable_hash() {
+  // The FNV-1a test vectors, the values must not change between releases
+  assert_eq!(utils::stable_hash(""), 0xcbf29ce484222325);
+  assert_eq!(utils::stable_hash("a"), 0xaf63dc4c8601ec8c);
+  assert_eq!(utils::stable_hash("foobar"), 0x85944171f73967e8);
+}
+
+#[test]
+fn jitter_ttl() {
+  let ttl = utils::jitter_ttl(300, 10, "host-a service.example.com.");
+  assert!((270..=330).contains(&ttl));
+  // The same host always gets the same TTL
+  assert_eq!(
+    utils::jitter_ttl(300, 10, "host-a service.example.com."),
+    ttl
+  );
+  assert_eq!(
+    utils::jitter_ttl(300, 0, "host-a service.example.com."),
+    300
+  );
+  assert_eq!(utils::jitter_ttl(5, 10, "host-a service.example.com."), 5);
+}
+
+#[test]
+fn normalize_values() {
+  assert_eq!(
+    utils::normalize_values(vec![
+      "192.0.2.2".to_string(),
+      "192.0.2.1".to_string(),
+      "192.0.2.2".to_string(),
+    ]),
+    vec!["192.0.2.1".to_string(), "192.0.2.2".to_string()]
+  );
+}
+
+#[test]
+fn detect_record_type() {
+  let values = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+  assert_eq!(
+    utils::detect_record_type(values(&["192.0.2.1", "192.0.2.2"])),
+    Ok(RrType::A)
+  );
+  assert_eq!(
+    utils::detect_record_type(values(&["2001:db8::1", "2001:db8::2"])),
+    Ok(RrType::Aaaa)
+  );
+  assert_eq!(
+    utils::detect_record_type(values(&["hello world"])),
+    Ok(RrType::Txt)
+  );
+  assert!(utils::detect_record_type(values(&["192.0.2.1", "2001:db8::1"])).is_err());
+}
+
+#[test]
+fn split_mixed_values() {
+  let values = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+  assert_eq!(
+    utils::split_mixed_values(&values(&["192.0.2.1", "2001:db8::1", "192.0.2.2"])),
+    Some((
+      values(&["192.0.2.1", "192.0.2.2"]),
+      values(&["2001:db8::1"])
+    ))
+  );
+  assert_eq!(utils::split_mixed_values(&values(&["192.0.2.1"])), None);
+  assert_eq!(
+    utils::split_mixed_values(&values(&["192.0.2.1", "hello"])),
+    None
+  );
+}
+
+#[test]
+fn check_value_count() {
+  assert!(utils::check_value_count(Some(&RrType::A), 400).is_ok());
+  assert!(utils::check_value_count(Some(&RrType::A), 401).is_err());
+  assert!(utils::check_value_count(None, 401).is_err());
+  assert!(utils::check_value_count(Some(&RrType::Cname), 1).is_ok());
+  assert!(utils::check_value_count(Some(&RrType::Cname), 2).is_err());
+}
+
+#[test]
+fn parse_hosted_zone_id() {
+  assert_eq!(
+    utils::parse_hosted_zone_id("Z0123456789"),
+    Ok("Z0123456789".to_string())
+  );
+  assert_eq!(
+    utils::parse_hosted_zone_id("/hostedzone/Z0123456789"),
+    Ok("Z0123456789".to_string())
+  );
+  assert_eq!(
+    utils::parse_hosted_zone_id("hostedzone/Z0123456789"),
+    Ok("Z0123456789".to_string())
+  );
+  assert!(utils::parse_hosted_zone_id("/change/C0123456789").is_err());
+  assert!(utils::parse_hosted_zone_id("").is_err());
+}
+
+#[test]
+fn expand_relative_name() {
+  assert_eq!(
+    utils::expand_relative_name("service", "example.com"),
+    "service.example.com."
+  );
+  assert_eq!(
+    utils::expand_relative_name("a.b", "example.com."),
+    "a.b.example.com."
+  );
+  assert_eq!(
+    utils::expand_relative_name("@", "example.com"),
+    "example.com."
+  );
+}
+
+#[test]
+fn is_in_zone() {
+  assert!(utils::is_in_zone("service.example.com.", "example.com."));
+  assert!(utils::is_in_zone("Example.com.", "example.com."));
+  assert!(!utils::is_in_zone("service.example.org.", "example.com."));
+  assert!(!utils::is_in_zone(
+    "service.notexample.com.",
+    "example.com."
+  ));
+}
+
+#[test]
+fn cgnat_address() {
+  assert!(utils::is_cgnat_address(&"100.64.0.1".parse().unwrap()));
+  assert!(utils::is_cgnat_address(&"100.127.255.254".parse().unwrap()));
+  assert!(!utils::is_cgnat_address(&"100.128.0.1".parse().unwrap()));
+  assert!(!utils::is_cgnat_address(&"192.168.1.10".parse().unwrap()));
+
+  let address = |interface: &str, address: &str| (interface.to_string(), address.to_string());
+  let eth0 = vec!["eth0".to_string()];
+  let interface_addresses = vec![
+    address("eth0", "192.168.1.10"),
+    address("eth1", "100.72.13.7"),
+  ];
+  assert_eq!(
+    utils::get_cgnat_address(&interface_addresses, &[], "203.0.113.5"),
+    Some("100.72.13.7".to_string())
+  );
+  // The address is not on the interface that connects the host to the internet
+  assert_eq!(
+    utils::get_cgnat_address(&interface_addresses, &eth0, "203.0.113.5"),
+    None
+  );
+  // The public address is assigned to the host
+  assert_eq!(
+    utils::get_cgnat_address(
+      &[
+        address("eth0", "100.72.13.7"),
+        address("eth1", "203.0.113.5")
+      ],
+      &[],
+      "203.0.113.5"
+    ),
+    None
+  );
+  // Behind a regular NAT
+  assert_eq!(
+    utils::get_cgnat_address(&[address("eth0", "192.168.1.10")], &eth0, "203.0.113.5"),
+    None
+  );
+  // Tailscale uses addresses in 100.64.0.0/10 for the tailnet
+  let tailscale = vec![
+    address("eth0", "192.168.1.10"),
+    address("tailscale0", "100.101.102.103"),
+  ];
+  assert_eq!(
+    utils::get_cgnat_address(&tailscale, &eth0, "203.0.113.5"),
+    None
+  );
+  assert_eq!(
+    utils::get_cgnat_address(&tailscale, &[], "203.0.113.5"),
+    None
+  );
+  assert_eq!(
+    utils::get_cgnat_address(&[address("wg0", "100.64.0.2")], &[], "203.0.113.5"),
+    None
+  );
+}
+
+#[test]
+fn select_ipv6_address() {
+  let values: Vec<String> = vec![
+    "2001:db8::a1b2".to_string(),
+    "2001:db8::c3d4".to_string(),
+    "2001:db8::5054:ff:fe12:3456".to_string(),
+    "2001:db8::e5f6".to_string(),
+  ];
+  // An RFC 7217 stable address is not EUI-64, so the kernel's flags decide what is temporary
+  let flags = std::collections::HashMap::from([
+    (
+      "2001:db8::a1b2".parse().unwrap(),
+      vec!["temporary".to_string(), "deprecated".to_string()],
+    ),
+    (
+      "2001:db8::c3d4".parse().unwrap(),
+      vec!["temporary".to_string()],
+    ),
+    (
+      "2001:db8::e5f6".parse().unwrap(),
+      vec!["mngtmpaddr".to_string()],
+    ),
+  ]);
+  assert_eq!(
+    utils::select_ipv6_address(values.clone(), &types::Ipv6Selection::Stable, &flags),
+    vec!["2001:db8::e5f6"]
+  );
+  assert_eq!(
+    utils::select_ipv6_address(values.clone(), &types::Ipv6Selection::Temporary, &flags),
+    vec!["2001:db8::c3d4"]
+  );
+  assert_eq!(
+    utils::select_ipv6_address(values.clone(), &types::Ipv6Selection::Eui64, &flags),
+    vec!["2001:db8::5054:ff:fe12:3456"]
+  );
+  // Without the flags nothing is known to be temporary
+  let no_flags = std::collections::HashMap::new();
+  assert_eq!(
+    utils::select_ipv6_address(values.clone(), &types::Ipv6Selection::Stable, &no_flags),
+    vec!["2001:db8::a1b2"]
+  );
+  assert!(
+    utils::select_ipv6_address(values, &types::Ipv6Selection::Temporary, &no_flags).is_empty()
+  );
+}
+
+#[test]
+fn filter_values() {
+  let values = vec![
+    "10.0.1.5".to_string(),
+    "172.17.0.2".to_string(),
+    "203.0.113.5".to_string(),
+  ];
+  let filters: Vec<types::ValueFilter> = vec!["exclude-cidr:10.0.0.0/8".parse().unwrap()];
+  assert_eq!(
+    utils::filter_values(values.clone(), &filters),
+    vec!["172.17.0.2".to_string(), "203.0.113.5".to_string()]
+  );
+  let filters: Vec<types::ValueFilter> = vec!["include-cidr:10.0.0.0/8".parse().unwrap()];
+  assert_eq!(
+    utils::filter_values(values.clone(), &filters),
+    vec!["10.0.1.5".to_string()]
+  );
+  // Both kinds of filters, the exclude-cidr filters take precedence
+  let filters: Vec<types::ValueFilter> = vec![
+    "include-cidr:10.0.0.0/8".parse().unwrap(),
+    "include-cidr:172.16.0.0/12".parse().unwrap(),
+    "exclude-cidr:10.0.1.0/24".parse().unwrap(),
+  ];
+  assert_eq!(
+    utils::filter_values(values.clone(), &filters),
+    vec!["172.17.0.2".to_string()]
+  );
+  let filters: Vec<types::ValueFilter> = vec!["include-cidr:2001:db8::/32".parse().unwrap()];
+  assert_eq!(
+    utils::filter_values(
+      vec!["2001:db8::1".to_string(), "fd00::1".to_string()],
+      &filters
+    ),
+    vec!["2001:db8::1".to_string()]
+  );
+  assert!(utils::filter_values(values, &filters).is_empty());
+
+  assert!("exclude-cidr:10.0.0.0"
+    .parse::<types::ValueFilter>()
+    .is_err());
+  assert!("cidr:10.0.0.0/8".parse::<types::ValueFilter>().is_err());
+}
+
+#[test]
+fn format_transition() {
+  assert_eq!(
+    utils::format_transition(
+      "service.example.com.",
+      &RrType::A,
+      &["192.0.2.1".to_string()],
+      &["192.0.2.2".to_string(), "192.0.2.3".to_string()]
+    ),
+    "service.example.com. A: 192.0.2.1 -> 192.0.2.2,192.0.2.3"
+  );
+  assert_eq!(
+    utils::format_transition(
+      "service.example.com.",
+      &RrType::A,
+      &[],
+      &["192.0.2.2".to_string()]
+    ),
+    "service.example.com. A: (none) -> 192.0.2.2"
+  );
+}
+
+#[test]
+fn quote_txt_value() {
+  assert_eq!(utils::quote_txt_value("v=spf1 -all"), "\"v=spf1 -all\"");
+  assert_eq!(
+    utils::quote_txt_value("\"already quoted\""),
+    "\"already quoted\""
+  );
+  assert_eq!(
+    utils::quote_txt_value("google-site-verification=\"abc\""),
+    "\"google-site-verification=\\\"abc\\\"\""
+  );
+  assert_eq!(utils::quote_txt_value("a\\b"), "\"a\\\\b\"");
+  let long = "a".repeat(300);
+  assert_eq!(
+    utils::quote_txt_value(&long),
+    format!("\"{}\" \"{}\"", "a".repeat(255), "a".repeat(45))
+  );
+  // Multibyte characters are not split
+  let quoted = utils::quote_txt_value(&format!("{}é", "a".repeat(254)));
+  assert_eq!(quoted, format!("\"{}\" \"\\303\\251\"", "a".repeat(254)));
+  // Escapes count as the byte that they represent
+  let quoted = utils::quote_txt_value(&"\"".repeat(300));
+  assert_eq!(
+    quoted,
+    format!("\"{}\" \"{}\"", "\\\"".repeat(255), "\\\"".repeat(45))
+  );
+}
+
+#[test]
+fn escape_txt_char() {
+  assert_eq!(utils::escape_txt_char('a'), "a");
+  assert_eq!(utils::escape_txt_char(' '), " ");
+  assert_eq!(utils::escape_txt_char('"'), "\\\"");
+  assert_eq!(utils::escape_txt_char('\\'), "\\\\");
+  assert_eq!(utils::escape_txt_char('\t'), "\\011");
+  assert_eq!(utils::escape_txt_char('é'), "\\303\\251");
+  assert_eq!(utils::escape_txt_char('✓'), "\\342\\234\\223");
+}
+
+#[test]
+fn get_txt_strings() {
+  assert_eq!(utils::get_txt_strings("\"abc\""), vec!["abc"]);
+  assert_eq!(
+    utils::get_txt_strings("\"abc\" \"def\""),
+    vec!["abc", "def"]
+  );
+  assert_eq!(
+    utils::get_txt_strings("\"say \\\"hi\\\"\""),
+    vec!["say \\\"hi\\\""]
+  );
+}
+
+#[test]
+fn check_value_lengths() {
+  let txt = Some(&RrType::Txt);
+  assert!(utils::check_value_lengths(txt, &[utils::quote_txt_value(&"a".repeat(1000))]).is_ok());
+  assert!(utils::check_value_lengths(txt, &[format!("\"{}\"", "a".repeat(255))]).is_ok());
+  assert!(utils::check_value_lengths(txt, &[format!("\"{}\"", "a".repeat(256))]).is_err());
+  // Escapes are counted as the byte that they represent
+  assert!(utils::check_value_lengths(txt, &[format!("\"{}\\\"\"", "a".repeat(254))]).is_ok());
+  assert!(utils::check_value_lengths(txt, &[format!("\"{}\\034\"", "a".repeat(254))]).is_ok());
+  assert!(utils::check_value_lengths(txt, &[utils::quote_txt_value(&"a".repeat(4000))]).is_err());
+  let values: Vec<String> = (0..9)
+    .map(|_| utils::quote_txt_value(&"a".repeat(3900)))
+    .collect();
+  assert!(utils::check_value_lengths(txt, &values).is_err());
+  assert!(utils::check_value_lengths(Some(&RrType::A), &["192.0.2.1".to_string()]).is_ok());
+}
+
+#[test]
+fn parse_ttl() {
+  assert_eq!(utils::parse_ttl("300"), Ok(300));
+  assert_eq!(utils::parse_ttl("0"), Ok(0));
+  assert_eq!(utils::parse_ttl("30s"), Ok(30));
+  assert_eq!(utils::parse_ttl("5m"), Ok(300));
+  assert_eq!(utils::parse_ttl("1h"), Ok(3600));
+  assert_eq!(utils::parse_ttl("1d"), Ok(86400));
+  assert_eq!(utils::parse_ttl("1w"), Ok(604800));
+  assert_eq!(utils::parse_ttl("1h30m"), Ok(5400));
+  assert_eq!(utils::parse_ttl("1d2h3m4s"), Ok(93784));
+  assert_eq!(utils::parse_ttl("2147483647"), Ok(utils::MAX_TTL));
+}
+
+#[test]
+fn parse_invalid_ttl() {
+  assert_eq!(
+    utils::parse_ttl(""),
+    Err("the TTL can not be empty".to_string())
+  );
+  assert_eq!(utils::parse_ttl("5x"), Err("invalid TTL: 5x".to_string()));
+  assert_eq!(utils::parse_ttl("m"), Err("invalid TTL: m".to_string()));
+  assert_eq!(
+    utils::parse_ttl("1h30"),
+    Err("invalid TTL (missing unit): 1h30".to_string())
+  );
+  assert_eq!(
+    utils::parse_ttl("-1"),
+    Err("TTL must be between 0 and 2147483647: -1".to_string())
+  );
+  assert_eq!(utils::parse_ttl("-5m"), Err("invalid TTL: -5m".to_string()));
+  assert_eq!(
+    utils::parse_ttl("2147483648"),
+    Err("TTL must be between 0 and 2147483647: 2147483648".to_string())
+  );
+  assert_eq!(
+    utils::parse_ttl("100000w"),
+    Err("TTL must be between 0 and 2147483647: 100000w".to_string())
+  );
+  assert_eq!(
+    utils::parse_ttl("99999999999999w"),
+    Err("TTL is too large: 99999999999999w".to_string())
+  );
+}
+
+#[test]
+fn render_template() {
+  let vars = std::collections::HashMap::from([
+    ("hostname", "web-1".to_string()),
+    ("region", "us-west-2".to_string()),
+    ("value", "{region}".to_string()),
+  ]);
+  assert_eq!(
+    utils::render_template("{hostname}.{region}.example.com", &vars),
+    "web-1.us-west-2.example.com"
+  );
+  assert_eq!(
+    utils::render_template("{unknown} {hostname}", &vars),
+    "{unknown} web-1"
+  );
+  // Inserted values are not expanded again
+  assert_eq!(utils::render_template("{value}", &vars), "{region}");
+  assert_eq!(utils::render_template("{{hostname}", &vars), "{web-1");
+  assert_eq!(utils::render_template("{hostname", &vars), "{hostname");
+}
+
+#[test]
+fn constant_time_eq() {
+  assert!(utils::constant_time_eq(b"secret", b"secret"));
+  assert!(!utils::constant_time_eq(b"secret", b"secreT"));
+  assert!(!utils::constant_time_eq(b"secret", b"secret2"));
+  assert!(!utils::constant_time_eq(b"", b"secret"));
+}
+
+#[test]
+fn get_secret() {
+  let path = std::env::temp_dir().join(format!("route53-update-secret-{}", std::process::id()));
+  std::fs::write(&path, "hunter2\n").unwrap();
+  assert_eq!(
+    utils::get_secret(None, Some(&path)),
+    Ok("hunter2".to_string())
+  );
+  assert_eq!(
+    utils::get_secret(Some("direct".to_string()), None),
+    Ok("direct".to_string())
+  );
+  std::fs::write(&path, "\n").unwrap();
+  assert!(utils::get_secret(None, Some(&path)).is_err());
+  std::fs::remove_file(&path).unwrap();
+  assert!(utils::get_secret(None, None).is_err());
+}
@@ -0,0 +1,16 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::weights;
+
+#[test]
+fn shift_weights() {
+  assert_eq!(weights::shift_weights(90, 10, 10), Ok((80, 20)));
+  assert_eq!(weights::shift_weights(100, 0, 25), Ok((75, 25)));
+  // The weight that is shifted is limited to the weight that is left
+  assert_eq!(weights::shift_weights(5, 95, 10), Ok((0, 100)));
+  // Small shifts make progress
+  assert_eq!(weights::shift_weights(3, 0, 1), Ok((2, 1)));
+  assert!(weights::shift_weights(0, 0, 10).is_err());
+  assert!(weights::shift_weights(255, 255, 10).is_err());
+}
@@ -0,0 +1,53 @@
+// Copyright 2023 Stefan Sundin
+// Licensed under GNU GPL v3 or later
+
+use route53_update::worker;
+
+#[test]
+fn parse_request() {
+  let allowed = vec!["*.example.com".to_string()];
+  let request = worker::parse_request(
+    r#"{"record_name": "web1.example.com", "value": ["192.0.2.1"], "ttl": 60}"#,
+    &allowed,
+  )
+  .unwrap();
+  assert_eq!(request.record_name, "web1.example.com");
+  assert_eq!(request.value, vec!["192.0.2.1"]);
+  assert_eq!(request.ttl, Some(60));
+
+  let request = worker::parse_request(
+    r#"{"record_name": "web2.example.com", "instance_id": "i-0123456789abcdef0"}"#,
+    &allowed,
+  )
+  .unwrap();
+  assert_eq!(request.instance_id.as_deref(), Some("i-0123456789abcdef0"));
+}
+
+#[test]
+fn parse_invalid_request() {
+  let allowed = vec!["*.example.com".to_string()];
+  for (body, err) in [
+    (
+      r#"{"record_name": "web1.example.org", "value": ["192.0.2.1"]}"#,
+      "updating web1.example.org is not allowed",
+    ),
+    (
+      r#"{"record_name": "web1.example.com"}"#,
+      "value or instance_id is required",
+    ),
+    (
+      r#"{"record_name": "web1.example.com", "value": ["192.0.2.1"], "instance_id": "i-0123456789abcdef0"}"#,
+      "can only use one of value or instance_id",
+    ),
+    (
+      r#"{"record_name": "web1.example.com", "instance_id": "web1"}"#,
+      "invalid instance ID: web1",
+    ),
+    (
+      r#"{"record_name": "web1.example.com", "type": "BOGUS", "value": ["192.0.2.1"]}"#,
+      "unsupported record type: BOGUS",
+    ),
+  ] {
+    assert_eq!(worker::parse_request(body, &allowed).unwrap_err(), err);
+  }
+}